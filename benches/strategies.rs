@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use file_reader::{analyze_reader, AnalyzeOptions, Corpus, KeyHasher, LineSize, Strategy};
+
+fn strategies(c: &mut Criterion) {
+    for line_size in [LineSize::Small, LineSize::Mixed, LineSize::Large] {
+        let corpus = Corpus {
+            lines: match line_size {
+                LineSize::Large => 1_000,
+                _ => 50_000,
+            },
+            line_size,
+            ..Corpus::default()
+        }
+        .generate();
+        let mut group = c.benchmark_group(format!("{:?} lines", line_size));
+        group.throughput(Throughput::Bytes(corpus.len() as u64));
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .adaptive_chunk_size(true)
+                .build();
+            group.bench_with_input(
+                BenchmarkId::from_parameter(format!("{:?}", strategy)),
+                &corpus,
+                |b, corpus| b.iter(|| analyze_reader(&corpus[..], &options).unwrap()),
+            );
+        }
+        group.finish();
+    }
+}
+
+fn hashers(c: &mut Criterion) {
+    let corpus = Corpus {
+        lines: 50_000,
+        ..Corpus::default()
+    }
+    .generate();
+    let mut group = c.benchmark_group("Hashers");
+    group.throughput(Throughput::Bytes(corpus.len() as u64));
+    for hasher in [KeyHasher::Std, KeyHasher::Fx, KeyHasher::AHash] {
+        let options = AnalyzeOptions::builder().hasher(hasher).build();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{:?}", hasher)),
+            &corpus,
+            |b, corpus| b.iter(|| analyze_reader(&corpus[..], &options).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, strategies, hashers);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+use std::fs::File;
+
+// NOTE: The hints are only given on Linux with the `fadvise` feature. They never change the
+// results, so failing to give them is ignored, and they're no-ops everywhere else.
+
+/// Tells the kernel the file is read from beginning to end, so it reads further ahead.
+#[cfg(all(feature = "fadvise", target_os = "linux"))]
+pub(crate) fn advise_sequential(file: &File) {
+    advise(file, libc::POSIX_FADV_SEQUENTIAL);
+}
+
+/// Drops the pages of the file from the page cache once it has been read,
+/// so a big analysis doesn't evict what the other processes of the box had cached.
+#[cfg(all(feature = "fadvise", target_os = "linux"))]
+pub(crate) fn drop_cache(file: &File) {
+    advise(file, libc::POSIX_FADV_DONTNEED);
+}
+
+/// Gives the advice about the whole file, a length of 0 reaching its end.
+#[cfg(all(feature = "fadvise", target_os = "linux"))]
+fn advise(file: &File, advice: libc::c_int) {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: the descriptor is open for as long as `file` lives, and the advice doesn't
+    // touch any memory of this process
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+    }
+}
+
+#[cfg(not(all(feature = "fadvise", target_os = "linux")))]
+pub(crate) fn advise_sequential(_file: &File) {}
+
+#[cfg(not(all(feature = "fadvise", target_os = "linux")))]
+pub(crate) fn drop_cache(_file: &File) {}
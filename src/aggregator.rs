@@ -0,0 +1,291 @@
+use crate::core::TypeLineCounter;
+use crate::parser::split_path;
+use serde_json::{json, Value};
+use std::{any::Any, cell::OnceCell, collections::HashSet, fmt};
+
+/// Statistic computed over the lines of every type, next to their count and bytes.
+///
+/// The aggregators registered in the options are cloned for every type and for every worker,
+/// so they must be registered empty. The clones of a type are merged once the workers finish,
+/// and when reports are merged or rolled up.
+pub trait Aggregator: AnyAggregator + Send + Sync {
+    /// Adds a line of the type.
+    fn observe(&mut self, record: &Record);
+    /// Adds the lines observed by another aggregator of the same kind, like the one of
+    /// another worker. Use `as_any` to downcast it.
+    fn merge(&mut self, other: &dyn Aggregator);
+    /// The statistic as it goes into the report.
+    fn report(&self) -> Value;
+}
+
+/// Cloning and downcasting of the aggregators, implemented for every `Aggregator + Clone`.
+pub trait AnyAggregator {
+    fn clone_box(&self) -> Box<dyn Aggregator>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Aggregator + Clone + 'static> AnyAggregator for T {
+    fn clone_box(&self) -> Box<dyn Aggregator> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Aggregator> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl fmt::Debug for dyn Aggregator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Aggregator({})", self.report())
+    }
+}
+
+/// A counted line as the aggregators see it.
+pub struct Record<'a> {
+    key: &'a str,
+    line: &'a [u8],
+    bytes: usize,
+    /// The line parsed the first time an aggregator looks into it, shared by all of them.
+    value: OnceCell<Option<Value>>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(key: &'a str, line: &'a [u8], bytes: usize) -> Self {
+        Self {
+            key,
+            line,
+            bytes,
+            value: OnceCell::new(),
+        }
+    }
+
+    /// Type the line is counted as.
+    pub fn key(&self) -> &str {
+        self.key
+    }
+
+    /// The line, with the fields extracted from it if any.
+    pub fn line(&self) -> &[u8] {
+        self.line
+    }
+
+    /// Bytes the line adds to its type.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// The line as JSON, `None` if it isn't.
+    pub fn value(&self) -> Option<&Value> {
+        self.value
+            .get_or_init(|| serde_json::from_slice(self.line).ok())
+            .as_ref()
+    }
+
+    /// A field of the line. Nested fields are separated by dots.
+    pub fn field(&self, path: &str) -> Option<&Value> {
+        self.field_at(&split_path(path))
+    }
+
+    fn field_at(&self, path: &[String]) -> Option<&Value> {
+        path.iter()
+            .try_fold(self.value()?, |value, field| value.get(field))
+    }
+}
+
+/// The count and bytes of the lines, as the report always has them.
+impl Aggregator for TypeLineCounter {
+    fn observe(&mut self, record: &Record) {
+        self.add_bytes(record.bytes());
+    }
+
+    fn merge(&mut self, other: &dyn Aggregator) {
+        if let Some(other) = other.as_any().downcast_ref::<Self>() {
+            TypeLineCounter::merge(self, other);
+        }
+    }
+
+    fn report(&self) -> Value {
+        json!({ "count": self.count, "bytes": self.bytes })
+    }
+}
+
+/// Sum of a numeric field. Lines without it are left out.
+#[derive(Debug, Clone)]
+pub struct Sum {
+    path: Vec<String>,
+    sum: f64,
+}
+
+impl Sum {
+    pub fn new(field: &str) -> Self {
+        Self {
+            path: split_path(field),
+            sum: 0.0,
+        }
+    }
+}
+
+impl Aggregator for Sum {
+    fn observe(&mut self, record: &Record) {
+        if let Some(n) = record.field_at(&self.path).and_then(Value::as_f64) {
+            self.sum += n;
+        }
+    }
+
+    fn merge(&mut self, other: &dyn Aggregator) {
+        if let Some(other) = other.as_any().downcast_ref::<Self>() {
+            self.sum += other.sum;
+        }
+    }
+
+    fn report(&self) -> Value {
+        json!(self.sum)
+    }
+}
+
+/// Number of distinct values of a field. All of them are kept in memory.
+#[derive(Debug, Clone)]
+pub struct Distinct {
+    path: Vec<String>,
+    values: HashSet<String>,
+}
+
+impl Distinct {
+    pub fn new(field: &str) -> Self {
+        Self {
+            path: split_path(field),
+            values: HashSet::new(),
+        }
+    }
+}
+
+impl Aggregator for Distinct {
+    fn observe(&mut self, record: &Record) {
+        let value = match record.field_at(&self.path) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => return,
+        };
+        self.values.insert(value);
+    }
+
+    fn merge(&mut self, other: &dyn Aggregator) {
+        if let Some(other) = other.as_any().downcast_ref::<Self>() {
+            self.values.extend(other.values.iter().cloned());
+        }
+    }
+
+    fn report(&self) -> Value {
+        json!(self.values.len())
+    }
+}
+
+/// Quantile of a numeric field, like the 0.99 of the latencies, by the nearest rank.
+/// It's exact, so all the values are kept in memory. `null` if no line had the field.
+#[derive(Debug, Clone)]
+pub struct Quantile {
+    path: Vec<String>,
+    quantile: f64,
+    values: Vec<f64>,
+}
+
+impl Quantile {
+    /// The quantile goes from 0 (the minimum) to 1 (the maximum).
+    pub fn new(field: &str, quantile: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&quantile) {
+            return Err(format!(
+                "invalid quantile `{}`, expected a number between 0 and 1",
+                quantile
+            ));
+        }
+        Ok(Self {
+            path: split_path(field),
+            quantile,
+            values: Vec::new(),
+        })
+    }
+}
+
+impl Aggregator for Quantile {
+    fn observe(&mut self, record: &Record) {
+        if let Some(n) = record.field_at(&self.path).and_then(Value::as_f64) {
+            self.values.push(n);
+        }
+    }
+
+    fn merge(&mut self, other: &dyn Aggregator) {
+        if let Some(other) = other.as_any().downcast_ref::<Self>() {
+            self.values.extend_from_slice(&other.values);
+        }
+    }
+
+    fn report(&self) -> Value {
+        let mut values = self.values.clone();
+        values.sort_by(f64::total_cmp);
+        let rank = (self.quantile * values.len() as f64).ceil() as usize;
+        values
+            .get(rank.saturating_sub(1))
+            .map_or(Value::Null, |value| json!(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe_all(aggregator: &mut dyn Aggregator, lines: &[&str]) {
+        for line in lines {
+            aggregator.observe(&Record::new("A", line.as_bytes(), line.len()));
+        }
+    }
+
+    #[test]
+    fn built_in_aggregators_report_their_statistic() {
+        let lines = [
+            "{\"user\":\"ann\",\"took\":{\"ms\":10}}",
+            "{\"user\":\"bob\",\"took\":{\"ms\":30}}",
+            "{\"user\":\"ann\",\"took\":{\"ms\":\"slow\"}}",
+            "{\"user\":1}",
+            "not json",
+        ];
+        let mut counter = TypeLineCounter::default();
+        observe_all(&mut counter, &lines);
+        assert_eq!(counter.report(), json!({"count": 5, "bytes": 115}));
+
+        let mut sum = Sum::new("took.ms");
+        observe_all(&mut sum, &lines);
+        assert_eq!(sum.report(), json!(40.0));
+
+        let mut distinct = Distinct::new("user");
+        observe_all(&mut distinct, &lines);
+        assert_eq!(distinct.report(), json!(3));
+
+        let mut median = Quantile::new("took.ms", 0.5).unwrap();
+        assert_eq!(median.report(), Value::Null);
+        observe_all(&mut median, &lines);
+        assert_eq!(median.report(), json!(10.0));
+        assert!(Quantile::new("took.ms", 1.5).is_err());
+    }
+
+    #[test]
+    fn aggregators_merge_the_ones_of_their_kind() {
+        let mut max = Quantile::new("n", 1.0).unwrap();
+        let mut other = max.clone();
+        observe_all(&mut max, &["{\"n\":1}", "{\"n\":5}"]);
+        observe_all(&mut other, &["{\"n\":9}"]);
+        max.merge(&other);
+        assert_eq!(max.report(), json!(9.0));
+        // aggregators of another kind are ignored
+        max.merge(&Sum::new("n"));
+        assert_eq!(max.report(), json!(9.0));
+
+        let boxed: Box<dyn Aggregator> = Box::new(max);
+        assert_eq!(boxed.clone().report(), json!(9.0));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::command::analyze_command;
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use std::process::Command;
+
+/// Azure blob referenced by a URL like `az://container/path/to/blob`,
+/// optionally followed by a SAS token as its query string (`az://container/blob?sv=...`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureBlob {
+    pub container: String,
+    pub blob: String,
+    pub sas_token: Option<String>,
+}
+
+impl std::str::FromStr for AzureBlob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid Azure URL `{}`, expected az://container/blob", s);
+        let rest = s.strip_prefix("az://").ok_or_else(invalid)?;
+        let (path, sas_token) = match rest.split_once('?') {
+            Some((path, sas_token)) => (path, Some(sas_token.to_string())),
+            None => (rest, None),
+        };
+        match path.split_once('/') {
+            Some((container, blob)) if !container.is_empty() && !blob.is_empty() => Ok(AzureBlob {
+                container: container.to_string(),
+                blob: blob.to_string(),
+                sas_token,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Analyzes an Azure blob in place, streaming it with `az storage blob download`.
+/// Without a SAS token in the URL, the credentials are taken from the environment
+/// as the az CLI does (`AZURE_STORAGE_ACCOUNT` with `AZURE_STORAGE_KEY` or
+/// `AZURE_STORAGE_SAS_TOKEN`, `AZURE_STORAGE_CONNECTION_STRING`...).
+pub fn analyze_azure(blob: &AzureBlob, options: &AnalyzeOptions) -> Result<Report, AnalyzeError> {
+    let mut command = Command::new("az");
+    command
+        .args(["storage", "blob", "download", "--no-progress"])
+        .args(["--container-name", &blob.container, "--name", &blob.blob])
+        .args(["--file", "/dev/stdout"]);
+    if let Some(sas_token) = &blob.sas_token {
+        command.args(["--sas-token", sas_token]);
+    }
+    analyze_command(command, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_blob_parses_the_container_the_blob_and_the_token() {
+        assert_eq!(
+            "az://logs/2024/app.jsonl?sv=2022&sig=abc".parse(),
+            Ok(AzureBlob {
+                container: "logs".to_string(),
+                blob: "2024/app.jsonl".to_string(),
+                sas_token: Some("sv=2022&sig=abc".to_string()),
+            })
+        );
+        assert_eq!(
+            "az://logs/app.jsonl"
+                .parse::<AzureBlob>()
+                .map(|b| b.sas_token),
+            Ok(None)
+        );
+        assert!("az://logs".parse::<AzureBlob>().is_err());
+        assert!("gs://logs/app.jsonl".parse::<AzureBlob>().is_err());
+    }
+}
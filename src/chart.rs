@@ -0,0 +1,104 @@
+use crate::models::Report;
+use crate::template::{escape, render};
+use std::{fs, io, path::Path};
+
+const TEMPLATE: &str = include_str!("templates/chart.svg");
+/// Types drawn in the chart. The rest are added up in a single bar.
+const MAX_BARS: usize = 20;
+const BAR_HEIGHT: usize = 24;
+const BAR_GAP: usize = 8;
+const LABEL_WIDTH: usize = 200;
+const BARS_WIDTH: usize = 480;
+const VALUE_WIDTH: usize = 120;
+const MARGIN: usize = 16;
+const TITLE_HEIGHT: usize = 32;
+
+/// SVG bar chart of the bytes of every type, from the largest to the smallest.
+pub fn bytes_chart(report: &Report) -> String {
+    let mut bars: Vec<(String, usize)> = report
+        .types
+        .iter()
+        .map(|(key, counter)| (key.to_string(), counter.bytes))
+        .collect();
+    bars.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if bars.len() > MAX_BARS {
+        let others = bars.len() - MAX_BARS + 1;
+        let bytes = bars.drain(MAX_BARS - 1..).map(|(_, bytes)| bytes).sum();
+        bars.push((format!("{} other types", others), bytes));
+    }
+    let max = bars
+        .iter()
+        .map(|(_, bytes)| *bytes)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let mut elements = String::new();
+    for (i, (label, bytes)) in bars.iter().enumerate() {
+        let y = TITLE_HEIGHT + i * (BAR_HEIGHT + BAR_GAP);
+        let text_y = y + BAR_HEIGHT / 2;
+        let width = (*bytes as f64 / max as f64 * BARS_WIDTH as f64).round();
+        elements.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" class=\"label\">{}</text>\n",
+            LABEL_WIDTH - BAR_GAP,
+            text_y,
+            escape(label)
+        ));
+        elements.push_str(&format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" class=\"bar\"/>\n",
+            LABEL_WIDTH, y, width, BAR_HEIGHT
+        ));
+        elements.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" class=\"value\">{}</text>\n",
+            LABEL_WIDTH as f64 + width + BAR_GAP as f64,
+            text_y,
+            bytes
+        ));
+    }
+    let width = LABEL_WIDTH + BARS_WIDTH + VALUE_WIDTH + MARGIN;
+    let height = TITLE_HEIGHT + bars.len() * (BAR_HEIGHT + BAR_GAP) + MARGIN;
+    render(
+        TEMPLATE,
+        &[
+            ("width", width.to_string()),
+            ("height", height.to_string()),
+            ("bars", elements),
+        ],
+    )
+}
+
+/// Writes `bytes_chart` to the given path.
+pub fn write_bytes_chart(report: &Report, path: &Path) -> io::Result<()> {
+    fs::write(path, bytes_chart(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_chart_draws_a_bar_per_type_sorted_by_bytes() {
+        let mut report = Report::default();
+        report.add_line("small".into(), 10);
+        report.add_line("<big>".into(), 40);
+        let svg = bytes_chart(&report);
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("class=\"bar\"").count(), 2);
+        let big = svg.find("&lt;big&gt;").unwrap();
+        assert!(big < svg.find(">small<").unwrap());
+        assert!(svg.contains("width=\"480\" height=\"24\""));
+        assert!(svg.contains("width=\"120\" height=\"24\""));
+    }
+
+    #[test]
+    fn bytes_chart_adds_up_the_smallest_types() {
+        let mut report = Report::default();
+        for i in 0..25 {
+            report.add_line(format!("T{}", i).into(), i + 1);
+        }
+        let svg = bytes_chart(&report);
+        assert_eq!(svg.matches("class=\"bar\"").count(), MAX_BARS);
+        // T0..T5 are the smallest ones
+        assert!(svg.contains(">6 other types<"));
+        assert!(svg.contains(">21<"));
+    }
+}
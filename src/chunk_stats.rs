@@ -0,0 +1,159 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Figures of a chunk, or a batch, once a worker parsed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkStats {
+    /// Position of the chunk in the input, from 0.
+    pub index: usize,
+    /// Slot of the worker that parsed it, from 0 to the number of threads.
+    pub worker: usize,
+    /// Byte offset where the chunk starts.
+    pub offset: u64,
+    pub bytes: usize,
+    pub lines: usize,
+    /// Time spent reading the chunk from the input, before handing it to the worker.
+    pub read: Duration,
+    /// Time the worker spent parsing it.
+    pub parse: Duration,
+}
+
+/// Smallest, middle and largest values of a figure over the chunks, by the nearest rank.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Distribution {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Distribution {
+    fn new(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(f64::total_cmp);
+        let rank = |quantile: f64| {
+            let rank = (quantile * values.len() as f64).ceil() as usize;
+            values[rank.saturating_sub(1)]
+        };
+        Self {
+            min: values[0],
+            p50: rank(0.5),
+            p90: rank(0.9),
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        }
+    }
+}
+
+/// How the figures of the chunks of an analysis are distributed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChunkSummary {
+    pub chunks: usize,
+    pub bytes: Distribution,
+    pub lines: Distribution,
+    /// Milliseconds reading every chunk.
+    pub read_ms: Distribution,
+    /// Milliseconds parsing every chunk.
+    pub parse_ms: Distribution,
+    /// Megabytes parsed per second by every chunk's worker.
+    pub parse_mb_per_second: Distribution,
+    /// Milliseconds spent parsing by every worker, to tell if the work was evenly spread.
+    pub busy_ms_by_worker: Vec<f64>,
+}
+
+/// Collects the figures of every chunk of an analysis, to summarize them once it finishes.
+/// Its `record` method matches the `on_chunk` callback. Clones share the same figures so one
+/// of them can be moved into the callback.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkTimings(Arc<Mutex<Vec<ChunkStats>>>);
+
+impl ChunkTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, stats: &ChunkStats) {
+        self.0
+            .lock()
+            .expect("The chunk timings lock is poisoned")
+            .push(*stats);
+    }
+
+    /// The distribution of the figures, `None` if no chunk was parsed.
+    pub fn summary(&self) -> Option<ChunkSummary> {
+        let chunks = self.0.lock().expect("The chunk timings lock is poisoned");
+        if chunks.is_empty() {
+            return None;
+        }
+        let millis = |duration: Duration| duration.as_secs_f64() * 1_000.0;
+        let distribution = |figure: &dyn Fn(&ChunkStats) -> f64| {
+            Distribution::new(chunks.iter().map(figure).collect())
+        };
+        let workers = chunks
+            .iter()
+            .map(|chunk| chunk.worker + 1)
+            .max()
+            .unwrap_or(0);
+        let mut busy_ms_by_worker = vec![0.0; workers];
+        for chunk in chunks.iter() {
+            busy_ms_by_worker[chunk.worker] += millis(chunk.parse);
+        }
+        Some(ChunkSummary {
+            chunks: chunks.len(),
+            bytes: distribution(&|chunk| chunk.bytes as f64),
+            lines: distribution(&|chunk| chunk.lines as f64),
+            read_ms: distribution(&|chunk| millis(chunk.read)),
+            parse_ms: distribution(&|chunk| millis(chunk.parse)),
+            parse_mb_per_second: distribution(&|chunk| {
+                chunk.bytes as f64 / 1_000_000.0 / chunk.parse.as_secs_f64().max(1e-9)
+            }),
+            busy_ms_by_worker,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_gives_the_distribution_of_the_chunks() {
+        let timings = ChunkTimings::new();
+        assert_eq!(timings.summary(), None);
+        for index in 0..10 {
+            timings.record(&ChunkStats {
+                index,
+                worker: index % 3,
+                offset: index as u64 * 1_000,
+                bytes: (index + 1) * 1_000,
+                lines: 10,
+                read: Duration::from_millis(1),
+                parse: Duration::from_millis(index as u64 + 1),
+            });
+        }
+        let summary = timings.summary().unwrap();
+        assert_eq!(summary.chunks, 10);
+        assert_eq!(
+            summary.bytes,
+            Distribution {
+                min: 1_000.0,
+                p50: 5_000.0,
+                p90: 9_000.0,
+                max: 10_000.0,
+                mean: 5_500.0,
+            }
+        );
+        assert_eq!(summary.lines.min, 10.0);
+        assert_eq!(summary.lines.max, 10.0);
+        assert_eq!(summary.parse_ms.p90, 9.0);
+        // every chunk parses 1 MB per second
+        assert!((summary.parse_mb_per_second.min - 1.0).abs() < 1e-9);
+        assert!((summary.parse_mb_per_second.max - 1.0).abs() < 1e-9);
+        assert_eq!(summary.busy_ms_by_worker, vec![22.0, 15.0, 18.0]);
+    }
+}
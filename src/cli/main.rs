@@ -1,39 +1,1302 @@
-use structopt::StructOpt;
+use file_reader::{
+    parse_bytes_limit, parse_count_limit, parse_duration, parse_speed, parse_throughput,
+    parse_timestamp, AnalyzeError, AnalyzeOptions, ChunkSizeCheck, ChunkTimings,
+    CompressionSampler, CostRate, ErrorReport, ExplodeBytes, ExternalSort, Format, Index, Indexer,
+    InvalidUtf8, KeyHasher, Labels, Order, Output, Pivot, Predicate, PrintOptions, Provenance,
+    Query, Rejects, Replay, SchemaVersion, SelfLog, SourceReports, Split, SplitStrategy, Strategy,
+    Template, TimeRange, TimeUnit, WorkerPanic,
+};
+#[cfg(feature = "corpus")]
+use file_reader::{Corpus, LineSize};
+use serde::Deserialize;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use structopt::{
+    clap::{AppSettings, Error, ErrorKind},
+    StructOpt,
+};
+
+/// Exit code used when the analysis failed for any other reason, like a missing file, binary
+/// content or invalid UTF-8 with `--invalid-utf8 error`.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code used when --max-error-rate is exceeded.
+const EXIT_TOO_MANY_ERRORS: i32 = 3;
+/// Exit code used when some results were lost and the report is incomplete.
+const EXIT_INCOMPLETE: i32 = 4;
+/// Exit code used when a type went over its --limit-bytes or --limit-count.
+const EXIT_LIMIT_EXCEEDED: i32 = 5;
 
 #[derive(StructOpt, PartialEq, Debug)]
 #[structopt(
     name("🌇  File Reader"),
     author("💻  Roberto Huertas <roberto.huertas@outlook.com>"),
-    long_about("🧰  Utility to parse JSON lines from a file")
+    long_about("🧰  Utility to parse JSON lines from a file"),
+    setting(AppSettings::SubcommandsNegateReqs)
 )]
 pub struct Cli {
-    /// Path to your file
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+    /// Path to your file. Use - to read from the stdin. With the gcs and azure features, gs:// and az:// objects are read in place
     #[structopt()]
-    pub file_path: String,
+    pub file_path: Option<String>,
+    /// More files to analyze. The results are merged, see --per-source and --source-report to keep them apart
+    #[structopt(conflicts_with = "estimate")]
+    pub more_file_paths: Vec<String>,
+    /// Reads the entries of the systemd journal instead of a file. Group them by a journal field like _SYSTEMD_UNIT or PRIORITY.
+    #[cfg(feature = "journal")]
+    #[structopt(long)]
+    pub journal: bool,
+    /// Arguments passed to journalctl to filter the entries (e.g. "-u nginx --since today").
+    #[cfg(feature = "journal")]
+    #[structopt(long, default_value = "")]
+    pub journal_args: String,
+    /// Reads the logs of the pods matching a label selector, as in `prod/app=api`, using the current kubectl context.
+    #[cfg(feature = "k8s")]
+    #[structopt(long)]
+    pub k8s: Option<file_reader::PodSelector>,
+    /// Reads the logs of a Docker container using the json-file logging driver. The application lines are unwrapped from the `log` field of Docker's envelope.
+    #[cfg(feature = "docker")]
+    #[structopt(long, conflicts_with = "sandbox")]
+    pub docker: Option<String>,
+    /// Field of an envelope holding the actual JSON line as a string (e.g. `log` for Docker logs). The line is decoded from it before being grouped.
+    #[structopt(long, conflicts_with = "sandbox")]
+    pub unwrap: Option<String>,
     /// If set, the file will be read by chunks. It works best for heavy files. If your file is not that big don't set this property as it will usually work faster.
     #[structopt(short = "c", long)]
     pub use_chunks: bool,
-    /// It defines the chunk size that the tool will use to read the file in chunks.
+    /// If set, the file will be read line by line but the lines will be parsed in batches using several threads. It doesn't depend on the chunk size.
+    #[structopt(short = "b", long, conflicts_with = "use-chunks")]
+    pub use_batches: bool,
+    /// Number of lines parsed together when reading the file in batches.
+    #[structopt(long, default_value = "10000")]
+    pub batch_lines: usize,
+    /// It defines the chunk size, in bytes, that the tool will use to read the file in chunks. Values smaller than 8 KiB are raised to it, and values whose chunks wouldn't fit in half the available memory are lowered, with a warning.
     #[structopt(long, default_value = "1000000")]
     pub chunk_size: usize,
+    /// If set, the chunk size is tuned during the run instead of using --chunk-size. Lines longer than the current chunk size make it grow.
+    #[structopt(long)]
+    pub adaptive_chunk_size: bool,
+    /// Maximum number of threads used to parse the chunks. Defaults to the number of available cores.
+    #[structopt(long)]
+    pub threads: Option<usize>,
+    /// Prints how the analysis is going to run to the stderr before running it: the strategy, the chunk size the tool settled on, the threads...
+    #[structopt(long)]
+    pub explain: bool,
+    /// If set, the threads parsing chunks or batches are pinned to the available cores. Useful for consistent benchmarks.
+    #[structopt(long)]
+    pub pin_threads: bool,
+    /// JSON field used to group the lines. Nested fields are separated by dots (`event.type`).
+    #[structopt(long, default_value = "type")]
+    pub group_by: String,
+    /// Comma separated list of JSON fields tried in order. The first one present in a line is used to group it. It overrides --group-by.
+    #[structopt(long)]
+    pub group_by_any: Option<String>,
+    /// Comma separated list of types. Only their lines are counted, and only the blocks holding them are read if the file has an up to date index (see the index subcommand).
+    #[structopt(long)]
+    pub only: Option<String>,
+    /// Groups the lines of any text by the first capture group of this regex (the whole match if it has none), like '^(\w+):', without parsing them as JSON. It implies --format raw. Lines not matching it are counted as UNMATCHED.
+    #[cfg(feature = "raw")]
+    #[structopt(long)]
+    pub group_by_regex: Option<String>,
+    /// Regex whose named capture groups become fields usable by --group-by and --duration-field, like 'status=(?P<status>\d{3})':message. It's matched against the field after the last colon of JSON lines, or against the whole line.
+    #[cfg(feature = "raw")]
+    #[structopt(long, conflicts_with = "sandbox")]
+    pub extract_regex: Option<file_reader::ExtractRegex>,
+    /// If set, only string values of the grouping field are accepted. Otherwise numbers, booleans and null are accepted too.
+    #[structopt(long)]
+    pub strict_keys: bool,
+    /// If set, lines whose grouping field is an array are counted once per element.
+    #[structopt(long)]
+    pub explode: bool,
+    /// How the bytes of an exploded line are attributed to its elements: full or divided.
+    #[structopt(long, default_value = "full")]
+    pub explode_bytes: ExplodeBytes,
+    /// Aggregates hierarchical types (`payment.card.authorized`) at this depth (`payment.card` for 2).
+    #[structopt(long)]
+    pub rollup_depth: Option<usize>,
+    /// If set, hierarchical types are printed as a tree with the subtotals of every parent.
+    #[structopt(long)]
+    pub tree: bool,
+    /// Separator between the levels of the hierarchical types.
+    #[structopt(long, default_value = ".")]
+    pub rollup_sep: String,
+    /// Whether the line terminator (`\n` or `\r\n`) counts towards the bytes of each line.
+    #[structopt(long, default_value = "true", parse(try_from_str))]
+    pub count_newlines: bool,
+    /// What to do with lines that are not valid UTF-8: error (stopping with exit code 1), replace or skip-line.
+    #[structopt(long, default_value = "error")]
+    pub invalid_utf8: InvalidUtf8,
+    /// What to do when a worker thread panics: abort, count its lines as PANIC or retry them one by one.
+    #[structopt(long, default_value = "abort")]
+    pub on_panic: WorkerPanic,
+    /// Layout of the file: auto, jsonl, json-array, json, json-seq, csv or logfmt.
+    #[structopt(long, default_value = "auto")]
+    pub format: Format,
+    /// If set, only the beginning of the file is analyzed and the results are extrapolated to its whole size.
+    #[structopt(long)]
+    pub estimate: bool,
+    /// Megabytes read from the beginning of the file when using --estimate.
+    #[structopt(long, default_value = "10")]
+    pub estimate_sample: u64,
+    /// Compresses a sample of the lines of every type and prints their gzip and zstd compression ratios, to find the event streams worth compressing at the producer. It needs the gzip or the zstd feature.
+    #[structopt(long)]
+    pub estimate_compression: bool,
+    /// Megabytes of lines of every type compressed by --estimate-compression.
+    #[structopt(long, default_value = "1")]
+    pub compression_sample: u64,
+    /// Number of times a failed read is retried, reopening the file and resuming from the last byte read. Useful for network filesystems.
+    #[structopt(long, default_value = "0")]
+    pub io_retries: u32,
+    /// Time to wait before retrying a failed read (500ms, 2s...).
+    #[structopt(long, default_value = "500ms", parse(try_from_str = parse_duration))]
+    pub io_retry_delay: Duration,
+    /// If set, the pages of the file are dropped from the page cache once it has been analyzed, so shared hosts keep what they had cached. Linux only.
+    #[structopt(long)]
+    pub drop_cache_after: bool,
+    /// If set, the file is read bypassing the page cache (O_DIRECT), so the timings are the ones of the disk instead of a warm cache. It needs the direct-io feature on Linux.
+    #[structopt(long)]
+    pub direct_io: bool,
+    /// Hardens the analysis for untrusted files, like the ones uploaded by users: lines are limited to 1 MiB, 64 levels of nesting and 10000 types unless other limits are given, nothing is extracted or unwrapped from them, and panicking lines are counted as PANIC.
+    #[structopt(long)]
+    pub sandbox: bool,
+    /// Lines longer than this amount of bytes aren't parsed but counted as TOO_LONG, without holding them in memory.
+    #[structopt(long)]
+    pub max_line_length: Option<usize>,
+    /// Lines nesting more objects or arrays than this aren't parsed but counted as TOO_DEEP.
+    #[structopt(long)]
+    pub max_depth: Option<usize>,
+    /// Maximum number of types kept in memory. The lines of the types past it are counted as OTHER.
+    #[structopt(long)]
+    pub max_types: Option<usize>,
+    /// Maximum throughput reading the file (50MB/s, 512KiB/s...).
+    #[structopt(long, parse(try_from_str = parse_throughput))]
+    pub max_throughput: Option<u64>,
+    /// Collector receiving the results as OTLP metrics over HTTP (e.g. http://collector:4318).
+    #[cfg(feature = "otlp")]
+    #[structopt(long, conflicts_with = "estimate")]
+    pub otlp: Option<String>,
+    /// Webhook (e.g. a Slack incoming webhook) notified when the --notify-when condition is met.
+    #[cfg(feature = "notify")]
+    #[structopt(long, requires = "notify-when", conflicts_with = "estimate")]
+    pub notify_webhook: Option<String>,
+    /// Condition over the results triggering the notification, like 'ERROR.count > 1000' or 'A.bytes >= 1048576'.
+    #[cfg(feature = "notify")]
+    #[structopt(long, requires = "notify-webhook")]
+    pub notify_when: Option<file_reader::Condition>,
+    /// Path of a self-contained HTML report with the summary, the top types, some error samples and the run metadata.
+    #[structopt(long, conflicts_with = "estimate")]
+    pub html: Option<String>,
+    /// Crosses two fields in a wide-format table with totals instead of grouping by a single one, e.g. "rows=type cols=status values=count". Values are count or bytes.
+    #[structopt(long)]
+    pub pivot: Option<Pivot>,
+    /// Path where the pivot table is written too, as HTML if it ends with .html and as CSV otherwise.
+    #[structopt(long, requires = "pivot")]
+    pub pivot_output: Option<String>,
+    /// If set, the results of every file (or pod) are printed before the merged results.
+    #[structopt(long, alias = "per-pod")]
+    pub per_source: bool,
+    /// Path where the results of every file (or pod) are written with a source column, as CSV if it ends with .csv and as JSON otherwise.
+    #[structopt(long, conflicts_with = "estimate")]
+    pub source_report: Option<String>,
+    /// Layout of the --source-report, --history, --error-report and --self-log outputs and of the /report of `serve`, for parsers that only know an older one. Version 1 has no report_version. The latest by default.
+    #[structopt(long)]
+    pub schema_version: Option<SchemaVersion>,
+    /// Path of an SVG bar chart with the bytes of every type.
+    #[structopt(long, conflicts_with = "estimate")]
+    pub chart: Option<String>,
+    /// Layout of the results: table, or snapshot for golden files (sorted, fixed-width, without timings).
+    #[structopt(long, default_value = "table")]
+    pub output: Output,
+    /// Layout of the line of every type instead of the tables, like '{type}\t{count}\t{bytes}'. Placeholders: type, count, bytes, first_line, first_offset, last_line, last_offset, duration and avg_duration.
+    #[structopt(long, conflicts_with = "pretty-print")]
+    pub template: Option<Template>,
+    /// Price per GB (10^9 bytes) of a sink, like 0.023 or s3=0.023, adding the estimated cost of every type to the tables. Repeat it for several sinks.
+    #[structopt(long, number_of_values = 1)]
+    pub cost_per_gb: Vec<CostRate>,
+    /// Budget of bytes of a type, like payment=5GB. The run exits with code 5 when a type goes over its budget. Repeat it for several types.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_bytes_limit))]
+    pub limit_bytes: Vec<file_reader::Condition>,
+    /// Budget of lines of a type, like debug=1e9. The run exits with code 5 when a type goes over its budget. Repeat it for several types.
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_count_limit))]
+    pub limit_count: Vec<file_reader::Condition>,
     /// If set, the result will be displayed in a pretty table
     #[structopt(short = "p", long)]
     pub pretty_print: bool,
-    /// If set, some additional errors will be derived to the stderr
-    #[structopt(short = "v", long)]
-    pub verbose_errors: bool,
+    /// Order of the types in the tables: key, count, or first-seen to list them as they first appeared in the file.
+    #[structopt(long)]
+    pub order: Option<Order>,
+    /// If set, the tables show the line number and byte offset of the first and last lines of every type.
+    #[structopt(long)]
+    pub detailed: bool,
+    /// Decimal places of the derived statistics, like the error rate of the HTML report. Halves are rounded to even.
+    #[structopt(long)]
+    pub precision: Option<usize>,
+    /// Keys longer than this amount of chars are shortened with an ellipsis in the printed tables.
+    #[structopt(long)]
+    pub max_key_width: Option<usize>,
+    /// If set, the shortened keys end with a hash of the full key, so similar keys stay apart.
+    #[structopt(long, requires = "max-key-width")]
+    pub hash_long_keys: bool,
+    /// Separates the thousands of the printed counts and bytes, with a comma or the given char.
+    #[structopt(long)]
+    pub thousands: Option<Option<char>>,
+    /// Path of a TOML config file. Its [labels] table replaces the headers and categories of the printed results, as in `error = "ERREUR"` or `total_count = "NOMBRE TOTAL"`.
+    #[structopt(long, global = true)]
+    pub config: Option<String>,
+    /// Path of a JSON lines file where the events of this run (progress, errors and summary) are written.
+    #[structopt(long)]
+    pub self_log: Option<String>,
+    /// Path of a JSON lines file where the results of every successful run are appended, for the trend subcommand.
+    #[structopt(long, conflicts_with = "estimate")]
+    pub history: Option<String>,
+    /// If set, some additional errors will be derived to the stderr. Twice (-vv), the size, lines, timings and worker of every chunk or batch are logged too, and summarized once the analysis finishes.
+    #[structopt(short = "v", long = "verbose-errors", parse(from_occurrences))]
+    pub verbose: u8,
+    /// If set, bytes after the JSON object of a line (e.g. a trailing comma) are ignored and counted separately instead of failing the line.
+    #[structopt(long)]
+    pub lenient_trailing: bool,
+    /// If set, lines are parsed as JSON5: single quotes, trailing commas, unquoted keys, comments...
+    #[cfg(feature = "relaxed")]
+    #[structopt(long, conflicts_with = "sandbox")]
+    pub relaxed: bool,
+    /// If set, `NaN`, `Infinity` and `-Infinity` literals are accepted as if they were `null`.
+    #[structopt(long)]
+    pub allow_non_finite: bool,
+    /// If set, lines cut in the middle of a JSON document (unbalanced braces or quotes) are counted as TRUNCATED instead of ERROR.
+    #[structopt(long)]
+    pub detect_truncated: bool,
+    /// Counts the lines byte for byte identical to an earlier line of the same type, per type and in total.
+    #[structopt(long, conflicts_with = "sandbox")]
+    pub exact_duplicates: bool,
+    /// Lists the N longest lines with their type, line number, byte offset, length and the beginning of the line.
+    #[structopt(long, value_name = "N")]
+    pub outliers: Option<usize>,
+    /// Field holding the duration of every line (e.g. duration_ms or elapsed), shown as total and average by type.
+    #[structopt(long)]
+    pub duration_field: Option<String>,
+    /// Unit of the numeric durations of --duration-field: s, ms, us or ns. Strings like 1.5s carry their own.
+    #[structopt(long, default_value = "ms")]
+    pub duration_unit: TimeUnit,
+    /// Hash algorithm of the map counting the types: std (SipHash), fx or ahash. The last two are faster with a handful of types.
+    #[structopt(long, default_value = "std")]
+    pub hasher: KeyHasher,
+    /// Stops the analysis at the size the file had when it was opened, or at the given offset,
+    /// leaving out the lines appended meanwhile.
+    #[structopt(long)]
+    pub snapshot_size: Option<Option<u64>>,
+    /// Field holding the timestamp of the lines of a time-ordered file, for --since and --until. Nested fields are separated by dots.
+    #[structopt(long)]
+    pub time_field: Option<String>,
+    /// Analyzes only the lines from this moment on (e.g. 2024-02-29T12:00:00Z, 2024-02-29 or 1709208000), found by binary search instead of scanning the file.
+    #[structopt(long, requires = "time-field")]
+    pub since: Option<String>,
+    /// Analyzes only the lines up to this moment, included. Like --since, it needs a time-ordered file.
+    #[structopt(long, requires = "time-field")]
+    pub until: Option<String>,
+    /// Unit of the numeric timestamps of --time-field: s, ms, us, ns, or auto to guess it for every line from its magnitude.
+    #[structopt(long, default_value = "auto")]
+    pub time_unit: TimeUnit,
+    /// Time zone of the dates and the timestamps without offset of --since and --until (e.g. Europe/Madrid). Defaults to UTC.
+    #[cfg(feature = "tz")]
+    #[structopt(long)]
+    pub tz: Option<file_reader::TimeZone>,
+    /// If set, the beginning of the file isn't checked for binary or compressed content.
+    #[structopt(long)]
+    pub skip_binary_check: bool,
+    /// Compares the bytes read with the bytes of the lines in the results, warning about the ranges left out with their offsets.
+    #[structopt(long)]
+    pub check_bytes: bool,
+    /// Lines starting with this prefix (e.g. '#') are ignored instead of counted as errors.
+    #[structopt(long)]
+    pub skip_comments: Option<String>,
+    /// Number of lines at the beginning of the file to ignore, like a header or a metadata object.
+    #[structopt(long, default_value = "0")]
+    pub skip_lines: usize,
+    /// Aborts the analysis if the ratio (0-1) of lines that couldn't be parsed goes over this value. The process exits with code 3.
+    #[structopt(long)]
+    pub max_error_rate: Option<f64>,
+    /// Writes the lines that couldn't be parsed to this JSON file instead of the stderr.
+    #[structopt(long)]
+    pub error_report: Option<String>,
+    /// Writes the lines that couldn't be parsed to one file per category in this directory (syntax.jsonl, missing_type.jsonl...).
+    #[structopt(long)]
+    pub rejects_dir: Option<String>,
+    /// Writes every line to the file of its type in this directory (A.jsonl, B.jsonl...).
+    #[structopt(long)]
+    pub split_dir: Option<String>,
+    /// How the workers share the files of --split-dir: mutex (a lock per file) or shards (a file per worker, merged at the end).
+    #[structopt(long, default_value = "mutex")]
+    pub split_strategy: SplitStrategy,
+    /// Appends _source_file, _line_number and _byte_offset to the lines written by --split-dir and --rejects-dir, so they can be traced back to where they were read.
+    #[structopt(long)]
+    pub provenance: bool,
+}
+
+#[derive(StructOpt, PartialEq, Debug)]
+pub enum Command {
+    /// Reports some details of the file (newlines, BOM, compression...) to help choosing the flags
+    Inspect {
+        /// Path to your file
+        file_path: String,
+        /// Chunk size you plan to use, in order to check whether the lines fit in it
+        #[structopt(long, default_value = "1000000")]
+        chunk_size: usize,
+        /// If set, the result will be displayed in a pretty table
+        #[structopt(short = "p", long)]
+        pretty_print: bool,
+        /// Decimal places of the average line length. Halves are rounded to even
+        #[structopt(long, default_value = "2")]
+        precision: usize,
+    },
+    /// Analyzes the file and serves the results as JSON over HTTP (/report, /types/{name}, /query?filter=count>1000). Requests are answered one at a time, and clients get 5 seconds to send them
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Path to your file
+        file_path: String,
+        /// Address to listen on
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// JSON field used to group the lines. Nested fields are separated by dots (`event.type`).
+        #[structopt(long, default_value = "type")]
+        group_by: String,
+    },
+    /// Runs a query written in a small SQL subset, e.g. "SELECT type, count(*), sum(length) FROM input WHERE level = 'error' GROUP BY type"
+    Sql {
+        /// Query to run. The table name after FROM is ignored
+        query: String,
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// If set, the result will be displayed in a pretty table
+        #[structopt(short = "p", long)]
+        pretty_print: bool,
+        /// Decimal places of the non integer results, like averages. Halves are rounded to even
+        #[structopt(long)]
+        precision: Option<usize>,
+        /// Runs the query with Polars, which takes the whole SQL language: joins, subqueries, ORDER BY, HAVING... The lines are the `input` table
+        #[cfg(feature = "polars")]
+        #[structopt(long)]
+        polars: bool,
+    },
+    /// Prints the lines matching an expression like 'type == "payment" && amount > 100', byte for byte
+    Grep {
+        /// Fields compared with strings, numbers, true, false or null using ==, !=, <, <=, > or >=, combined with &&, || and parentheses. Nested fields are separated by dots
+        #[structopt(long = "where")]
+        predicate: Predicate,
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// Number of threads matching the lines. Defaults to the number of available cores
+        #[structopt(long)]
+        threads: Option<usize>,
+    },
+    /// Loads the file once and answers commands like `group type`, `filter level=error`, `top 5 bytes` or `sample payment 3` without reading it again
+    Repl {
+        /// Path to your file. The stdin is left for the commands
+        file_path: String,
+        /// JSON field the lines are grouped by at first. Nested fields are separated by dots
+        #[structopt(long, default_value = "type")]
+        group_by: String,
+    },
+    /// Prints the counts and bytes of every type in the last runs kept with --history, with their change since the previous run and a sparkline
+    Trend {
+        /// Path of the history file
+        history_path: String,
+        /// Number of runs compared
+        #[structopt(long, default_value = "10")]
+        runs: usize,
+        /// If set, the result will be displayed in a pretty table
+        #[structopt(short = "p", long)]
+        pretty_print: bool,
+    },
+    /// Re-emits the lines keeping the pacing of their timestamps, as a realistic load generator
+    Replay {
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// Field holding the timestamp (RFC 3339 string or epoch number). Nested fields are separated by dots
+        #[structopt(long, default_value = "timestamp")]
+        timestamp_field: String,
+        /// Unit of the numeric timestamps: s, ms, us, ns, or auto to guess it for every line from its magnitude
+        #[structopt(long, default_value = "auto")]
+        time_unit: TimeUnit,
+        /// How many times faster than the original the lines are emitted, e.g. 10x
+        #[structopt(long, default_value = "1x", parse(try_from_str = parse_speed))]
+        speed: f64,
+        /// Sends the lines to this TCP address (host:port) instead of the stdout
+        #[structopt(long)]
+        connect: Option<String>,
+    },
+    /// Prints the first lines holding a whole JSON document, skipping the broken ones
+    Head {
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// Number of lines to print
+        #[structopt(short = "n", long, default_value = "10")]
+        lines: usize,
+    },
+    /// Prints the last lines holding a whole JSON document, skipping the broken ones. Big files are read backwards from their end
+    Tail {
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// Number of lines to print
+        #[structopt(short = "n", long, default_value = "10")]
+        lines: usize,
+    },
+    /// Sorts the lines by a field, spilling sorted runs to disk so files bigger than the memory can be sorted
+    Sort {
+        /// Path to your file. Use - to read from the stdin
+        file_path: String,
+        /// Field to sort by. Numbers go before strings and lines without the field last. Nested fields are separated by dots
+        #[structopt(long)]
+        by: String,
+        /// Path of the sorted file. The lines are written to the stdout if not set
+        #[structopt(short = "o", long)]
+        output: Option<String>,
+        /// Bytes of lines sorted in memory before spilling them to disk
+        #[structopt(long, default_value = "100000000")]
+        run_size: usize,
+        /// Directory of the sorted runs. The system temporary directory by default
+        #[structopt(long)]
+        temp_dir: Option<String>,
+    },
+    /// Writes a sidecar index (<file>.idx) with the blocks holding every type and their time bounds, so later runs with --only or --since/--until skip the other blocks
+    Index {
+        /// Path to your file
+        file_path: String,
+        /// Comma separated list of JSON fields tried in order to group the lines. It must match the --group-by or --group-by-any of the runs using the index
+        #[structopt(long, default_value = "type")]
+        group_by: String,
+        /// Field holding the timestamp of the lines, to keep the time bounds of every block
+        #[structopt(long)]
+        time_field: Option<String>,
+        /// Unit of the numeric timestamps: s, ms, us, ns, or auto to guess it for every line from its magnitude. It must match the --time-unit of the runs using the index
+        #[structopt(long, default_value = "auto")]
+        time_unit: TimeUnit,
+        /// Bytes of lines per block. Smaller blocks skip more bytes but make bigger indexes
+        #[structopt(long, default_value = "1048576")]
+        block_size: u64,
+    },
+    /// Generates a synthetic corpus of JSON lines, useful to reproduce the benchmarks
+    #[cfg(feature = "corpus")]
+    Generate {
+        /// Path of the file to create
+        file_path: String,
+        /// Number of lines to generate
+        #[structopt(long, default_value = "100000")]
+        lines: usize,
+        /// Number of distinct types
+        #[structopt(long, default_value = "4")]
+        types: usize,
+        /// Distribution of the line sizes: small, mixed or large
+        #[structopt(long, default_value = "small")]
+        line_size: LineSize,
+        /// Percentage of lines that are not valid JSON
+        #[structopt(long, default_value = "0")]
+        error_rate: u8,
+        /// Seed of the generator. The same seed always produces the same corpus
+        #[structopt(long, default_value = "42")]
+        seed: u64,
+    },
 }
 
 fn main() -> std::io::Result<()> {
     let cli: Cli = Cli::from_args();
     let current_dir = std::env::current_dir()?;
-    let path = current_dir.join(cli.file_path);
-    file_reader::start(
-        path,
-        cli.pretty_print,
-        cli.use_chunks,
-        cli.chunk_size,
-        cli.verbose_errors,
+    let config = match &cli.config {
+        Some(config_path) => load_config(&current_dir.join(config_path))?,
+        None => Config::default(),
+    };
+    if let Some(command) = cli.command {
+        let schema_version = cli.schema_version.unwrap_or_default();
+        return run_command(command, &current_dir, &config, schema_version);
+    }
+    #[cfg(feature = "journal")]
+    let journal = cli.journal;
+    #[cfg(not(feature = "journal"))]
+    let journal = false;
+    #[cfg(feature = "k8s")]
+    let k8s = cli.k8s.is_some();
+    #[cfg(not(feature = "k8s"))]
+    let k8s = false;
+    #[cfg(feature = "docker")]
+    let docker_log = match &cli.docker {
+        Some(container) => Some(file_reader::docker_log_path(container)?),
+        None => None,
+    };
+    #[cfg(not(feature = "docker"))]
+    let docker_log: Option<std::path::PathBuf> = None;
+    let file_path = cli.file_path.unwrap_or_else(|| {
+        if !journal && !k8s && docker_log.is_none() {
+            Error::with_description(
+                "The following required arguments were not provided:\n    <file-path>",
+                ErrorKind::MissingRequiredArgument,
+            )
+            .exit()
+        }
+        String::new()
+    });
+    let path = match &docker_log {
+        Some(log) => log.clone(),
+        None => current_dir.join(&file_path),
+    };
+    if let Some(pivot) = cli.pivot {
+        let table = if file_path == "-" {
+            pivot.run(std::io::stdin().lock())?
+        } else {
+            pivot.run(std::io::BufReader::new(std::fs::File::open(&path)?))?
+        };
+        let print = PrintOptions {
+            pretty: cli.pretty_print,
+            labels: config.labels.clone(),
+            ..PrintOptions::default()
+        };
+        file_reader::print_pivot(&print, &table);
+        if let Some(output) = cli.pivot_output {
+            let output_path = current_dir.join(&output);
+            match output_path.extension().and_then(|e| e.to_str()) {
+                Some("html") => std::fs::write(output_path, table.to_html())?,
+                _ => {
+                    table.write_csv(std::io::BufWriter::new(std::fs::File::create(output_path)?))?
+                }
+            }
+            println!("Pivot table written to {}", output);
+        }
+        return Ok(());
+    }
+    let threads = cli
+        .threads
+        .unwrap_or_else(|| AnalyzeOptions::default().threads());
+    let chunk_size = ChunkSizeCheck::new(cli.chunk_size, threads);
+    // --explain shows it along with the rest of the settings
+    if cli.use_chunks && !cli.adaptive_chunk_size && !chunk_size.is_ok() && !cli.explain {
+        eprintln!("WARNING: the chunk size is {}.", chunk_size);
+    }
+    let mut options = AnalyzeOptions::builder()
+        .group_by(cli.group_by)
+        .strict_keys(cli.strict_keys)
+        .explode(cli.explode)
+        .explode_bytes(cli.explode_bytes)
+        .strategy(if cli.use_chunks {
+            Strategy::Chunks
+        } else if cli.use_batches {
+            Strategy::Batches
+        } else {
+            Strategy::Naive
+        })
+        .chunk_size(chunk_size.effective())
+        .adaptive_chunk_size(cli.adaptive_chunk_size)
+        .batch_lines(cli.batch_lines)
+        .pin_threads(cli.pin_threads)
+        .count_newlines(cli.count_newlines)
+        .invalid_utf8(cli.invalid_utf8)
+        .worker_panic(cli.on_panic)
+        .format(cli.format)
+        .skip_lines(cli.skip_lines)
+        .io_retries(cli.io_retries)
+        .io_retry_delay(cli.io_retry_delay)
+        .lenient_trailing(cli.lenient_trailing)
+        .allow_non_finite(cli.allow_non_finite)
+        .detect_truncated(cli.detect_truncated)
+        .exact_duplicates(cli.exact_duplicates)
+        .outliers(cli.outliers.unwrap_or(0))
+        .positions(cli.detailed || cli.order == Some(Order::FirstSeen))
+        .hasher(cli.hasher)
+        .check_binary(!cli.skip_binary_check)
+        .check_bytes(cli.check_bytes)
+        .drop_cache_after(cli.drop_cache_after)
+        .direct_io(cli.direct_io)
+        .sandbox(cli.sandbox)
+        .verbose_errors(cli.verbose > 0);
+    if let Some(max) = cli.max_line_length {
+        options = options.max_line_length(max);
+    }
+    if let Some(max) = cli.max_depth {
+        options = options.max_depth(max);
+    }
+    if let Some(max) = cli.max_types {
+        options = options.max_types(max);
+    }
+    if let Some(paths) = cli.group_by_any {
+        options = options.group_by_any(paths.split(','));
+    }
+    if let Some(types) = cli.only {
+        options = options.only(types.split(','));
+    }
+    if let Some(depth) = cli.rollup_depth {
+        options = options.rollup(depth, cli.rollup_sep.as_str());
+    }
+    if let Some(threads) = cli.threads {
+        options = options.threads(threads);
+    }
+    #[cfg(feature = "relaxed")]
+    {
+        options = options.relaxed(cli.relaxed);
+    }
+    #[cfg(feature = "raw")]
+    if let Some(regex) = cli.group_by_regex {
+        let regex = regex::Regex::new(&regex).unwrap_or_else(|e| {
+            Error::with_description(&e.to_string(), ErrorKind::InvalidValue).exit()
+        });
+        options = options.group_by_regex(regex);
+    }
+    #[cfg(feature = "raw")]
+    if let Some(extract) = cli.extract_regex {
+        options = options.extract_regex(extract);
+    }
+    if let Some(prefix) = cli.skip_comments {
+        options = options.skip_comments(prefix);
+    }
+    if let Some(field) = cli.duration_field {
+        options = options
+            .duration_field(field)
+            .duration_unit(cli.duration_unit);
+    }
+    if let Some(field) = cli.unwrap {
+        options = options.unwrap_field(field);
+    } else if docker_log.is_some() {
+        options = options.unwrap_field("log");
+    }
+    match cli.snapshot_size {
+        Some(Some(size)) => options = options.snapshot_size(size),
+        Some(None) => options = options.snapshot(true),
+        None => {}
+    }
+    if let Some(field) = cli.time_field {
+        #[cfg(feature = "tz")]
+        let tz = cli.tz;
+        let bound =
+            |bound: Option<String>| {
+                let bound = bound?;
+                #[cfg(feature = "tz")]
+                let parsed = match &tz {
+                    Some(tz) => tz.parse_timestamp(&bound),
+                    None => parse_timestamp(&bound),
+                };
+                #[cfg(not(feature = "tz"))]
+                let parsed = parse_timestamp(&bound);
+                Some(parsed.unwrap_or_else(|e| {
+                    Error::with_description(&e, ErrorKind::InvalidValue).exit()
+                }))
+            };
+        options = options.time_range(TimeRange {
+            field,
+            since: bound(cli.since),
+            until: bound(cli.until),
+            unit: cli.time_unit,
+        });
+    }
+    if let Some(max_throughput) = cli.max_throughput {
+        options = options.max_throughput(max_throughput);
+    }
+    if let Some(max_error_rate) = cli.max_error_rate {
+        options = options.max_error_rate(max_error_rate);
+    }
+    // the HTML report shows some error samples too
+    let error_report = (cli.error_report.is_some() || cli.html.is_some()).then(ErrorReport::new);
+    if cli.provenance && cli.split_dir.is_none() && cli.rejects_dir.is_none() {
+        eprintln!("WARNING: --provenance only changes the lines of --split-dir and --rejects-dir");
+    }
+    let provenance = cli.provenance.then(|| {
+        Provenance::new(match &docker_log {
+            Some(log) => log.display().to_string(),
+            None if journal => "journal".to_string(),
+            None => file_path.clone(),
+        })
+    });
+    if let Some(provenance) = provenance.clone() {
+        options = options.on_source(move |source| provenance.set_source(source));
+    }
+    let rejects = match &cli.rejects_dir {
+        Some(dir) => {
+            let rejects = Rejects::new(current_dir.join(dir))?;
+            Some(match provenance.clone() {
+                Some(provenance) => rejects.with_provenance(provenance),
+                None => rejects,
+            })
+        }
+        None => None,
+    };
+    let split = match &cli.split_dir {
+        Some(dir) => {
+            let split = Split::new(current_dir.join(dir), cli.split_strategy)?;
+            Some(match provenance.clone() {
+                Some(provenance) => split.with_provenance(provenance),
+                None => split,
+            })
+        }
+        None => None,
+    };
+    if cli.estimate_compression && cfg!(not(any(feature = "gzip", feature = "zstd"))) {
+        Error::with_description(
+            "--estimate-compression needs the gzip or the zstd feature",
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    let compression_sample = cli.compression_sample as usize * 1_000_000;
+    let compression = cli
+        .estimate_compression
+        .then(|| CompressionSampler::new(compression_sample));
+    if split.is_some() || compression.is_some() {
+        let split_recorder = split.clone();
+        let sampler = compression.clone();
+        options = options.on_line(move |key, line_number, offset, line| {
+            if let Some(split) = &split_recorder {
+                split.record(key, line_number, offset, line);
+            }
+            if let Some(sampler) = &sampler {
+                sampler.record(key, line);
+            }
+        });
+    }
+    let chunk_timings = if cli.verbose > 1 {
+        log_chunks();
+        Some(ChunkTimings::new())
+    } else {
+        None
+    };
+    if let Some(timings) = chunk_timings.clone() {
+        options = options.on_chunk(move |stats| timings.record(stats));
+    }
+    let self_log = match &cli.self_log {
+        Some(path) => Some(SelfLog::create(
+            &current_dir.join(path),
+            chunk_size.effective() as u64,
+            cli.schema_version.unwrap_or_default(),
+        )?),
+        None => None,
+    };
+    if let Some(log) = self_log.clone() {
+        options = options.on_progress(move |done, total| log.progress(done, total));
+    }
+    if error_report.is_some() || rejects.is_some() || self_log.is_some() {
+        let report_collector = error_report.clone();
+        let rejects_collector = rejects.clone();
+        let log_collector = self_log.clone();
+        options = options.on_error(move |line_number, offset, line, e| {
+            if let Some(report) = &report_collector {
+                report.record(line_number, offset, line, e);
+            }
+            if let Some(rejects) = &rejects_collector {
+                rejects.record(line_number, offset, line, e);
+            }
+            if let Some(log) = &log_collector {
+                log.error(line_number, offset, line, e);
+            }
+        });
+    }
+    let options = options.build();
+    if cli.explain {
+        explain(&options, &chunk_size);
+    }
+    let print = PrintOptions {
+        output: cli.output,
+        pretty: cli.pretty_print,
+        max_key_width: cli.max_key_width,
+        hash_long_keys: cli.hash_long_keys,
+        thousands: cli.thousands.map(|separator| separator.unwrap_or(',')),
+        tree: if cli.tree {
+            Some(cli.rollup_sep.clone())
+        } else {
+            None
+        },
+        order: cli.order,
+        detailed: cli.detailed,
+        precision: cli.precision,
+        template: cli.template,
+        costs: cli.cost_per_gb,
+        limits: cli.limit_bytes.into_iter().chain(cli.limit_count).collect(),
+        labels: config.labels,
+    };
+    #[cfg(feature = "otlp")]
+    let analysis_start = std::time::SystemTime::now();
+    if let Some(log) = &self_log {
+        log.started(if journal {
+            "journal"
+        } else if k8s {
+            "k8s"
+        } else {
+            &file_path
+        });
+    }
+    let init = Instant::now();
+    let mut outcome = Ok(file_reader::Report::default());
+    let mut sources = None;
+    if journal {
+        #[cfg(feature = "journal")]
+        {
+            let args: Vec<String> = cli
+                .journal_args
+                .split_whitespace()
+                .map(String::from)
+                .collect();
+            outcome = file_reader::start_with("the journal", &print, || {
+                file_reader::analyze_journal(&args, &options)
+            });
+        }
+    } else if k8s {
+        #[cfg(feature = "k8s")]
+        if let Some(selector) = &cli.k8s {
+            let per_source = cli.per_source;
+            outcome = file_reader::start_with("the pods", &print, || {
+                let pods = file_reader::analyze_pods(selector, &options)?;
+                if per_source {
+                    print_sources(&print, &pods);
+                }
+                let results = pods.merged();
+                sources = Some(pods);
+                Ok(results)
+            });
+        }
+    } else if cfg!(feature = "gcs") && file_path.starts_with("gs://") && docker_log.is_none() {
+        #[cfg(feature = "gcs")]
+        {
+            outcome = file_reader::start_with(&file_path, &print, || {
+                file_reader::analyze_gcs(&file_path, &options)
+            });
+        }
+    } else if cfg!(feature = "azure") && file_path.starts_with("az://") && docker_log.is_none() {
+        #[cfg(feature = "azure")]
+        {
+            let blob = file_path
+                .parse::<file_reader::AzureBlob>()
+                .map_err(std::io::Error::other)?;
+            outcome = file_reader::start_with(&blob.blob, &print, || {
+                file_reader::analyze_azure(&blob, &options)
+            });
+        }
+    } else if file_path == "-" && docker_log.is_none() {
+        outcome = file_reader::start_with("the stdin", &print, || {
+            file_reader::analyze_reader(std::io::stdin().lock(), &options)
+        });
+    } else if !cli.more_file_paths.is_empty() || cli.source_report.is_some() {
+        let paths: Vec<_> = std::iter::once(path)
+            .chain(cli.more_file_paths.iter().map(|p| current_dir.join(p)))
+            .collect();
+        let per_source = cli.per_source;
+        outcome = file_reader::start_with("the files", &print, || {
+            let files = file_reader::analyze_files(&paths, &options)?;
+            if per_source {
+                print_sources(&print, &files);
+            }
+            let results = files.merged();
+            sources = Some(files);
+            Ok(results)
+        });
+    } else if cli.estimate {
+        let sample_size = cli.estimate_sample * 1_000_000;
+        file_reader::start_estimate(path, &print, sample_size, &options);
+    } else {
+        outcome = file_reader::start(path, &print, &options);
+    }
+    let duration = init.elapsed();
+    let schema_version = cli.schema_version.unwrap_or_default();
+    if let Some(log) = &self_log {
+        log.finished(&outcome, duration);
+        log.finish()?;
+    }
+    if let (Some(history_path), Ok(results)) = (&cli.history, &outcome) {
+        file_reader::HistoryEntry::new(results.clone())
+            .with_schema(schema_version)
+            .append_to(&current_dir.join(history_path))?;
+    }
+    if let (Some(report_path), Some(sources)) = (&cli.source_report, &sources) {
+        sources.write_to(&current_dir.join(report_path), schema_version)?;
+        println!("Results by source written to {}", report_path);
+    }
+    if let (Some(html_path), Some(errors), Ok(results)) = (&cli.html, &error_report, &outcome) {
+        let html = file_reader::HtmlReport {
+            source: if file_path.is_empty() {
+                "the analysis"
+            } else {
+                &file_path
+            },
+            report: results,
+            errors: &errors.entries(),
+            options: &options,
+            duration,
+            precision: cli.precision,
+            labels: &print.labels,
+        };
+        html.write_to(&current_dir.join(html_path))?;
+        println!("HTML report written to {}", html_path);
+    }
+    if let (Some(chart_path), Ok(results)) = (&cli.chart, &outcome) {
+        file_reader::write_bytes_chart(results, &current_dir.join(chart_path))?;
+        println!("Chart written to {}", chart_path);
+    }
+    #[cfg(feature = "otlp")]
+    if let (Some(endpoint), Ok(results)) = (&cli.otlp, &outcome) {
+        file_reader::export_otlp(endpoint, results, analysis_start)?;
+    }
+    #[cfg(feature = "notify")]
+    if let (Some(url), Some(condition), Ok(results)) =
+        (&cli.notify_webhook, &cli.notify_when, &outcome)
+    {
+        let source = if file_path.is_empty() {
+            "the analysis"
+        } else {
+            &file_path
+        };
+        if file_reader::notify_webhook(url, condition, source, results)? {
+            println!("Condition `{}` met, webhook notified", condition);
+        }
+    }
+    if let (Some(report_path), Some(error_report)) = (cli.error_report, &error_report) {
+        error_report.write_to(&current_dir.join(&report_path), schema_version)?;
+        println!(
+            "{} errors written to {}",
+            error_report.entries().len(),
+            report_path
+        );
+    }
+    if let (Some(dir), Some(rejects)) = (cli.rejects_dir, rejects) {
+        for (category, lines) in rejects.finish()? {
+            println!(
+                "{} rejected lines written to {}/{}.jsonl",
+                lines, dir, category
+            );
+        }
+    }
+    if let Some(summary) = chunk_timings.as_ref().and_then(ChunkTimings::summary) {
+        file_reader::print_chunk_summary(&print, &summary);
+    }
+    if let Some(sampler) = &compression {
+        file_reader::print_compression_estimate(&print, &sampler.estimate()?);
+    }
+    if let (Some(dir), Some(split)) = (cli.split_dir, split) {
+        for (file, lines) in split.finish()? {
+            println!("{} lines written to {}/{}", lines, dir, file);
+        }
+    }
+    match outcome {
+        Err(AnalyzeError::TooManyErrors { .. }) => std::process::exit(EXIT_TOO_MANY_ERRORS),
+        Err(AnalyzeError::Incomplete { .. }) => std::process::exit(EXIT_INCOMPLETE),
+        Ok(results) if print.limits.iter().any(|limit| limit.is_met(&results)) => {
+            std::process::exit(EXIT_LIMIT_EXCEEDED)
+        }
+        Err(_) => std::process::exit(EXIT_FAILURE),
+        Ok(_) => {}
+    }
+    Ok(())
+}
+
+/// Prints how the analysis runs with the options, the values the tool settled on included.
+fn explain(options: &AnalyzeOptions, chunk_size: &ChunkSizeCheck) {
+    eprintln!("Strategy: {:?}", options.strategy());
+    match options.strategy() {
+        Strategy::Chunks if options.adaptive_chunk_size() => {
+            eprintln!("Chunk size: tuned during the run")
+        }
+        Strategy::Chunks => eprintln!("Chunk size: {}", chunk_size),
+        Strategy::Batches => eprintln!("Batch lines: {}", options.batch_lines()),
+        Strategy::Naive => {}
+    }
+    if options.strategy() != Strategy::Naive {
+        let pinned = if options.pin_threads() {
+            ", pinned"
+        } else {
+            ""
+        };
+        eprintln!("Threads: {}{}", options.threads(), pinned);
+    }
+    eprintln!("Format: {}", options.format());
+    eprintln!("Group by: {}", options.group_by().join(", "));
+    let limits = [
+        ("Max line length", options.max_line_length()),
+        ("Max depth", options.max_depth()),
+        ("Max types", options.max_types()),
+    ];
+    for (limit, value) in limits.iter() {
+        if let Some(value) = value {
+            eprintln!("{}: {}", limit, value);
+        }
+    }
+}
+
+/// Logs the events of the chunks to the stderr.
+#[cfg(feature = "log-chunks")]
+fn log_chunks() {
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(std::io::stderr)
+        .finish();
+    // a subscriber set earlier keeps receiving them
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+/// Without the `log-chunks` feature only the summary of the chunks is shown.
+#[cfg(not(feature = "log-chunks"))]
+fn log_chunks() {
+    eprintln!(
+        "WARNING: logging every chunk needs the `log-chunks` feature, only their summary is shown"
     );
+}
+
+fn print_sources(print: &PrintOptions, sources: &SourceReports) {
+    for (source, report) in &sources.0 {
+        println!("SOURCE: {}", source);
+        file_reader::print_table(print, report);
+    }
+    println!("ALL SOURCES:");
+}
+
+/// Settings read from the `--config` file.
+#[derive(Deserialize, Default)]
+struct Config {
+    #[serde(default)]
+    labels: Labels,
+}
+
+fn load_config(path: &Path) -> std::io::Result<Config> {
+    let config = std::fs::read_to_string(path)?;
+    toml::from_str(&config).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// the schema version is only needed by the `serve` subcommand
+#[cfg_attr(not(feature = "serve"), allow(unused_variables))]
+fn run_command(
+    command: Command,
+    current_dir: &Path,
+    config: &Config,
+    schema_version: SchemaVersion,
+) -> std::io::Result<()> {
+    match command {
+        Command::Inspect {
+            file_path,
+            chunk_size,
+            pretty_print,
+            precision,
+        } => {
+            let inspection = file_reader::inspect(&current_dir.join(file_path))?;
+            let print = PrintOptions {
+                pretty: pretty_print,
+                precision: Some(precision),
+                labels: config.labels.clone(),
+                ..PrintOptions::default()
+            };
+            file_reader::print_inspection(&print, &inspection, &inspection.hints(chunk_size));
+        }
+        Command::Sql {
+            query,
+            file_path,
+            pretty_print,
+            precision,
+            #[cfg(feature = "polars")]
+            polars,
+        } => {
+            #[cfg(feature = "polars")]
+            let polars_result = if polars {
+                let lines = if file_path == "-" {
+                    file_reader::read_lines_frame(std::io::stdin().lock())?
+                } else {
+                    file_reader::lines_frame(current_dir.join(&file_path))?
+                };
+                Some(file_reader::query_polars(&query, lines)?)
+            } else {
+                None
+            };
+            #[cfg(not(feature = "polars"))]
+            let polars_result = None;
+            let result = match polars_result {
+                Some(result) => result,
+                None => {
+                    let query: Query = query.parse().unwrap_or_else(|e: String| {
+                        Error::with_description(&e, ErrorKind::InvalidValue).exit()
+                    });
+                    if file_path == "-" {
+                        query.run(std::io::stdin().lock())?
+                    } else {
+                        let f = std::fs::File::open(current_dir.join(file_path))?;
+                        query.run(std::io::BufReader::new(f))?
+                    }
+                }
+            };
+            let print = PrintOptions {
+                pretty: pretty_print,
+                precision,
+                labels: config.labels.clone(),
+                ..PrintOptions::default()
+            };
+            file_reader::print_query_result(&print, &result);
+        }
+        #[cfg(feature = "serve")]
+        Command::Serve {
+            file_path,
+            listen,
+            group_by,
+        } => {
+            let options = AnalyzeOptions::builder().group_by(group_by).build();
+            let report = file_reader::analyze(&current_dir.join(&file_path), &options)
+                .map_err(std::io::Error::other)?;
+            println!("Serving the results of {} on http://{}", file_path, listen);
+            file_reader::serve(&report, listen, schema_version)?;
+        }
+        Command::Repl {
+            file_path,
+            group_by,
+        } => {
+            let f = std::fs::File::open(current_dir.join(file_path))?;
+            let mut repl = file_reader::Repl::load(f, &group_by)?;
+            println!("Type `help` to see the commands.");
+            repl.run(std::io::stdin().lock(), std::io::stdout().lock())?;
+        }
+        Command::Trend {
+            history_path,
+            runs,
+            pretty_print,
+        } => {
+            let f = std::fs::File::open(current_dir.join(history_path))?;
+            let runs = file_reader::read_history(std::io::BufReader::new(f), runs.max(1))?;
+            let print = PrintOptions {
+                pretty: pretty_print,
+                labels: config.labels.clone(),
+                ..PrintOptions::default()
+            };
+            file_reader::print_trend(&print, &file_reader::Trend::new(&runs));
+        }
+        Command::Grep {
+            predicate,
+            file_path,
+            threads,
+        } => {
+            let threads = threads
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            if file_path == "-" {
+                file_reader::grep(std::io::stdin().lock(), &predicate, threads, stdout)?;
+            } else {
+                let f = std::fs::File::open(current_dir.join(file_path))?;
+                file_reader::grep(std::io::BufReader::new(f), &predicate, threads, stdout)?;
+            }
+        }
+        Command::Replay {
+            file_path,
+            timestamp_field,
+            time_unit,
+            speed,
+            connect,
+        } => {
+            let replay = Replay {
+                timestamp_field,
+                speed,
+                time_unit,
+            };
+            let reader: Box<dyn std::io::BufRead> = if file_path == "-" {
+                Box::new(std::io::stdin().lock())
+            } else {
+                let f = std::fs::File::open(current_dir.join(file_path))?;
+                Box::new(std::io::BufReader::new(f))
+            };
+            let stats = match connect {
+                Some(address) => {
+                    let stream = std::net::TcpStream::connect(address)?;
+                    replay.run(reader, std::io::BufWriter::new(stream))?
+                }
+                None => replay.run(reader, std::io::stdout().lock())?,
+            };
+            eprintln!(
+                "Replayed {} lines ({} without timestamp)",
+                stats.lines, stats.untimed
+            );
+        }
+        Command::Head { file_path, lines } => {
+            let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            if file_path == "-" {
+                file_reader::head(std::io::stdin().lock(), lines, stdout)?;
+            } else {
+                let f = std::fs::File::open(current_dir.join(file_path))?;
+                file_reader::head(std::io::BufReader::new(f), lines, stdout)?;
+            }
+        }
+        Command::Tail { file_path, lines } => {
+            let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+            if file_path == "-" {
+                // the stdin can't be read backwards
+                let mut input = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut input)?;
+                file_reader::tail(std::io::Cursor::new(input), lines, stdout)?;
+            } else {
+                let f = std::fs::File::open(current_dir.join(file_path))?;
+                file_reader::tail(f, lines, stdout)?;
+            }
+        }
+        Command::Sort {
+            file_path,
+            by,
+            output,
+            run_size,
+            temp_dir,
+        } => {
+            let sort = ExternalSort {
+                by,
+                run_size,
+                temp_dir: temp_dir.map_or_else(std::env::temp_dir, |dir| current_dir.join(dir)),
+                ..ExternalSort::default()
+            };
+            let reader: Box<dyn std::io::BufRead> = if file_path == "-" {
+                Box::new(std::io::stdin().lock())
+            } else {
+                let f = std::fs::File::open(current_dir.join(file_path))?;
+                Box::new(std::io::BufReader::new(f))
+            };
+            let stats = match output {
+                Some(path) => {
+                    let f = std::fs::File::create(current_dir.join(path))?;
+                    sort.run(reader, std::io::BufWriter::new(f))?
+                }
+                None => sort.run(reader, std::io::BufWriter::new(std::io::stdout().lock()))?,
+            };
+            eprintln!(
+                "Sorted {} lines ({} without the field) using {} runs",
+                stats.lines, stats.unkeyed, stats.runs
+            );
+        }
+        Command::Index {
+            file_path,
+            group_by,
+            time_field,
+            time_unit,
+            block_size,
+        } => {
+            let indexer = Indexer {
+                group_by: group_by.split(',').map(String::from).collect(),
+                time_field,
+                time_unit,
+                block_size,
+            };
+            let path = current_dir.join(file_path);
+            let f = std::fs::File::open(&path)?;
+            let index = indexer.build(std::io::BufReader::new(f))?;
+            let index_path = Index::path_of(&path);
+            index.write_to(&index_path)?;
+            eprintln!(
+                "Indexed {} types in {} blocks into {}",
+                index.types.len(),
+                index.blocks.len(),
+                index_path.display()
+            );
+        }
+        #[cfg(feature = "corpus")]
+        Command::Generate {
+            file_path,
+            lines,
+            types,
+            line_size,
+            error_rate,
+            seed,
+        } => {
+            let corpus = Corpus {
+                lines,
+                types,
+                line_size,
+                error_rate,
+                seed,
+            };
+            let f = std::fs::File::create(current_dir.join(file_path))?;
+            let bytes = corpus.write_to(std::io::BufWriter::new(f))?;
+            println!("Generated {} lines ({} bytes)", lines, bytes);
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,50 @@
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use crate::reader::analyze_reader;
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+/// Analyzes the lines printed by a command, like `journalctl` or `kubectl logs`,
+/// as they are written. Fails if the command doesn't succeed.
+pub(crate) fn analyze_command(
+    mut command: Command,
+    options: &AnalyzeOptions,
+) -> Result<Report, AnalyzeError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command.stdout(Stdio::piped()).spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .expect("The stdout of the command is piped");
+    let results = analyze_reader(stdout, options);
+    // stops the command if the analysis ended early, e.g. because of an error
+    if results.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let results = results?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{} exited with {}", program, status)).into());
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_command_reads_the_output_and_checks_the_status() {
+        let mut command = Command::new("printf");
+        command.arg(r#"{"type":"A"}\n{"type":"B"}\n"#);
+        let results = analyze_command(command, &AnalyzeOptions::default()).unwrap();
+        assert_eq!(results.get("A").map(|r| r.count), Some(1));
+        assert_eq!(results.get("B").map(|r| r.count), Some(1));
+
+        let command = Command::new("false");
+        assert!(analyze_command(command, &AnalyzeOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+/// How well the lines of a type compress, as the bytes of the sample per compressed byte.
+/// A ratio is `None` when the tool is built without the feature of its compressor.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TypeCompression {
+    pub sampled_lines: u64,
+    pub sampled_bytes: u64,
+    pub gzip: Option<f64>,
+    pub zstd: Option<f64>,
+}
+
+/// Ratios of every type, sorted by type.
+pub type CompressionEstimate = BTreeMap<String, TypeCompression>;
+
+/// Keeps the lines of every type as they're counted, up to a number of bytes per type, to
+/// estimate how well every event stream would compress at the producer. Lines are compressed
+/// together, one per line, as a producer batching them would.
+/// Clones share the same samples so one of them can be moved into the `on_line` callback.
+/// The workers only share a read lock over the types, and the lines of a full sample are left
+/// out without locking it.
+#[derive(Debug, Clone)]
+pub struct CompressionSampler {
+    max_bytes: usize,
+    samples: Arc<RwLock<HashMap<String, Sample>>>,
+}
+
+#[derive(Debug, Default)]
+struct Sample {
+    /// Whether a line didn't fit, so the sample won't take any more.
+    full: AtomicBool,
+    lines: Mutex<SampledLines>,
+}
+
+#[derive(Debug, Default)]
+struct SampledLines {
+    lines: u64,
+    bytes: Vec<u8>,
+}
+
+impl CompressionSampler {
+    pub fn new(max_bytes_per_type: usize) -> Self {
+        Self {
+            max_bytes: max_bytes_per_type,
+            samples: Arc::default(),
+        }
+    }
+
+    /// Adds the raw line to the sample of its type, unless it's full.
+    /// Its signature matches the `on_line` callback.
+    pub fn record(&self, key: &str, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Some(sample) = self
+            .samples
+            .read()
+            .expect("The compression samples lock is poisoned")
+            .get(key)
+        {
+            return sample.add(line, self.max_bytes);
+        }
+        self.samples
+            .write()
+            .expect("The compression samples lock is poisoned")
+            .entry(key.to_string())
+            .or_default()
+            .add(line, self.max_bytes);
+    }
+
+    /// Compresses the sample of every type with the available compressors.
+    pub fn estimate(&self) -> io::Result<CompressionEstimate> {
+        if cfg!(not(any(feature = "gzip", feature = "zstd"))) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "estimating the compression needs the `gzip` or the `zstd` feature",
+            ));
+        }
+        let samples = self
+            .samples
+            .read()
+            .expect("The compression samples lock is poisoned");
+        samples
+            .iter()
+            .map(|(key, sample)| {
+                let sample = sample
+                    .lines
+                    .lock()
+                    .expect("The compression sample lock is poisoned");
+                #[cfg(feature = "gzip")]
+                let gzip = Some(sample.ratio(gzip_size(&sample.bytes)?));
+                #[cfg(not(feature = "gzip"))]
+                let gzip = None;
+                #[cfg(feature = "zstd")]
+                let zstd = Some(sample.ratio(zstd_size(&sample.bytes)?));
+                #[cfg(not(feature = "zstd"))]
+                let zstd = None;
+                let compression = TypeCompression {
+                    sampled_lines: sample.lines,
+                    sampled_bytes: sample.bytes.len() as u64,
+                    gzip,
+                    zstd,
+                };
+                Ok((key.clone(), compression))
+            })
+            .collect()
+    }
+}
+
+impl Sample {
+    fn add(&self, line: &[u8], max_bytes: usize) {
+        if self.full.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut sample = self
+            .lines
+            .lock()
+            .expect("The compression sample lock is poisoned");
+        // checked again, as it may have filled up while waiting for the lock
+        if self.full.load(Ordering::Relaxed) {
+            return;
+        }
+        // the first line is always kept, so every type gets a ratio
+        if sample.lines > 0 && sample.bytes.len() + line.len() + 1 > max_bytes {
+            self.full.store(true, Ordering::Relaxed);
+            return;
+        }
+        sample.bytes.extend_from_slice(line);
+        sample.bytes.push(b'\n');
+        sample.lines += 1;
+    }
+}
+
+impl SampledLines {
+    /// Bytes of the sample per compressed byte.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn ratio(&self, compressed_bytes: usize) -> f64 {
+        self.bytes.len() as f64 / compressed_bytes as f64
+    }
+}
+
+/// Size of the bytes compressed with gzip at the default level.
+#[cfg(feature = "gzip")]
+fn gzip_size(bytes: &[u8]) -> io::Result<usize> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?.len())
+}
+
+/// Size of the bytes compressed with zstd at the default level.
+#[cfg(feature = "zstd")]
+fn zstd_size(bytes: &[u8]) -> io::Result<usize> {
+    Ok(zstd::bulk::compress(bytes, zstd::DEFAULT_COMPRESSION_LEVEL)?.len())
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetitive_types_compress_better() {
+        let sampler = CompressionSampler::new(10_000);
+        let mut seed: u64 = 7;
+        for i in 0..1_000 {
+            sampler.record("heartbeat", b"{\"type\":\"heartbeat\",\"status\":\"ok\"}\n");
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let line = format!(
+                "{{\"type\":\"token\",\"id\":{},\"value\":\"{:x}\"}}",
+                i, seed
+            );
+            sampler.record("token", line.as_bytes());
+        }
+        let estimate = sampler.estimate().unwrap();
+        let heartbeat = estimate["heartbeat"];
+        // the sample stops at the first line not fitting
+        assert_eq!(heartbeat.sampled_lines, 10_000 / 35);
+        assert_eq!(heartbeat.sampled_bytes, heartbeat.sampled_lines * 35);
+        let token = estimate["token"];
+        for (repetitive, random) in [(heartbeat.gzip, token.gzip), (heartbeat.zstd, token.zstd)] {
+            if let (Some(repetitive), Some(random)) = (repetitive, random) {
+                assert!(repetitive > 10.0 * random, "{} {}", repetitive, random);
+                assert!(random > 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn workers_share_the_samples() {
+        let sampler = CompressionSampler::new(10_000);
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let sampler = sampler.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1_000 {
+                        sampler
+                            .record("heartbeat", b"{\"type\":\"heartbeat\",\"status\":\"ok\"}\n");
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let sample = &sampler.estimate().unwrap()["heartbeat"];
+        assert_eq!(sample.sampled_lines, 10_000 / 35);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::models::{Report, TypeLineCounter};
+use crate::throttle::parse_size;
+
+/// Condition over the aggregates of a report, like `ERROR.count > 1000`
+/// or `A.bytes >= 1048576`. Types missing from the report have a count and bytes of 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub key: String,
+    pub comparison: Comparison,
+}
+
+/// Comparison of the count or the bytes of a type with a value, like `count > 1000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comparison {
+    pub metric: Metric,
+    pub operator: Operator,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Count,
+    Bytes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Condition {
+    pub fn is_met(&self, report: &Report) -> bool {
+        self.comparison.matches(&self.counter(report))
+    }
+
+    /// The count or the bytes of the type compared.
+    pub fn actual(&self, report: &Report) -> u64 {
+        self.comparison.actual(&self.counter(report))
+    }
+
+    fn counter(&self, report: &Report) -> TypeLineCounter {
+        report.get(&self.key).copied().unwrap_or_default()
+    }
+}
+
+impl Comparison {
+    pub fn matches(&self, counter: &TypeLineCounter) -> bool {
+        let actual = self.actual(counter);
+        match self.operator {
+            Operator::Greater => actual > self.value,
+            Operator::GreaterOrEqual => actual >= self.value,
+            Operator::Less => actual < self.value,
+            Operator::LessOrEqual => actual <= self.value,
+            Operator::Equal => actual == self.value,
+            Operator::NotEqual => actual != self.value,
+        }
+    }
+
+    /// The count or the bytes of the counter, as compared.
+    pub fn actual(&self, counter: &TypeLineCounter) -> u64 {
+        (match self.metric {
+            Metric::Count => counter.count,
+            Metric::Bytes => counter.bytes,
+        }) as u64
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.key, self.comparison)
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let metric = match self.metric {
+            Metric::Count => "count",
+            Metric::Bytes => "bytes",
+        };
+        let operator = match self.operator {
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+        };
+        write!(f, "{} {} {}", metric, operator, self.value)
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the type may contain dots, so the metric is after the last one before the operator
+        let operator = s.find(['>', '<', '=', '!']).unwrap_or(s.len());
+        let (key, comparison) = s[..operator].rsplit_once('.').ok_or_else(|| {
+            format!(
+                "invalid condition `{}`, expected <type>.count or <type>.bytes",
+                s
+            )
+        })?;
+        let comparison = format!("{}{}", comparison, &s[operator..])
+            .parse()
+            .map_err(|e: String| e.replacen("comparison", "condition", 1))?;
+        Ok(Condition {
+            key: key.trim().to_string(),
+            comparison,
+        })
+    }
+}
+
+impl std::str::FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("invalid comparison `{}`, {}", s, reason);
+        // the two chars operators go first so `>=` isn't read as `>`
+        let (i, operator, len) = [
+            (">=", Operator::GreaterOrEqual),
+            ("<=", Operator::LessOrEqual),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            (">", Operator::Greater),
+            ("<", Operator::Less),
+        ]
+        .iter()
+        .find_map(|(symbol, operator)| s.find(symbol).map(|i| (i, *operator, symbol.len())))
+        .ok_or_else(|| invalid("expected one of >, >=, <, <=, == or !="))?;
+        let metric = match s[..i].trim() {
+            "count" => Metric::Count,
+            "bytes" => Metric::Bytes,
+            _ => return Err(invalid("expected count or bytes")),
+        };
+        let value = s[i + len..]
+            .trim()
+            .parse()
+            .map_err(|_| invalid("expected a number after the operator"))?;
+        Ok(Comparison {
+            metric,
+            operator,
+            value,
+        })
+    }
+}
+
+/// Parses byte budgets like `payment=5GB` into the condition breaking them, `payment.bytes > 5000000000`.
+/// Sizes take the units of the throughputs.
+pub fn parse_bytes_limit(s: &str) -> Result<Condition, String> {
+    limit(s, Metric::Bytes, parse_size, "a size like payment=5GB")
+}
+
+/// Parses line budgets like `debug=1e9` into the condition breaking them, `debug.count > 1000000000`.
+pub fn parse_count_limit(s: &str) -> Result<Condition, String> {
+    let parse_count = |count: &str| match count.trim().parse::<f64>() {
+        Ok(count) if count >= 0.0 && count <= u64::MAX as f64 && count.fract() == 0.0 => {
+            Some(count as u64)
+        }
+        _ => None,
+    };
+    limit(s, Metric::Count, parse_count, "a count like debug=1e9")
+}
+
+fn limit(
+    s: &str,
+    metric: Metric,
+    parse: impl Fn(&str) -> Option<u64>,
+    expected: &str,
+) -> Result<Condition, String> {
+    let invalid = || format!("invalid limit `{}`, expected {}", s, expected);
+    let (key, value) = s.rsplit_once('=').ok_or_else(invalid)?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Condition {
+        key: key.to_string(),
+        comparison: Comparison {
+            metric,
+            operator: Operator::Greater,
+            value: parse(value).ok_or_else(invalid)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn condition_is_evaluated_against_the_report() {
+        let mut report = Report::default();
+        report.add_line("ERROR".into(), 10);
+        report.add_line("ERROR".into(), 20);
+        report.add_line("event.login".into(), 5);
+        let met = |s: &str| s.parse::<Condition>().unwrap().is_met(&report);
+        assert!(met("ERROR.count > 1"));
+        assert!(!met("ERROR.count>2"));
+        assert!(met("ERROR.count >= 2"));
+        assert!(met("ERROR.bytes == 30"));
+        assert!(met("event.login.bytes <= 5"));
+        assert!(met("MISSING.count < 1"));
+        assert!(met("MISSING.count != 1"));
+        let condition: Condition = "ERROR.count>=2".parse().unwrap();
+        assert_eq!(condition.to_string(), "ERROR.count >= 2");
+    }
+
+    #[test]
+    fn comparison_is_evaluated_against_a_counter() {
+        let counter = TypeLineCounter {
+            count: 3,
+            bytes: 30,
+        };
+        let matches = |s: &str| s.parse::<Comparison>().unwrap().matches(&counter);
+        assert!(matches("count > 2"));
+        assert!(matches("bytes<=30"));
+        assert!(!matches("bytes != 30"));
+        assert!("lines > 2".parse::<Comparison>().is_err());
+        assert!("A.count > 2".parse::<Comparison>().is_err());
+    }
+
+    #[test]
+    fn condition_rejects_invalid_expressions() {
+        assert!("ERROR.count".parse::<Condition>().is_err());
+        assert!("ERROR.lines > 1".parse::<Condition>().is_err());
+        assert!("ERROR.count > many".parse::<Condition>().is_err());
+        assert!("ERROR > 1".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn limits_are_the_conditions_breaking_them() {
+        let bytes = parse_bytes_limit("payment=5GB").unwrap();
+        assert_eq!(bytes.to_string(), "payment.bytes > 5000000000");
+        let count = parse_count_limit("debug=1e9").unwrap();
+        assert_eq!(count.to_string(), "debug.count > 1000000000");
+        assert_eq!(
+            parse_count_limit("a=b=12").unwrap().to_string(),
+            "a=b.count > 12"
+        );
+
+        let mut report = Report::default();
+        report.add_line("debug".into(), 10);
+        report.add_line("debug".into(), 10);
+        assert!(parse_count_limit("debug=1").unwrap().is_met(&report));
+        assert!(!parse_count_limit("debug=2").unwrap().is_met(&report));
+        assert!(!parse_bytes_limit("debug=20").unwrap().is_met(&report));
+
+        for invalid in ["payment", "=5GB", "payment=5Gb", "payment=-1"] {
+            assert!(parse_bytes_limit(invalid).is_err(), "{}", invalid);
+        }
+        for invalid in ["debug=1.5", "debug=-1", "debug=many", "debug=inf"] {
+            assert!(parse_count_limit(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, ops::Add};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+
+// NOTE: This module only uses `core` and `alloc`, so the classification of the lines
+// and their counters build without the standard library (`default-features = false`)
+// for devices pre-aggregating their telemetry before uploading it.
+
+/// Type of the lines that aren't JSON objects holding the field.
+pub const ERROR_TYPE: &str = "ERROR";
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLineCounter {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl TypeLineCounter {
+    pub fn add_bytes(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+
+    pub fn merge(&mut self, other: &TypeLineCounter) {
+        self.count += other.count;
+        self.bytes += other.bytes;
+    }
+}
+
+impl Add for TypeLineCounter {
+    type Output = TypeLineCounter;
+
+    fn add(mut self, other: TypeLineCounter) -> TypeLineCounter {
+        self.merge(&other);
+        self
+    }
+}
+
+/// Number of lines of a type holding a duration and their sum.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DurationCounter {
+    pub count: usize,
+    /// Sum of the durations in whole nanoseconds. Unlike floats, integers add up to the
+    /// same total whatever the order the lines are counted and merged in.
+    pub nanos: i64,
+}
+
+impl DurationCounter {
+    pub fn add_seconds(&mut self, seconds: f64) {
+        let nanos = seconds * 1e9;
+        // rounded half away from zero, `as` saturates the out of range values
+        let nanos = (nanos + if nanos < 0.0 { -0.5 } else { 0.5 }) as i64;
+        self.count += 1;
+        self.nanos = self.nanos.saturating_add(nanos);
+    }
+
+    pub fn merge(&mut self, other: &DurationCounter) {
+        self.count += other.count;
+        self.nanos = self.nanos.saturating_add(other.nanos);
+    }
+
+    /// Sum of the durations in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.nanos as f64 / 1e9
+    }
+
+    /// Mean duration of the lines, `None` if there's none.
+    pub fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.seconds() / self.count as f64)
+    }
+}
+
+/// Lines counted by type in a `BTreeMap`, which doesn't need a source of randomness
+/// like the `HashMap` of a `Report`. Only the types are serialized.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct TypeCounts {
+    #[serde(skip)]
+    paths: Vec<Vec<String>>,
+    pub types: BTreeMap<String, TypeLineCounter>,
+}
+
+impl TypeCounts {
+    /// Counts grouping the lines by a field. Nested fields are separated by dots.
+    pub fn new(group_by: &str) -> Self {
+        Self {
+            paths: vec![split_path(group_by)],
+            types: BTreeMap::new(),
+        }
+    }
+
+    /// Counts a line, without its terminator, under its type.
+    pub fn add_line(&mut self, line: &[u8]) {
+        let key = classify(line, &self.paths).unwrap_or_else(|_| ERROR_TYPE.to_string());
+        self.types.entry(key).or_default().add_bytes(line.len());
+    }
+
+    pub fn merge(&mut self, other: &TypeCounts) {
+        for (key, counter) in &other.types {
+            self.types.entry(key.clone()).or_default().merge(counter);
+        }
+    }
+}
+
+/// Type of a line: the value of the first of the paths found in it. Values other than
+/// strings are turned into their JSON representation.
+pub fn classify(line: &[u8], paths: &[Vec<String>]) -> serde_json::Result<String> {
+    match parse_key(line, paths, false, false, false)? {
+        (Key::Single(key), _) => Ok(key),
+        (Key::Exploded(_), _) => unreachable!("Keys are only exploded when asked to"),
+    }
+}
+
+// NOTE: Instead of deserializing the whole line into a struct or a `serde_json::Value`
+// we only keep the value of the field we're grouping by and ignore the rest.
+// This way we still validate that the line is valid JSON while avoiding allocations.
+// Scalar values other than strings (numbers, booleans and null) are converted
+// to their JSON representation unless `strict` is set.
+// Every path is a list of nested fields. When several paths are given, the value of
+// the first one present in the line is used.
+// When `explode` is set, arrays of scalar values yield one key per element.
+// If `lenient_trailing` is set, the bytes found after the JSON object are ignored
+// instead of failing. The number of those bytes is returned along with the key.
+pub(crate) fn parse_key(
+    line: &[u8],
+    paths: &[Vec<String>],
+    strict: bool,
+    explode: bool,
+    lenient_trailing: bool,
+) -> serde_json::Result<(Key, usize)> {
+    let group_key = GroupKey::new(paths, strict, explode);
+    let mut deserializer = serde_json::Deserializer::from_slice(line);
+    // the line itself must be an object
+    let key = de::Deserializer::deserialize_map(&mut deserializer, group_key)?;
+    let trailing = match deserializer.end() {
+        Ok(()) => 0,
+        // the error points to the first trailing byte (columns are 1-based)
+        Err(e) if lenient_trailing => line.trim_ascii_end().len() - (e.column() - 1),
+        Err(e) => return Err(e),
+    };
+    let key = key
+        .map(|(_, key)| key)
+        .ok_or_else(|| missing_field(paths))?;
+    Ok((key, trailing))
+}
+
+pub(crate) fn missing_field(paths: &[Vec<String>]) -> serde_json::Error {
+    let paths: Vec<_> = paths.iter().map(|path| path.join(".")).collect();
+    de::Error::custom(format_args!("missing field `{}`", paths.join("`, `")))
+}
+
+/// Value(s) a line will be grouped by.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Key {
+    Single(String),
+    Exploded(Vec<String>),
+}
+
+/// Maximum number of paths that can be tried for a single line.
+pub(crate) const MAX_PATHS: usize = 64;
+
+/// Splits a dot separated path (`event.type`) into its fields.
+pub(crate) fn split_path(path: &str) -> Vec<String> {
+    path.split('.').map(str::to_string).collect()
+}
+
+/// Looks for the paths in the current value and returns the preferred one found, if any,
+/// along with its index. `active` is a bitmask of the paths whose first `depth` fields
+/// lead to the current value. A bitmask keeps the lookup free of allocations.
+pub(crate) struct GroupKey<'p> {
+    paths: &'p [Vec<String>],
+    active: u64,
+    depth: usize,
+    strict: bool,
+    explode: bool,
+}
+
+impl<'p> GroupKey<'p> {
+    pub(crate) fn new(paths: &'p [Vec<String>], strict: bool, explode: bool) -> Self {
+        Self {
+            paths: &paths[..paths.len().min(MAX_PATHS)],
+            active: u64::MAX,
+            depth: 0,
+            strict,
+            explode,
+        }
+    }
+
+    fn is_active(&self, index: usize) -> bool {
+        self.active & (1 << index) != 0
+    }
+
+    /// Index of the preferred path ending at the current value, if any.
+    fn terminal(&self) -> Option<usize> {
+        (0..self.paths.len()).find(|i| self.is_active(*i) && self.paths[*i].len() == self.depth)
+    }
+
+    /// Bitmask of the paths going through the given field.
+    fn nested(&self, field: &str) -> u64 {
+        self.paths
+            .iter()
+            .enumerate()
+            .filter(|(i, path)| {
+                self.is_active(*i) && path.len() > self.depth && path[self.depth] == field
+            })
+            .fold(0, |mask, (i, _)| mask | (1 << i))
+    }
+
+    fn scalar<E>(
+        self,
+        value: impl FnOnce() -> String,
+        is_string: bool,
+    ) -> Result<Option<(usize, Key)>, E>
+    where
+        E: de::Error,
+    {
+        match self.terminal() {
+            Some(_) if self.strict && !is_string => {
+                Err(E::custom("invalid type, expected a string"))
+            }
+            Some(index) => Ok(Some((index, Key::Single(value())))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'de, 'p> DeserializeSeed<'de> for GroupKey<'p> {
+    type Value = Option<(usize, Key)>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'p> Visitor<'de> for GroupKey<'p> {
+    type Value = Option<(usize, Key)>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.terminal().is_some() {
+            formatter.write_str("a string, number, boolean or null")
+        } else {
+            formatter.write_str("a JSON object")
+        }
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        if self.terminal().is_some() {
+            return Err(de::Error::invalid_type(de::Unexpected::Map, &self));
+        }
+        let mut value: Option<(usize, Key)> = None;
+        while let Some(active) = map.next_key_seed(FieldMatcher(&self))? {
+            if active == 0 {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            let found = map.next_value_seed(GroupKey {
+                active,
+                depth: self.depth + 1,
+                ..self
+            })?;
+            // keep the preferred path and, for the same one, the first occurrence
+            if let Some((index, key)) = found {
+                if value.as_ref().is_none_or(|(best, _)| index < *best) {
+                    value = Some((index, key));
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        match self.terminal() {
+            Some(index) if self.explode => {
+                // elements are scalar values ending the same path
+                let element = GroupKey {
+                    explode: false,
+                    ..self
+                };
+                let mut keys = Vec::new();
+                while let Some(key) = seq.next_element_seed(GroupKey { ..element })? {
+                    if let Some((_, Key::Single(key))) = key {
+                        keys.push(key);
+                    }
+                }
+                if keys.is_empty() {
+                    return Err(de::Error::invalid_length(0, &"a non empty array"));
+                }
+                Ok(Some((index, Key::Exploded(keys))))
+            }
+            Some(_) => Err(de::Error::invalid_type(de::Unexpected::Seq, &self)),
+            None => {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(None)
+            }
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| v.to_string(), true)
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| v, true)
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| v.to_string(), false)
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| v.to_string(), false)
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // integral floats share the key of their integer counterpart (3.0 -> "3")
+        let key = serde_json::Number::from_f64(v)
+            .map(|n| n.to_string())
+            .ok_or_else(|| E::custom("invalid number"))?;
+        self.scalar(|| key, false)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| v.to_string(), false)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.scalar(|| "null".to_string(), false)
+    }
+}
+
+/// Compares the keys of the object against the next field of the active paths
+/// without allocating them. It returns the paths going through the key.
+struct FieldMatcher<'g, 'p>(&'g GroupKey<'p>);
+
+impl<'de, 'g, 'p> DeserializeSeed<'de> for FieldMatcher<'g, 'p> {
+    type Value = u64;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 'g, 'p> Visitor<'de> for FieldMatcher<'g, 'p> {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field name")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(self.0.nested(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_counts_classify_the_lines_without_std() {
+        let mut counts = TypeCounts::new("event.type");
+        counts.add_line(br#"{"event":{"type":"boot"}}"#);
+        counts.add_line(br#"{"event":{"type":"boot"},"n":1}"#);
+        counts.add_line(br#"{"event":{"type":7}}"#);
+        counts.add_line(b"garbage");
+        let mut other = TypeCounts::new("event.type");
+        other.add_line(br#"{"event":{"type":"boot"}}"#);
+        counts.merge(&other);
+
+        let types: Vec<(&str, usize, usize)> = counts
+            .types
+            .iter()
+            .map(|(key, c)| (key.as_str(), c.count, c.bytes))
+            .collect();
+        assert_eq!(types, vec![("7", 1, 20), ("ERROR", 1, 7), ("boot", 3, 81)]);
+        assert_eq!(
+            classify(br#"{"a":{"b":null}}"#, &[split_path("a.b")]).unwrap(),
+            "null"
+        );
+        assert!(classify(br#"{"a":1}"#, &[split_path("b")]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+
+/// Distribution of the sizes of the generated lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSize {
+    /// Lines between 30 and 100 bytes.
+    Small,
+    /// Mostly small lines with a few ones of some kilobytes.
+    Mixed,
+    /// Lines between 4 and 64 kilobytes.
+    Large,
+}
+
+impl std::str::FromStr for LineSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(LineSize::Small),
+            "mixed" => Ok(LineSize::Mixed),
+            "large" => Ok(LineSize::Large),
+            _ => Err(format!(
+                "unknown value `{}`, expected one of: small, mixed, large",
+                s
+            )),
+        }
+    }
+}
+
+/// Shape of a synthetic corpus of JSON lines.
+/// The same options always produce the same corpus.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    pub lines: usize,
+    /// Number of distinct values of the `type` field.
+    pub types: usize,
+    pub line_size: LineSize,
+    /// Percentage (0-100) of lines that are not valid JSON.
+    pub error_rate: u8,
+    pub seed: u64,
+}
+
+impl Default for Corpus {
+    fn default() -> Self {
+        Self {
+            lines: 100_000,
+            types: 4,
+            line_size: LineSize::Small,
+            error_rate: 0,
+            seed: 42,
+        }
+    }
+}
+
+impl Corpus {
+    /// Writes the corpus and returns the number of bytes written.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<u64> {
+        let mut rng = XorShift(self.seed.max(1));
+        let mut written = 0;
+        let mut line = Vec::new();
+        for id in 0..self.lines {
+            line.clear();
+            let kind = rng.next() as usize % self.types.max(1);
+            let payload = "x".repeat(self.payload_len(&mut rng));
+            if (rng.next() % 100) < u64::from(self.error_rate) {
+                write!(
+                    line,
+                    "{{\"type\":\"T{}\",\"id\":{} \"payload\":\"{}\"",
+                    kind, id, payload
+                )?;
+            } else {
+                write!(
+                    line,
+                    "{{\"type\":\"T{}\",\"id\":{},\"payload\":\"{}\"}}",
+                    kind, id, payload
+                )?;
+            }
+            line.push(b'\n');
+            writer.write_all(&line)?;
+            written += line.len() as u64;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Generates the whole corpus in memory.
+    pub fn generate(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_to(&mut buf)
+            .expect("Writing to a Vec never fails");
+        buf
+    }
+
+    fn payload_len(&self, rng: &mut XorShift) -> usize {
+        let n = rng.next() as usize;
+        match self.line_size {
+            LineSize::Small => n % 70,
+            LineSize::Mixed if n.is_multiple_of(100) => 1024 + n % 8192,
+            LineSize::Mixed => n % 70,
+            LineSize::Large => 4096 + n % (60 * 1024),
+        }
+    }
+}
+
+/// Tiny deterministic generator. Good enough to shape a corpus.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_reader, AnalyzeOptions};
+
+    #[test]
+    fn generate_is_deterministic_and_respects_the_shape() {
+        let corpus = Corpus {
+            lines: 1_000,
+            types: 3,
+            error_rate: 10,
+            ..Corpus::default()
+        };
+        let content = corpus.generate();
+        assert_eq!(content, corpus.generate());
+        let report = analyze_reader(&content[..], &AnalyzeOptions::default()).unwrap();
+        let lines: usize = report.types.values().map(|c| c.count).sum();
+        assert_eq!(lines, 1_000);
+        assert_eq!(report.len(), 4);
+        let errors = report.get("ERROR").map(|r| r.count).unwrap();
+        assert!(errors > 50 && errors < 150, "{} errors", errors);
+    }
+}
@@ -0,0 +1,67 @@
+/// Bytes in a GB, a power of 1000 like in the throughputs.
+const GB: f64 = 1_000_000_000.0;
+
+/// Price of storing or ingesting a GB in a sink, to estimate what every type costs there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostRate {
+    /// Name of the sink, like `s3` or `datadog`. Just `COST` when not given.
+    pub sink: Option<String>,
+    pub per_gb: f64,
+}
+
+impl CostRate {
+    /// Estimated cost of the bytes in this sink.
+    pub fn cost(&self, bytes: usize) -> f64 {
+        bytes as f64 / GB * self.per_gb
+    }
+
+    /// Name of the cost in the tables, like `COST (s3)`.
+    pub fn label(&self) -> String {
+        match &self.sink {
+            Some(sink) => format!("COST ({})", sink),
+            None => "COST".to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for CostRate {
+    type Err = String;
+
+    /// Parses prices like `0.023` or `s3=0.023`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sink, price) = match s.split_once('=') {
+            Some((sink, price)) => (Some(sink.trim().to_string()), price),
+            None => (None, s),
+        };
+        match price.trim().parse::<f64>() {
+            Ok(per_gb) if per_gb.is_finite() && per_gb >= 0.0 && sink.as_deref() != Some("") => {
+                Ok(CostRate { sink, per_gb })
+            }
+            _ => Err(format!(
+                "invalid cost `{}`, expected a price per GB like 0.023 or s3=0.023",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_rates_price_the_bytes_by_gb() {
+        let s3: CostRate = "s3=0.023".parse().unwrap();
+        assert_eq!(s3.sink.as_deref(), Some("s3"));
+        assert_eq!(s3.label(), "COST (s3)");
+        assert!((s3.cost(5_000_000_000) - 0.115).abs() < 1e-12);
+
+        let plain: CostRate = "0.5".parse().unwrap();
+        assert_eq!(plain.label(), "COST");
+        assert_eq!(plain.cost(2_000_000_000), 1.0);
+
+        for invalid in ["cheap", "s3=", "=0.1", "-1", "NaN"] {
+            assert!(invalid.parse::<CostRate>().is_err(), "{}", invalid);
+        }
+    }
+}
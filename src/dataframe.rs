@@ -0,0 +1,152 @@
+use crate::sql::QueryResult;
+use polars::prelude::*;
+use polars::sql::SQLContext;
+use serde_json::{json, Value};
+use std::{
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+/// Name of the table holding the lines in the queries run by `query_polars`.
+pub const POLARS_TABLE: &str = "input";
+
+/// The lines of a JSON lines file as a Polars `LazyFrame`, so any expression or query runs over
+/// them. The schema is inferred from the first lines, nested objects becoming struct columns,
+/// and the file is only read once the frame is collected.
+pub fn lines_frame(path: impl AsRef<Path>) -> io::Result<LazyFrame> {
+    LazyJsonLineReader::new(PlPath::Local(path.as_ref().into()))
+        .finish()
+        .map_err(polars_error)
+}
+
+/// The lines of a reader, like the stdin, as a Polars `LazyFrame`. Unlike the ones of a file,
+/// they're read into memory first.
+pub fn read_lines_frame(mut reader: impl Read) -> io::Result<LazyFrame> {
+    let mut lines = Vec::new();
+    reader.read_to_end(&mut lines)?;
+    let frame = JsonLineReader::new(Cursor::new(lines))
+        .finish()
+        .map_err(polars_error)?;
+    Ok(frame.lazy())
+}
+
+/// Runs a query written in the SQL dialect of Polars over the lines, the `input` table. Unlike
+/// `Query`, it takes joins, subqueries, `ORDER BY`, `HAVING`... but lines that aren't
+/// JSON objects fail the query instead of being left out, and there's no `length` column.
+pub fn query_polars(query: &str, lines: LazyFrame) -> io::Result<QueryResult> {
+    let mut context = SQLContext::new();
+    context.register(POLARS_TABLE, lines);
+    let frame = context
+        .execute(query)
+        .and_then(LazyFrame::collect)
+        .map_err(polars_error)?;
+    let columns = frame.get_columns();
+    let rows = (0..frame.height())
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| column.get(row).map(any_value_to_json))
+                .collect::<PolarsResult<Vec<_>>>()
+        })
+        .collect::<PolarsResult<Vec<_>>>()
+        .map_err(polars_error)?;
+    Ok(QueryResult {
+        columns: columns
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect(),
+        rows,
+        errors: 0,
+    })
+}
+
+fn any_value_to_json(value: AnyValue) -> Value {
+    match value {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(b) => json!(b),
+        AnyValue::String(s) => json!(s),
+        AnyValue::StringOwned(s) => json!(s.as_str()),
+        AnyValue::UInt8(n) => json!(n),
+        AnyValue::UInt16(n) => json!(n),
+        AnyValue::UInt32(n) => json!(n),
+        AnyValue::UInt64(n) => json!(n),
+        AnyValue::Int8(n) => json!(n),
+        AnyValue::Int16(n) => json!(n),
+        AnyValue::Int32(n) => json!(n),
+        AnyValue::Int64(n) => json!(n),
+        // NaN and the infinities become null, as JSON has no room for them
+        AnyValue::Float32(n) => json!(n),
+        AnyValue::Float64(n) => json!(n),
+        // lists, structs and the rest as Polars prints them
+        other => json!(other.to_string()),
+    }
+}
+
+fn polars_error(e: PolarsError) -> io::Error {
+    io::Error::other(format!("polars: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_polars_runs_full_sql_over_the_lines() {
+        let lines = r#"{"type":"A","amount":10,"account":{"id":1}}
+{"type":"B","amount":5,"account":{"id":2}}
+{"type":"A","amount":20,"account":{"id":1}}
+{"type":"C","amount":null,"account":{"id":3}}
+"#;
+        let result = query_polars(
+            "SELECT type, count(*) AS lines, sum(amount) AS total \
+             FROM input WHERE amount IS NOT NULL GROUP BY type ORDER BY total DESC",
+            read_lines_frame(lines.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(result.columns, vec!["type", "lines", "total"]);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![json!("A"), json!(2), json!(30)],
+                vec![json!("B"), json!(1), json!(5)],
+            ]
+        );
+
+        // subqueries and nested fields, which the SQL subset doesn't have
+        let result = query_polars(
+            "SELECT id, total FROM (SELECT account.id AS id, sum(amount) AS total \
+             FROM input GROUP BY account.id) AS totals WHERE total > 1 ORDER BY id",
+            read_lines_frame(lines.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            vec![vec![json!(1), json!(30)], vec![json!(2), json!(5)]]
+        );
+
+        assert!(query_polars(
+            "SELECT nope FROM input",
+            read_lines_frame(lines.as_bytes()).unwrap()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn lines_frame_scans_a_file() {
+        let path = std::env::temp_dir().join(format!("fr-polars-{}.jsonl", std::process::id()));
+        std::fs::write(
+            &path,
+            "{\"type\":\"A\"}\n{\"type\":\"B\"}\n{\"type\":\"A\"}\n",
+        )
+        .unwrap();
+        let result = query_polars(
+            "SELECT type, count(*) AS lines FROM input GROUP BY type ORDER BY type",
+            lines_frame(&path).unwrap(),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result.unwrap().rows,
+            vec![vec![json!("A"), json!(2)], vec![json!("B"), json!(1)]]
+        );
+    }
+}
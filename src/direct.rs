@@ -0,0 +1,187 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Alignment of the offsets and buffers of direct reads, a multiple of the usual block sizes.
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+const ALIGNMENT: usize = 4096;
+/// Bytes read from the disk at once when bypassing the page cache.
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+const DIRECT_BLOCK_SIZE: usize = 1 << 20;
+
+/// A file opened for an analysis, either through the page cache or bypassing it.
+pub(crate) enum InputFile {
+    Cached(File),
+    #[cfg(all(feature = "direct-io", target_os = "linux"))]
+    Direct(DirectReader),
+}
+
+impl InputFile {
+    /// Opens the file, bypassing the page cache if `direct` is set.
+    pub(crate) fn open(path: &Path, direct: bool) -> io::Result<Self> {
+        if !direct {
+            return File::open(path).map(InputFile::Cached);
+        }
+        #[cfg(all(feature = "direct-io", target_os = "linux"))]
+        return DirectReader::open(path).map(InputFile::Direct);
+        #[cfg(not(all(feature = "direct-io", target_os = "linux")))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "direct IO needs the `direct-io` feature on Linux",
+        ))
+    }
+
+    /// The file, unless it bypasses the page cache.
+    pub(crate) fn cached(&self) -> Option<&File> {
+        match self {
+            InputFile::Cached(file) => Some(file),
+            #[cfg(all(feature = "direct-io", target_os = "linux"))]
+            InputFile::Direct(_) => None,
+        }
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            InputFile::Cached(file) => file.read(buf),
+            #[cfg(all(feature = "direct-io", target_os = "linux"))]
+            InputFile::Direct(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for InputFile {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        match self {
+            InputFile::Cached(file) => file.seek(position),
+            #[cfg(all(feature = "direct-io", target_os = "linux"))]
+            InputFile::Direct(reader) => reader.seek(position),
+        }
+    }
+}
+
+/// Reader of a file opened with `O_DIRECT`, whose reads must start at aligned offsets and
+/// land in aligned buffers. The disk is read by aligned blocks handed out as asked for.
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+pub(crate) struct DirectReader {
+    file: File,
+    /// Bigger than a block so an aligned block fits in it wherever the allocation starts.
+    buf: Vec<u8>,
+    /// Beginning of the aligned block within `buf`.
+    aligned: usize,
+    /// Bytes of the block already handed out and bytes read into it.
+    start: usize,
+    end: usize,
+    /// Offset in the file of the beginning of the block.
+    block_offset: u64,
+}
+
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+impl DirectReader {
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)?;
+        let buf = vec![0; DIRECT_BLOCK_SIZE + ALIGNMENT];
+        let aligned = buf.as_ptr().align_offset(ALIGNMENT);
+        Ok(Self {
+            file,
+            buf,
+            aligned,
+            start: 0,
+            end: 0,
+            block_offset: 0,
+        })
+    }
+
+    /// Reads the next block. Only the last block of the file is shorter.
+    fn fill(&mut self) -> io::Result<()> {
+        self.block_offset += self.end as u64;
+        let block = &mut self.buf[self.aligned..self.aligned + DIRECT_BLOCK_SIZE];
+        let mut read = 0;
+        // a short read means the end of the file, which leaves the offset unaligned
+        while read < DIRECT_BLOCK_SIZE {
+            match self.file.read(&mut block[read..]) {
+                Ok(0) => break,
+                Ok(n) if n % ALIGNMENT != 0 => {
+                    read += n;
+                    break;
+                }
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.start = 0;
+        self.end = read;
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+impl Read for DirectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.start == self.end {
+            self.fill()?;
+        }
+        let block = &self.buf[self.aligned + self.start..self.aligned + self.end];
+        let read = block.len().min(buf.len());
+        buf[..read].copy_from_slice(&block[..read]);
+        self.start += read;
+        Ok(read)
+    }
+}
+
+#[cfg(all(feature = "direct-io", target_os = "linux"))]
+impl Seek for DirectReader {
+    /// Moves to the aligned offset before the position and skips the bytes up to it.
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let current = self.block_offset + self.start as u64;
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        let aligned = target - target % ALIGNMENT as u64;
+        self.file.seek(SeekFrom::Start(aligned))?;
+        self.block_offset = aligned;
+        self.end = 0;
+        self.fill()?;
+        self.start = ((target - aligned) as usize).min(self.end);
+        Ok(target)
+    }
+}
+
+#[cfg(all(test, feature = "direct-io", target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direct_reads_match_the_cached_ones() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(format!("fr-direct-{}.jsonl", std::process::id()));
+        let content: Vec<u8> = (0..300_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        std::fs::write(&path, &content).unwrap();
+        let mut reader = match DirectReader::open(&path) {
+            Ok(reader) => reader,
+            // some filesystems, like tmpfs, don't support direct IO
+            Err(_) => return std::fs::remove_file(&path).unwrap(),
+        };
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+
+        reader.seek(SeekFrom::Start(5_000)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &content[5_000..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,26 @@
+use std::{io, path::PathBuf, process::Command};
+
+/// Path of the JSON log file of a container, as written by Docker's `json-file` logging driver.
+/// Every line of the file wraps a line of the container output in a `{"log": "...", "stream": ...}`
+/// envelope, so analyze it with `unwrap_field("log")`. Reading it usually requires root.
+pub fn docker_log_path(container: &str) -> io::Result<PathBuf> {
+    let output = Command::new("docker")
+        .args(["inspect", "--format", "{{.LogPath}}", container])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "docker inspect exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if path.is_empty() {
+        // other logging drivers, like journald, don't write a file
+        return Err(io::Error::other(format!(
+            "the container {} doesn't use the json-file logging driver",
+            container
+        )));
+    }
+    Ok(PathBuf::from(path))
+}
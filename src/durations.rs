@@ -0,0 +1,84 @@
+use crate::replay::TimeUnit;
+use serde_json::Value;
+
+/// Seconds of a duration field: a number in the given unit, or a string with its own unit
+/// like `250ms`, `1.5s`, `3m` or `2h` (`ns`, `us` and `d` work too).
+pub(crate) fn duration_seconds(value: &Value, unit: TimeUnit) -> Option<f64> {
+    let seconds = match value {
+        Value::Number(n) => unit.seconds(n.as_f64()?),
+        Value::String(s) => parse_duration_string(s)?,
+        _ => return None,
+    };
+    seconds.is_finite().then_some(seconds)
+}
+
+fn parse_duration_string(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let factor = match unit.trim() {
+        "ns" => 1e-9,
+        "us" | "µs" => 1e-6,
+        "ms" => 1e-3,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3_600.0,
+        "d" => 86_400.0,
+        _ => return None,
+    };
+    Some(number * factor)
+}
+
+/// The duration for humans, like `3h 12m 5s`, `1.5s` or `250ms`.
+pub(crate) fn human_duration(seconds: f64) -> String {
+    if seconds < 0.0 {
+        return format!("-{}", human_duration(-seconds));
+    }
+    if seconds < 1.0 {
+        return format!("{}ms", (seconds * 1_000.0).round());
+    }
+    if seconds < 60.0 {
+        return format!("{}s", (seconds * 10.0).round() / 10.0);
+    }
+    let mut rest = seconds.round() as u64;
+    let mut parts = Vec::new();
+    for (unit, length) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if rest >= length {
+            parts.push(format!("{}{}", rest / length, unit));
+            rest %= length;
+        }
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn durations_are_numbers_or_strings_with_units() {
+        let ms = TimeUnit::Milliseconds;
+        assert_eq!(duration_seconds(&json!(1500), ms), Some(1.5));
+        assert_eq!(duration_seconds(&json!(2), TimeUnit::Seconds), Some(2.0));
+        assert_eq!(duration_seconds(&json!("250ms"), ms), Some(0.25));
+        assert_eq!(duration_seconds(&json!("1.5s"), ms), Some(1.5));
+        assert_eq!(duration_seconds(&json!("3m"), ms), Some(180.0));
+        assert_eq!(duration_seconds(&json!("2 h"), ms), Some(7_200.0));
+        assert_eq!(duration_seconds(&json!("soon"), ms), None);
+        assert_eq!(duration_seconds(&json!(null), ms), None);
+    }
+
+    #[test]
+    fn durations_are_shown_for_humans() {
+        assert_eq!(human_duration(0.25), "250ms");
+        assert_eq!(human_duration(1.54), "1.5s");
+        assert_eq!(human_duration(12.0), "12s");
+        assert_eq!(human_duration(11_525.0), "3h 12m 5s");
+        assert_eq!(human_duration(90_005.0), "1d 1h 5s");
+        assert_eq!(human_duration(-0.5), "-500ms");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::inspect::Compression;
+use crate::models::Report;
+use std::{error::Error, fmt, io};
+
+#[derive(Debug)]
+pub enum AnalyzeError {
+    /// The source couldn't be read.
+    Io(io::Error),
+    /// The analysis was cancelled. It holds the results computed until then.
+    Cancelled(Box<Report>),
+    /// A line wasn't valid UTF-8 and `InvalidUtf8::Error` was set.
+    InvalidUtf8 { line_number: usize, offset: u64 },
+    /// The ratio of lines that couldn't be parsed went over `max_error_rate`.
+    TooManyErrors { errors: u64, lines: u64 },
+    /// Some results couldn't be gathered. It holds the report without them and the bytes they accounted for.
+    Incomplete {
+        results: Box<Report>,
+        lost_bytes: u64,
+    },
+    /// The beginning of the input looks like binary or compressed content instead of text.
+    BinaryContent { compression: Option<Compression> },
+    /// A line read by chunks, without a `max_line_length`, didn't fit in the largest chunk the
+    /// workers can hold in memory.
+    LineTooLong { offset: u64, max_chunk_size: usize },
+}
+
+impl fmt::Display for AnalyzeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyzeError::Io(e) => write!(f, "{}", e),
+            AnalyzeError::Cancelled(_) => f.write_str("the analysis was cancelled"),
+            AnalyzeError::InvalidUtf8 {
+                line_number,
+                offset,
+            } => write!(
+                f,
+                "line {} (byte offset {}) is not valid UTF-8",
+                line_number, offset
+            ),
+            AnalyzeError::Incomplete { lost_bytes, .. } => write!(
+                f,
+                "the results of at least {} bytes were lost, the report is incomplete",
+                lost_bytes
+            ),
+            AnalyzeError::TooManyErrors { errors, lines } => write!(
+                f,
+                "{} of {} lines couldn't be parsed, which is over the maximum error rate",
+                errors, lines
+            ),
+            AnalyzeError::BinaryContent {
+                compression: Some(compression),
+            } => write!(
+                f,
+                "the input looks {:?} compressed, not like JSON lines. Decompress it first (e.g. `zcat file.gz | fr -`)",
+                compression
+            ),
+            AnalyzeError::BinaryContent { compression: None } => f.write_str(
+                "the input looks like binary content, not like JSON lines. Check the file and the --format it's read with (jsonl, csv, logfmt...), or disable the check with --skip-binary-check",
+            ),
+            AnalyzeError::LineTooLong {
+                offset,
+                max_chunk_size,
+            } => write!(
+                f,
+                "the line at byte offset {} is longer than {} bytes, the largest chunk that fits in memory. Set --max-line-length to count such lines as TOO_LONG without reading them whole",
+                offset, max_chunk_size
+            ),
+        }
+    }
+}
+
+impl Error for AnalyzeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnalyzeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AnalyzeError {
+    fn from(e: io::Error) -> Self {
+        AnalyzeError::Io(e)
+    }
+}
@@ -0,0 +1,373 @@
+use crate::provenance::Provenance;
+use crate::schema::SchemaVersion;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Maximum number of bytes of the raw line kept in every entry.
+const MAX_CONTENT_LENGTH: usize = 200;
+/// Bytes of the line shown at each side of the error position in the descriptions.
+const SNIPPET_CONTEXT: usize = 30;
+
+/// A line that couldn't be parsed.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ErrorEntry {
+    pub line_number: usize,
+    pub offset: u64,
+    /// See `error_category`.
+    pub category: String,
+    pub message: String,
+    /// Raw line, cut at `MAX_CONTENT_LENGTH` bytes.
+    pub content: String,
+    pub truncated: bool,
+}
+
+/// Entries of an error report along with the version of its layout.
+#[derive(Serialize)]
+struct VersionedEntries {
+    report_version: u32,
+    entries: Vec<ErrorEntry>,
+}
+
+/// Thread safe collection of the parsing errors of an analysis.
+/// Clones share the same entries so one of them can be moved into the `on_error` callback.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorReport(Arc<Mutex<Vec<ErrorEntry>>>);
+
+impl ErrorReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry. Its signature matches the `on_error` callback.
+    pub fn record(&self, line_number: usize, offset: u64, line: &[u8], e: &serde_json::Error) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let truncated = line.len() > MAX_CONTENT_LENGTH;
+        let content = String::from_utf8_lossy(&line[..line.len().min(MAX_CONTENT_LENGTH)]);
+        let entry = ErrorEntry {
+            line_number,
+            offset,
+            category: error_category(e),
+            message: e.to_string(),
+            content: content.into_owned(),
+            truncated,
+        };
+        self.0
+            .lock()
+            .expect("The error report lock is poisoned")
+            .push(entry);
+    }
+
+    /// Entries sorted by line number, as the chunks may be parsed in any order.
+    pub fn entries(&self) -> Vec<ErrorEntry> {
+        let mut entries = self
+            .0
+            .lock()
+            .expect("The error report lock is poisoned")
+            .clone();
+        entries.sort_by_key(|entry| entry.line_number);
+        entries
+    }
+
+    /// Writes the entries as JSON, under `entries` next to the `report_version` of the layout.
+    /// Version 1 was a bare array of the entries.
+    pub fn write_to(&self, path: &Path, version: SchemaVersion) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        let entries = self.entries();
+        match version.report_version() {
+            Some(report_version) => serde_json::to_writer_pretty(
+                writer,
+                &VersionedEntries {
+                    report_version,
+                    entries,
+                },
+            )?,
+            None => serde_json::to_writer_pretty(writer, &entries)?,
+        }
+        Ok(())
+    }
+}
+
+/// Category of a parsing error: `missing_<field>` when the grouping field isn't found
+/// (`missing_field` if several were tried) or `syntax`, `data`, `eof` or `io`
+/// as classified by serde_json.
+pub fn error_category(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    if let Some(fields) = message.strip_prefix("missing field `") {
+        return match fields.split_once('`') {
+            Some((field, rest)) if !rest.starts_with(", `") => format!("missing_{}", field),
+            _ => "missing_field".to_string(),
+        };
+    }
+    format!("{:?}", e.classify()).to_lowercase()
+}
+
+/// Human description of the error: its category, column and message, followed by the part of
+/// the line around the error position with a caret pointing at it.
+pub fn describe_error(line: &[u8], e: &serde_json::Error) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let message = e.to_string();
+    let location = format!(" at line {} column {}", e.line(), e.column());
+    let message = message.strip_suffix(&location).unwrap_or(&message);
+    // the column is the 1-based byte the parser stopped at
+    let position = e.column().saturating_sub(1).min(line.len());
+    let start = position.saturating_sub(SNIPPET_CONTEXT);
+    let end = (position + SNIPPET_CONTEXT).min(line.len());
+    let printable = |bytes: &[u8]| -> String {
+        String::from_utf8_lossy(bytes)
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect()
+    };
+    let before = printable(&line[start..position]);
+    let after = printable(&line[position..end]);
+    let ellipsis = |cut: bool| if cut { "…" } else { "" };
+    let prefix = format!("{}{}", ellipsis(start > 0), before);
+    format!(
+        "{} error at column {}: {}\n  {}{}{}\n  {}^",
+        error_category(e),
+        e.column(),
+        message,
+        prefix,
+        after,
+        ellipsis(end < line.len()),
+        " ".repeat(prefix.chars().count())
+    )
+}
+
+/// Writes the lines that couldn't be parsed into one file per category
+/// (`syntax.jsonl`, `missing_type.jsonl`...) so each class of problem can be handled separately.
+/// Clones share the same files so one of them can be moved into the `on_error` callback.
+#[derive(Debug, Clone)]
+pub struct Rejects {
+    dir: PathBuf,
+    files: Arc<Mutex<HashMap<String, RejectsFile>>>,
+    provenance: Option<Provenance>,
+}
+
+#[derive(Debug, Default)]
+struct RejectsFile {
+    writer: Option<BufWriter<File>>,
+    lines: usize,
+    /// First error creating or writing the file, reported by `finish`.
+    error: Option<io::Error>,
+}
+
+impl RejectsFile {
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        if let Some(writer) = &mut self.writer {
+            writer.write_all(line)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl Rejects {
+    /// Creates the directory if needed. The files are created as the errors show up.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            files: Arc::default(),
+            provenance: None,
+        })
+    }
+
+    /// Appends the fields of the provenance to every line written. The lines that aren't JSON
+    /// objects, most of the rejected ones, become an object holding them under `_line`.
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Writes the raw line to the file of its category. Its signature matches the `on_error` callback.
+    pub fn record(&self, line_number: usize, offset: u64, line: &[u8], e: &serde_json::Error) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let annotated = self
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.annotate(line, line_number, offset));
+        let line = annotated.as_deref().unwrap_or(line);
+        let category = error_category(e);
+        let mut files = self.files.lock().expect("The rejects lock is poisoned");
+        let file = files.entry(category).or_insert_with_key(|category| {
+            match File::create(self.dir.join(format!("{}.jsonl", category))) {
+                Ok(f) => RejectsFile {
+                    writer: Some(BufWriter::new(f)),
+                    ..RejectsFile::default()
+                },
+                Err(e) => RejectsFile {
+                    error: Some(e),
+                    ..RejectsFile::default()
+                },
+            }
+        });
+        if file.error.is_none() {
+            if let Err(e) = file.write_line(line) {
+                file.error = Some(e);
+            }
+        }
+        file.lines += 1;
+    }
+
+    /// Flushes the files and returns the number of lines of each category.
+    pub fn finish(&self) -> io::Result<Vec<(String, usize)>> {
+        let mut files = self.files.lock().expect("The rejects lock is poisoned");
+        let mut summary = Vec::with_capacity(files.len());
+        for (category, file) in files.iter_mut() {
+            if let Some(e) = file.error.take() {
+                return Err(e);
+            }
+            if let Some(writer) = &mut file.writer {
+                writer.flush()?;
+            }
+            summary.push((category.clone(), file.lines));
+        }
+        summary.sort();
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_reader, AnalyzeOptions, Strategy};
+
+    #[test]
+    fn error_report_collects_the_errors_of_every_strategy() {
+        let long_line = format!("{{\"type\":\"A\" {}\n", "x".repeat(300));
+        let file_content = format!(
+            "{{\"type\":\"B\"}}\n{{\"type\":\"B\"\r\n{}{{\"foo\":\"A\"}}\n",
+            long_line
+        );
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let report = ErrorReport::new();
+            let collector = report.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(400)
+                .batch_lines(1)
+                .on_error(move |line_number, offset, line, e| {
+                    collector.record(line_number, offset, line, e)
+                })
+                .build();
+            analyze_reader(file_content.as_bytes(), &options).unwrap();
+            let entries = report.entries();
+            let positions: Vec<_> = entries.iter().map(|e| (e.line_number, e.offset)).collect();
+            assert_eq!(positions, vec![(2, 13), (3, 26), (4, 339)]);
+            assert_eq!(entries[0].category, "eof");
+            assert_eq!(entries[0].content, "{\"type\":\"B\"");
+            assert!(!entries[0].truncated);
+            assert_eq!(entries[1].category, "syntax");
+            assert_eq!(entries[1].content.len(), MAX_CONTENT_LENGTH);
+            assert!(entries[1].truncated);
+            assert_eq!(entries[2].category, "missing_type");
+        }
+    }
+
+    #[test]
+    fn error_report_is_written_with_its_version() {
+        let report = ErrorReport::new();
+        let e = serde_json::from_str::<serde_json::Value>("{\"type\":").unwrap_err();
+        report.record(1, 0, b"{\"type\":", &e);
+        let path =
+            std::env::temp_dir().join(format!("fr-error-report-{}.json", std::process::id()));
+        let written = |version| {
+            report.write_to(&path, version).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&fs::read(&path).unwrap()).unwrap()
+        };
+        let latest = written(SchemaVersion::LATEST);
+        assert_eq!(latest["report_version"], 2);
+        assert_eq!(latest["entries"][0]["category"], "eof");
+        let v1 = written(SchemaVersion::new(1).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(v1[0]["category"], "eof");
+    }
+
+    #[test]
+    fn rejects_routes_the_lines_by_category() {
+        let dir = std::env::temp_dir().join(format!("fr-rejects-{}", std::process::id()));
+        let file_content = "{\"type\":\"B\"}\n{\"type\":\"B\" x}\r\n{\"foo\":1}\n{\"bar\":1}\n";
+        let rejects = Rejects::new(&dir).unwrap();
+        let collector = rejects.clone();
+        let options = AnalyzeOptions::builder()
+            .on_error(move |line_number, offset, line, e| {
+                collector.record(line_number, offset, line, e)
+            })
+            .build();
+        analyze_reader(file_content.as_bytes(), &options).unwrap();
+        assert_eq!(
+            rejects.finish().unwrap(),
+            vec![("missing_type".to_string(), 2), ("syntax".to_string(), 1)]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("missing_type.jsonl")).unwrap(),
+            "{\"foo\":1}\n{\"bar\":1}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("syntax.jsonl")).unwrap(),
+            "{\"type\":\"B\" x}\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn error_category_names_the_missing_fields() {
+        let missing = |fields: &str| {
+            let e: serde_json::Error =
+                serde::de::Error::custom(format_args!("missing field `{}`", fields));
+            error_category(&e)
+        };
+        assert_eq!(missing("type"), "missing_type");
+        assert_eq!(missing("event.type"), "missing_event.type");
+        assert_eq!(missing("type`, `kind"), "missing_field");
+    }
+
+    #[test]
+    fn describe_error_points_at_the_error_position() {
+        let describe = |line: &[u8]| {
+            let e = serde_json::from_slice::<serde_json::Value>(line).unwrap_err();
+            describe_error(line, &e)
+        };
+        assert_eq!(
+            describe(b"{\"type\":\"A\" \"x\":1}\n"),
+            "syntax error at column 13: expected `,` or `}`\n  {\"type\":\"A\" \"x\":1}\n              ^"
+        );
+        let long = format!(
+            "{{\"pad\":\"{}\",\"x\":tru,\"rest\":\"{}\"}}",
+            "p".repeat(50),
+            "r".repeat(50)
+        );
+        let description = describe(long.as_bytes());
+        let mut lines = description.lines();
+        assert_eq!(
+            lines.next(),
+            Some("syntax error at column 68: expected ident")
+        );
+        let snippet = lines.next().unwrap();
+        let caret = lines.next().unwrap();
+        assert_eq!(
+            snippet,
+            format!(
+                "  …{}\",\"x\":tru,\"rest\":\"{}…",
+                "p".repeat(21),
+                "r".repeat(21)
+            )
+        );
+        assert_eq!(snippet.chars().nth(caret.chars().count() - 1), Some(','));
+        assert_eq!(
+            describe(b"{\"a\":").lines().next(),
+            Some("eof error at column 5: EOF while parsing a value")
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use crate::printer::{self, Output, PrintOptions};
+use crate::reader::analyze_reader;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// Z score of the 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// Extrapolated figures of a single type, with their 95% confidence bounds.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TypeEstimate {
+    pub count: u64,
+    pub count_low: u64,
+    pub count_high: u64,
+    pub bytes: u64,
+    pub bytes_low: u64,
+    pub bytes_high: u64,
+}
+
+/// Results of an analysis extrapolated from a sample of the beginning of a file.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Estimate {
+    pub size: u64,
+    pub sampled_bytes: u64,
+    pub sampled_lines: u64,
+    /// Whether the sample covered the whole file, so the figures are not estimates at all.
+    pub exact: bool,
+    pub types: HashMap<Cow<'static, str>, TypeEstimate>,
+}
+
+pub fn start_estimate(
+    path: PathBuf,
+    print: &PrintOptions,
+    sample_size: u64,
+    options: &AnalyzeOptions,
+) {
+    let init = Instant::now();
+    match estimate(&path, sample_size, options) {
+        Ok(estimate) => printer::print_estimate(print, &estimate),
+        Err(e) => eprintln!("Error trying to read the file {:?} - {}", path, e),
+    }
+    if print.output == Output::Table {
+        println!("Took {:?} microseconds", init.elapsed().as_micros());
+    }
+}
+
+/// Analyzes only the first `sample_size` bytes of the file and extrapolates
+/// the results to its whole size.
+pub fn estimate(
+    path: &Path,
+    sample_size: u64,
+    options: &AnalyzeOptions,
+) -> Result<Estimate, AnalyzeError> {
+    let mut f = File::open(path)?;
+    let size = f.metadata()?.len();
+    let mut sample = Vec::with_capacity(sample_size.min(size) as usize);
+    f.by_ref().take(sample_size).read_to_end(&mut sample)?;
+    if (sample.len() as u64) < size {
+        // the last line of the sample is probably incomplete
+        let end = sample
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |i| i + 1);
+        sample.truncate(end);
+    }
+    let report = analyze_reader(&sample[..], options)?;
+    Ok(extrapolate(&report, sample.len() as u64, size))
+}
+
+/// Extrapolates the report of the first `sampled_bytes` of a file to its whole `size`.
+pub fn extrapolate(sample: &Report, sampled_bytes: u64, size: u64) -> Estimate {
+    let sampled_lines = sample.types.values().map(|c| c.count as u64).sum::<u64>();
+    let exact = sampled_bytes >= size;
+    let factor = if exact || sampled_bytes == 0 {
+        1.0
+    } else {
+        size as f64 / sampled_bytes as f64
+    };
+    let types = sample
+        .types
+        .iter()
+        .map(|(key, counter)| {
+            let count = counter.count as f64;
+            let bytes = counter.bytes as f64;
+            // normal approximation of the proportion of lines of this type
+            let proportion = count / sampled_lines as f64;
+            let margin = if exact {
+                0.0
+            } else {
+                Z_95 * (proportion * (1.0 - proportion) / sampled_lines as f64).sqrt()
+            };
+            let low = ((proportion - margin) / proportion).max(0.0);
+            let high = (proportion + margin) / proportion;
+            let type_estimate = TypeEstimate {
+                count: (count * factor).round() as u64,
+                // the lines in the sample have been seen for sure
+                count_low: ((count * factor * low).round() as u64).max(counter.count as u64),
+                count_high: (count * factor * high).round() as u64,
+                bytes: (bytes * factor).round() as u64,
+                bytes_low: ((bytes * factor * low).round() as u64).max(counter.bytes as u64),
+                bytes_high: (bytes * factor * high).round() as u64,
+            };
+            (key.clone(), type_estimate)
+        })
+        .collect();
+    Estimate {
+        size,
+        sampled_bytes,
+        sampled_lines,
+        exact,
+        types,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Report {
+        let mut report = Report::new();
+        for _ in 0..90 {
+            report.add_line(Cow::Borrowed("A"), 10);
+        }
+        for _ in 0..10 {
+            report.add_line(Cow::Borrowed("B"), 100);
+        }
+        report
+    }
+
+    #[test]
+    fn extrapolate_scales_the_sample_to_the_file_size() {
+        let estimate = extrapolate(&sample(), 2_000, 20_000);
+        assert!(!estimate.exact);
+        assert_eq!(estimate.sampled_lines, 100);
+        let a = estimate.types["A"];
+        assert_eq!((a.count, a.bytes), (900, 9_000));
+        assert!(a.count_low < 900 && a.count_high > 900);
+        let b = estimate.types["B"];
+        assert_eq!((b.count, b.bytes), (100, 10_000));
+        // rarer types get relatively wider bounds
+        assert!((b.count_high - b.count_low) * 9 > a.count_high - a.count_low);
+        assert!(b.bytes_low >= 1_000);
+    }
+
+    #[test]
+    fn extrapolate_is_exact_when_the_sample_covers_the_file() {
+        let estimate = extrapolate(&sample(), 2_000, 2_000);
+        assert!(estimate.exact);
+        let a = estimate.types["A"];
+        assert_eq!((a.count_low, a.count, a.count_high), (90, 90, 90));
+        assert_eq!((a.bytes_low, a.bytes, a.bytes_high), (900, 900, 900));
+    }
+
+    #[test]
+    fn estimate_ignores_the_incomplete_line_of_the_sample() {
+        let path = Path::new("file2.txt");
+        let size = std::fs::metadata(path).unwrap().len();
+        let options = AnalyzeOptions::default();
+        let sampled = estimate(path, size - 10, &options).unwrap();
+        assert!(!sampled.exact);
+        assert!(sampled.sampled_bytes < size - 10);
+        let exact = estimate(path, size, &options).unwrap();
+        assert!(exact.exact);
+        assert_eq!(exact.types["B"].count, 3);
+    }
+}
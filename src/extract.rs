@@ -0,0 +1,178 @@
+use crate::parser::split_path;
+use regex::Regex;
+use serde_json::{Map, Number, Value};
+use std::{fmt, str::FromStr};
+
+/// Regex whose named capture groups become fields of the lines, usable to group them or as
+/// their duration. It's matched against raw lines, or against a string field of JSON lines.
+#[derive(Debug, Clone)]
+pub struct ExtractRegex {
+    regex: Regex,
+    /// Field of JSON lines the regex is matched against, the whole line if there's none.
+    /// Raw lines are always matched whole.
+    field: Option<Vec<String>>,
+}
+
+impl ExtractRegex {
+    /// Extraction from the `field` of JSON lines, or from the whole line if there's none.
+    /// Nested fields are separated by dots.
+    pub fn new(regex: Regex, field: Option<&str>) -> Result<Self, String> {
+        if regex.capture_names().flatten().next().is_none() {
+            return Err(format!(
+                "the regex `{}` has no named capture groups, like (?P<status>\\d{{3}})",
+                regex
+            ));
+        }
+        Ok(Self {
+            regex,
+            field: field.map(split_path),
+        })
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub fn field(&self) -> Option<String> {
+        self.field.as_ref().map(|path| path.join("."))
+    }
+
+    /// The named groups matched in the text. Numbers are kept as numbers.
+    fn captures(&self, text: &str) -> Option<Map<String, Value>> {
+        let captures = self.regex.captures(text)?;
+        let fields = self
+            .regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| {
+                let value = captures.name(name)?.as_str();
+                let value = match value.parse::<Number>() {
+                    Ok(number) => Value::Number(number),
+                    Err(_) => Value::String(value.to_string()),
+                };
+                Some((name.to_string(), value))
+            })
+            .collect();
+        Some(fields)
+    }
+
+    /// The line as a JSON object with the captured fields, replacing the fields of the same name.
+    /// Raw lines become an object of the captured fields alone. `None` if the regex doesn't match
+    /// or the line isn't a JSON object with the field.
+    pub(crate) fn enrich(&self, line: &[u8], raw: bool) -> Option<Vec<u8>> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let object = if raw {
+            self.captures(std::str::from_utf8(line).ok()?)?
+        } else {
+            let mut object: Map<String, Value> = serde_json::from_slice(line).ok()?;
+            let captured = match &self.field {
+                Some(path) => {
+                    let (first, rest) = path.split_first()?;
+                    let value = rest
+                        .iter()
+                        .try_fold(object.get(first)?, |value, field| value.get(field))?;
+                    self.captures(value.as_str()?)?
+                }
+                // without a field, the whole line is matched
+                None => self.captures(std::str::from_utf8(line).ok()?)?,
+            };
+            object.extend(captured);
+            object
+        };
+        serde_json::to_vec(&object).ok()
+    }
+}
+
+impl PartialEq for ExtractRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.field == other.field
+    }
+}
+
+impl fmt::Display for ExtractRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field() {
+            Some(field) => write!(f, "{}:{}", self.regex, field),
+            None => write!(f, "{}", self.regex),
+        }
+    }
+}
+
+impl FromStr for ExtractRegex {
+    type Err = String;
+
+    /// `regex:field`, or the regex alone for raw lines. What follows the last colon is only
+    /// taken as the field when it looks like one, so regexes ending in `:` are kept whole.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((regex, field)) = s.rsplit_once(':') {
+            let is_field = !field.is_empty()
+                && field
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+            if let (true, Ok(regex)) = (is_field, Regex::new(regex)) {
+                return Self::new(regex, Some(field));
+            }
+        }
+        let regex = Regex::new(s).map_err(|e| e.to_string())?;
+        Self::new(regex, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn enriched(extract: &ExtractRegex, line: &str, raw: bool) -> Option<Value> {
+        let enriched = extract.enrich(line.as_bytes(), raw)?;
+        Some(serde_json::from_slice(&enriched).unwrap())
+    }
+
+    #[test]
+    fn named_groups_become_fields() {
+        let extract: ExtractRegex = r"status=(?P<status>\d{3}) took (?P<took>\w+):message"
+            .parse()
+            .unwrap();
+        assert_eq!(extract.field().as_deref(), Some("message"));
+        assert_eq!(
+            enriched(
+                &extract,
+                "{\"type\":\"A\",\"status\":1,\"message\":\"GET / status=404 took 3ms\"}\n",
+                false
+            ),
+            Some(json!({
+                "type": "A",
+                "status": 404,
+                "took": "3ms",
+                "message": "GET / status=404 took 3ms"
+            }))
+        );
+        assert_eq!(
+            enriched(&extract, "{\"message\":\"no status\"}", false),
+            None
+        );
+        assert_eq!(enriched(&extract, "{\"message\":1}", false), None);
+        assert_eq!(enriched(&extract, "not json", false), None);
+
+        let nested: ExtractRegex = r"(?P<user>\w+)@:meta.from".parse().unwrap();
+        assert_eq!(
+            enriched(&nested, "{\"meta\":{\"from\":\"ann@example.com\"}}", false)
+                .map(|line| line["user"].clone()),
+            Some(json!("ann"))
+        );
+    }
+
+    #[test]
+    fn raw_lines_become_objects_of_their_groups() {
+        let extract: ExtractRegex = r"^(?P<service>\w+):".parse().unwrap();
+        assert_eq!(extract.field(), None);
+        assert_eq!(
+            enriched(&extract, "web: GET /\r\n", true),
+            Some(json!({"service": "web"}))
+        );
+        assert_eq!(enriched(&extract, "GET /\n", true), None);
+        assert!("(\\d+)".parse::<ExtractRegex>().is_err());
+        assert!("(?P<a>".parse::<ExtractRegex>().is_err());
+    }
+}
@@ -0,0 +1,525 @@
+use crate::parser::is_truncated;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    io::{self, BufRead, Read},
+    str::FromStr,
+};
+
+/// Layout of the input. Everything but JSON lines is converted to JSON lines before the analysis,
+/// so the bytes accounted are the ones of the converted lines.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// Sniffed from the beginning of the input.
+    #[default]
+    Auto,
+    /// One JSON document per line.
+    Jsonl,
+    /// A single JSON array whose elements are the documents.
+    JsonArray,
+    /// Documents one after another, possibly pretty printed over several lines.
+    Json,
+    /// RFC 7464 sequences: every document is preceded by a record separator.
+    JsonSeq,
+    /// Comma separated values with a header. Every row becomes an object of strings.
+    Csv,
+    /// `key=value` pairs separated by spaces. Every line becomes an object of strings.
+    Logfmt,
+    /// Lines of any text, grouped by a regex instead of a JSON field. Never sniffed.
+    #[cfg(feature = "raw")]
+    Raw,
+}
+
+const RECORD_SEPARATOR: u8 = 0x1E;
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Format::Auto),
+            "jsonl" => Ok(Format::Jsonl),
+            "json-array" => Ok(Format::JsonArray),
+            "json" => Ok(Format::Json),
+            "json-seq" => Ok(Format::JsonSeq),
+            "csv" => Ok(Format::Csv),
+            "logfmt" => Ok(Format::Logfmt),
+            #[cfg(feature = "raw")]
+            "raw" => Ok(Format::Raw),
+            #[cfg(not(feature = "raw"))]
+            "raw" => Err("the `raw` format needs the `raw` feature".to_string()),
+            _ => Err(format!(
+                "unknown format `{}`, expected one of: auto, jsonl, json-array, json, json-seq, csv, logfmt, raw",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Auto => "auto",
+            Format::Jsonl => "jsonl",
+            Format::JsonArray => "json-array",
+            Format::Json => "json",
+            Format::JsonSeq => "json-seq",
+            Format::Csv => "csv",
+            Format::Logfmt => "logfmt",
+            #[cfg(feature = "raw")]
+            Format::Raw => "raw",
+        })
+    }
+}
+
+impl Format {
+    /// Whether the input is already made of lines, so it isn't converted.
+    pub(crate) fn is_lines(self) -> bool {
+        match self {
+            Format::Auto | Format::Jsonl => true,
+            #[cfg(feature = "raw")]
+            Format::Raw => true,
+            _ => false,
+        }
+    }
+}
+
+/// Guesses the format from a sample of the beginning of the input. Never returns `Auto`.
+/// When in doubt, the input is considered JSON lines.
+pub fn sniff(sample: &[u8]) -> Format {
+    let sample = sample.strip_prefix(UTF8_BOM).unwrap_or(sample);
+    let start = sample
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(sample.len());
+    let sample = &sample[start..];
+    let mut lines = sample
+        .split(|b| *b == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty());
+    let first = match lines.next() {
+        Some(first) => first,
+        None => return Format::Jsonl,
+    };
+    match first[0] {
+        RECORD_SEPARATOR => Format::JsonSeq,
+        // a broken line is still a line, unless it's the beginning of a longer document
+        b'{' | b'[' if !is_truncated(first) => {
+            // a whole array in a single line is an array, not a line
+            if first[0] == b'[' && is_json(first) && lines.next().is_none() {
+                Format::JsonArray
+            } else {
+                Format::Jsonl
+            }
+        }
+        // the first line may be cut, the following ones aren't
+        _ if sample
+            .split(|b| *b == b'\n')
+            .skip(1)
+            .any(|line| matches!(line.first(), Some(b'{') | Some(b'[')) && is_json(line)) =>
+        {
+            Format::Jsonl
+        }
+        b'[' => Format::JsonArray,
+        b'{' => Format::Json,
+        _ if is_logfmt(first) => Format::Logfmt,
+        _ if first.contains(&b',') => Format::Csv,
+        _ => Format::Jsonl,
+    }
+}
+
+/// Whether the line is a whole JSON document.
+fn is_json(line: &[u8]) -> bool {
+    serde_json::from_slice::<serde::de::IgnoredAny>(line).is_ok()
+}
+
+/// Whether the line starts with a `key=` pair.
+fn is_logfmt(line: &[u8]) -> bool {
+    let token = line
+        .split(|b| b.is_ascii_whitespace())
+        .next()
+        .unwrap_or(line);
+    match token.iter().position(|b| *b == b'=') {
+        Some(0) | None => false,
+        Some(i) => token[..i]
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.')),
+    }
+}
+
+/// Reader converting the input in any other format into JSON lines.
+pub(crate) struct JsonLinesReader<R> {
+    inner: R,
+    format: Format,
+    /// Converted line being handed out and how much of it has been read.
+    line: Vec<u8>,
+    position: usize,
+    in_array: bool,
+    header: Option<Vec<String>>,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    pub(crate) fn new(inner: R, format: Format) -> Self {
+        Self {
+            inner,
+            format,
+            line: Vec::new(),
+            position: 0,
+            in_array: false,
+            header: None,
+        }
+    }
+
+    /// Puts the next line in `line`. Returns false at the end of the input.
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        self.position = 0;
+        let found = match self.format {
+            Format::JsonArray | Format::Json => self.next_value()?,
+            Format::JsonSeq => self.next_record()?,
+            Format::Csv => self.next_row()?,
+            Format::Logfmt => self.next_logfmt()?,
+            // JSON lines, and raw lines, are already lines
+            _ => {
+                let read = self.inner.read_until(b'\n', &mut self.line)?;
+                if self.line.last() == Some(&b'\n') {
+                    self.line.pop();
+                }
+                read > 0
+            }
+        };
+        if found {
+            self.line.push(b'\n');
+        }
+        Ok(found)
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.inner.fill_buf()?.first().copied())
+    }
+
+    fn next_value(&mut self) -> io::Result<bool> {
+        loop {
+            match self.peek()? {
+                None => return Ok(false),
+                Some(b) if b.is_ascii_whitespace() => self.inner.consume(1),
+                Some(b'[') if self.format == Format::JsonArray && !self.in_array => {
+                    self.inner.consume(1);
+                    self.in_array = true;
+                }
+                Some(b']') if self.in_array => {
+                    self.inner.consume(1);
+                    self.in_array = false;
+                }
+                Some(b',') if self.in_array => self.inner.consume(1),
+                Some(_) => {
+                    let delimiters: &[u8] = if self.in_array { b",]" } else { b"" };
+                    self.read_value(delimiters)?;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Copies a single JSON value, dropping the newlines used to pretty print it.
+    /// Scalars end at whitespace or at one of the delimiters.
+    fn read_value(&mut self, delimiters: &[u8]) -> io::Result<()> {
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        while let Some(byte) = self.peek()? {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' if depth > 0 => depth -= 1,
+                    b if depth == 0 && (b.is_ascii_whitespace() || delimiters.contains(&b)) => {
+                        return Ok(())
+                    }
+                    b'\n' | b'\r' => {
+                        self.inner.consume(1);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            self.line.push(byte);
+            self.inner.consume(1);
+            if depth == 0 && !in_string && matches!(byte, b'}' | b']' | b'"') {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    fn next_record(&mut self) -> io::Result<bool> {
+        loop {
+            let mut record = Vec::new();
+            if self.inner.read_until(b'\n', &mut record)? == 0 {
+                return Ok(false);
+            }
+            self.line.extend(
+                record
+                    .iter()
+                    .filter(|b| **b != RECORD_SEPARATOR && **b != b'\n' && **b != b'\r'),
+            );
+            if !self.line.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn next_row(&mut self) -> io::Result<bool> {
+        if self.header.is_none() {
+            match self.read_csv_record()? {
+                Some(header) => self.header = Some(header),
+                None => return Ok(false),
+            }
+        }
+        let fields = match self.read_csv_record()? {
+            Some(fields) => fields,
+            None => return Ok(false),
+        };
+        let header = self.header.as_deref().unwrap_or_default();
+        let object: Map<String, Value> = fields
+            .into_iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let name = header.get(i).cloned().unwrap_or_else(|| i.to_string());
+                (name, Value::String(field))
+            })
+            .collect();
+        serde_json::to_writer(&mut self.line, &object)?;
+        Ok(true)
+    }
+
+    /// Reads the fields of the next non empty record. Quoted fields may span several lines.
+    fn read_csv_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        loop {
+            let mut record = Vec::new();
+            loop {
+                if self.inner.read_until(b'\n', &mut record)? == 0 {
+                    break;
+                }
+                if record.iter().filter(|b| **b == b'"').count() % 2 == 0 {
+                    break;
+                }
+            }
+            if record.is_empty() {
+                return Ok(None);
+            }
+            let record = String::from_utf8_lossy(&record);
+            let record = record.trim_end_matches(['\n', '\r']);
+            if !record.is_empty() {
+                return Ok(Some(csv_fields(record)));
+            }
+        }
+    }
+
+    fn next_logfmt(&mut self) -> io::Result<bool> {
+        loop {
+            let mut line = Vec::new();
+            if self.inner.read_until(b'\n', &mut line)? == 0 {
+                return Ok(false);
+            }
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                serde_json::to_writer(&mut self.line, &logfmt_object(&line))?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for JsonLinesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.line.len() && !self.next_line()? {
+            return Ok(0);
+        }
+        let pending = &self.line[self.position..];
+        let len = pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&pending[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+fn csv_fields(record: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let (mut quoted, mut chars) = (false, record.chars().peekable());
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Keys without a value are `true`. Quoted values accept backslash escapes.
+fn logfmt_object(line: &str) -> Map<String, Value> {
+    let mut object = Map::new();
+    let mut chars = line.trim().chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        let value = if chars.next_if_eq(&'=').is_some() {
+            let mut value = String::new();
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+            Value::String(value)
+        } else if key.is_empty() {
+            break;
+        } else {
+            Value::Bool(true)
+        };
+        if !key.is_empty() {
+            object.insert(key, value);
+        }
+    }
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(input: &str, format: Format) -> String {
+        let mut output = String::new();
+        JsonLinesReader::new(input.as_bytes(), format)
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    /// The objects of the converted lines, as the order of their keys depends on the features
+    /// `serde_json` is built with.
+    fn objects(lines: &str) -> Vec<serde_json::Value> {
+        lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sniff_detects_every_format() {
+        assert_eq!(
+            sniff(b"{\"type\":\"A\"}\n{\"type\":\"B\"}\n"),
+            Format::Jsonl
+        );
+        assert_eq!(
+            sniff(b"[{\"type\":\"A\"},{\"type\":\"B\"}]"),
+            Format::JsonArray
+        );
+        assert_eq!(sniff(b"[\n  {\"type\": \"A\"}\n]\n"), Format::JsonArray);
+        assert_eq!(sniff(b"{\n  \"type\": \"A\"\n}\n"), Format::Json);
+        assert_eq!(
+            sniff(b"[\n  {\"type\":\"A\"},\n  {\"type\":\"B\"}\n]"),
+            Format::JsonArray
+        );
+        // broken or cut lines don't make JSON lines look like something else
+        assert_eq!(sniff(b"{\"type\":\"A\" \"b\"}\n"), Format::Jsonl);
+        assert_eq!(
+            sniff(b"{\"type\":\"A\",\"b\n{\"type\":\"B\"}\n"),
+            Format::Jsonl
+        );
+        assert_eq!(sniff(b"\x1e{\"type\":\"A\"}\n"), Format::JsonSeq);
+        assert_eq!(sniff(b"type,size\nA,10\n"), Format::Csv);
+        assert_eq!(
+            sniff(b"level=info type=A msg=\"hi there\"\n"),
+            Format::Logfmt
+        );
+        assert_eq!(sniff(b"\xEF\xBB\xBF\n\n{\"type\":\"A\"}\n"), Format::Jsonl);
+        assert_eq!(sniff(b""), Format::Jsonl);
+        assert_eq!(sniff(b"not json at all\n"), Format::Jsonl);
+    }
+
+    #[test]
+    fn json_arrays_and_pretty_documents_become_lines() {
+        let array = "[\n  {\n    \"type\": \"A\",\n    \"tags\": [1, 2]\n  },\n  {\"type\":\"B,]\"} , 3\n]\n";
+        assert_eq!(
+            convert(array, Format::JsonArray),
+            "{    \"type\": \"A\",    \"tags\": [1, 2]  }\n{\"type\":\"B,]\"}\n3\n"
+        );
+        let pretty = "{\n  \"type\": \"A\"\n}\n{\n  \"type\": \"B\\\"}\"\n}";
+        assert_eq!(
+            convert(pretty, Format::Json),
+            "{  \"type\": \"A\"}\n{  \"type\": \"B\\\"}\"}\n"
+        );
+    }
+
+    #[test]
+    fn json_seq_records_lose_their_separators() {
+        let seq = "\x1e{\"type\":\"A\"}\n\x1e{\"type\":\"B\"}\r\n\n";
+        assert_eq!(
+            convert(seq, Format::JsonSeq),
+            "{\"type\":\"A\"}\n{\"type\":\"B\"}\n"
+        );
+    }
+
+    #[test]
+    fn csv_rows_become_objects() {
+        let csv = "type,msg\r\nA,\"hello, \"\"world\"\"\"\n\nB,\"two\nlines\",extra\n";
+        assert_eq!(
+            objects(&convert(csv, Format::Csv)),
+            objects("{\"msg\":\"hello, \\\"world\\\"\",\"type\":\"A\"}\n{\"2\":\"extra\",\"msg\":\"two\\nlines\",\"type\":\"B\"}\n")
+        );
+    }
+
+    #[test]
+    fn logfmt_lines_become_objects() {
+        let logfmt = "type=A msg=\"hi \\\"you\\\"\" debug\n\n=x type=B\n";
+        assert_eq!(
+            objects(&convert(logfmt, Format::Logfmt)),
+            objects(
+                "{\"debug\":true,\"msg\":\"hi \\\"you\\\"\",\"type\":\"A\"}\n{\"type\":\"B\"}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn format_parses_its_names() {
+        for format in [
+            Format::Auto,
+            Format::Jsonl,
+            Format::JsonArray,
+            Format::Json,
+            Format::JsonSeq,
+            Format::Csv,
+            Format::Logfmt,
+        ] {
+            assert_eq!(format.to_string().parse::<Format>(), Ok(format));
+        }
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
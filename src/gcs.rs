@@ -0,0 +1,13 @@
+use crate::command::analyze_command;
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use std::process::Command;
+
+/// Analyzes a Google Cloud Storage object in place, like `gs://bucket/logs/app.jsonl`,
+/// streaming it with `gcloud storage cat` and the credentials of the gcloud CLI.
+pub fn analyze_gcs(url: &str, options: &AnalyzeOptions) -> Result<Report, AnalyzeError> {
+    let mut command = Command::new("gcloud");
+    command.args(["storage", "cat", url]);
+    analyze_command(command, options)
+}
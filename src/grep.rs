@@ -0,0 +1,101 @@
+use crate::sql::Predicate;
+use std::io::{self, BufRead, Write};
+
+/// Lines matched at once by the worker threads.
+const GREP_BATCH_LINES: usize = 10_000;
+
+/// Lines seen by a grep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GrepStats {
+    pub lines: usize,
+    pub matched: usize,
+}
+
+/// Writes the lines matching the predicate, byte for byte and in their original order.
+/// Batches of lines are matched in `threads` threads while the next batch is being read.
+pub fn grep(
+    mut reader: impl BufRead,
+    predicate: &Predicate,
+    threads: usize,
+    mut writer: impl Write,
+) -> io::Result<GrepStats> {
+    let threads = threads.max(1);
+    let mut stats = GrepStats::default();
+    let mut batch = read_batch(&mut reader)?;
+    while !batch.is_empty() {
+        let (matches, next) = std::thread::scope(|scope| {
+            let size = batch.len().div_ceil(threads);
+            let workers: Vec<_> = batch
+                .chunks(size)
+                .map(|lines| {
+                    scope.spawn(move || {
+                        lines
+                            .iter()
+                            .map(|line| predicate.matches(line))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            let next = read_batch(&mut reader);
+            let matches: Vec<bool> = workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("A grep thread panicked"))
+                .collect();
+            (matches, next)
+        });
+        for (line, matched) in batch.iter().zip(matches) {
+            if matched {
+                writer.write_all(line)?;
+                stats.matched += 1;
+            }
+        }
+        stats.lines += batch.len();
+        batch = next?;
+    }
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Raw lines, terminators included.
+fn read_batch(reader: &mut impl BufRead) -> io::Result<Vec<Vec<u8>>> {
+    let mut batch = Vec::new();
+    while batch.len() < GREP_BATCH_LINES {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        batch.push(line);
+    }
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grep_keeps_the_matching_lines_untouched() {
+        let mut input = String::new();
+        for i in 0..25_000 {
+            input.push_str(&format!("{{ \"type\": \"A\", \"n\": {} }}\r\n", i));
+        }
+        input.push_str("{\"type\":\"B\"\n{\"type\":\"A\",\"n\":-1}");
+        let predicate: Predicate = "type == \"A\" && (n < 2 || n >= 24999)".parse().unwrap();
+        let mut output = Vec::new();
+        let stats = grep(input.as_bytes(), &predicate, 3, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{ \"type\": \"A\", \"n\": 0 }\r\n\
+             { \"type\": \"A\", \"n\": 1 }\r\n\
+             { \"type\": \"A\", \"n\": 24999 }\r\n\
+             {\"type\":\"A\",\"n\":-1}"
+        );
+        assert_eq!(
+            stats,
+            GrepStats {
+                lines: 25_002,
+                matched: 4
+            }
+        );
+    }
+}
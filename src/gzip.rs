@@ -0,0 +1,571 @@
+use crate::error::AnalyzeError;
+use crate::inspect::Compression;
+use crate::models::{CompressionStats, Report, ReportHasher};
+use crate::options::AnalyzeOptions;
+use crate::reader::{analyze_decoded, CountingReader};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use std::{
+    io::{self, Cursor, Read},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+};
+
+/// First bytes of a gzip stream.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// First bytes of a zstd frame.
+#[cfg(feature = "zstd")]
+pub(crate) const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Magic numbers of the zstd skippable frames, like the one holding the seek table of a
+/// seekable file.
+#[cfg(feature = "zstd")]
+const ZSTD_SKIPPABLE_MAGIC: std::ops::RangeInclusive<u32> = 0x184D_2A50..=0x184D_2A5F;
+
+/// Largest zstd frame decompressed whole by a thread. Larger ones are decompressed as a stream.
+#[cfg(feature = "zstd")]
+const ZSTD_MAX_FRAME_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Decompressed bytes of the zstd frames every thread is given at a time.
+#[cfg(feature = "zstd")]
+const ZSTD_BYTES_PER_THREAD: u64 = 1024 * 1024;
+
+/// Bytes of the header of a BGZF block up to its size.
+pub(crate) const BGZF_HEADER_LEN: usize = 18;
+
+/// BGZF blocks decompressed by every thread at a time. Blocks are 64 KiB at most.
+const BGZF_BLOCKS_PER_THREAD: usize = 16;
+
+/// Analyzes a gzip compressed input, keeping the bytes read and what they decompressed to.
+/// Concatenated gzip members, as left by `cat a.gz b.gz`, are read one after the other.
+/// BGZF files, as written by `bgzip`, are made of small members whose size is in their header,
+/// so several of them are decompressed at the same time.
+pub(crate) fn analyze_gzip<S: ReportHasher>(
+    reader: impl Read,
+    bgzf: bool,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    let compressed = CountingReader::new(reader);
+    let compressed_bytes = compressed.count.clone();
+    let decoder: Box<dyn Read> = if bgzf {
+        Box::new(BgzfReader::new(compressed, options.threads()))
+    } else {
+        Box::new(MultiGzDecoder::new(compressed))
+    };
+    analyze_compressed(
+        decoder,
+        compressed_bytes,
+        Compression::Gzip,
+        options,
+        partials,
+    )
+}
+
+/// Analyzes a zstd compressed input, keeping the bytes read and what they decompressed to.
+/// Concatenated frames, as left by `cat a.zst b.zst`, are read one after the other, and the
+/// small ones, like the frames of a seekable file, are decompressed at the same time.
+#[cfg(feature = "zstd")]
+pub(crate) fn analyze_zstd<S: ReportHasher>(
+    reader: impl Read,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    let compressed = CountingReader::new(reader);
+    let compressed_bytes = compressed.count.clone();
+    let decoder = ZstdFramesReader::new(compressed, options.threads());
+    analyze_compressed(
+        decoder,
+        compressed_bytes,
+        Compression::Zstd,
+        options,
+        partials,
+    )
+}
+
+/// Analyzes what the decoder decompresses, reporting it along with the bytes read by the decoder.
+fn analyze_compressed<S: ReportHasher>(
+    decoder: impl Read,
+    compressed_bytes: Arc<AtomicU64>,
+    compression: Compression,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    let decompressed = CountingReader::new(decoder);
+    let decompressed_bytes = decompressed.count.clone();
+    // the size of the file isn't the size of the lines, so the progress is unknown
+    let mut results = analyze_decoded(decompressed, None, options, partials)?;
+    results.compression = Some(CompressionStats {
+        compression,
+        compressed_bytes: compressed_bytes.load(Ordering::Relaxed),
+        decompressed_bytes: decompressed_bytes.load(Ordering::Relaxed),
+    });
+    Ok(results)
+}
+
+/// Whether the gzip header is the one of a BGZF block: a `BC` extra subfield holding its size.
+pub(crate) fn is_bgzf(header: &[u8]) -> bool {
+    bgzf_block_size(header).is_some()
+}
+
+/// Total size of the BGZF block starting with the header.
+fn bgzf_block_size(header: &[u8]) -> Option<usize> {
+    match header {
+        [0x1F, 0x8B, 8, flags, _, _, _, _, _, _, 6, 0, b'B', b'C', 2, 0, low, high, ..]
+            if flags & 4 != 0 =>
+        {
+            Some(usize::from(u16::from_le_bytes([*low, *high])) + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Reader decompressing the blocks of a BGZF file on several threads, keeping their order.
+struct BgzfReader<R> {
+    inner: R,
+    threads: usize,
+    decompressed: Cursor<Vec<u8>>,
+}
+
+impl<R: Read> BgzfReader<R> {
+    fn new(inner: R, threads: usize) -> Self {
+        Self {
+            inner,
+            threads: threads.max(1),
+            decompressed: Cursor::new(Vec::new()),
+        }
+    }
+
+    /// The next compressed block, or `None` at the end of the file.
+    fn read_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut block = vec![0; BGZF_HEADER_LEN];
+        let read = (&mut self.inner)
+            .take(BGZF_HEADER_LEN as u64)
+            .read(&mut block)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.inner.read_exact(&mut block[read..])?;
+        let size = bgzf_block_size(&block)
+            .filter(|size| *size > BGZF_HEADER_LEN)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid BGZF block header")
+            })?;
+        block.resize(size, 0);
+        self.inner.read_exact(&mut block[BGZF_HEADER_LEN..])?;
+        Ok(Some(block))
+    }
+
+    /// Decompresses the next blocks, spread over the threads. False at the end of the file.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut blocks = Vec::new();
+        while blocks.len() < self.threads * BGZF_BLOCKS_PER_THREAD {
+            match self.read_block()? {
+                Some(block) => blocks.push(block),
+                None => break,
+            }
+        }
+        if blocks.is_empty() {
+            return Ok(false);
+        }
+        let decompressed = decompress_blocks(&blocks, self.threads, |block, decompressed| {
+            GzDecoder::new(block).read_to_end(decompressed).map(drop)
+        })?;
+        self.decompressed = Cursor::new(decompressed);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.decompressed.read(buf)?;
+            // blocks may be empty, like the one closing the file
+            if read > 0 || buf.is_empty() || !self.fill()? {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+/// Decompresses the blocks spread over the threads, keeping their order.
+fn decompress_blocks(
+    blocks: &[Vec<u8>],
+    threads: usize,
+    decompress: fn(&[u8], &mut Vec<u8>) -> io::Result<()>,
+) -> io::Result<Vec<u8>> {
+    let per_thread = blocks.len().div_ceil(threads).max(1);
+    let decompressed = thread::scope(|scope| {
+        let workers: Vec<_> = blocks
+            .chunks(per_thread)
+            .map(|blocks| {
+                scope.spawn(move || -> io::Result<Vec<u8>> {
+                    let mut decompressed = Vec::new();
+                    for block in blocks {
+                        decompress(block, &mut decompressed)?;
+                    }
+                    Ok(decompressed)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("A decompression worker panicked"))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    Ok(decompressed.concat())
+}
+
+/// A zstd frame as read from the input.
+#[cfg(feature = "zstd")]
+enum ZstdFrame {
+    /// A whole frame whose header says it decompresses to no more than `ZSTD_MAX_FRAME_SIZE`.
+    Small { frame: Vec<u8>, content_size: u64 },
+    /// The beginning of a frame that may decompress to more, read up to its blocks.
+    Large(Vec<u8>),
+}
+
+/// Decoder of what's left of a zstd input, starting with the beginning of a frame already read.
+#[cfg(feature = "zstd")]
+type ZstdStream<R> =
+    zstd::stream::read::Decoder<'static, io::BufReader<io::Chain<Cursor<Vec<u8>>, R>>>;
+
+/// Reader decompressing the frames of a zstd file on several threads, keeping their order.
+/// Seekable files, and any other made of many small frames, are split by the sizes of the blocks
+/// of the frames, without their seek table. Once a frame doesn't say it's small, the rest of the
+/// input is decompressed as a stream on the current thread instead.
+#[cfg(feature = "zstd")]
+struct ZstdFramesReader<R> {
+    /// `None` once the rest of the input is handed to `stream`.
+    inner: Option<R>,
+    threads: usize,
+    decompressed: Cursor<Vec<u8>>,
+    stream: Option<ZstdStream<R>>,
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> ZstdFramesReader<R> {
+    fn new(inner: R, threads: usize) -> Self {
+        Self {
+            inner: Some(inner),
+            threads: threads.max(1),
+            decompressed: Cursor::new(Vec::new()),
+            stream: None,
+        }
+    }
+
+    /// Appends `len` bytes of the input to `frame`. False if the input ended before any of them.
+    fn read_into(inner: &mut R, frame: &mut Vec<u8>, len: usize) -> io::Result<bool> {
+        let start = frame.len();
+        inner.take(len as u64).read_to_end(frame)?;
+        match frame.len() - start {
+            read if read == len => Ok(true),
+            0 if len > 0 => Ok(false),
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the zstd frame is truncated",
+            )),
+        }
+    }
+
+    /// The next frame, or `None` at the end of the input. Skippable frames, like the seek table
+    /// of a seekable file, are skipped.
+    fn read_frame(inner: &mut R) -> io::Result<Option<ZstdFrame>> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        loop {
+            let mut frame = Vec::new();
+            if !Self::read_into(inner, &mut frame, 4)? {
+                return Ok(None);
+            }
+            let magic = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+            if ZSTD_SKIPPABLE_MAGIC.contains(&magic) {
+                Self::read_into(inner, &mut frame, 4)?;
+                let size = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+                let skipped = io::copy(&mut inner.take(u64::from(size)), &mut io::sink())?;
+                if skipped < u64::from(size) {
+                    return Err(invalid("the zstd skippable frame is truncated"));
+                }
+                continue;
+            }
+            if frame != ZSTD_MAGIC {
+                return Err(invalid("invalid zstd frame header"));
+            }
+            Self::read_into(inner, &mut frame, 1)?;
+            let descriptor = frame[4];
+            let single_segment = descriptor & 0x20 != 0;
+            let window_len = usize::from(!single_segment);
+            let dictionary_len = [0, 1, 2, 4][usize::from(descriptor & 3)];
+            let content_size_len = match descriptor >> 6 {
+                0 => usize::from(single_segment),
+                flag => 1 << flag,
+            };
+            Self::read_into(
+                inner,
+                &mut frame,
+                window_len + dictionary_len + content_size_len,
+            )?;
+            let content_size = frame[frame.len() - content_size_len..]
+                .iter()
+                .rev()
+                .fold(0, |size, byte| size << 8 | u64::from(*byte))
+                // sizes of 2 bytes start at 256
+                + if content_size_len == 2 { 256 } else { 0 };
+            if content_size_len == 0 || content_size > ZSTD_MAX_FRAME_SIZE {
+                return Ok(Some(ZstdFrame::Large(frame)));
+            }
+            loop {
+                let header = frame.len();
+                Self::read_into(inner, &mut frame, 3)?;
+                let block =
+                    u32::from_le_bytes([frame[header], frame[header + 1], frame[header + 2], 0]);
+                let size = match (block >> 1) & 3 {
+                    // raw and compressed blocks
+                    0 | 2 => block >> 3,
+                    // a byte repeated
+                    1 => 1,
+                    _ => return Err(invalid("invalid zstd block type")),
+                };
+                Self::read_into(inner, &mut frame, size as usize)?;
+                if block & 1 != 0 {
+                    break;
+                }
+            }
+            if descriptor & 4 != 0 {
+                // the checksum of the content
+                Self::read_into(inner, &mut frame, 4)?;
+            }
+            return Ok(Some(ZstdFrame::Small {
+                frame,
+                content_size,
+            }));
+        }
+    }
+
+    /// Decompresses the next frames, spread over the threads. False at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        let inner = match &mut self.inner {
+            Some(inner) => inner,
+            None => return Ok(false),
+        };
+        let mut frames = Vec::new();
+        let mut content_size = 0;
+        while content_size < self.threads as u64 * ZSTD_BYTES_PER_THREAD {
+            match Self::read_frame(inner)? {
+                Some(ZstdFrame::Small {
+                    frame,
+                    content_size: size,
+                }) => {
+                    frames.push(frame);
+                    content_size += size;
+                }
+                Some(ZstdFrame::Large(header)) => {
+                    let rest = Cursor::new(header).chain(self.inner.take().expect("no input"));
+                    self.stream = Some(zstd::stream::read::Decoder::new(rest)?);
+                    break;
+                }
+                None => break,
+            }
+        }
+        if frames.is_empty() {
+            return Ok(self.stream.is_some());
+        }
+        self.decompressed = Cursor::new(decompress_blocks(
+            &frames,
+            self.threads,
+            |frame, decompressed| zstd::stream::copy_decode(frame, decompressed),
+        )?);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> Read for ZstdFramesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.decompressed.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            // the frames before the stream are read first
+            if let Some(stream) = &mut self.stream {
+                return stream.read(buf);
+            }
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_bgzf;
+    use crate::{analyze_reader, AnalyzeOptions, Compression};
+    use flate2::{write::GzEncoder, Compression as Level, GzBuilder};
+    use std::io::Write;
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// BGZF file with a block for every piece of the content and the empty block closing it.
+    fn bgzf(content: &[u8], block_size: usize) -> Vec<u8> {
+        let mut file = Vec::new();
+        for piece in content.chunks(block_size).chain(std::iter::once(&[][..])) {
+            let mut encoder = GzBuilder::new()
+                .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                .write(Vec::new(), Level::default());
+            encoder.write_all(piece).unwrap();
+            let mut block = encoder.finish().unwrap();
+            let size = (block.len() as u16 - 1).to_le_bytes();
+            block[16..18].copy_from_slice(&size);
+            file.extend(block);
+        }
+        file
+    }
+
+    #[test]
+    fn bgzf_blocks_are_decompressed_in_parallel() {
+        let lines: String = (0..2_000)
+            .map(|i| format!("{{\"type\":\"{}\",\"n\":{}}}\n", i % 7, i))
+            .collect();
+        let compressed = bgzf(lines.as_bytes(), 1_000);
+        assert!(is_bgzf(&compressed));
+        assert!(!is_bgzf(&gzip(lines.as_bytes())));
+
+        let expected = analyze_reader(lines.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        for threads in [1, 3, 8] {
+            let options = AnalyzeOptions::builder().threads(threads).build();
+            let report = analyze_reader(&compressed[..], &options).unwrap();
+            assert_eq!(report.types, expected.types);
+            let stats = report.compression.unwrap();
+            assert_eq!(stats.compressed_bytes, compressed.len() as u64);
+            assert_eq!(stats.decompressed_bytes, lines.len() as u64);
+        }
+
+        let truncated = &compressed[..compressed.len() - 40];
+        assert!(analyze_reader(truncated, &AnalyzeOptions::default()).is_err());
+    }
+
+    #[test]
+    fn gzip_inputs_are_decompressed_and_their_sizes_reported() {
+        let lines = "{\"type\":\"A\",\"padding\":\"xxxxxxxxxxxxxxxxxxxx\"}\n".repeat(100);
+        let last = "{\"type\":\"B\"}\n";
+        // concatenated members are read as a single stream
+        let mut compressed = gzip(lines.as_bytes());
+        compressed.extend(gzip(last.as_bytes()));
+
+        let report = analyze_reader(&compressed[..], &AnalyzeOptions::default()).unwrap();
+        let plain = lines + last;
+        let expected = analyze_reader(plain.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert_eq!(report.types, expected.types);
+
+        let stats = report.compression.unwrap();
+        assert_eq!(stats.compression, Compression::Gzip);
+        assert_eq!(stats.compressed_bytes, compressed.len() as u64);
+        assert_eq!(stats.decompressed_bytes, plain.len() as u64);
+        assert!(stats.ratio() > 10.0);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_inputs_are_decompressed_and_their_sizes_reported() {
+        use crate::Strategy;
+
+        let lines = "{\"type\":\"A\",\"padding\":\"xxxxxxxxxxxxxxxxxxxx\"}\n".repeat(100);
+        let last = "{\"type\":\"B\"}\n";
+        // concatenated frames are read as a single stream
+        let mut compressed = zstd::encode_all(lines.as_bytes(), 0).unwrap();
+        compressed.extend(zstd::encode_all(last.as_bytes(), 0).unwrap());
+
+        let plain = lines + last;
+        let expected = analyze_reader(plain.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder().strategy(strategy).build();
+            let report = analyze_reader(&compressed[..], &options).unwrap();
+            assert_eq!(report.types, expected.types, "{:?}", strategy);
+
+            let stats = report.compression.unwrap();
+            assert_eq!(stats.compression, Compression::Zstd);
+            assert_eq!(stats.compressed_bytes, compressed.len() as u64);
+            assert_eq!(stats.decompressed_bytes, plain.len() as u64);
+            assert!(stats.ratio() > 10.0);
+        }
+
+        let truncated = &compressed[..compressed.len() / 2];
+        assert!(analyze_reader(truncated, &AnalyzeOptions::default()).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_frames_are_decompressed_in_parallel() {
+        use super::{ZstdFrame, ZstdFramesReader};
+
+        let lines: Vec<String> = (0..3_000)
+            .map(|i| {
+                format!(
+                    "{{\"type\":\"{}\",\"n\":{},\"id\":\"{:x}\"}}\n",
+                    i % 7,
+                    i,
+                    i * 2_654_435_761u64
+                )
+            })
+            .chain(std::iter::once(format!(
+                "{{\"type\":\"rle\",\"x\":\"{}\"}}\n",
+                "x".repeat(300_000)
+            )))
+            .collect();
+        let plain = lines.concat();
+        // small frames saying their size, like the ones of a seekable file, and its seek table
+        let frames = |lines: &[String]| -> Vec<u8> {
+            lines
+                .chunks(100)
+                .flat_map(|lines| zstd::bulk::compress(lines.concat().as_bytes(), 3).unwrap())
+                .collect()
+        };
+        let mut seekable = frames(&lines);
+        seekable.extend(0x184D_2A5Eu32.to_le_bytes());
+        seekable.extend(9u32.to_le_bytes());
+        seekable.extend([0, 0, 0, 0, 0, 0x8F, 0x92, 0xEA, 0xB1]);
+        // a frame without its size in the header, streamed along with the rest
+        let mut mixed = frames(&lines[..1_000]);
+        mixed.extend(zstd::encode_all(lines[1_000..2_000].concat().as_bytes(), 3).unwrap());
+        mixed.extend(frames(&lines[2_000..]));
+
+        // the blocks of the frames are followed up to the end of every frame
+        let mut input = &seekable[..];
+        let mut small_frames = 0;
+        while let Some(frame) = ZstdFramesReader::read_frame(&mut input).unwrap() {
+            assert!(matches!(frame, ZstdFrame::Small { .. }));
+            small_frames += 1;
+        }
+        assert_eq!(small_frames, 31);
+        let mut input = &mixed[..];
+        for _ in 0..10 {
+            let frame = ZstdFramesReader::read_frame(&mut input).unwrap();
+            assert!(matches!(frame, Some(ZstdFrame::Small { .. })));
+        }
+        let frame = ZstdFramesReader::read_frame(&mut input).unwrap();
+        assert!(matches!(frame, Some(ZstdFrame::Large(_))));
+
+        let expected = analyze_reader(plain.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        for compressed in [&seekable, &mixed] {
+            for threads in [1, 3, 8] {
+                let options = AnalyzeOptions::builder().threads(threads).build();
+                let report = analyze_reader(&compressed[..], &options).unwrap();
+                assert_eq!(report.types, expected.types);
+                let stats = report.compression.unwrap();
+                assert_eq!(stats.compressed_bytes, compressed.len() as u64);
+                assert_eq!(stats.decompressed_bytes, plain.len() as u64);
+            }
+            let truncated = &compressed[..compressed.len() - 100];
+            assert!(analyze_reader(truncated, &AnalyzeOptions::default()).is_err());
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::error_report::ErrorEntry;
+use crate::labels::Labels;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use crate::printer::round_half_even;
+use crate::template::{escape, render};
+use std::{
+    fs,
+    hash::BuildHasher,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TEMPLATE: &str = include_str!("templates/report.html");
+/// Types listed in the chart. The rest are added up in a single row.
+const MAX_TYPES: usize = 20;
+/// Error entries shown as samples.
+const MAX_ERROR_SAMPLES: usize = 10;
+/// Types holding the lines that couldn't be parsed.
+const ERROR_TYPES: [&str; 2] = ["ERROR", "TRUNCATED"];
+
+/// Self-contained HTML report of an analysis, suitable to be attached to an email
+/// or published as a CI artifact. It doesn't load any external resource.
+pub struct HtmlReport<'a> {
+    pub source: &'a str,
+    pub report: &'a Report,
+    /// Errors collected during the run, e.g. with an `ErrorReport`. Only a few are shown.
+    pub errors: &'a [ErrorEntry],
+    pub options: &'a AnalyzeOptions,
+    pub duration: Duration,
+    /// Decimal places of the error rate. Defaults to 2.
+    pub precision: Option<usize>,
+    /// Replacements of the headers and the category types of the table of types.
+    pub labels: &'a Labels,
+}
+
+impl HtmlReport<'_> {
+    pub fn render(&self) -> String {
+        let lines: usize = self.report.types.values().map(|c| c.count).sum();
+        let bytes: usize = self.report.types.values().map(|c| c.bytes).sum();
+        let errors: usize = ERROR_TYPES
+            .iter()
+            .map(|key| self.report.get(key).map_or(0, |c| c.count))
+            .sum();
+        let error_rate = if lines == 0 {
+            0.0
+        } else {
+            errors as f64 * 100.0 / lines as f64
+        };
+        render(
+            TEMPLATE,
+            &[
+                ("source", escape(self.source)),
+                ("lines", lines.to_string()),
+                ("bytes", bytes.to_string()),
+                ("types", self.report.len().to_string()),
+                ("errors", errors.to_string()),
+                ("error_rate", self.error_rate(error_rate)),
+                (
+                    "type_header",
+                    escape(self.labels.get("TYPE").unwrap_or("Type")),
+                ),
+                (
+                    "count_header",
+                    escape(self.labels.get("COUNT").unwrap_or("Count")),
+                ),
+                (
+                    "bytes_header",
+                    escape(self.labels.get("BYTES").unwrap_or("Bytes")),
+                ),
+                ("type_rows", self.type_rows(bytes)),
+                ("error_samples", self.error_samples()),
+                ("metadata_rows", self.metadata_rows()),
+            ],
+        )
+    }
+
+    fn error_rate(&self, error_rate: f64) -> String {
+        let decimals = self.precision.unwrap_or(2);
+        format!("{:.*}%", decimals, round_half_even(error_rate, decimals))
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.render())
+    }
+
+    fn type_rows(&self, total_bytes: usize) -> String {
+        let mut types: Vec<_> = self.report.types.iter().collect();
+        types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        let mut rows = String::new();
+        for (key, counter) in types.iter().take(MAX_TYPES) {
+            let share = if total_bytes == 0 {
+                0.0
+            } else {
+                counter.bytes as f64 * 100.0 / total_bytes as f64
+            };
+            let class = if ERROR_TYPES.contains(&key.as_ref()) {
+                "bar error"
+            } else {
+                "bar"
+            };
+            rows.push_str(&format!(
+                "  <tr><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td><div class=\"{}\" style=\"width: {:.1}%\"></div></td></tr>\n",
+                escape(self.labels.label(key)),
+                counter.count,
+                counter.bytes,
+                class,
+                share
+            ));
+        }
+        if types.len() > MAX_TYPES {
+            let (count, bytes) = types[MAX_TYPES..]
+                .iter()
+                .fold((0, 0), |(count, bytes), (_, c)| {
+                    (count + c.count, bytes + c.bytes)
+                });
+            rows.push_str(&format!(
+                "  <tr><td>{} other types</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td></td></tr>\n",
+                types.len() - MAX_TYPES,
+                count,
+                bytes
+            ));
+        }
+        rows
+    }
+
+    fn error_samples(&self) -> String {
+        if self.errors.is_empty() {
+            return "<p class=\"empty\">No error samples were collected.</p>".to_string();
+        }
+        let mut samples = String::from(
+            "<table>\n  <tr><th>Line</th><th>Category</th><th>Message</th><th>Content</th></tr>\n",
+        );
+        for entry in self.errors.iter().take(MAX_ERROR_SAMPLES) {
+            samples.push_str(&format!(
+                "  <tr><td class=\"number\">{}</td><td>{}</td><td>{}</td><td><code>{}{}</code></td></tr>\n",
+                entry.line_number,
+                escape(&entry.category),
+                escape(&entry.message),
+                escape(&entry.content),
+                if entry.truncated { "…" } else { "" }
+            ));
+        }
+        samples.push_str("</table>");
+        if self.errors.len() > MAX_ERROR_SAMPLES {
+            samples.push_str(&format!(
+                "\n<p class=\"empty\">{} more errors not shown.</p>",
+                self.errors.len() - MAX_ERROR_SAMPLES
+            ));
+        }
+        samples
+    }
+
+    fn metadata_rows(&self) -> String {
+        let metadata = [
+            ("Generated at", utc_timestamp(SystemTime::now())),
+            ("Duration", format!("{:?}", self.duration)),
+            ("Grouped by", self.options.group_by().join(", ")),
+            ("Strategy", format!("{:?}", self.options.strategy())),
+            (
+                "Version",
+                format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ),
+        ];
+        metadata
+            .iter()
+            .map(|(label, value)| {
+                format!("  <tr><th>{}</th><td>{}</td></tr>\n", label, escape(value))
+            })
+            .collect()
+    }
+}
+
+impl<S: BuildHasher> Report<S> {
+    /// HTML table of the types, the biggest first, with a row of totals.
+    pub fn to_html_table(&self) -> String {
+        let mut types: Vec<_> = self.types.iter().collect();
+        types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        let mut table =
+            String::from("<table>\n  <tr><th>Type</th><th>Count</th><th>Bytes</th></tr>\n");
+        for (key, counter) in &types {
+            table.push_str(&format!(
+                "  <tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(key),
+                counter.count,
+                counter.bytes
+            ));
+        }
+        let (count, bytes) = types.iter().fold((0, 0), |(count, bytes), (_, c)| {
+            (count + c.count, bytes + c.bytes)
+        });
+        table.push_str(&format!(
+            "  <tr><th>Total</th><th>{}</th><th>{}</th></tr>\n</table>",
+            count, bytes
+        ));
+        table
+    }
+
+    /// Renders the report inline in evcxr notebooks, which call this method on the values
+    /// they show.
+    pub fn evcxr_display(&self) {
+        println!(
+            "EVCXR_BEGIN_CONTENT text/html\n{}\nEVCXR_END_CONTENT",
+            self.to_html_table()
+        );
+    }
+}
+
+/// Formats the time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub(crate) fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // civil date from the days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_reader, ErrorReport};
+
+    #[test]
+    fn html_report_contains_the_summary_the_types_and_the_errors() {
+        let file_content = "{\"type\":\"A\"}\n{\"type\":\"<b>\"}\n{\"type\":\"A\" x}\n";
+        let errors = ErrorReport::new();
+        let collector = errors.clone();
+        let options = AnalyzeOptions::builder()
+            .on_error(move |line_number, offset, line, e| {
+                collector.record(line_number, offset, line, e)
+            })
+            .build();
+        let report = analyze_reader(file_content.as_bytes(), &options).unwrap();
+        let html = HtmlReport {
+            source: "app.log",
+            report: &report,
+            errors: &errors.entries(),
+            options: &options,
+            duration: Duration::from_millis(12),
+            precision: None,
+            labels: &Labels::default(),
+        }
+        .render();
+        assert!(html.contains("<p class=\"subtitle\">app.log</p>"));
+        assert!(html.contains("<div class=\"value\">3</div><div class=\"label\">lines</div>"));
+        assert!(html.contains("errors (33.33%)"));
+        assert!(html.contains("<td>&lt;b&gt;</td>"));
+        assert!(html.contains("<code>{&quot;type&quot;:&quot;A&quot; x}</code>"));
+        assert!(html.contains("<tr><th>Grouped by</th><td>type</td></tr>"));
+        assert!(!html.contains("{{"));
+    }
+
+    #[test]
+    fn reports_render_as_an_html_table() {
+        let report = analyze_reader(
+            "{\"type\":\"A\"}\n{\"type\":\"<b>\"}\n{\"type\":\"A\"}\n".as_bytes(),
+            &AnalyzeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            report.to_html_table(),
+            "<table>\n  <tr><th>Type</th><th>Count</th><th>Bytes</th></tr>\n  \
+             <tr><td>A</td><td>2</td><td>26</td></tr>\n  \
+             <tr><td>&lt;b&gt;</td><td>1</td><td>15</td></tr>\n  \
+             <tr><th>Total</th><th>3</th><th>41</th></tr>\n</table>"
+        );
+    }
+
+    #[test]
+    fn utc_timestamp_formats_the_civil_date() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc_timestamp(leap_day), "2024-02-29 12:34:56 UTC");
+    }
+}
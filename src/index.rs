@@ -0,0 +1,383 @@
+use crate::options::AnalyzeOptions;
+use crate::parser::{parse_key, split_path, Key};
+use crate::projection::Projection;
+use crate::replay::{timestamp, TimeUnit};
+use crate::time_range::TimeRange;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Version of the index format. Indexes of other versions are ignored.
+const INDEX_VERSION: u32 = 1;
+/// Type of the lines that couldn't be parsed, like in the analysis.
+const ERROR_TYPE: &str = "ERROR";
+
+/// Byte range of whole lines, end excluded.
+pub type ByteRange = (u64, u64);
+
+/// Sidecar index of a JSON lines file. The file is cut in blocks of whole lines, and the index
+/// keeps where every type is and the time bounds of every block, so the analyses of a few
+/// types or of a time range only read the blocks holding them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Index {
+    pub version: u32,
+    /// Size of the file when it was indexed. The index is stale if the size changes.
+    pub file_size: u64,
+    /// JSON fields the lines were grouped by.
+    pub group_by: Vec<String>,
+    /// Field holding the timestamp of the lines, if the time bounds were indexed.
+    pub time_field: Option<String>,
+    /// Unit the numeric timestamps were read in.
+    #[serde(default)]
+    pub time_unit: TimeUnit,
+    pub blocks: Vec<IndexBlock>,
+    /// Byte ranges of the blocks holding every type, consecutive blocks being coalesced.
+    pub types: BTreeMap<String, Vec<ByteRange>>,
+}
+
+/// Block of whole lines of an indexed file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexBlock {
+    pub start: u64,
+    pub end: u64,
+    pub lines: usize,
+    /// Earliest and latest timestamps of the lines of the block, in seconds since the epoch.
+    /// `None` if no line has a timestamp.
+    pub time_bounds: Option<(f64, f64)>,
+}
+
+/// Builds the index of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Indexer {
+    /// JSON fields tried in order to group the lines, like `AnalyzeOptions::group_by_any`.
+    pub group_by: Vec<String>,
+    /// Field holding the timestamp of the lines. Nested fields are separated by dots.
+    pub time_field: Option<String>,
+    /// Unit of the numeric timestamps of the lines.
+    pub time_unit: TimeUnit,
+    /// Bytes of lines per block. Smaller blocks skip more bytes but make bigger indexes.
+    pub block_size: u64,
+}
+
+impl Default for Indexer {
+    fn default() -> Self {
+        Self {
+            group_by: vec!["type".to_string()],
+            time_field: None,
+            time_unit: TimeUnit::Auto,
+            block_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Indexer {
+    /// Indexes the lines of the reader.
+    pub fn build(&self, mut reader: impl BufRead) -> io::Result<Index> {
+        let key_paths: Vec<Vec<String>> = self.group_by.iter().map(|p| split_path(p)).collect();
+        let time_field = self.time_field.as_deref().map(Projection::single);
+        let mut blocks = Vec::new();
+        let mut block_types: Vec<BTreeSet<String>> = Vec::new();
+        let mut block = IndexBlock {
+            start: 0,
+            end: 0,
+            lines: 0,
+            time_bounds: None,
+        };
+        let mut types = BTreeSet::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            block.end += read as u64;
+            block.lines += 1;
+            types.insert(match parse_key(&line, &key_paths, false, false, false) {
+                Ok((Key::Single(key), _)) => key,
+                Ok((Key::Exploded(_), _)) => unreachable!("Keys are never exploded when indexing"),
+                Err(_) => ERROR_TYPE.to_string(),
+            });
+            let seconds = time_field
+                .as_ref()
+                .and_then(|field| timestamp(&line, field, self.time_unit));
+            if let Some(seconds) = seconds {
+                block.time_bounds = Some(match block.time_bounds {
+                    Some((min, max)) => (min.min(seconds), max.max(seconds)),
+                    None => (seconds, seconds),
+                });
+            }
+            if block.end - block.start >= self.block_size {
+                let end = block.end;
+                blocks.push(std::mem::replace(
+                    &mut block,
+                    IndexBlock {
+                        start: end,
+                        end,
+                        lines: 0,
+                        time_bounds: None,
+                    },
+                ));
+                block_types.push(std::mem::take(&mut types));
+            }
+        }
+        if block.lines > 0 {
+            blocks.push(block);
+            block_types.push(types);
+        }
+
+        let mut type_ranges: BTreeMap<String, Vec<ByteRange>> = BTreeMap::new();
+        for (block, types) in blocks.iter().zip(block_types) {
+            for key in types {
+                type_ranges
+                    .entry(key)
+                    .or_default()
+                    .push((block.start, block.end));
+            }
+        }
+        for ranges in type_ranges.values_mut() {
+            *ranges = coalesce(std::mem::take(ranges));
+        }
+        Ok(Index {
+            version: INDEX_VERSION,
+            file_size: blocks.last().map_or(0, |block| block.end),
+            group_by: self.group_by.clone(),
+            time_field: self.time_field.clone(),
+            time_unit: self.time_unit,
+            blocks,
+            types: type_ranges,
+        })
+    }
+}
+
+impl Index {
+    /// Path of the index of a file: the same path with an `.idx` extension appended.
+    pub fn path_of(file: &Path) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Whether the index can be used to analyze a file of `file_size` bytes with the options.
+    pub fn fits(&self, file_size: u64, options: &AnalyzeOptions) -> bool {
+        self.version == INDEX_VERSION
+            && self.file_size == file_size
+            && self.group_by == options.group_by
+            && !options.explode
+            && options.unwrap_path.is_none()
+    }
+
+    /// Byte ranges of the blocks holding any of the types and, if the time range is on the
+    /// indexed field, some line within it. Other lines of the blocks may be out of the range.
+    pub fn ranges(&self, only: &HashSet<String>, time_range: Option<&TimeRange>) -> Vec<ByteRange> {
+        let typed = coalesce(
+            only.iter()
+                .filter_map(|key| self.types.get(key))
+                .flatten()
+                .copied()
+                .collect(),
+        );
+        let time_range = time_range.filter(|range| {
+            Some(&range.field) == self.time_field.as_ref() && range.unit == self.time_unit
+        });
+        let timed = match time_range {
+            Some(range) => coalesce(
+                self.blocks
+                    .iter()
+                    .filter(|block| match block.time_bounds {
+                        Some((min, max)) => {
+                            range.since.is_none_or(|since| max >= since)
+                                && range.until.is_none_or(|until| min <= until)
+                        }
+                        None => true,
+                    })
+                    .map(|block| (block.start, block.end))
+                    .collect(),
+            ),
+            None => vec![(0, self.file_size)],
+        };
+        intersect(&typed, &timed)
+    }
+}
+
+/// Sorts the ranges and merges the ones overlapping or touching.
+pub(crate) fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_unstable();
+    let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match coalesced.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => coalesced.push((start, end)),
+        }
+    }
+    coalesced
+}
+
+/// Bytes within both lists of sorted and disjoint ranges.
+pub(crate) fn intersect(a: &[ByteRange], b: &[ByteRange]) -> Vec<ByteRange> {
+    let mut intersection = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start < end {
+            intersection.push((start, end));
+        }
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    intersection
+}
+
+/// Reads only the given byte ranges of the inner reader, one after another.
+pub(crate) struct RangesReader<R> {
+    inner: R,
+    ranges: VecDeque<ByteRange>,
+    /// Bytes left in the current range, which the inner reader is positioned at.
+    remaining: u64,
+}
+
+impl<R: Read + Seek> RangesReader<R> {
+    pub(crate) fn new(inner: R, ranges: Vec<ByteRange>) -> Self {
+        Self {
+            inner,
+            ranges: ranges.into(),
+            remaining: 0,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for RangesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            match self.ranges.pop_front() {
+                Some((start, end)) => {
+                    self.inner.seek(SeekFrom::Start(start))?;
+                    self.remaining = end - start;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.remaining as usize);
+        let read = self.inner.read(&mut buf[..len])?;
+        if read == 0 {
+            // the file is shorter than the index says
+            self.remaining = 0;
+            self.ranges.clear();
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const LOGS: &str = "{\"type\":\"A\",\"ts\":1}\n{\"type\":\"B\",\"ts\":2}\n\
+                        {\"type\":\"A\",\"ts\":3}\n{\"type\":\"A\",\"ts\":4}\n\
+                        not json\n{\"type\":\"C\",\"ts\":6}\n{\"type\":\"A\",\"ts\":7}";
+
+    fn index() -> Index {
+        Indexer {
+            time_field: Some("ts".to_string()),
+            block_size: 25,
+            ..Indexer::default()
+        }
+        .build(LOGS.as_bytes())
+        .unwrap()
+    }
+
+    fn read(ranges: Vec<ByteRange>) -> String {
+        let mut content = String::new();
+        RangesReader::new(Cursor::new(LOGS), ranges)
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn only(types: &[&str]) -> HashSet<String> {
+        types.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn index_keeps_the_blocks_of_every_type() {
+        let index = index();
+        assert_eq!(index.file_size, LOGS.len() as u64);
+        assert_eq!(index.blocks.len(), 4);
+        assert_eq!(index.blocks[0].lines, 2);
+        assert_eq!(index.blocks[0].time_bounds, Some((1.0, 2.0)));
+        assert_eq!(index.blocks[2].time_bounds, Some((6.0, 6.0)));
+        assert_eq!(index.types["A"], [(0, 80), (109, 128)]);
+        assert_eq!(index.types["ERROR"], [(80, 109)]);
+
+        let ranges = index.ranges(&only(&["B", "C"]), None);
+        assert_eq!(
+            read(ranges),
+            "{\"type\":\"A\",\"ts\":1}\n{\"type\":\"B\",\"ts\":2}\n\
+             not json\n{\"type\":\"C\",\"ts\":6}\n"
+        );
+        assert!(index.ranges(&only(&["D"]), None).is_empty());
+    }
+
+    #[test]
+    fn index_skips_the_blocks_out_of_the_time_range() {
+        let index = index();
+        let range = TimeRange {
+            field: "ts".to_string(),
+            since: Some(3.5),
+            until: Some(6.0),
+            unit: TimeUnit::Auto,
+        };
+        let ranges = index.ranges(&only(&["A", "C"]), Some(&range));
+        assert_eq!(
+            read(ranges),
+            "{\"type\":\"A\",\"ts\":3}\n{\"type\":\"A\",\"ts\":4}\n\
+             not json\n{\"type\":\"C\",\"ts\":6}\n"
+        );
+        // the bounds of another field can't be used
+        let range = TimeRange {
+            field: "time".to_string(),
+            ..range
+        };
+        assert_eq!(index.ranges(&only(&["A"]), Some(&range)), index.types["A"]);
+        // nor are the bounds of timestamps read in another unit
+        let range = TimeRange {
+            field: "ts".to_string(),
+            unit: TimeUnit::Milliseconds,
+            ..range
+        };
+        assert_eq!(index.ranges(&only(&["A"]), Some(&range)), index.types["A"]);
+    }
+
+    #[test]
+    fn ranges_are_coalesced_and_intersected() {
+        assert_eq!(
+            coalesce(vec![(5, 8), (0, 2), (2, 4), (7, 9)]),
+            [(0, 4), (5, 9)]
+        );
+        assert_eq!(
+            intersect(&[(0, 4), (5, 9), (12, 20)], &[(3, 6), (8, 15)]),
+            [(3, 4), (5, 6), (8, 9), (12, 15)]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Amount of bytes read from the beginning of the file to compute the estimates.
+const SAMPLE_SIZE: u64 = 1_000_000;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlineStyle {
+    Lf,
+    CrLf,
+    Mixed,
+    /// No newline was found in the sample.
+    Unknown,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Lz4,
+    Zip,
+}
+
+/// Ratio of invalid UTF-8 bytes from which a sample is considered binary.
+const MAX_INVALID_UTF8_RATIO: f64 = 0.1;
+
+/// Quick overview of a file, computed from a sample of its first bytes.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Inspection {
+    pub size: u64,
+    pub sampled_bytes: u64,
+    pub sampled_lines: u64,
+    pub estimated_lines: u64,
+    pub average_line_length: Option<f64>,
+    pub longest_sampled_line: usize,
+    pub newline: NewlineStyle,
+    pub bom: Option<Bom>,
+    pub compression: Option<Compression>,
+    pub ends_with_newline: bool,
+}
+
+impl Inspection {
+    /// Some advice about the flags to use with this file.
+    pub fn hints(&self, chunk_size: usize) -> Vec<String> {
+        let mut hints = Vec::new();
+        if let Some(compression) = self.compression {
+            hints.push(format!(
+                "The file seems to be {:?} compressed. Decompress it before analyzing it.",
+                compression
+            ));
+        }
+        if let Some(bom) = self.bom {
+            hints.push(format!(
+                "The file starts with a {:?} BOM. The first line will be counted as an error.",
+                bom
+            ));
+        }
+        if self.longest_sampled_line >= chunk_size {
+            hints.push(format!(
+                "Some lines are longer than the chunk size ({} bytes). Increase --chunk-size to use the chunked strategy.",
+                chunk_size
+            ));
+        }
+        if self.newline == NewlineStyle::CrLf || self.newline == NewlineStyle::Mixed {
+            hints.push(
+                "The file uses CRLF line endings. Use --count-newlines false if the terminators shouldn't be counted."
+                    .to_string(),
+            );
+        }
+        hints
+    }
+}
+
+pub fn inspect(path: &Path) -> io::Result<Inspection> {
+    let mut f = File::open(path)?;
+    let size = f.metadata()?.len();
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE.min(size) as usize);
+    f.by_ref().take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+    let last_byte = if size > 0 {
+        let mut last = [0; 1];
+        f.seek(SeekFrom::End(-1))?;
+        f.read_exact(&mut last)?;
+        Some(last[0])
+    } else {
+        None
+    };
+    Ok(inspect_sample(&sample, size, last_byte))
+}
+
+/// Computes the inspection of a file of `size` bytes from the sample of its first bytes.
+pub fn inspect_sample(sample: &[u8], size: u64, last_byte: Option<u8>) -> Inspection {
+    let mut lf = 0;
+    let mut crlf = 0;
+    let mut longest_sampled_line = 0;
+    let mut line_start = 0;
+    for (i, c) in sample.iter().enumerate() {
+        if *c == b'\n' {
+            if i > 0 && sample[i - 1] == b'\r' {
+                crlf += 1;
+            } else {
+                lf += 1;
+            }
+            longest_sampled_line = longest_sampled_line.max(i + 1 - line_start);
+            line_start = i + 1;
+        }
+    }
+    // an unfinished line is still a lower bound of the longest one
+    longest_sampled_line = longest_sampled_line.max(sample.len() - line_start);
+
+    let sampled_lines = (lf + crlf) as u64;
+    let average_line_length = if sampled_lines > 0 {
+        Some(line_start as f64 / sampled_lines as f64)
+    } else {
+        None
+    };
+    let estimated_lines = match average_line_length {
+        Some(average) => (size as f64 / average).round() as u64,
+        None if size > 0 => 1,
+        None => 0,
+    };
+    let newline = match (lf, crlf) {
+        (0, 0) => NewlineStyle::Unknown,
+        (_, 0) => NewlineStyle::Lf,
+        (0, _) => NewlineStyle::CrLf,
+        _ => NewlineStyle::Mixed,
+    };
+
+    Inspection {
+        size,
+        sampled_bytes: sample.len() as u64,
+        sampled_lines,
+        estimated_lines,
+        average_line_length,
+        longest_sampled_line,
+        newline,
+        bom: detect_bom(sample),
+        compression: detect_compression(sample),
+        ends_with_newline: last_byte == Some(b'\n'),
+    }
+}
+
+fn detect_bom(sample: &[u8]) -> Option<Bom> {
+    match sample {
+        [0xEF, 0xBB, 0xBF, ..] => Some(Bom::Utf8),
+        [0xFF, 0xFE, ..] => Some(Bom::Utf16Le),
+        [0xFE, 0xFF, ..] => Some(Bom::Utf16Be),
+        _ => None,
+    }
+}
+
+/// Whether the sample looks like binary content rather than text: it's compressed,
+/// it has null bytes or, when `check_utf8` is set, too many invalid UTF-8 sequences.
+/// Returns the compression if it was detected.
+pub(crate) fn detect_binary(sample: &[u8], check_utf8: bool) -> Option<Option<Compression>> {
+    if let Some(compression) = detect_compression(sample) {
+        return Some(Some(compression));
+    }
+    if sample.contains(&0) {
+        return Some(None);
+    }
+    if check_utf8
+        && invalid_utf8_bytes(sample) as f64 > sample.len() as f64 * MAX_INVALID_UTF8_RATIO
+    {
+        return Some(None);
+    }
+    None
+}
+
+/// Bytes of the invalid UTF-8 sequences. A sequence cut at the end of the sample is not invalid.
+fn invalid_utf8_bytes(mut sample: &[u8]) -> usize {
+    let mut invalid = 0;
+    while let Err(e) = std::str::from_utf8(sample) {
+        match e.error_len() {
+            Some(len) => {
+                invalid += len;
+                sample = &sample[e.valid_up_to() + len..];
+            }
+            None => break,
+        }
+    }
+    invalid
+}
+
+fn detect_compression(sample: &[u8]) -> Option<Compression> {
+    match sample {
+        [0x1F, 0x8B, ..] => Some(Compression::Gzip),
+        [0x28, 0xB5, 0x2F, 0xFD, ..] => Some(Compression::Zstd),
+        [b'B', b'Z', b'h', ..] => Some(Compression::Bzip2),
+        [0xFD, b'7', b'z', b'X', b'Z', 0x00, ..] => Some(Compression::Xz),
+        [0x04, 0x22, 0x4D, 0x18, ..] => Some(Compression::Lz4),
+        [b'P', b'K', 0x03, 0x04, ..] => Some(Compression::Zip),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspect_sample_detects_the_newline_style() {
+        let lf = inspect_sample(b"{}\n{}\n", 6, Some(b'\n'));
+        assert_eq!(lf.newline, NewlineStyle::Lf);
+        let crlf = inspect_sample(b"{}\r\n{}\r\n", 8, Some(b'\n'));
+        assert_eq!(crlf.newline, NewlineStyle::CrLf);
+        let mixed = inspect_sample(b"{}\r\n{}\n", 7, Some(b'\n'));
+        assert_eq!(mixed.newline, NewlineStyle::Mixed);
+        let unknown = inspect_sample(b"{}", 2, Some(b'}'));
+        assert_eq!(unknown.newline, NewlineStyle::Unknown);
+        assert!(!unknown.ends_with_newline);
+    }
+
+    #[test]
+    fn inspect_sample_extrapolates_the_lines_to_the_whole_file() {
+        let inspection = inspect_sample(b"{\"type\":\"A\"}\n{\"type\":\"B\"}\n", 1300, Some(b'\n'));
+        assert_eq!(inspection.sampled_lines, 2);
+        assert_eq!(inspection.average_line_length, Some(13.0));
+        assert_eq!(inspection.estimated_lines, 100);
+        assert_eq!(inspection.longest_sampled_line, 13);
+    }
+
+    #[test]
+    fn detect_binary_spots_compressed_and_binary_samples() {
+        assert_eq!(
+            detect_binary(&[0x1F, 0x8B, 0x08, 0x00], true),
+            Some(Some(Compression::Gzip))
+        );
+        assert_eq!(detect_binary(b"{\"type\":\"A\"}\n\0\x01", true), Some(None));
+        let mostly_invalid = [0xC3, 0x28, 0xFF, b'{', b'}'];
+        assert_eq!(detect_binary(&mostly_invalid, true), Some(None));
+        assert_eq!(detect_binary(&mostly_invalid, false), None);
+        // a char cut at the end of the sample is fine
+        assert_eq!(
+            detect_binary("{\"type\":\"é\"}".as_bytes().split_at(12).0, true),
+            None
+        );
+        assert_eq!(detect_binary(b"{\"type\":\"A\"}\n", true), None);
+    }
+
+    #[test]
+    fn inspect_sample_detects_boms_and_compression() {
+        let bom = inspect_sample(b"\xEF\xBB\xBF{}\n", 6, Some(b'\n'));
+        assert_eq!(bom.bom, Some(Bom::Utf8));
+        assert_eq!(bom.hints(1_000).len(), 1);
+        let gzip = inspect_sample(b"\x1F\x8B\x08\x00", 4, Some(0));
+        assert_eq!(gzip.compression, Some(Compression::Gzip));
+        assert_eq!(gzip.hints(1_000).len(), 1);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::command::analyze_command;
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use std::process::Command;
+
+/// Analyzes the entries of the systemd journal, as printed by `journalctl -o json`.
+/// `args` are passed to `journalctl` to filter the entries (`-u nginx`, `--since today`...).
+/// Journal fields like `_SYSTEMD_UNIT` or `PRIORITY` are good candidates to group by.
+pub fn analyze_journal(args: &[String], options: &AnalyzeOptions) -> Result<Report, AnalyzeError> {
+    let mut command = Command::new("journalctl");
+    command.args(["-o", "json", "--no-pager"]).args(args);
+    analyze_command(command, options)
+}
@@ -0,0 +1,87 @@
+use crate::command::analyze_command;
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use crate::sources::SourceReports;
+use std::{io, process::Command};
+
+/// Pods of a namespace matching a label selector, as in `--k8s default/app=api`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PodSelector {
+    pub namespace: String,
+    pub labels: String,
+}
+
+impl std::str::FromStr for PodSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((namespace, labels)) if !namespace.is_empty() && !labels.is_empty() => {
+                Ok(PodSelector {
+                    namespace: namespace.to_string(),
+                    labels: labels.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "invalid pod selector `{}`, expected namespace/label-selector",
+                s
+            )),
+        }
+    }
+}
+
+/// Analyzes the logs of every pod matching the selector, as printed by `kubectl logs`,
+/// using the current kubectl context. Returns the report of every pod by name.
+pub fn analyze_pods(
+    selector: &PodSelector,
+    options: &AnalyzeOptions,
+) -> Result<SourceReports, AnalyzeError> {
+    let output = Command::new("kubectl")
+        .args(["get", "pods", "-o", "name"])
+        .args(["-n", &selector.namespace, "-l", &selector.labels])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = format!("kubectl exited with {}: {}", output.status, stderr.trim());
+        return Err(io::Error::other(message).into());
+    }
+    let mut reports = SourceReports::new();
+    for pod in String::from_utf8_lossy(&output.stdout).lines() {
+        let name = pod.strip_prefix("pod/").unwrap_or(pod);
+        if let Some(on_source) = &options.on_source {
+            (on_source.0)(name);
+        }
+        let report = analyze_pod(&selector.namespace, pod, options)?;
+        reports.insert(name, report);
+    }
+    Ok(reports)
+}
+
+fn analyze_pod(
+    namespace: &str,
+    pod: &str,
+    options: &AnalyzeOptions,
+) -> Result<Report, AnalyzeError> {
+    let mut command = Command::new("kubectl");
+    command.args(["logs", "--all-containers", "-n", namespace, pod]);
+    analyze_command(command, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pod_selector_splits_the_namespace_and_the_labels() {
+        assert_eq!(
+            "prod/app=api,tier in (web)".parse(),
+            Ok(PodSelector {
+                namespace: "prod".to_string(),
+                labels: "app=api,tier in (web)".to_string(),
+            })
+        );
+        assert!("app=api".parse::<PodSelector>().is_err());
+        assert!("prod/".parse::<PodSelector>().is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Deserialize;
+use std::{collections::HashMap, iter::FromIterator};
+
+/// Replacements of the column headers and the category types (like `ERROR`) of the printed
+/// results, so they can be shared with people reading another language. The keys of the
+/// machine readable outputs are never replaced.
+///
+/// It deserializes from a table like `{ error = "ERREUR", total_count = "NOMBRE TOTAL" }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "HashMap<String, String>")]
+pub struct Labels {
+    /// Replacements by the label as printed, like `TOTAL COUNT`.
+    labels: HashMap<String, String>,
+}
+
+impl Labels {
+    /// Replaces the label, named as printed (`TOTAL COUNT`) or in snake case (`total_count`).
+    pub fn insert(&mut self, label: &str, replacement: impl Into<String>) {
+        self.labels.insert(normalize(label), replacement.into());
+    }
+
+    /// The replacement of the label, if it has one.
+    pub fn get(&self, label: &str) -> Option<&str> {
+        self.labels.get(label).map(String::as_str)
+    }
+
+    /// The label as printed: its replacement, or itself.
+    pub fn label<'a>(&'a self, label: &'a str) -> &'a str {
+        self.get(label).unwrap_or(label)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+impl<L: AsRef<str>, R: Into<String>> FromIterator<(L, R)> for Labels {
+    fn from_iter<I: IntoIterator<Item = (L, R)>>(iter: I) -> Self {
+        let mut labels = Labels::default();
+        for (label, replacement) in iter {
+            labels.insert(label.as_ref(), replacement);
+        }
+        labels
+    }
+}
+
+impl From<HashMap<String, String>> for Labels {
+    fn from(labels: HashMap<String, String>) -> Self {
+        labels.into_iter().collect()
+    }
+}
+
+/// `total_count` and `total-count` name the `TOTAL COUNT` label.
+fn normalize(label: &str) -> String {
+    label.trim().replace(['_', '-'], " ").to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_are_named_as_printed_or_in_snake_case() {
+        let labels: Labels = [
+            ("error", "ERREUR"),
+            ("total_count", "NOMBRE TOTAL"),
+            ("TOTAL BYTES", "OCTETS"),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        assert_eq!(labels.label("ERROR"), "ERREUR");
+        assert_eq!(labels.label("TOTAL COUNT"), "NOMBRE TOTAL");
+        assert_eq!(labels.label("TOTAL BYTES"), "OCTETS");
+        assert_eq!(labels.label("TYPE"), "TYPE");
+        // types coming from the data are only replaced when they match the label exactly
+        assert_eq!(labels.get("error"), None);
+    }
+}
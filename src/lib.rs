@@ -1,5 +1,238 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod advice;
+#[cfg(feature = "std")]
+mod aggregator;
+#[cfg(feature = "azure")]
+mod azure;
+#[cfg(feature = "std")]
+mod chart;
+#[cfg(feature = "std")]
+mod chunk_stats;
+#[cfg(any(
+    feature = "journal",
+    feature = "k8s",
+    feature = "gcs",
+    feature = "azure"
+))]
+mod command;
+#[cfg(feature = "std")]
+mod compressibility;
+#[cfg(feature = "std")]
+mod condition;
+mod core;
+#[cfg(feature = "corpus")]
+mod corpus;
+#[cfg(feature = "std")]
+mod cost;
+#[cfg(feature = "polars")]
+mod dataframe;
+#[cfg(feature = "std")]
+mod direct;
+#[cfg(feature = "docker")]
+mod docker;
+#[cfg(feature = "std")]
+mod durations;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod error_report;
+#[cfg(feature = "std")]
+mod estimate;
+#[cfg(feature = "raw")]
+mod extract;
+#[cfg(feature = "std")]
+mod format;
+#[cfg(feature = "gcs")]
+mod gcs;
+#[cfg(feature = "std")]
+mod grep;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "std")]
+mod html;
+#[cfg(feature = "std")]
+mod index;
+#[cfg(feature = "std")]
+mod inspect;
+#[cfg(feature = "journal")]
+mod journal;
+#[cfg(feature = "k8s")]
+mod k8s;
+#[cfg(feature = "std")]
+mod labels;
+#[cfg(feature = "std")]
 mod models;
+#[cfg(feature = "notify")]
+mod notify;
+#[cfg(feature = "std")]
+mod options;
+#[cfg(feature = "otlp")]
+mod otlp;
+#[cfg(feature = "std")]
+mod parser;
+#[cfg(feature = "std")]
+mod partials;
+#[cfg(feature = "std")]
+mod pivot;
+#[cfg(feature = "std")]
 mod printer;
+#[cfg(feature = "std")]
+mod projection;
+#[cfg(feature = "std")]
+mod provenance;
+#[cfg(feature = "std")]
 mod reader;
+#[cfg(feature = "std")]
+mod records;
+#[cfg(feature = "std")]
+mod repl;
+#[cfg(feature = "std")]
+mod replay;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod schema;
+#[cfg(feature = "std")]
+mod self_log;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "std")]
+mod sort;
+#[cfg(feature = "std")]
+mod sources;
+#[cfg(feature = "std")]
+mod split;
+#[cfg(feature = "std")]
+mod sql;
+#[cfg(feature = "std")]
+mod template;
+#[cfg(feature = "std")]
+mod throttle;
+#[cfg(feature = "std")]
+mod time_range;
+#[cfg(feature = "tz")]
+mod time_zone;
+#[cfg(feature = "std")]
+mod tree;
+#[cfg(feature = "std")]
+mod trend;
 
-pub use reader::start;
+pub use self::core::{classify, DurationCounter, TypeCounts, TypeLineCounter, ERROR_TYPE};
+#[cfg(feature = "std")]
+pub use aggregator::{Aggregator, AnyAggregator, Distinct, Quantile, Record, Sum};
+#[cfg(feature = "azure")]
+pub use azure::{analyze_azure, AzureBlob};
+#[cfg(feature = "std")]
+pub use chart::{bytes_chart, write_bytes_chart};
+#[cfg(feature = "std")]
+pub use chunk_stats::{ChunkStats, ChunkSummary, ChunkTimings, Distribution};
+#[cfg(feature = "std")]
+pub use compressibility::{CompressionEstimate, CompressionSampler, TypeCompression};
+#[cfg(feature = "std")]
+pub use condition::{
+    parse_bytes_limit, parse_count_limit, Comparison, Condition, Metric, Operator,
+};
+#[cfg(feature = "corpus")]
+pub use corpus::{Corpus, LineSize};
+#[cfg(feature = "std")]
+pub use cost::CostRate;
+#[cfg(feature = "polars")]
+pub use dataframe::{lines_frame, query_polars, read_lines_frame, POLARS_TABLE};
+#[cfg(feature = "docker")]
+pub use docker::docker_log_path;
+#[cfg(feature = "std")]
+pub use error::AnalyzeError;
+#[cfg(feature = "std")]
+pub use error_report::{describe_error, error_category, ErrorEntry, ErrorReport, Rejects};
+#[cfg(feature = "std")]
+pub use estimate::{estimate, extrapolate, start_estimate, Estimate, TypeEstimate};
+#[cfg(feature = "raw")]
+pub use extract::ExtractRegex;
+#[cfg(feature = "std")]
+pub use format::{sniff, Format};
+#[cfg(feature = "gcs")]
+pub use gcs::analyze_gcs;
+#[cfg(feature = "std")]
+pub use grep::{grep, GrepStats};
+#[cfg(feature = "std")]
+pub use html::HtmlReport;
+#[cfg(feature = "std")]
+pub use index::{ByteRange, Index, IndexBlock, Indexer};
+#[cfg(feature = "std")]
+pub use inspect::{inspect, inspect_sample, Bom, Compression, Inspection, NewlineStyle};
+#[cfg(feature = "journal")]
+pub use journal::analyze_journal;
+#[cfg(feature = "k8s")]
+pub use k8s::{analyze_pods, PodSelector};
+#[cfg(feature = "std")]
+pub use labels::Labels;
+#[cfg(feature = "std")]
+pub use models::{
+    Aggregates, ByteCheck, CompressionStats, LinePosition, Outlier, Outliers, Report, ReportHasher,
+    TypeLineResults, PREVIEW_CHARS,
+};
+#[cfg(feature = "notify")]
+pub use notify::notify_webhook;
+#[cfg(feature = "std")]
+pub use options::{
+    AnalyzeOptions, AnalyzeOptionsBuilder, CancellationToken, ChunkCallback, ChunkSizeCheck,
+    ErrorCallback, ExplodeBytes, InvalidUtf8, KeyHasher, LineCallback, ProgressCallback,
+    SourceCallback, Strategy, WorkerPanic,
+};
+#[cfg(feature = "otlp")]
+pub use otlp::export_otlp;
+#[cfg(feature = "std")]
+pub use partials::{analyze_partials, analyze_reader_partials, PartialReports};
+#[cfg(feature = "std")]
+pub use pivot::{Pivot, PivotTable, PivotValues};
+/// The Polars crate the frames of `lines_frame` come from, to build expressions over them.
+#[cfg(feature = "polars")]
+pub use polars;
+#[cfg(feature = "std")]
+pub use printer::{
+    print_chunk_summary, print_compression_estimate, print_estimate, print_inspection, print_pivot,
+    print_query_result, print_table, print_trend, round_half_even, snapshot, Order, Output,
+    PrintOptions,
+};
+#[cfg(feature = "std")]
+pub use provenance::Provenance;
+#[cfg(feature = "std")]
+pub use reader::{
+    analyze, analyze_files, analyze_reader, analyze_reader_with_hasher, start, start_with,
+};
+#[cfg(feature = "std")]
+pub use records::{head, tail};
+#[cfg(feature = "std")]
+pub use repl::{Ranking, Repl, ReplCommand};
+#[cfg(feature = "std")]
+pub use replay::{parse_speed, Replay, ReplayStats, TimeUnit};
+#[cfg(feature = "std")]
+pub use retry::{parse_duration, RetryingReader};
+#[cfg(feature = "std")]
+pub use schema::{SchemaVersion, REPORT_VERSION};
+#[cfg(feature = "std")]
+pub use self_log::SelfLog;
+#[cfg(feature = "serve")]
+pub use serve::serve;
+#[cfg(feature = "std")]
+pub use sort::{ExternalSort, SortStats};
+#[cfg(feature = "std")]
+pub use sources::{SourceEntry, SourceReports};
+#[cfg(feature = "std")]
+pub use split::{Split, SplitStrategy};
+#[cfg(feature = "std")]
+pub use sql::{Predicate, Query, QueryResult};
+#[cfg(feature = "std")]
+pub use template::Template;
+#[cfg(feature = "std")]
+pub use throttle::{parse_throughput, ThrottledReader};
+#[cfg(feature = "std")]
+pub use time_range::{parse_timestamp, TimeRange};
+#[cfg(feature = "tz")]
+pub use time_zone::TimeZone;
+#[cfg(feature = "std")]
+pub use trend::{read_history, sparkline, HistoryEntry, Trend, TypeTrend};
@@ -1,29 +1,893 @@
-use serde::Deserialize;
-use std::{borrow::Cow, collections::HashMap};
+use crate::aggregator::{Aggregator, Record};
+pub use crate::core::{DurationCounter, TypeLineCounter};
+use crate::format::Format;
+use crate::index::{coalesce, ByteRange};
+use crate::inspect::Compression;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    cmp,
+    cmp::Reverse,
+    collections::{
+        hash_map::{Entry, RandomState},
+        BTreeMap, BinaryHeap, HashMap, HashSet,
+    },
+    hash::BuildHasher,
+    ops::{Add, AddAssign},
+};
 
-pub type TypeLineResults<'a> = HashMap<Cow<'a, str>, TypeLineCounter>;
+pub type TypeLineResults<'a, S = RandomState> = HashMap<Cow<'a, str>, TypeLineCounter, S>;
+/// Aggregators of a type by the name they were registered with.
+pub type Aggregates = BTreeMap<String, Box<dyn Aggregator>>;
 
-#[derive(Deserialize, Debug)]
-pub struct TypeLine {
-    #[serde(rename(deserialize = "type"))]
-    pub linetype: String,
+/// Hashers a report can count the types with. With a handful of types, non cryptographic
+/// hashers like FxHash or aHash are faster than the default SipHash.
+pub trait ReportHasher: BuildHasher + Default + Clone + Send + 'static {}
+
+impl<S: BuildHasher + Default + Clone + Send + 'static> ReportHasher for S {}
+
+/// Where a line starts in the input.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LinePosition {
+    pub line_number: usize,
+    /// Byte offset of the beginning of the line.
+    pub offset: u64,
+}
+
+/// Characters of a line kept to preview it.
+pub const PREVIEW_CHARS: usize = 80;
+
+/// One of the longest lines of the input.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Outlier {
+    /// Bytes of the line, without its terminator.
+    pub length: usize,
+    #[serde(rename = "type")]
+    pub key: Cow<'static, str>,
+    #[serde(flatten)]
+    pub position: LinePosition,
+    /// The first characters of the line.
+    pub preview: String,
+}
+
+impl Outlier {
+    /// Longer lines go last and, between lines of the same length, the earlier ones do.
+    fn rank(&self) -> (usize, Reverse<LinePosition>) {
+        (self.length, Reverse(self.position))
+    }
+}
+
+impl Ord for Outlier {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.rank()
+            .cmp(&other.rank())
+            .then_with(|| self.key.cmp(&other.key))
+    }
+}
+
+impl PartialOrd for Outlier {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The longest lines of the input, up to `max` of them, kept in a min-heap so the shortest
+/// one is dropped as soon as a longer line comes.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(from = "Vec<Outlier>", into = "Vec<Outlier>")]
+pub struct Outliers {
+    max: usize,
+    heap: BinaryHeap<Reverse<Outlier>>,
+}
+
+impl Outliers {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            heap: BinaryHeap::with_capacity(max + 1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Keeps the line if it's among the `max` longest ones. A line already kept at the same
+    /// position, as the elements of an exploded line, is only replaced by a longer measure of it.
+    pub(crate) fn add(
+        &mut self,
+        max: usize,
+        key: &str,
+        length: usize,
+        line: &[u8],
+        position: LinePosition,
+    ) {
+        self.max = max;
+        if max == 0 {
+            return;
+        }
+        if self.heap.len() == self.max {
+            let shortest = &self.heap.peek().expect("The heap is full").0;
+            if (length, Reverse(position)) <= shortest.rank() {
+                return;
+            }
+        }
+        match self.heap.iter().find(|kept| kept.0.position == position) {
+            Some(kept) if kept.0.length >= length => return,
+            Some(_) => self.heap.retain(|kept| kept.0.position != position),
+            None => {}
+        }
+        self.push(Outlier {
+            length,
+            key: Cow::Owned(key.to_string()),
+            position,
+            preview: preview(line),
+        });
+    }
+
+    fn push(&mut self, outlier: Outlier) {
+        self.heap.push(Reverse(outlier));
+        if self.heap.len() > self.max {
+            self.heap.pop();
+        }
+    }
+
+    /// The lines kept, the longest first.
+    pub fn sorted(&self) -> Vec<&Outlier> {
+        let mut sorted: Vec<_> = self.heap.iter().map(|kept| &kept.0).collect();
+        sorted.sort_by(|a, b| b.cmp(a));
+        sorted
+    }
+
+    pub fn merge(&mut self, other: Outliers) {
+        self.max = self.max.max(other.max);
+        for outlier in other.heap {
+            self.push(outlier.0);
+        }
+    }
+
+    fn rename(self, rename: impl Fn(Cow<'static, str>) -> Cow<'static, str>) -> Outliers {
+        Outliers {
+            max: self.max,
+            heap: self
+                .heap
+                .into_iter()
+                .map(|Reverse(outlier)| {
+                    Reverse(Outlier {
+                        key: rename(outlier.key),
+                        ..outlier
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PartialEq for Outliers {
+    fn eq(&self, other: &Self) -> bool {
+        self.sorted() == other.sorted()
+    }
+}
+
+impl From<Vec<Outlier>> for Outliers {
+    fn from(outliers: Vec<Outlier>) -> Self {
+        let mut kept = Outliers::new(outliers.len());
+        for outlier in outliers {
+            kept.push(outlier);
+        }
+        kept
+    }
+}
+
+impl From<Outliers> for Vec<Outlier> {
+    fn from(outliers: Outliers) -> Self {
+        outliers.sorted().into_iter().cloned().collect()
+    }
+}
+
+/// The first characters of the line, without its terminator, followed by `...` when it's cut.
+fn preview(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    // a character takes up to 4 bytes, so that's enough to fill the preview
+    let head = &line[..line.len().min(PREVIEW_CHARS * 4)];
+    let text = String::from_utf8_lossy(head);
+    let mut chars = text.chars();
+    let mut preview: String = chars.by_ref().take(PREVIEW_CHARS).collect();
+    if chars.next().is_some() || head.len() < line.len() {
+        preview.push_str("...");
+    }
+    preview
+}
+
+/// Aggregated results of an analysis.
+/// Reports can be combined with `merge` (or `+`) so partial results coming
+/// from different chunks, files or runs end up in a single report.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(bound(serialize = "S: BuildHasher", deserialize = "S: BuildHasher + Default"))]
+pub struct Report<S = RandomState> {
+    #[serde(serialize_with = "sorted")]
+    pub types: TypeLineResults<'static, S>,
+    /// Lines whose trailing bytes were ignored and how many bytes those were.
+    #[serde(default)]
+    pub trailing_garbage: TypeLineCounter,
+    /// Format of the input, when it was sniffed and it wasn't JSON lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
+    /// Bytes appended to the file while it was analyzed, so the results reflect a moving target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_growth: Option<u64>,
+    /// Bytes read and what they decompressed to, when the input was compressed.
+    /// The bytes of the types are always the decompressed ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionStats>,
+    /// Lines identical to an earlier line of the same type, by type.
+    /// Only counted when the analysis looks for exact duplicates.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub duplicates: TypeLineResults<'static>,
+    /// Digests of the distinct lines of every type and their bytes, so duplicates are found
+    /// across the partial reports of an analysis. They're dropped once it finishes.
+    #[serde(skip)]
+    pub(crate) digests: HashMap<Cow<'static, str>, HashMap<u64, usize>>,
+    /// Position of the first line of every type. Only kept when the analysis tracks it.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub first_seen: HashMap<Cow<'static, str>, LinePosition>,
+    /// Position of the last line of every type. Only kept when the analysis tracks it.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub last_seen: HashMap<Cow<'static, str>, LinePosition>,
+    /// Durations of the lines of every type. Only summed when the analysis has a duration field.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub durations: HashMap<Cow<'static, str>, DurationCounter>,
+    /// How the bytes read add up against the bytes of the lines. Only kept when the analysis checks it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_check: Option<ByteCheck>,
+    /// Statistics of every type computed by the aggregators of the analysis, serialized as
+    /// what they report. Reports read back from JSON don't have them.
+    #[serde(
+        default,
+        skip_deserializing,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "reported"
+    )]
+    pub aggregates: HashMap<Cow<'static, str>, Aggregates>,
+    /// The longest lines, longest first. Only kept when the analysis looks for them.
+    #[serde(default, skip_serializing_if = "Outliers::is_empty")]
+    pub outliers: Outliers,
+}
+
+/// Bytes read from an input compared with the bytes of the lines that made it into the report.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ByteCheck {
+    /// Bytes read from the input, once decompressed.
+    pub read_bytes: u64,
+    /// Bytes of the lines that made it into the report, terminators included.
+    pub line_bytes: u64,
+    /// Bytes of the `\n` and `\r\n` terminators of those lines.
+    pub terminator_bytes: u64,
+    /// Bytes counted under the types.
+    pub counted_bytes: u64,
+    /// Ranges of bytes read whose lines never made it into the report.
+    pub missing: Vec<ByteRange>,
+    /// Ranges of the lines that made it into the report. They're dropped once the analysis finishes.
+    #[serde(skip)]
+    pub(crate) spans: Vec<ByteRange>,
+}
+
+impl ByteCheck {
+    /// Records the lines found in `length` bytes from `offset`.
+    pub(crate) fn add_span(&mut self, offset: u64, length: u64, terminator_bytes: u64) {
+        self.line_bytes += length;
+        self.terminator_bytes += terminator_bytes;
+        match self.spans.last_mut() {
+            Some(last) if last.1 == offset => last.1 += length,
+            _ => self.spans.push((offset, offset + length)),
+        }
+    }
+
+    /// Closes the check once `read_bytes` were read, finding the ranges no line covered.
+    pub(crate) fn finish(&mut self, read_bytes: u64, counted_bytes: u64) {
+        self.read_bytes = read_bytes;
+        self.counted_bytes = counted_bytes;
+        let mut next = 0;
+        for (start, end) in coalesce(std::mem::take(&mut self.spans)) {
+            if start > next {
+                self.missing.push((next, start));
+            }
+            next = next.max(end);
+        }
+        if read_bytes > next {
+            self.missing.push((next, read_bytes));
+        }
+    }
+
+    /// Bytes of the lines expected under the types, leaving their terminators out unless
+    /// they're counted.
+    pub fn expected_bytes(&self, count_newlines: bool) -> u64 {
+        if count_newlines {
+            self.line_bytes
+        } else {
+            self.line_bytes - self.terminator_bytes
+        }
+    }
+
+    /// Whether every byte read belongs to a line of the report.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.read_bytes == self.line_bytes
+    }
+
+    pub fn merge(&mut self, other: ByteCheck) {
+        self.read_bytes += other.read_bytes;
+        self.line_bytes += other.line_bytes;
+        self.terminator_bytes += other.terminator_bytes;
+        self.counted_bytes += other.counted_bytes;
+        self.missing.extend(other.missing);
+        self.spans.extend(other.spans);
+    }
+}
+
+/// Sizes of a compressed input, on disk and once decompressed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub compression: Compression,
+    pub compressed_bytes: u64,
+    pub decompressed_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Decompressed bytes per compressed byte.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.decompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    pub fn merge(&mut self, other: &CompressionStats) {
+        self.compressed_bytes += other.compressed_bytes;
+        self.decompressed_bytes += other.decompressed_bytes;
+    }
+}
+
+/// Serializes a map with its keys sorted, so the same results always give the same bytes.
+fn sorted<K: Ord + Serialize, V: Serialize, H, T: Serializer>(
+    map: &HashMap<K, V, H>,
+    serializer: T,
+) -> Result<T::Ok, T::Error> {
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
 }
 
-#[derive(Debug, Default)]
-pub struct TypeLineCounter {
-    pub count: usize,
-    pub bytes: usize,
+/// Serializes the aggregates as what they report, with the types sorted.
+fn reported<T: Serializer>(
+    aggregates: &HashMap<Cow<'static, str>, Aggregates>,
+    serializer: T,
+) -> Result<T::Ok, T::Error> {
+    serializer.collect_map(
+        aggregates
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(key, aggregates)| {
+                let reports: BTreeMap<_, _> = aggregates
+                    .iter()
+                    .map(|(name, aggregator)| (name, aggregator.report()))
+                    .collect();
+                (key, reports)
+            }),
+    )
 }
 
-impl TypeLineCounter {
-    pub fn add_bytes(&mut self, bytes: usize) {
-        self.count += 1;
-        self.bytes += bytes;
+/// Whether the aggregators report the same for every type.
+fn same_aggregates(
+    a: &HashMap<Cow<'static, str>, Aggregates>,
+    b: &HashMap<Cow<'static, str>, Aggregates>,
+) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(key, aggregates)| {
+            b.get(key).is_some_and(|other| {
+                aggregates.len() == other.len()
+                    && aggregates
+                        .iter()
+                        .zip(other)
+                        .all(|((name, a), (other_name, b))| {
+                            name == other_name && a.report() == b.report()
+                        })
+            })
+        })
+}
+
+/// Merges the aggregators of the same type and name.
+fn merge_aggregates(
+    aggregates: &mut HashMap<Cow<'static, str>, Aggregates>,
+    other: impl IntoIterator<Item = (Cow<'static, str>, Aggregates)>,
+) {
+    for (key, other) in other {
+        let aggregates = aggregates.entry(key).or_default();
+        for (name, aggregator) in other {
+            match aggregates.get_mut(&name) {
+                Some(kept) => kept.merge(aggregator.as_ref()),
+                None => {
+                    aggregates.insert(name, aggregator);
+                }
+            }
+        }
+    }
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
     }
 }
 
-#[derive(Debug)]
-pub struct IntermediateTypeLineCounter<'a> {
-    pub key: Cow<'a, str>,
-    pub bytes: usize,
+impl<S: BuildHasher> PartialEq for Report<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.types == other.types
+            && self.trailing_garbage == other.trailing_garbage
+            && self.format == other.format
+            && self.file_growth == other.file_growth
+            && self.compression == other.compression
+            && self.duplicates == other.duplicates
+            && self.digests == other.digests
+            && self.first_seen == other.first_seen
+            && self.last_seen == other.last_seen
+            && self.durations == other.durations
+            && self.byte_check == other.byte_check
+            && same_aggregates(&self.aggregates, &other.aggregates)
+            && self.outliers == other.outliers
+    }
+}
+
+impl<S: BuildHasher + Default> Report<S> {
+    /// The same report counting the types with another hasher.
+    pub fn with_hasher<T: BuildHasher + Default>(self) -> Report<T> {
+        Report {
+            types: self.types.into_iter().collect(),
+            trailing_garbage: self.trailing_garbage,
+            format: self.format,
+            file_growth: self.file_growth,
+            compression: self.compression,
+            duplicates: self.duplicates,
+            digests: self.digests,
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            durations: self.durations,
+            byte_check: self.byte_check,
+            aggregates: self.aggregates,
+            outliers: self.outliers,
+        }
+    }
+
+    pub fn add_line(&mut self, key: Cow<'static, str>, bytes: usize) {
+        self.types.entry(key).or_default().add_bytes(bytes);
+    }
+
+    /// Counts the line as a duplicate if a line of the same type had the same digest.
+    pub(crate) fn add_digest(&mut self, key: &str, digest: u64, bytes: usize) {
+        let digests = match self.digests.get_mut(key) {
+            Some(digests) => digests,
+            None => self.digests.entry(Cow::Owned(key.to_string())).or_default(),
+        };
+        if digests.insert(digest, bytes).is_some() {
+            self.duplicates
+                .entry(Cow::Owned(key.to_string()))
+                .or_default()
+                .add_bytes(bytes);
+        }
+    }
+
+    /// Keeps the position of the line as the last one of its type, and as the first one
+    /// if the type hadn't been seen.
+    pub(crate) fn add_position(&mut self, key: &str, position: LinePosition) {
+        match self.last_seen.get_mut(key) {
+            Some(last) => *last = position,
+            None => {
+                self.first_seen
+                    .insert(Cow::Owned(key.to_string()), position);
+                self.last_seen.insert(Cow::Owned(key.to_string()), position);
+            }
+        }
+    }
+
+    /// Adds the duration of a line to its type.
+    pub(crate) fn add_duration(&mut self, key: &str, seconds: f64) {
+        match self.durations.get_mut(key) {
+            Some(durations) => durations.add_seconds(seconds),
+            None => self
+                .durations
+                .entry(Cow::Owned(key.to_string()))
+                .or_default()
+                .add_seconds(seconds),
+        }
+    }
+
+    /// Hands the line to the aggregators of its type, cloning the registered ones
+    /// the first time the type is seen.
+    pub(crate) fn observe(
+        &mut self,
+        record: &Record,
+        registered: &[(String, Box<dyn Aggregator>)],
+    ) {
+        let aggregates = match self.aggregates.get_mut(record.key()) {
+            Some(aggregates) => aggregates,
+            None => self
+                .aggregates
+                .entry(Cow::Owned(record.key().to_string()))
+                .or_insert_with(|| registered.iter().cloned().collect()),
+        };
+        for aggregator in aggregates.values_mut() {
+            aggregator.observe(record);
+        }
+    }
+
+    /// Total lines identical to an earlier line and their bytes.
+    pub fn total_duplicates(&self) -> TypeLineCounter {
+        self.duplicates
+            .values()
+            .fold(TypeLineCounter::default(), |total, counter| {
+                total + *counter
+            })
+    }
+
+    pub fn merge(&mut self, other: Report<S>) {
+        for (key, counter) in other.types {
+            self.types.entry(key).or_default().merge(&counter);
+        }
+        for (key, counter) in other.duplicates {
+            self.duplicates.entry(key).or_default().merge(&counter);
+        }
+        for (key, durations) in other.durations {
+            self.durations.entry(key).or_default().merge(&durations);
+        }
+        merge_aggregates(&mut self.aggregates, other.aggregates);
+        self.outliers.merge(other.outliers);
+        keep_positions(&mut self.first_seen, other.first_seen, cmp::min);
+        keep_positions(&mut self.last_seen, other.last_seen, cmp::max);
+        for (key, digests) in other.digests {
+            match self.digests.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(digests);
+                }
+                Entry::Occupied(mut entry) => {
+                    let key = entry.key().clone();
+                    for (digest, bytes) in digests {
+                        if entry.get_mut().insert(digest, bytes).is_some() {
+                            self.duplicates
+                                .entry(key.clone())
+                                .or_default()
+                                .add_bytes(bytes);
+                        }
+                    }
+                }
+            }
+        }
+        self.trailing_garbage.merge(&other.trailing_garbage);
+        self.format = self.format.or(other.format);
+        self.file_growth = match (self.file_growth, other.file_growth) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        match (&mut self.compression, other.compression) {
+            (Some(compression), Some(other)) => compression.merge(&other),
+            (compression, other) => *compression = compression.or(other),
+        }
+        match (&mut self.byte_check, other.byte_check) {
+            (Some(check), Some(other)) => check.merge(other),
+            (check, other) => *check = check.take().or(other),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&TypeLineCounter> {
+        self.types.get(key)
+    }
+
+    /// Merges the types sharing their first `depth` levels, the levels being separated by `separator`.
+    /// Types with fewer levels are kept as they are. A depth of 0 changes nothing.
+    pub fn rollup(self, depth: usize, separator: &str) -> Report<S> {
+        if depth == 0 || separator.is_empty() {
+            return self;
+        }
+        self.rename(|key| rolled_key(key, depth, separator))
+    }
+
+    /// Counts as `other` the types that would take `into` past `max` types once merged with it,
+    /// keeping the new types with the most lines. `other` takes one of the places.
+    pub(crate) fn cap_types(self, into: &Report<S>, max: usize, other: &'static str) -> Report<S> {
+        let kept_types = into.len() - usize::from(into.get(other).is_some());
+        let room = max.saturating_sub(1).saturating_sub(kept_types);
+        let mut new_types: Vec<_> = self
+            .types
+            .iter()
+            .filter(|(key, _)| key.as_ref() != other && into.get(key).is_none())
+            .collect();
+        if new_types.len() <= room {
+            return self;
+        }
+        new_types.sort_by(|(a, a_counter), (b, b_counter)| {
+            b_counter.count.cmp(&a_counter.count).then_with(|| a.cmp(b))
+        });
+        let dropped: HashSet<String> = new_types
+            .into_iter()
+            .skip(room)
+            .map(|(key, _)| key.to_string())
+            .collect();
+        self.rename(|key| {
+            if dropped.contains(key.as_ref()) {
+                Cow::Borrowed(other)
+            } else {
+                key
+            }
+        })
+    }
+
+    /// Renames the types, merging the ones getting the same name.
+    fn rename(self, rename: impl Fn(Cow<'static, str>) -> Cow<'static, str>) -> Report<S> {
+        fn roll<S: BuildHasher + Default>(
+            results: TypeLineResults<'static, S>,
+            rename: &impl Fn(Cow<'static, str>) -> Cow<'static, str>,
+        ) -> TypeLineResults<'static, S> {
+            let mut rolled = TypeLineResults::default();
+            for (key, counter) in results {
+                rolled.entry(rename(key)).or_default().merge(&counter);
+            }
+            rolled
+        }
+        let roll_positions = |positions: HashMap<Cow<'static, str>, LinePosition>, pick| {
+            let mut rolled = HashMap::new();
+            let positions = positions
+                .into_iter()
+                .map(|(key, position)| (rename(key), position));
+            keep_positions(&mut rolled, positions, pick);
+            rolled
+        };
+        let mut durations: HashMap<_, DurationCounter> = HashMap::new();
+        for (key, counter) in self.durations {
+            durations.entry(rename(key)).or_default().merge(&counter);
+        }
+        let mut aggregates = HashMap::new();
+        merge_aggregates(
+            &mut aggregates,
+            self.aggregates
+                .into_iter()
+                .map(|(key, aggregates)| (rename(key), aggregates)),
+        );
+        let mut digests: HashMap<_, HashMap<u64, usize>> = HashMap::new();
+        for (key, lines) in self.digests {
+            digests.entry(rename(key)).or_default().extend(lines);
+        }
+        Report {
+            types: roll(self.types, &rename),
+            durations,
+            aggregates,
+            duplicates: roll(self.duplicates, &rename),
+            first_seen: roll_positions(self.first_seen, cmp::min),
+            last_seen: roll_positions(self.last_seen, cmp::max),
+            digests,
+            outliers: self.outliers.rename(&rename),
+            ..self
+        }
+    }
+}
+
+/// Adds the positions, picking the earliest or the latest one of the types already there.
+fn keep_positions(
+    positions: &mut HashMap<Cow<'static, str>, LinePosition>,
+    other: impl IntoIterator<Item = (Cow<'static, str>, LinePosition)>,
+    pick: fn(LinePosition, LinePosition) -> LinePosition,
+) {
+    for (key, position) in other {
+        let kept = positions.entry(key).or_insert(position);
+        *kept = pick(*kept, position);
+    }
+}
+
+/// The key cut at its `depth` level.
+fn rolled_key(key: Cow<'static, str>, depth: usize, separator: &str) -> Cow<'static, str> {
+    match key.match_indices(separator).nth(depth - 1) {
+        Some((end, _)) => Cow::Owned(key[..end].to_string()),
+        None => key,
+    }
+}
+
+impl<S: BuildHasher + Default> Add for Report<S> {
+    type Output = Report<S>;
+
+    fn add(mut self, other: Report<S>) -> Report<S> {
+        self.merge(other);
+        self
+    }
+}
+
+impl<S: BuildHasher + Default> AddAssign for Report<S> {
+    fn add_assign(&mut self, other: Report<S>) {
+        self.merge(other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    fn report(entries: &[(&'static str, usize, usize)]) -> Report {
+        let mut report = Report::new();
+        for (key, count, bytes) in entries {
+            report.types.insert(
+                Cow::Borrowed(*key),
+                TypeLineCounter {
+                    count: *count,
+                    bytes: *bytes,
+                },
+            );
+        }
+        report
+    }
+
+    #[test]
+    fn rollup_merges_the_types_sharing_their_first_levels() {
+        let types = report(&[
+            ("payment.card.authorized", 1, 10),
+            ("payment.card.declined", 2, 20),
+            ("payment.transfer", 3, 30),
+            ("payment", 4, 40),
+            ("ERROR", 5, 50),
+        ]);
+        assert_eq!(
+            types.clone().rollup(2, "."),
+            report(&[
+                ("payment.card", 3, 30),
+                ("payment.transfer", 3, 30),
+                ("payment", 4, 40),
+                ("ERROR", 5, 50),
+            ])
+        );
+        assert_eq!(
+            types.clone().rollup(1, "."),
+            report(&[("payment", 10, 100), ("ERROR", 5, 50)])
+        );
+        assert_eq!(types.clone().rollup(0, "."), types);
+        assert_eq!(
+            report(&[("a::b::c", 1, 10), ("a::b::d", 1, 10)]).rollup(2, "::"),
+            report(&[("a::b", 2, 20)])
+        );
+    }
+
+    #[test]
+    fn cap_types_counts_the_new_types_past_the_limit_as_other() {
+        let into = report(&[("A", 1, 10), ("OTHER", 1, 10)]);
+        let partial = report(&[("A", 1, 10), ("B", 1, 10), ("C", 3, 30), ("D", 1, 10)]);
+        assert_eq!(
+            partial.clone().cap_types(&into, 3, "OTHER"),
+            report(&[("A", 1, 10), ("C", 3, 30), ("OTHER", 2, 20)])
+        );
+        assert_eq!(partial.clone().cap_types(&into, 10, "OTHER"), partial);
+        assert_eq!(
+            partial.cap_types(&Report::new(), 1, "OTHER"),
+            report(&[("OTHER", 6, 60)])
+        );
+    }
+
+    #[test]
+    fn merge_adds_counters_of_shared_keys_and_keeps_the_rest() {
+        let mut a = report(&[("A", 1, 10), ("B", 2, 20)]);
+        let b = report(&[("B", 3, 30), ("C", 4, 40)]);
+        a.merge(b);
+        assert_eq!(a, report(&[("A", 1, 10), ("B", 5, 50), ("C", 4, 40)]));
+    }
+
+    #[test]
+    fn add_operators_behave_like_merge() {
+        let a = report(&[("A", 1, 10)]);
+        let b = report(&[("A", 2, 5), ("B", 1, 1)]);
+        let mut c = a.clone();
+        c += b.clone();
+        assert_eq!(a + b, c);
+        assert_eq!(
+            c.get("A"),
+            Some(&TypeLineCounter {
+                count: 3,
+                bytes: 15
+            })
+        );
+    }
+
+    #[test]
+    fn merge_finds_duplicates_across_reports() {
+        let mut a = Report::new();
+        a.add_digest("A", 1, 10);
+        a.add_digest("A", 1, 10);
+        a.add_digest("A", 2, 20);
+        let mut b = Report::new();
+        b.add_digest("A", 2, 20);
+        b.add_digest("A", 3, 30);
+        // same line, different type
+        b.add_digest("B", 1, 10);
+        a.merge(b);
+        assert_eq!(a.duplicates, report(&[("A", 2, 30)]).types);
+        assert_eq!(
+            a.total_duplicates(),
+            TypeLineCounter {
+                count: 2,
+                bytes: 30
+            }
+        );
+    }
+
+    #[test]
+    fn reports_can_change_their_hasher() {
+        let original = report(&[("A", 1, 10), ("B", 2, 20)]);
+        let mut other: Report<BuildHasherDefault<DefaultHasher>> = original.clone().with_hasher();
+        other.add_line(Cow::Borrowed("A"), 5);
+        assert_eq!(other.get("A").map(|c| c.count), Some(2));
+        let back: Report = other.with_hasher();
+        assert_eq!(back, original + report(&[("A", 1, 5)]));
+    }
+
+    #[test]
+    fn outliers_keep_the_longest_lines_across_merges() {
+        let position = |line_number| LinePosition {
+            line_number,
+            offset: line_number as u64 * 100,
+        };
+        let mut a = Report::new();
+        let mut b = Report::new();
+        let add = |report: &mut Report, line_number, line: &str| {
+            report
+                .outliers
+                .add(2, "A", line.len(), line.as_bytes(), position(line_number));
+        };
+        add(&mut a, 1, &"x".repeat(10));
+        add(&mut a, 2, &"x".repeat(50));
+        add(&mut b, 3, &"x".repeat(30));
+        add(&mut b, 4, "short");
+        a.merge(b);
+        let lengths: Vec<_> = a.outliers.sorted().iter().map(|o| o.length).collect();
+        assert_eq!(lengths, vec![50, 30]);
+        assert_eq!(a.outliers.sorted()[1].position, position(3));
+
+        let json = serde_json::to_string(&a).unwrap();
+        let restored: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.outliers, a.outliers);
+        assert_eq!(
+            preview("Ä".repeat(100).as_bytes()),
+            format!("{}...", "Ä".repeat(80))
+        );
+        assert_eq!(preview(b"short\r\n"), "short");
+    }
+
+    #[test]
+    fn report_survives_a_serde_round_trip() {
+        let original = report(&[("A", 1, 10), ("ERROR", 2, 2)]);
+        let json = serde_json::to_string(&original).unwrap();
+        let restored: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(original, restored);
+    }
 }
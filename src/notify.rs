@@ -0,0 +1,28 @@
+use crate::condition::Condition;
+use crate::models::Report;
+use serde_json::json;
+use std::io;
+
+/// Posts a message to a webhook when the condition is met by the report.
+/// The body is `{"text": "..."}`, as expected by Slack incoming webhooks and many others.
+/// Returns whether the notification was sent.
+pub fn notify_webhook(
+    url: &str,
+    condition: &Condition,
+    source: &str,
+    report: &Report,
+) -> io::Result<bool> {
+    if !condition.is_met(report) {
+        return Ok(false);
+    }
+    let counter = report.get(&condition.key).copied().unwrap_or_default();
+    let text = format!(
+        "fr: {} met the condition `{}` ({} lines, {} bytes)",
+        source, condition, counter.count, counter.bytes
+    );
+    ureq::post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&json!({ "text": text }).to_string())
+        .map_err(|e| io::Error::other(format!("notifying the webhook: {}", e)))?;
+    Ok(true)
+}
@@ -0,0 +1,1187 @@
+use crate::aggregator::Aggregator;
+use crate::chunk_stats::ChunkStats;
+#[cfg(feature = "raw")]
+use crate::extract::ExtractRegex;
+use crate::format::Format;
+use crate::parser::split_path;
+use crate::projection::Projection;
+use crate::replay::TimeUnit;
+use crate::time_range::TimeRange;
+use std::{
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::available_parallelism,
+    time::Duration,
+};
+
+const DEFAULT_GROUP_BY: &str = "type";
+const DEFAULT_CHUNK_SIZE: usize = 1_000_000;
+const DEFAULT_BATCH_LINES: usize = 10_000;
+const DEFAULT_IO_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Limits of the sandbox, unless others are set.
+const SANDBOX_MAX_LINE_LENGTH: usize = 1024 * 1024;
+const SANDBOX_MAX_DEPTH: usize = 64;
+const SANDBOX_MAX_TYPES: usize = 10_000;
+const SANDBOX_MAX_OUTLIERS: usize = 100;
+/// Bytes a `BufReader` reads at once. Smaller chunks only add overhead.
+const MIN_CHUNK_SIZE: usize = 8 * 1024;
+const UNKNOWN_MEMORY_MAX_CHUNK_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Parsing strategy used to go through the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Reads the file line by line in the current thread. Ideal for small files.
+    #[default]
+    Naive,
+    /// Reads the file by chunks and parses them in several threads. Ideal for big files.
+    Chunks,
+    /// Reads the file line by line like `Naive` but parses batches of lines in several threads.
+    /// It's not affected by the size of the lines.
+    Batches,
+}
+
+/// How the bytes of a line are attributed when its grouping field is an exploded array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExplodeBytes {
+    /// Every element accounts for all the bytes of the line.
+    #[default]
+    Full,
+    /// The bytes of the line are divided among its elements.
+    Divided,
+}
+
+impl FromStr for ExplodeBytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(ExplodeBytes::Full),
+            "divided" => Ok(ExplodeBytes::Divided),
+            _ => Err(format!(
+                "unknown value `{}`, expected one of: full, divided",
+                s
+            )),
+        }
+    }
+}
+
+/// Hash algorithm of the map counting the types. Use `analyze_reader_with_hasher`
+/// to get the report with any other `BuildHasher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyHasher {
+    /// SipHash, the default of the standard library.
+    #[default]
+    Std,
+    /// FxHash, the fastest with short keys.
+    #[cfg(feature = "fast-hashers")]
+    Fx,
+    /// aHash.
+    #[cfg(feature = "fast-hashers")]
+    AHash,
+}
+
+impl FromStr for KeyHasher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "std" => Ok(KeyHasher::Std),
+            #[cfg(feature = "fast-hashers")]
+            "fx" => Ok(KeyHasher::Fx),
+            #[cfg(feature = "fast-hashers")]
+            "ahash" => Ok(KeyHasher::AHash),
+            #[cfg(not(feature = "fast-hashers"))]
+            "fx" | "ahash" => Err(format!(
+                "the `{}` hasher needs the `fast-hashers` feature",
+                s
+            )),
+            _ => Err(format!(
+                "unknown hasher `{}`, expected one of: std, fx, ahash",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do with the lines that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidUtf8 {
+    /// Stops the analysis with an `AnalyzeError::InvalidUtf8`.
+    #[default]
+    Error,
+    /// Replaces the invalid sequences with `U+FFFD` and parses the line anyway.
+    Replace,
+    /// Doesn't parse the line and counts it under the `INVALID_UTF8` type.
+    SkipLine,
+}
+
+impl FromStr for InvalidUtf8 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(InvalidUtf8::Error),
+            "replace" => Ok(InvalidUtf8::Replace),
+            "skip-line" => Ok(InvalidUtf8::SkipLine),
+            _ => Err(format!(
+                "unknown value `{}`, expected one of: error, replace, skip-line",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do when a worker thread panics parsing its lines. Unless it's `Abort`, a worker
+/// panicking outside the parsing, in a callback, loses the results of its chunk, and the
+/// analysis ends with `AnalyzeError::Incomplete`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WorkerPanic {
+    /// Lets the panic stop the analysis.
+    #[default]
+    Abort,
+    /// Counts all the lines of the chunk under the `PANIC` type. The callbacks notified about
+    /// its lines before the panic aren't taken back.
+    Count,
+    /// Parses the chunk again line by line, counting only the lines that panic under the `PANIC` type.
+    Retry,
+}
+
+impl FromStr for WorkerPanic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(WorkerPanic::Abort),
+            "count" => Ok(WorkerPanic::Count),
+            "retry" => Ok(WorkerPanic::Retry),
+            _ => Err(format!(
+                "unknown value `{}`, expected one of: abort, count, retry",
+                s
+            )),
+        }
+    }
+}
+
+/// Called with the number of bytes processed so far and the total bytes to process, if known.
+pub type ProgressCallback = dyn Fn(u64, Option<u64>) + Send + Sync;
+/// Called with the line number, the byte offset where the line starts, the raw line and the parsing error.
+pub type ErrorCallback = dyn Fn(usize, u64, &[u8], &serde_json::Error) + Send + Sync;
+/// Called with the type a line is counted as, its line number, the byte offset where it starts and
+/// the raw line, once per type when exploded.
+pub type LineCallback = dyn Fn(&str, usize, u64, &[u8]) + Send + Sync;
+/// Called with the name of every file of `analyze_files`, or pod of `analyze_pods`, before
+/// analyzing it.
+pub type SourceCallback = dyn Fn(&str) + Send + Sync;
+/// Called with the figures of every chunk, or batch, once it's parsed.
+pub type ChunkCallback = dyn Fn(&ChunkStats) + Send + Sync;
+
+/// Wrapper allowing the options to be cloned and debugged while holding closures.
+pub(crate) struct Callback<F: ?Sized>(pub(crate) Arc<F>);
+
+impl<F: ?Sized> Clone for Callback<F> {
+    fn clone(&self) -> Self {
+        Callback(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+/// A chunk size checked against its sensible bounds, since it's easy to take it for a number
+/// of lines: bigger than what a `BufReader` reads at once, and small enough for the chunks held
+/// by the workers to take no more than half of the available memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSizeCheck {
+    Ok(usize),
+    TooSmall {
+        requested: usize,
+        min: usize,
+    },
+    TooLarge {
+        requested: usize,
+        max: usize,
+        threads: usize,
+        available_memory: u64,
+    },
+}
+
+impl ChunkSizeCheck {
+    /// Checks the chunk size of an analysis with `threads` workers.
+    pub fn new(chunk_size: usize, threads: usize) -> Self {
+        Self::with_memory(chunk_size, threads, available_memory())
+    }
+
+    fn with_memory(chunk_size: usize, threads: usize, available_memory: Option<u64>) -> Self {
+        if chunk_size < MIN_CHUNK_SIZE {
+            return ChunkSizeCheck::TooSmall {
+                requested: chunk_size,
+                min: MIN_CHUNK_SIZE,
+            };
+        }
+        match available_memory {
+            Some(available) if chunk_size > Self::max_with_memory(threads, available) => {
+                ChunkSizeCheck::TooLarge {
+                    requested: chunk_size,
+                    max: Self::max_with_memory(threads, available),
+                    threads,
+                    available_memory: available,
+                }
+            }
+            _ => ChunkSizeCheck::Ok(chunk_size),
+        }
+    }
+
+    /// Largest chunk size the workers of an analysis with `threads` workers can hold in memory,
+    /// which the chunks grown to fit a long line don't go past. It's 1 GiB if the available
+    /// memory isn't known.
+    pub(crate) fn max(threads: usize) -> usize {
+        available_memory().map_or(UNKNOWN_MEMORY_MAX_CHUNK_SIZE, |available| {
+            Self::max_with_memory(threads, available)
+        })
+    }
+
+    fn max_with_memory(threads: usize, available_memory: u64) -> usize {
+        // every worker holds a chunk while the next one is read
+        let chunks = threads.max(1) as u64 + 1;
+        (available_memory / 2 / chunks).max(MIN_CHUNK_SIZE as u64) as usize
+    }
+
+    /// The chunk size within the bounds.
+    pub fn effective(&self) -> usize {
+        match *self {
+            ChunkSizeCheck::Ok(chunk_size) => chunk_size,
+            ChunkSizeCheck::TooSmall { min, .. } => min,
+            ChunkSizeCheck::TooLarge { max, .. } => max,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, ChunkSizeCheck::Ok(_))
+    }
+}
+
+impl fmt::Display for ChunkSizeCheck {
+    /// The effective chunk size, explaining why it differs from the requested one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ChunkSizeCheck::Ok(chunk_size) => write!(f, "{} bytes", chunk_size),
+            ChunkSizeCheck::TooSmall { requested, min } => write!(
+                f,
+                "{} bytes, raised from {}: the chunk size is in bytes, and chunks smaller than the {} bytes read at once only add overhead. Use --batch-lines to parse a number of lines together",
+                min, requested, min
+            ),
+            ChunkSizeCheck::TooLarge {
+                requested,
+                max,
+                threads,
+                available_memory,
+            } => write!(
+                f,
+                "{} bytes, lowered from {}: {} chunks of {} bytes, one per thread and the one being read, would take more than half of the {} bytes of available memory",
+                max,
+                requested,
+                threads.max(1) + 1,
+                requested,
+                available_memory
+            ),
+        }
+    }
+}
+
+/// Memory the system can give without swapping, if it tells.
+#[cfg(target_os = "linux")]
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Allows aborting a running analysis from another thread.
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Configuration of an analysis.
+/// Use `AnalyzeOptions::builder()` to create one.
+#[derive(Debug, Clone)]
+pub struct AnalyzeOptions {
+    pub(crate) group_by: Vec<String>,
+    pub(crate) key_paths: Vec<Vec<String>>,
+    pub(crate) only: Option<HashSet<String>>,
+    pub(crate) strict_keys: bool,
+    pub(crate) explode: bool,
+    pub(crate) explode_bytes: ExplodeBytes,
+    pub(crate) rollup: Option<(usize, String)>,
+    pub(crate) strategy: Strategy,
+    pub(crate) chunk_size: usize,
+    pub(crate) adaptive_chunk_size: bool,
+    pub(crate) batch_lines: usize,
+    pub(crate) threads: usize,
+    pub(crate) pin_threads: bool,
+    pub(crate) verbose_errors: bool,
+    pub(crate) count_newlines: bool,
+    pub(crate) invalid_utf8: InvalidUtf8,
+    pub(crate) worker_panic: WorkerPanic,
+    pub(crate) format: Format,
+    pub(crate) max_error_rate: Option<f64>,
+    pub(crate) comment_prefix: Option<String>,
+    pub(crate) lenient_trailing: bool,
+    pub(crate) allow_non_finite: bool,
+    pub(crate) detect_truncated: bool,
+    pub(crate) exact_duplicates: bool,
+    pub(crate) positions: bool,
+    pub(crate) outliers: usize,
+    pub(crate) hasher: KeyHasher,
+    pub(crate) check_binary: bool,
+    pub(crate) check_bytes: bool,
+    pub(crate) drop_cache_after: bool,
+    pub(crate) direct_io: bool,
+    pub(crate) max_line_length: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_types: Option<usize>,
+    pub(crate) sandbox: bool,
+    #[cfg(feature = "relaxed")]
+    pub(crate) relaxed: bool,
+    #[cfg(feature = "raw")]
+    pub(crate) group_by_regex: Option<regex::Regex>,
+    #[cfg(feature = "raw")]
+    pub(crate) extract_regex: Option<ExtractRegex>,
+    pub(crate) skip_lines: usize,
+    pub(crate) io_retries: u32,
+    pub(crate) io_retry_delay: Duration,
+    pub(crate) snapshot: bool,
+    pub(crate) snapshot_size: Option<u64>,
+    pub(crate) time_range: Option<TimeRange>,
+    pub(crate) max_throughput: Option<u64>,
+    pub(crate) unwrap_field: Option<String>,
+    pub(crate) unwrap_path: Option<Vec<String>>,
+    pub(crate) duration_field: Option<String>,
+    pub(crate) duration_projection: Option<Projection>,
+    pub(crate) duration_unit: TimeUnit,
+    pub(crate) on_progress: Option<Callback<ProgressCallback>>,
+    pub(crate) on_error: Option<Callback<ErrorCallback>>,
+    pub(crate) on_line: Option<Callback<LineCallback>>,
+    pub(crate) on_source: Option<Callback<SourceCallback>>,
+    pub(crate) on_chunk: Option<Callback<ChunkCallback>>,
+    pub(crate) aggregators: Vec<(String, Box<dyn Aggregator>)>,
+    pub(crate) cancellation_token: Option<CancellationToken>,
+}
+
+impl Default for AnalyzeOptions {
+    fn default() -> Self {
+        Self {
+            group_by: vec![DEFAULT_GROUP_BY.to_string()],
+            key_paths: vec![split_path(DEFAULT_GROUP_BY)],
+            only: None,
+            strict_keys: false,
+            explode: false,
+            explode_bytes: ExplodeBytes::default(),
+            rollup: None,
+            strategy: Strategy::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            adaptive_chunk_size: false,
+            batch_lines: DEFAULT_BATCH_LINES,
+            threads: available_parallelism().map(|n| n.get()).unwrap_or(1),
+            pin_threads: false,
+            verbose_errors: false,
+            count_newlines: true,
+            invalid_utf8: InvalidUtf8::default(),
+            worker_panic: WorkerPanic::default(),
+            format: Format::default(),
+            max_error_rate: None,
+            comment_prefix: None,
+            lenient_trailing: false,
+            allow_non_finite: false,
+            detect_truncated: false,
+            exact_duplicates: false,
+            positions: false,
+            outliers: 0,
+            hasher: KeyHasher::default(),
+            check_binary: true,
+            check_bytes: false,
+            drop_cache_after: false,
+            direct_io: false,
+            max_line_length: None,
+            max_depth: None,
+            max_types: None,
+            sandbox: false,
+            #[cfg(feature = "relaxed")]
+            relaxed: false,
+            #[cfg(feature = "raw")]
+            group_by_regex: None,
+            #[cfg(feature = "raw")]
+            extract_regex: None,
+            skip_lines: 0,
+            io_retries: 0,
+            io_retry_delay: DEFAULT_IO_RETRY_DELAY,
+            snapshot: false,
+            snapshot_size: None,
+            time_range: None,
+            max_throughput: None,
+            unwrap_field: None,
+            unwrap_path: None,
+            duration_field: None,
+            duration_projection: None,
+            duration_unit: TimeUnit::Milliseconds,
+            on_progress: None,
+            on_error: None,
+            on_line: None,
+            on_source: None,
+            on_chunk: None,
+            aggregators: Vec::new(),
+            cancellation_token: None,
+        }
+    }
+}
+
+impl AnalyzeOptions {
+    pub fn builder() -> AnalyzeOptionsBuilder {
+        AnalyzeOptionsBuilder::default()
+    }
+
+    /// Applies the limits of the sandbox and drops whatever goes beyond counting the lines.
+    /// The positions and durations are kept, as they take the same room per type whatever the
+    /// number of lines and so are bounded by `max_types`.
+    fn harden(&mut self) {
+        self.max_line_length.get_or_insert(SANDBOX_MAX_LINE_LENGTH);
+        self.max_depth.get_or_insert(SANDBOX_MAX_DEPTH);
+        self.max_types.get_or_insert(SANDBOX_MAX_TYPES);
+        self.outliers = self.outliers.min(SANDBOX_MAX_OUTLIERS);
+        // one digest per distinct line and one value per distinct field
+        self.exact_duplicates = false;
+        self.aggregators.clear();
+        if self.worker_panic == WorkerPanic::Abort {
+            self.worker_panic = WorkerPanic::Retry;
+        }
+        self.unwrap_field = None;
+        self.unwrap_path = None;
+        #[cfg(feature = "raw")]
+        {
+            self.extract_regex = None;
+        }
+        #[cfg(feature = "relaxed")]
+        {
+            self.relaxed = false;
+        }
+    }
+
+    pub fn only(&self) -> Option<&HashSet<String>> {
+        self.only.as_ref()
+    }
+
+    pub fn group_by(&self) -> &[String] {
+        &self.group_by
+    }
+
+    pub fn strict_keys(&self) -> bool {
+        self.strict_keys
+    }
+
+    pub fn explode(&self) -> bool {
+        self.explode
+    }
+
+    pub fn explode_bytes(&self) -> ExplodeBytes {
+        self.explode_bytes
+    }
+
+    pub fn rollup(&self) -> Option<(usize, &str)> {
+        self.rollup
+            .as_ref()
+            .map(|(depth, separator)| (*depth, separator.as_str()))
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn adaptive_chunk_size(&self) -> bool {
+        self.adaptive_chunk_size
+    }
+
+    pub fn batch_lines(&self) -> usize {
+        self.batch_lines
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn pin_threads(&self) -> bool {
+        self.pin_threads
+    }
+
+    pub fn verbose_errors(&self) -> bool {
+        self.verbose_errors
+    }
+
+    pub fn count_newlines(&self) -> bool {
+        self.count_newlines
+    }
+
+    pub fn invalid_utf8(&self) -> InvalidUtf8 {
+        self.invalid_utf8
+    }
+
+    pub fn worker_panic(&self) -> WorkerPanic {
+        self.worker_panic
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn max_error_rate(&self) -> Option<f64> {
+        self.max_error_rate
+    }
+
+    pub fn comment_prefix(&self) -> Option<&str> {
+        self.comment_prefix.as_deref()
+    }
+
+    pub fn lenient_trailing(&self) -> bool {
+        self.lenient_trailing
+    }
+
+    #[cfg(feature = "relaxed")]
+    pub fn relaxed(&self) -> bool {
+        self.relaxed
+    }
+
+    #[cfg(feature = "raw")]
+    pub fn group_by_regex(&self) -> Option<&regex::Regex> {
+        self.group_by_regex.as_ref()
+    }
+
+    #[cfg(feature = "raw")]
+    pub fn extract_regex(&self) -> Option<&ExtractRegex> {
+        self.extract_regex.as_ref()
+    }
+
+    pub fn allow_non_finite(&self) -> bool {
+        self.allow_non_finite
+    }
+
+    pub fn detect_truncated(&self) -> bool {
+        self.detect_truncated
+    }
+
+    pub fn exact_duplicates(&self) -> bool {
+        self.exact_duplicates
+    }
+
+    pub fn positions(&self) -> bool {
+        self.positions
+    }
+
+    pub fn outliers(&self) -> usize {
+        self.outliers
+    }
+
+    pub fn hasher(&self) -> KeyHasher {
+        self.hasher
+    }
+
+    pub fn check_binary(&self) -> bool {
+        self.check_binary
+    }
+
+    pub fn check_bytes(&self) -> bool {
+        self.check_bytes
+    }
+
+    pub fn drop_cache_after(&self) -> bool {
+        self.drop_cache_after
+    }
+
+    pub fn direct_io(&self) -> bool {
+        self.direct_io
+    }
+
+    pub fn max_line_length(&self) -> Option<usize> {
+        self.max_line_length
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn max_types(&self) -> Option<usize> {
+        self.max_types
+    }
+
+    pub fn sandbox(&self) -> bool {
+        self.sandbox
+    }
+
+    pub fn skip_lines(&self) -> usize {
+        self.skip_lines
+    }
+
+    pub fn io_retries(&self) -> u32 {
+        self.io_retries
+    }
+
+    pub fn io_retry_delay(&self) -> Duration {
+        self.io_retry_delay
+    }
+
+    pub fn snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    pub fn snapshot_size(&self) -> Option<u64> {
+        self.snapshot_size
+    }
+
+    pub fn time_range(&self) -> Option<&TimeRange> {
+        self.time_range.as_ref()
+    }
+
+    pub fn max_throughput(&self) -> Option<u64> {
+        self.max_throughput
+    }
+
+    pub fn unwrap_field(&self) -> Option<&str> {
+        self.unwrap_field.as_deref()
+    }
+
+    pub fn duration_field(&self) -> Option<&str> {
+        self.duration_field.as_deref()
+    }
+
+    pub fn duration_unit(&self) -> TimeUnit {
+        self.duration_unit
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AnalyzeOptionsBuilder {
+    options: AnalyzeOptions,
+}
+
+impl AnalyzeOptionsBuilder {
+    /// JSON field whose value will be used to group the lines.
+    /// Nested fields are separated by dots (`event.type`).
+    pub fn group_by(self, path: impl Into<String>) -> Self {
+        self.group_by_any(vec![path.into()])
+    }
+
+    /// Counts only the lines of these types, before any rollup. When the file has an up to date
+    /// index (see `Indexer`), only the blocks holding them are read. Only `analyze` uses the index.
+    pub fn only<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.only = Some(types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Several JSON fields, tried in order. The first one present in a line is used to group it.
+    /// It allows aggregating files mixing different envelope formats.
+    /// Only the first 64 paths are taken into account.
+    pub fn group_by_any<I, S>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.group_by = paths.into_iter().map(Into::into).collect();
+        self.options.key_paths = self
+            .options
+            .group_by
+            .iter()
+            .map(|path| split_path(path))
+            .collect();
+        self
+    }
+
+    /// If set, only string values are accepted as keys.
+    /// Otherwise numbers, booleans and null are converted to their JSON representation.
+    pub fn strict_keys(mut self, strict_keys: bool) -> Self {
+        self.options.strict_keys = strict_keys;
+        self
+    }
+
+    /// If set, lines whose grouping field is an array are counted once per element.
+    pub fn explode(mut self, explode: bool) -> Self {
+        self.options.explode = explode;
+        self
+    }
+
+    /// How the bytes of an exploded line are attributed to its elements.
+    pub fn explode_bytes(mut self, explode_bytes: ExplodeBytes) -> Self {
+        self.options.explode_bytes = explode_bytes;
+        self
+    }
+
+    /// Aggregates hierarchical types like `payment.card.authorized` at the given depth
+    /// (`payment.card` with depth 2 and `.` as separator). See `Report::rollup`.
+    pub fn rollup(mut self, depth: usize, separator: impl Into<String>) -> Self {
+        self.options.rollup = Some((depth, separator.into()));
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.options.strategy = strategy;
+        self
+    }
+
+    /// Size in bytes of the chunks used by the `Chunks` strategy. It's raised if a line
+    /// doesn't fit in it.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.options.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// If set, the `Chunks` strategy ignores `chunk_size` and tunes the size of the chunks
+    /// during the run, comparing the time spent reading them with the time spent parsing them.
+    /// Lines longer than the current chunk size make it grow instead of failing.
+    pub fn adaptive_chunk_size(mut self, adaptive_chunk_size: bool) -> Self {
+        self.options.adaptive_chunk_size = adaptive_chunk_size;
+        self
+    }
+
+    /// Number of lines parsed together by the `Batches` strategy.
+    pub fn batch_lines(mut self, batch_lines: usize) -> Self {
+        self.options.batch_lines = batch_lines.max(1);
+        self
+    }
+
+    /// Maximum number of threads parsing chunks or batches at the same time.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads.max(1);
+        self
+    }
+
+    /// If set, the threads parsing chunks or batches are pinned to the available cores
+    /// in a round robin fashion. It makes benchmarks more consistent. It needs the `affinity` feature.
+    pub fn pin_threads(mut self, pin_threads: bool) -> Self {
+        self.options.pin_threads = pin_threads;
+        self
+    }
+
+    /// If set, parsing errors will be derived to the stderr.
+    pub fn verbose_errors(mut self, verbose_errors: bool) -> Self {
+        self.options.verbose_errors = verbose_errors;
+        self
+    }
+
+    /// Whether the line terminator (`\n` or `\r\n`) counts towards the bytes of a line.
+    /// It's applied the same way by every strategy. Defaults to `true`.
+    pub fn count_newlines(mut self, count_newlines: bool) -> Self {
+        self.options.count_newlines = count_newlines;
+        self
+    }
+
+    /// What to do with the lines that are not valid UTF-8.
+    pub fn invalid_utf8(mut self, invalid_utf8: InvalidUtf8) -> Self {
+        self.options.invalid_utf8 = invalid_utf8;
+        self
+    }
+
+    /// What to do when a worker of the `Chunks` or `Batches` strategies panics parsing its lines.
+    pub fn worker_panic(mut self, worker_panic: WorkerPanic) -> Self {
+        self.options.worker_panic = worker_panic;
+        self
+    }
+
+    /// Layout of the input. By default it's sniffed from the first bytes
+    /// and the detected format is kept in `Report::format`.
+    pub fn format(mut self, format: Format) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// Aborts the analysis with an `AnalyzeError::TooManyErrors` when the ratio (0-1)
+    /// of lines that couldn't be parsed goes over `max_error_rate`.
+    pub fn max_error_rate(mut self, max_error_rate: f64) -> Self {
+        self.options.max_error_rate = Some(max_error_rate);
+        self
+    }
+
+    /// Lines starting with `prefix` (e.g. `#`) are ignored instead of counted as errors.
+    pub fn skip_comments(mut self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.options.comment_prefix = if prefix.is_empty() {
+            None
+        } else {
+            Some(prefix)
+        };
+        self
+    }
+
+    /// If set, bytes found after the JSON object of a line (e.g. a trailing comma) are ignored
+    /// instead of counting the line as an error. They're accounted in `Report::trailing_garbage`.
+    pub fn lenient_trailing(mut self, lenient_trailing: bool) -> Self {
+        self.options.lenient_trailing = lenient_trailing;
+        self
+    }
+
+    /// If set, lines are parsed as JSON5 (single quotes, trailing commas, unquoted keys, comments...).
+    /// It's slower than the strict parser.
+    #[cfg(feature = "relaxed")]
+    pub fn relaxed(mut self, relaxed: bool) -> Self {
+        self.options.relaxed = relaxed;
+        self
+    }
+
+    /// Groups the lines by the first capture group of the regex, or by the whole match if it
+    /// has none, without parsing them as JSON. The format is set to `Format::Raw`.
+    /// Lines not matching it are counted as `UNMATCHED`.
+    #[cfg(feature = "raw")]
+    pub fn group_by_regex(mut self, regex: regex::Regex) -> Self {
+        self.options.format = Format::Raw;
+        self.options.group_by_regex = Some(regex);
+        self
+    }
+
+    /// Named capture groups of the regex become fields of the lines, which `group_by` and
+    /// `duration_field` can use. Raw lines are made of those fields alone, JSON lines get them
+    /// on top of theirs. Lines the regex doesn't match keep the fields they had.
+    #[cfg(feature = "raw")]
+    pub fn extract_regex(mut self, extract_regex: ExtractRegex) -> Self {
+        self.options.extract_regex = Some(extract_regex);
+        self
+    }
+
+    /// If set, lines with `NaN`, `Infinity` or `-Infinity` literals (as emitted by Python)
+    /// are accepted as if those values were `null`.
+    pub fn allow_non_finite(mut self, allow_non_finite: bool) -> Self {
+        self.options.allow_non_finite = allow_non_finite;
+        self
+    }
+
+    /// If set, lines cut in the middle of a JSON document (unbalanced braces or quotes),
+    /// as when producers cap the size of the lines, are counted as `TRUNCATED`
+    /// instead of `ERROR`. They still count as errors for the error rate.
+    pub fn detect_truncated(mut self, detect_truncated: bool) -> Self {
+        self.options.detect_truncated = detect_truncated;
+        self
+    }
+
+    /// If set, lines byte for byte identical to an earlier line of the same type are counted
+    /// in `Report::duplicates`. A digest of every distinct line is kept during the analysis.
+    pub fn exact_duplicates(mut self, exact_duplicates: bool) -> Self {
+        self.options.exact_duplicates = exact_duplicates;
+        self
+    }
+
+    /// If set, the positions of the first and last lines of every type are kept in
+    /// `Report::first_seen` and `Report::last_seen`.
+    pub fn positions(mut self, positions: bool) -> Self {
+        self.options.positions = positions;
+        self
+    }
+
+    /// Number of the longest lines kept in `Report::outliers`, with their type, position and
+    /// a preview. None by default.
+    pub fn outliers(mut self, outliers: usize) -> Self {
+        self.options.outliers = outliers;
+        self
+    }
+
+    /// Hash algorithm of the map counting the types. The report is returned with the
+    /// default hasher anyway. `KeyHasher::Std` by default.
+    pub fn hasher(mut self, hasher: KeyHasher) -> Self {
+        self.options.hasher = hasher;
+        self
+    }
+
+    /// Whether the beginning of the input is checked for binary or compressed content,
+    /// aborting the analysis instead of counting every line as an error. Enabled by default.
+    pub fn check_binary(mut self, check_binary: bool) -> Self {
+        self.options.check_binary = check_binary;
+        self
+    }
+
+    /// If set, the bytes read are compared with the bytes of the lines that made it into the
+    /// report, keeping the ranges left out in `Report::byte_check`.
+    pub fn check_bytes(mut self, check_bytes: bool) -> Self {
+        self.options.check_bytes = check_bytes;
+        self
+    }
+
+    /// If set, the pages of the file are dropped from the page cache once it has been analyzed,
+    /// so the run doesn't evict what other processes had cached. Only `analyze` uses it, and only
+    /// on Linux with the `fadvise` feature.
+    pub fn drop_cache_after(mut self, drop_cache_after: bool) -> Self {
+        self.options.drop_cache_after = drop_cache_after;
+        self
+    }
+
+    /// If set, the file is read with `O_DIRECT`, bypassing the page cache, so the timings are the
+    /// ones of the disk instead of the ones of a warm cache. Only `analyze` uses it, and it fails
+    /// unless it runs on Linux with the `direct-io` feature and a filesystem supporting it.
+    pub fn direct_io(mut self, direct_io: bool) -> Self {
+        self.options.direct_io = direct_io;
+        self
+    }
+
+    /// Lines longer than `max_line_length` bytes, without their terminator, aren't parsed but
+    /// counted under the `TOO_LONG` type. No more than about that many bytes of them are kept
+    /// in memory, whatever the strategy.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.options.max_line_length = Some(max_line_length);
+        self
+    }
+
+    /// Lines nesting more than `max_depth` objects or arrays aren't parsed but counted under
+    /// the `TOO_DEEP` type.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Keeps no more than `max_types` types in memory. Once there are that many, the lines of
+    /// new types are counted under the `OTHER` type, which takes one of the places.
+    pub fn max_types(mut self, max_types: usize) -> Self {
+        self.options.max_types = Some(max_types);
+        self
+    }
+
+    /// Hardens the analysis for untrusted inputs, like files uploaded by users: the line length,
+    /// nesting depth and number of types are limited to 1 MiB, 64 and 10 000 unless other limits
+    /// are set, no more than 100 outliers are kept, the fields aren't extracted, unwrapped or
+    /// relaxed, the exact duplicates and the aggregates, which grow with every distinct line or
+    /// value, aren't computed, and panicking lines are counted under the `PANIC` type instead of
+    /// stopping the analysis, whatever the strategy.
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.options.sandbox = sandbox;
+        self
+    }
+
+    /// Number of lines at the beginning of the file to ignore, like a header or a metadata object.
+    pub fn skip_lines(mut self, skip_lines: usize) -> Self {
+        self.options.skip_lines = skip_lines;
+        self
+    }
+
+    /// Number of consecutive times a failed read of a file is retried before giving up.
+    /// The file is opened again and read from the last byte read successfully.
+    /// Only `analyze` uses it, as generic readers can't be reopened.
+    pub fn io_retries(mut self, io_retries: u32) -> Self {
+        self.options.io_retries = io_retries;
+        self
+    }
+
+    /// Time to wait before retrying a failed read. Defaults to 500ms.
+    pub fn io_retry_delay(mut self, io_retry_delay: Duration) -> Self {
+        self.options.io_retry_delay = io_retry_delay;
+        self
+    }
+
+    /// Whether the analysis of a file stops at the size it had when it was opened,
+    /// leaving out what's appended meanwhile. Only `analyze` uses it.
+    pub fn snapshot(mut self, snapshot: bool) -> Self {
+        self.options.snapshot = snapshot;
+        self
+    }
+
+    /// Offset where the analysis of a file stops, instead of the size it had when it was opened.
+    /// Only `analyze` uses it.
+    pub fn snapshot_size(mut self, snapshot_size: u64) -> Self {
+        self.options.snapshot = true;
+        self.options.snapshot_size = Some(snapshot_size);
+        self
+    }
+
+    /// Analyzes only the lines of a time-ordered file within the range, skipping straight to
+    /// them. Their line numbers count from the beginning of the range. Only `analyze` uses it.
+    pub fn time_range(mut self, time_range: TimeRange) -> Self {
+        self.options.time_range = Some(time_range);
+        self
+    }
+
+    /// Maximum number of bytes read per second, so background analyses are polite neighbors.
+    pub fn max_throughput(mut self, bytes_per_second: u64) -> Self {
+        self.options.max_throughput = Some(bytes_per_second);
+        self
+    }
+
+    /// Field of an envelope holding the actual JSON line as a string, like the `log` field
+    /// of Docker logs. The line is decoded from it before being grouped.
+    /// Nested fields are separated by dots.
+    pub fn unwrap_field(mut self, path: impl Into<String>) -> Self {
+        let path = path.into();
+        self.options.unwrap_path = Some(split_path(&path));
+        self.options.unwrap_field = Some(path);
+        self
+    }
+
+    /// Field holding the duration of every line, like `duration_ms` or `elapsed`, summed by type
+    /// in `Report::durations`. Nested fields are separated by dots.
+    pub fn duration_field(mut self, path: impl Into<String>) -> Self {
+        let path = path.into();
+        self.options.duration_projection = Some(Projection::single(&path));
+        self.options.duration_field = Some(path);
+        self
+    }
+
+    /// Unit of the numeric durations. Strings like `1.5s` carry their own. Defaults to milliseconds.
+    pub fn duration_unit(mut self, unit: TimeUnit) -> Self {
+        self.options.duration_unit = unit;
+        self
+    }
+
+    /// Registers a callback to be notified about the progress of the analysis.
+    pub fn on_progress(mut self, f: impl Fn(u64, Option<u64>) + Send + Sync + 'static) -> Self {
+        self.options.on_progress = Some(Callback(Arc::new(f)));
+        self
+    }
+
+    /// Registers a callback receiving the lines that couldn't be parsed.
+    /// When set, errors won't be derived to the stderr.
+    pub fn on_error(
+        mut self,
+        f: impl Fn(usize, u64, &[u8], &serde_json::Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.on_error = Some(Callback(Arc::new(f)));
+        self
+    }
+
+    /// Registers a callback receiving every counted line along with its type and its position.
+    /// It's called from the worker threads, so lines of a type may arrive out of order.
+    pub fn on_line(mut self, f: impl Fn(&str, usize, u64, &[u8]) + Send + Sync + 'static) -> Self {
+        self.options.on_line = Some(Callback(Arc::new(f)));
+        self
+    }
+
+    /// Registers a callback receiving the name of every file of `analyze_files`, or pod of
+    /// `analyze_pods`, before it's analyzed, so the lines of the other callbacks can be told apart.
+    pub fn on_source(mut self, f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.options.on_source = Some(Callback(Arc::new(f)));
+        self
+    }
+
+    /// Registers a callback receiving the size, lines, timings and worker of every chunk, or
+    /// batch, once parsed. The naive strategy has none.
+    pub fn on_chunk(mut self, f: impl Fn(&ChunkStats) + Send + Sync + 'static) -> Self {
+        self.options.on_chunk = Some(Callback(Arc::new(f)));
+        self
+    }
+
+    /// Registers a statistic computed over the lines of every type, kept in `Report::aggregates`
+    /// under `name`. A registered aggregator replaces any other with the same name.
+    /// It's cloned for every type and worker, so it must be registered without observations.
+    pub fn aggregator(mut self, name: &str, aggregator: impl Aggregator + 'static) -> Self {
+        self.options.aggregators.retain(|(other, _)| other != name);
+        self.options
+            .aggregators
+            .push((name.to_string(), Box::new(aggregator)));
+        self
+    }
+
+    /// Token used to abort the analysis. The partial results will be returned
+    /// in an `AnalyzeError::Cancelled`.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancellation_token = Some(token);
+        self
+    }
+
+    pub fn build(mut self) -> AnalyzeOptions {
+        if self.options.sandbox {
+            self.options.harden();
+        }
+        self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_sizes_are_kept_within_their_bounds() {
+        let gib = 1 << 30;
+        let check = ChunkSizeCheck::with_memory(10_000, 8, Some(gib));
+        assert_eq!(check, ChunkSizeCheck::Ok(10_000));
+        assert_eq!(check.to_string(), "10000 bytes");
+
+        // taken for a number of lines
+        let check = ChunkSizeCheck::with_memory(500, 8, Some(gib));
+        assert_eq!(check.effective(), MIN_CHUNK_SIZE);
+        assert!(check.to_string().starts_with("8192 bytes, raised from 500"));
+
+        // 9 chunks of 100 MB don't fit in half a GiB
+        let check = ChunkSizeCheck::with_memory(100_000_000, 8, Some(gib));
+        assert_eq!(check.effective(), (gib / 2 / 9) as usize);
+        assert!(!check.is_ok());
+        assert_eq!(
+            ChunkSizeCheck::with_memory(100_000_000, 8, None),
+            ChunkSizeCheck::Ok(100_000_000)
+        );
+
+        // the most a long line can make the chunks grow to
+        assert_eq!(
+            ChunkSizeCheck::max_with_memory(8, gib),
+            (gib / 2 / 9) as usize
+        );
+        assert_eq!(ChunkSizeCheck::max_with_memory(8, 1024), MIN_CHUNK_SIZE);
+        assert!(ChunkSizeCheck::max(8) >= MIN_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn sandbox_drops_the_outputs_growing_with_the_lines() {
+        let builder = || {
+            AnalyzeOptions::builder()
+                .exact_duplicates(true)
+                .outliers(1_000_000)
+                .aggregator("users", crate::Distinct::new("user"))
+                .aggregator("p99", crate::Quantile::new("latency", 0.99).unwrap())
+        };
+        let options = builder().build();
+        assert!(options.exact_duplicates());
+        assert_eq!(options.outliers(), 1_000_000);
+        assert_eq!(options.aggregators.len(), 2);
+
+        let options = builder().sandbox(true).build();
+        assert!(!options.exact_duplicates());
+        assert_eq!(options.outliers(), SANDBOX_MAX_OUTLIERS);
+        assert!(options.aggregators.is_empty());
+        assert_eq!(options.max_types(), Some(SANDBOX_MAX_TYPES));
+
+        let options = AnalyzeOptions::builder().outliers(5).sandbox(true).build();
+        assert_eq!(options.outliers(), 5);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::models::Report;
+use serde_json::{json, Value};
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Exports the count and the bytes of every type as OTLP metrics (`fr.lines` and `fr.bytes`)
+/// to a collector accepting OTLP over HTTP or HTTPS with JSON, like `http://collector:4318`.
+/// `start` is the moment the analysis began, used as the start of the cumulative sums.
+pub fn export_otlp(endpoint: &str, report: &Report, start: SystemTime) -> io::Result<()> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let url = if endpoint.ends_with("/v1/metrics") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/metrics", endpoint)
+    };
+    let body = otlp_metrics(report, start, SystemTime::now()).to_string();
+    ureq::post(&url)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| io::Error::other(format!("exporting the metrics to {}: {}", url, e)))?;
+    Ok(())
+}
+
+fn otlp_metrics(report: &Report, start: SystemTime, end: SystemTime) -> Value {
+    let sum = |name: &str, unit: &str, value: fn(u64, u64) -> u64| {
+        let data_points: Vec<Value> = report
+            .types
+            .iter()
+            .map(|(key, counter)| {
+                json!({
+                    "attributes": [{ "key": "type", "value": { "stringValue": key } }],
+                    "startTimeUnixNano": unix_nanos(start),
+                    "timeUnixNano": unix_nanos(end),
+                    // 64 bits integers are strings in the JSON encoding of OTLP
+                    "asInt": value(counter.count as u64, counter.bytes as u64).to_string(),
+                })
+            })
+            .collect();
+        json!({
+            "name": name,
+            "unit": unit,
+            "sum": {
+                // cumulative
+                "aggregationTemporality": 2,
+                "isMonotonic": true,
+                "dataPoints": data_points,
+            },
+        })
+    };
+    json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": "fr" } }],
+            },
+            "scopeMetrics": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "metrics": [
+                    sum("fr.lines", "1", |count, _| count),
+                    sum("fr.bytes", "By", |_, bytes| bytes),
+                ],
+            }],
+        }],
+    })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    let nanos = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    nanos.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn otlp_metrics_has_a_data_point_per_type() {
+        let mut report = Report::default();
+        report.add_line("A".into(), 10);
+        report.add_line("A".into(), 20);
+        report.add_line("B".into(), 5);
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        let end = UNIX_EPOCH + Duration::from_secs(2);
+        let metrics = otlp_metrics(&report, start, end);
+        let metrics = &metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "fr.lines");
+        assert_eq!(metrics[1]["name"], "fr.bytes");
+        let points = |i: usize| -> Vec<(String, String)> {
+            metrics[i]["sum"]["dataPoints"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| {
+                    let key = p["attributes"][0]["value"]["stringValue"].as_str().unwrap();
+                    (key.to_string(), p["asInt"].as_str().unwrap().to_string())
+                })
+                .collect()
+        };
+        let mut lines = points(0);
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![("A".into(), "2".into()), ("B".into(), "1".into())]
+        );
+        let mut bytes = points(1);
+        bytes.sort();
+        assert_eq!(
+            bytes,
+            vec![("A".into(), "30".into()), ("B".into(), "5".into())]
+        );
+        let point = &metrics[0]["sum"]["dataPoints"][0];
+        assert_eq!(point["startTimeUnixNano"], "1000000000");
+        assert_eq!(point["timeUnixNano"], "2000000000");
+    }
+
+    #[test]
+    fn otlp_endpoints_can_use_https() {
+        // a server closing the connection fails the handshake, but the scheme is supported
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("https://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || drop(listener.accept()));
+        let error = export_otlp(&endpoint, &Report::default(), UNIX_EPOCH).unwrap_err();
+        server.join().unwrap();
+        assert!(!error.to_string().contains("Unknown Scheme"), "{}", error);
+    }
+}
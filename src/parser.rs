@@ -0,0 +1,320 @@
+#[cfg(feature = "relaxed")]
+use crate::core::{missing_field, GroupKey};
+pub(crate) use crate::core::{parse_key, split_path, Key};
+#[cfg(feature = "relaxed")]
+use serde::de;
+
+/// Same as `parse_key` but accepting JSON5: single quotes, trailing commas,
+/// unquoted keys, comments...
+#[cfg(feature = "relaxed")]
+pub(crate) fn parse_key_relaxed(
+    line: &str,
+    paths: &[Vec<String>],
+    strict: bool,
+    explode: bool,
+) -> serde_json::Result<Key> {
+    let group_key = GroupKey::new(paths, strict, explode);
+    let mut deserializer = json5::Deserializer::from_str(line).map_err(de::Error::custom)?;
+    let key = de::Deserializer::deserialize_map(&mut deserializer, group_key)
+        .map_err(de::Error::custom)?;
+    key.map(|(_, key)| key).ok_or_else(|| missing_field(paths))
+}
+
+/// Replaces the `NaN`, `Infinity` and `-Infinity` literals found outside of strings
+/// with `null`. Returns `None` if there's none of them.
+pub(crate) fn replace_non_finite(line: &[u8]) -> Option<Vec<u8>> {
+    const LITERALS: [&[u8]; 3] = [b"-Infinity", b"Infinity", b"NaN"];
+    let mut replaced = Vec::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut found = false;
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == b'\\' {
+                escaped = true;
+            } else if c == b'"' {
+                in_string = false;
+            }
+        } else if c == b'"' {
+            in_string = true;
+        } else if let Some(literal) = LITERALS.iter().find(|l| line[i..].starts_with(l)) {
+            replaced.extend_from_slice(b"null");
+            i += literal.len();
+            found = true;
+            continue;
+        }
+        replaced.push(c);
+        i += 1;
+    }
+    if found {
+        Some(replaced)
+    } else {
+        None
+    }
+}
+
+/// Whether the line looks cut in the middle of a JSON document, as when producers cap
+/// the size of the lines: it opens more braces or brackets than it closes, or it ends
+/// within a string.
+pub(crate) fn is_truncated(line: &[u8]) -> bool {
+    let line = line.trim_ascii();
+    if !matches!(line.first(), Some(b'{') | Some(b'[')) {
+        return false;
+    }
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &c in line {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == b'\\' {
+                escaped = true;
+            } else if c == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                // closes more than it opens, that's not a cut
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+    in_string || depth > 0
+}
+
+/// Deepest nesting of braces and brackets of the line, outside of its strings.
+/// Unbalanced lines are measured all the same.
+pub(crate) fn nesting_depth(line: &[u8]) -> usize {
+    let mut depth = 0usize;
+    let mut deepest = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &c in line {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == b'\\' {
+                escaped = true;
+            } else if c == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<Vec<String>> {
+        paths.iter().map(|path| split_path(path)).collect()
+    }
+
+    fn single(key: &str) -> Key {
+        Key::Single(key.to_string())
+    }
+
+    fn key_of(
+        line: &[u8],
+        paths: &[Vec<String>],
+        strict: bool,
+        explode: bool,
+    ) -> serde_json::Result<Key> {
+        parse_key(line, paths, strict, explode, false).map(|(key, _)| key)
+    }
+
+    #[test]
+    fn parse_key_returns_the_value_of_the_field() {
+        let line = br#"{"type":"B","foo":"bar","items":["one","two"]}"#;
+        assert_eq!(
+            key_of(line, &paths(&["type"]), true, false).unwrap(),
+            single("B")
+        );
+        assert_eq!(
+            key_of(line, &paths(&["foo"]), true, false).unwrap(),
+            single("bar")
+        );
+    }
+
+    #[test]
+    fn parse_key_fails_when_the_field_is_missing() {
+        let line = br#"{"type1":"B","foo":"bar"}"#;
+        assert!(key_of(line, &paths(&["type"]), true, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_fails_when_the_line_is_not_valid_json() {
+        assert!(key_of(
+            br#"{"type":"B" "foo":"bar"}"#,
+            &paths(&["type"]),
+            true,
+            false
+        )
+        .is_err());
+        assert!(key_of(br#"{"type":"B"} trailing"#, &paths(&["type"]), true, false).is_err());
+        assert!(key_of(b"", &paths(&["type"]), true, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_ignores_trailing_bytes_when_lenient() {
+        let type_path = paths(&["type"]);
+        let line = b"{\"type\":\"B\"}, x\r\n";
+        assert!(parse_key(line, &type_path, true, false, false).is_err());
+        assert_eq!(
+            parse_key(line, &type_path, true, false, true).unwrap(),
+            (single("B"), 3)
+        );
+        assert_eq!(
+            parse_key(b"{\"type\":\"B\"} \n", &type_path, true, false, true).unwrap(),
+            (single("B"), 0)
+        );
+        assert!(parse_key(b"{\"type\":\"B\",}", &type_path, true, false, true).is_err());
+    }
+
+    #[cfg(feature = "relaxed")]
+    #[test]
+    fn parse_key_relaxed_accepts_json5() {
+        let line = "{type: 'B', // comment\n nested: {kind: 0x10,}, items: ['a',],}\n";
+        assert!(key_of(line.as_bytes(), &paths(&["type"]), true, false).is_err());
+        assert_eq!(
+            parse_key_relaxed(line, &paths(&["type"]), true, false).unwrap(),
+            single("B")
+        );
+        assert_eq!(
+            parse_key_relaxed(line, &paths(&["nested.kind"]), false, false).unwrap(),
+            single("16")
+        );
+        assert!(parse_key_relaxed(line, &paths(&["foo"]), false, false).is_err());
+        assert!(parse_key_relaxed("{type: 'B'", &paths(&["type"]), false, false).is_err());
+    }
+
+    #[test]
+    fn is_truncated_detects_unbalanced_lines() {
+        assert!(is_truncated(br#"{"type":"A","payload":{"a":[1,2"#));
+        assert!(is_truncated(br#"{"type":"A","msg":"cut here"#));
+        assert!(is_truncated(b"  {\"type\":\"A\\\"}\n"));
+        assert!(!is_truncated(br#"{"type":"A","msg":"{[{"}"#));
+        assert!(!is_truncated(br#"{"type":"A" "b":1}"#));
+        assert!(!is_truncated(br#"{"type":"A"}}"#));
+        assert!(!is_truncated(b"not json {"));
+    }
+
+    #[test]
+    fn nesting_depth_skips_the_strings() {
+        assert_eq!(nesting_depth(br#"{"type":"A"}"#), 1);
+        assert_eq!(nesting_depth(br#"{"a":[{"b":[]},{"c":"[[[{"}]}"#), 4);
+        assert_eq!(nesting_depth(br#"{"a":"\"[[","b":[1]}"#), 2);
+        assert_eq!(nesting_depth(b"[[[[[[[[[["), 10);
+        assert_eq!(nesting_depth(b"]]]{"), 1);
+        assert_eq!(nesting_depth(b"not json"), 0);
+    }
+
+    #[test]
+    fn replace_non_finite_only_rewrites_literals_outside_strings() {
+        assert_eq!(
+            replace_non_finite(br#"{"type":"NaN","a":NaN,"b":[-Infinity,Infinity]}"#).unwrap(),
+            br#"{"type":"NaN","a":null,"b":[null,null]}"#.to_vec()
+        );
+        assert_eq!(
+            replace_non_finite(br#"{"type":"a\"NaN","a":NaN}"#).unwrap(),
+            br#"{"type":"a\"NaN","a":null}"#.to_vec()
+        );
+        assert!(replace_non_finite(br#"{"type":"NaN"}"#).is_none());
+    }
+
+    #[test]
+    fn parse_key_accepts_scalar_values_unless_strict() {
+        let cases: [(&[u8], &str); 6] = [
+            (br#"{"type":3}"#, "3"),
+            (br#"{"type":-3}"#, "-3"),
+            (br#"{"type":3.5}"#, "3.5"),
+            (br#"{"type":3.0}"#, "3"),
+            (br#"{"type":true}"#, "true"),
+            (br#"{"type":null}"#, "null"),
+        ];
+        for (line, key) in &cases {
+            assert_eq!(
+                key_of(line, &paths(&["type"]), false, false).unwrap(),
+                single(key)
+            );
+            assert!(key_of(line, &paths(&["type"]), true, false).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_key_fails_for_non_scalar_values() {
+        assert!(key_of(br#"{"type":["B"]}"#, &paths(&["type"]), false, false).is_err());
+        assert!(key_of(br#"{"type":{"B":1}}"#, &paths(&["type"]), false, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_fails_when_the_line_is_not_an_object() {
+        assert!(key_of(br#"["type","B"]"#, &paths(&["type"]), true, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_follows_nested_paths() {
+        let line = br#"{"foo":1,"event":{"id":3,"type":"B"}}"#;
+        assert_eq!(
+            key_of(line, &paths(&["event.type"]), true, false).unwrap(),
+            single("B")
+        );
+        assert!(key_of(line, &paths(&["event.kind"]), true, false).is_err());
+        assert!(key_of(line, &paths(&["foo.type"]), true, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_uses_the_first_path_present() {
+        let any = paths(&["type", "event.type", "meta.kind"]);
+        let cases: [(&[u8], &str); 4] = [
+            (br#"{"type":"A","event":{"type":"B"}}"#, "A"),
+            (br#"{"event":{"type":"B"},"type":"A"}"#, "A"),
+            (br#"{"meta":{"kind":"C"},"event":{"type":"B"}}"#, "B"),
+            (br#"{"meta":{"kind":"C"}}"#, "C"),
+        ];
+        for (line, key) in &cases {
+            assert_eq!(key_of(line, &any, true, false).unwrap(), single(key));
+        }
+        assert!(key_of(br#"{"event":{"kind":"C"}}"#, &any, true, false).is_err());
+    }
+
+    #[test]
+    fn parse_key_explodes_arrays_when_asked_to() {
+        let line = br#"{"tags":["a",1,"b"],"type":"B"}"#;
+        let tags = paths(&["tags"]);
+        assert_eq!(
+            key_of(line, &tags, false, true).unwrap(),
+            Key::Exploded(vec!["a".to_string(), "1".to_string(), "b".to_string()])
+        );
+        assert!(key_of(line, &tags, true, true).is_err());
+        assert!(key_of(line, &tags, false, false).is_err());
+        assert_eq!(
+            key_of(line, &paths(&["type"]), false, true).unwrap(),
+            single("B")
+        );
+        assert!(key_of(br#"{"tags":[]}"#, &tags, false, true).is_err());
+        assert!(key_of(br#"{"tags":[["a"]]}"#, &tags, false, true).is_err());
+    }
+}
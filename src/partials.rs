@@ -0,0 +1,88 @@
+use crate::error::AnalyzeError;
+use crate::models::Report;
+use crate::options::AnalyzeOptions;
+use crate::reader::analyze_source;
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::mpsc::{channel, Receiver},
+    thread::{spawn, JoinHandle},
+};
+
+/// Iterator over the partial reports of an analysis running in the background.
+/// Every item holds the results of a single chunk. Once the iterator is exhausted,
+/// `finish` returns the merged report.
+pub struct PartialReports {
+    receiver: Receiver<Report>,
+    handle: JoinHandle<Result<Report, AnalyzeError>>,
+}
+
+impl PartialReports {
+    /// Waits for the analysis to end and returns the final report.
+    pub fn finish(self) -> Result<Report, AnalyzeError> {
+        self.handle.join().expect("The analysis thread panicked")
+    }
+}
+
+impl Iterator for PartialReports {
+    type Item = Report;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Same as `analyze` but yielding the partial results as soon as every chunk is parsed.
+pub fn analyze_partials(path: &Path, options: &AnalyzeOptions) -> io::Result<PartialReports> {
+    let f = File::open(path)?;
+    let total = f.metadata().ok().map(|m| m.len());
+    Ok(spawn_analysis(f, total, options))
+}
+
+/// Same as `analyze_reader` but yielding the partial results as soon as every chunk is parsed.
+pub fn analyze_reader_partials(
+    reader: impl Read + Send + 'static,
+    options: &AnalyzeOptions,
+) -> PartialReports {
+    spawn_analysis(reader, None, options)
+}
+
+fn spawn_analysis(
+    reader: impl Read + Send + 'static,
+    total: Option<u64>,
+    options: &AnalyzeOptions,
+) -> PartialReports {
+    let (tx, rx) = channel();
+    let options = options.clone();
+    let handle = spawn(move || analyze_source(reader, total, &options, Some(tx)));
+    PartialReports {
+        receiver: rx,
+        handle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Strategy;
+
+    #[test]
+    fn partial_reports_add_up_to_the_final_report() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"B" "foo":"baz"}
+{"type":"A","foo":"bar"}
+"#;
+        for strategy in [Strategy::Naive, Strategy::Chunks] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(30)
+                .build();
+            let mut partials = analyze_reader_partials(file_content.as_bytes(), &options);
+            let merged = partials.by_ref().fold(Report::new(), |acc, p| acc + p);
+            let report = partials.finish().unwrap();
+            assert_eq!(merged, report);
+            assert_eq!(report.get("ERROR").map(|r| r.count), Some(1));
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::sql::Query;
+use crate::template::escape;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+};
+
+/// Wide-format table crossing two fields, like `rows=type cols=status values=count`:
+/// one row per value of the first field and one column per value of the second one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pivot {
+    pub rows: String,
+    pub cols: String,
+    pub values: PivotValues,
+}
+
+/// Figure shown in the cells of a pivot table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PivotValues {
+    Count,
+    Bytes,
+}
+
+/// Result of a pivot, with the totals of every row and column.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PivotTable {
+    pub row_field: String,
+    pub columns: Vec<String>,
+    /// Label, cells and total of every row.
+    pub rows: Vec<(String, Vec<u64>, u64)>,
+    pub column_totals: Vec<u64>,
+    pub total: u64,
+    /// Lines that couldn't be parsed as JSON and were left out.
+    pub errors: usize,
+}
+
+impl Pivot {
+    /// Groups the lines of the reader by both fields and lays the groups out as a table.
+    pub fn run(&self, reader: impl BufRead) -> io::Result<PivotTable> {
+        let value = match self.values {
+            PivotValues::Count => "count(*)",
+            PivotValues::Bytes => "sum(length)",
+        };
+        let query: Query = format!(
+            "SELECT {rows}, {cols}, {value} FROM input GROUP BY {rows}, {cols}",
+            rows = quote(&self.rows),
+            cols = quote(&self.cols),
+            value = value
+        )
+        .parse()
+        .map_err(io::Error::other)?;
+        let result = query.run(reader)?;
+        let mut cells: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+        for row in &result.rows {
+            let value = row[2].as_u64().unwrap_or(0);
+            *cells
+                .entry(label(&row[0]))
+                .or_default()
+                .entry(label(&row[1]))
+                .or_default() += value;
+        }
+        let mut columns: Vec<String> = cells.values().flat_map(|c| c.keys().cloned()).collect();
+        columns.sort();
+        columns.dedup();
+        let mut table = PivotTable {
+            row_field: self.rows.clone(),
+            column_totals: vec![0; columns.len()],
+            errors: result.errors,
+            ..PivotTable::default()
+        };
+        for (label, row) in cells {
+            let values: Vec<u64> = columns
+                .iter()
+                .map(|column| row.get(column).copied().unwrap_or(0))
+                .collect();
+            for (total, value) in table.column_totals.iter_mut().zip(&values) {
+                *total += value;
+            }
+            let total = values.iter().sum();
+            table.total += total;
+            table.rows.push((label, values, total));
+        }
+        table.columns = columns;
+        Ok(table)
+    }
+}
+
+impl std::str::FromStr for Pivot {
+    type Err = String;
+
+    /// Parses `rows=<field> cols=<field> [values=count|bytes]`, separated by spaces or commas.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut rows, mut cols, mut values) = (None, None, PivotValues::Count);
+        for part in s.split([' ', ',']).filter(|part| !part.is_empty()) {
+            match part.split_once('=') {
+                Some(("rows", field)) if !field.is_empty() => rows = Some(field.to_string()),
+                Some(("cols", field)) if !field.is_empty() => cols = Some(field.to_string()),
+                Some(("values", "count")) => values = PivotValues::Count,
+                Some(("values", "bytes")) => values = PivotValues::Bytes,
+                _ => {
+                    return Err(format!(
+                        "invalid pivot `{}`, expected rows=<field> cols=<field> values=count|bytes",
+                        s
+                    ))
+                }
+            }
+        }
+        match (rows, cols) {
+            (Some(rows), Some(cols)) => Ok(Pivot { rows, cols, values }),
+            _ => Err(format!(
+                "invalid pivot `{}`, both rows and cols are required",
+                s
+            )),
+        }
+    }
+}
+
+impl PivotTable {
+    /// Writes the table as CSV, with a header, a `TOTAL` column and a `TOTAL` row.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        let header = std::iter::once(self.row_field.as_str())
+            .chain(self.columns.iter().map(String::as_str))
+            .chain(std::iter::once("TOTAL"))
+            .map(csv_field)
+            .collect::<Vec<_>>();
+        writeln!(writer, "{}", header.join(","))?;
+        for (label, values, total) in &self.rows {
+            writeln!(
+                writer,
+                "{},{},{}",
+                csv_field(label),
+                join(values, ","),
+                total
+            )?;
+        }
+        writeln!(
+            writer,
+            "TOTAL,{},{}",
+            join(&self.column_totals, ","),
+            self.total
+        )?;
+        writer.flush()
+    }
+
+    /// Renders the table as a standalone HTML `<table>` element.
+    pub fn to_html(&self) -> String {
+        let cells = |values: &[u64], total: u64| {
+            values
+                .iter()
+                .chain(std::iter::once(&total))
+                .map(|value| format!("<td class=\"number\">{}</td>", value))
+                .collect::<String>()
+        };
+        let mut html = String::from("<table>\n  <tr>");
+        html.push_str(&format!("<th>{}</th>", escape(&self.row_field)));
+        for column in &self.columns {
+            html.push_str(&format!("<th>{}</th>", escape(column)));
+        }
+        html.push_str("<th>TOTAL</th></tr>\n");
+        for (label, values, total) in &self.rows {
+            html.push_str(&format!(
+                "  <tr><th>{}</th>{}</tr>\n",
+                escape(label),
+                cells(values, *total)
+            ));
+        }
+        html.push_str(&format!(
+            "  <tr><th>TOTAL</th>{}</tr>\n</table>\n",
+            cells(&self.column_totals, self.total)
+        ));
+        html
+    }
+}
+
+/// Double quotes make the field a name in the query, whatever it contains.
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn label(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn join(values: &[u64], separator: &str) -> String {
+    let values: Vec<String> = values.iter().map(u64::to_string).collect();
+    values.join(separator)
+}
+
+/// Quotes the field if it has commas, quotes or newlines.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: &str = r#"{"type":"A","status":200}
+{"type":"A","status":500}
+{"type":"A","status":200}
+{"type":"B,C","status":200}
+{"type":"B,C"}
+"#;
+
+    #[test]
+    fn pivot_crosses_both_fields_with_totals() {
+        let pivot: Pivot = "rows=type cols=status values=count".parse().unwrap();
+        let table = pivot.run(LINES.as_bytes()).unwrap();
+        assert_eq!(table.columns, vec!["200", "500", "null"]);
+        assert_eq!(
+            table.rows,
+            vec![
+                ("A".to_string(), vec![2, 1, 0], 3),
+                ("B,C".to_string(), vec![1, 0, 1], 2),
+            ]
+        );
+        assert_eq!(table.column_totals, vec![3, 1, 1]);
+        assert_eq!(table.total, 5);
+
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "type,200,500,null,TOTAL\nA,2,1,0,3\n\"B,C\",1,0,1,2\nTOTAL,3,1,1,5\n"
+        );
+        let html = table.to_html();
+        assert!(html.contains("<tr><th>A</th><td class=\"number\">2</td>"));
+        assert!(html.contains("<tr><th>TOTAL</th><td class=\"number\">3</td>"));
+    }
+
+    #[test]
+    fn pivot_sums_the_bytes_when_asked_to() {
+        let pivot: Pivot = "rows=status,cols=type,values=bytes".parse().unwrap();
+        let table = pivot.run(LINES.as_bytes()).unwrap();
+        assert_eq!(table.columns, vec!["A", "B,C"]);
+        assert_eq!(table.rows[0], ("200".to_string(), vec![52, 28], 80));
+    }
+
+    #[test]
+    fn pivot_requires_both_fields() {
+        assert!("rows=type".parse::<Pivot>().is_err());
+        assert!("rows=type cols=status values=avg".parse::<Pivot>().is_err());
+        assert!("type status".parse::<Pivot>().is_err());
+    }
+}
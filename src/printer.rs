@@ -1,37 +1,1125 @@
-use crate::models::TypeLineResults;
-use prettytable::{cell, row, Table};
+use crate::chunk_stats::ChunkSummary;
+use crate::compressibility::CompressionEstimate;
+use crate::condition::Condition;
+use crate::cost::CostRate;
+use crate::durations::human_duration;
+use crate::estimate::Estimate;
+use crate::html::utc_timestamp;
+use crate::inspect::Inspection;
+use crate::labels::Labels;
+use crate::models::{DurationCounter, LinePosition, Report, TypeLineCounter};
+use crate::pivot::PivotTable;
+use crate::sql::QueryResult;
+use crate::template::Template;
+use crate::tree::{tree_rows, TreeRow};
+use crate::trend::{sparkline, Trend};
+#[cfg(feature = "tables")]
+use prettytable::{Cell, Row, Table};
+use std::{
+    borrow::Cow,
+    time::{Duration, UNIX_EPOCH},
+};
 
-pub fn print_table(pretty_print: bool, results: &TypeLineResults) {
-    if pretty_print {
-        print_pretty_table(results);
+/// How the results are shown to humans. Machine readable outputs always have the full keys.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    pub output: Output,
+    pub pretty: bool,
+    /// Keys longer than this amount of chars are shortened with an ellipsis.
+    pub max_key_width: Option<usize>,
+    /// Shortened keys end with a hash of the full key, so keys sharing their beginning stay apart.
+    pub hash_long_keys: bool,
+    /// Separator put between every three digits of the counts and bytes, like `12,345,678`.
+    pub thousands: Option<char>,
+    /// Prints hierarchical types as a tree, their levels being separated by this.
+    pub tree: Option<String>,
+    /// Order of the types in the tables. They are printed in no particular order if not set.
+    pub order: Option<Order>,
+    /// Shows the line number and byte offset of the first and last lines of every type.
+    /// It needs `Report::first_seen` and `Report::last_seen`.
+    pub detailed: bool,
+    /// Decimal places of the derived statistics, like averages. They're printed as computed if not set.
+    pub precision: Option<usize>,
+    /// Layout of the line of every type, replacing the tables and everything around them.
+    pub template: Option<Template>,
+    /// Prices per GB of the sinks, adding the estimated cost of every type to the tables.
+    pub costs: Vec<CostRate>,
+    /// Budgets of the types, the conditions breaking them being reported below the tables.
+    pub limits: Vec<Condition>,
+    /// Replacements of the headers and the category types, like `ERROR`. Snapshots keep theirs.
+    pub labels: Labels,
+}
+
+/// Layout of the printed results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    /// Tables for humans, lean or pretty.
+    #[default]
+    Table,
+    /// Stable layout for golden files: sorted, fixed-width, with full keys and raw numbers
+    /// and without timings.
+    Snapshot,
+}
+
+impl std::str::FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Output::Table),
+            "snapshot" => Ok(Output::Snapshot),
+            _ => Err(format!(
+                "unknown output `{}`, expected one of: table, snapshot",
+                s
+            )),
+        }
+    }
+}
+
+/// Order of the types in the tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Alphabetically by type.
+    Key,
+    /// Most common types first.
+    Count,
+    /// In the order the types first appeared in the input. It needs `Report::first_seen`,
+    /// types without a position go last.
+    FirstSeen,
+}
+
+impl std::str::FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(Order::Key),
+            "count" => Ok(Order::Count),
+            "first-seen" => Ok(Order::FirstSeen),
+            _ => Err(format!(
+                "unknown order `{}`, expected one of: key, count, first-seen",
+                s
+            )),
+        }
+    }
+}
+
+impl PrintOptions {
+    /// The key as shown in the tables, with the replacement of its label if it's a category.
+    pub fn key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self.labels.get(key) {
+            Some(label) => Cow::Owned(self.shorten(label).into_owned()),
+            None => self.shorten(key),
+        }
+    }
+
+    /// The header or category as shown in the tables.
+    pub fn label<'a>(&'a self, label: &'a str) -> &'a str {
+        self.labels.label(label)
+    }
+
+    fn shorten<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let width = match self.max_key_width {
+            Some(width) if key.chars().count() > width => width,
+            _ => return Cow::Borrowed(key),
+        };
+        if self.hash_long_keys {
+            let hash = format!("{:08x}", fnv1a(key.as_bytes()));
+            let prefix: String = key.chars().take(width.saturating_sub(9)).collect();
+            Cow::Owned(format!("{}…{}", prefix, hash))
+        } else {
+            let prefix: String = key.chars().take(width.saturating_sub(1)).collect();
+            Cow::Owned(format!("{}…", prefix))
+        }
+    }
+}
+
+impl PrintOptions {
+    /// The number as shown in the tables.
+    pub fn number(&self, n: impl ToString) -> String {
+        let digits = n.to_string();
+        let separator = match self.thousands {
+            Some(separator) if digits.len() > 3 => separator,
+            _ => return digits,
+        };
+        let mut number = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                number.push(separator);
+            }
+            number.push(digit);
+        }
+        number
+    }
+}
+
+impl PrintOptions {
+    /// The position as shown in the tables, empty if it wasn't tracked.
+    fn position(&self, position: Option<&LinePosition>) -> String {
+        match position {
+            Some(position) => format!(
+                "{} (byte {})",
+                self.number(position.line_number),
+                self.number(position.offset)
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+impl PrintOptions {
+    /// The derived statistic as shown in the tables, rounded to `precision` decimal places.
+    pub fn decimal(&self, value: f64) -> String {
+        match self.precision {
+            Some(decimals) => format!("{:.*}", decimals, round_half_even(value, decimals)),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// Rounds to the given decimal places with the halves going to the even digit (banker's rounding),
+/// so the roundings of merged and recomputed statistics don't drift upwards.
+pub fn round_half_even(value: f64, decimals: usize) -> f64 {
+    let scale = 10f64.powi(decimals as i32);
+    let rounded = (value * scale).round_ties_even() / scale;
+    if rounded.is_finite() {
+        rounded
     } else {
-        print_lean_table(results);
+        value
     }
 }
 
-fn print_pretty_table(results: &TypeLineResults) {
-    let mut table = Table::new();
-    table.add_row(row!["TYPE", "TOTAL COUNT", "TOTAL BYTES"]);
-    for (key, counter) in results {
-        table.add_row(row![
-            &key,
-            counter.count.to_string(),
-            counter.bytes.to_string()
-        ]);
+/// 32 bits FNV-1a, stable across runs and platforms unlike the std hasher.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub fn print_table(print: &PrintOptions, results: &Report) {
+    if print.output == Output::Snapshot {
+        print!("{}", snapshot(results));
+        return;
     }
-    table.printstd();
+    if let Some(template) = &print.template {
+        for (key, counter) in ordered_types(print, results) {
+            println!("{}", template.render(key, counter, results));
+        }
+        return;
+    }
+    match (&print.tree, print.pretty) {
+        (Some(separator), true) => print_pretty_tree(print, &tree_rows(results, separator)),
+        (Some(separator), false) => print_lean_tree(print, &tree_rows(results, separator)),
+        (None, true) => print_pretty_table(print, results),
+        (None, false) => print_lean_table(print, results),
+    }
+    let trailing = &results.trailing_garbage;
+    if trailing.count > 0 {
+        println!(
+            "{}: {} lines | {} bytes ignored",
+            print.label("TRAILING GARBAGE"),
+            print.number(trailing.count),
+            print.number(trailing.bytes)
+        );
+    }
+    let duplicates = results.total_duplicates();
+    if duplicates.count > 0 {
+        println!(
+            "{}: {} lines | {} bytes",
+            print.label("EXACT DUPLICATES"),
+            print.number(duplicates.count),
+            print.number(duplicates.bytes)
+        );
+        let mut types: Vec<_> = results.duplicates.iter().collect();
+        types.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        for (key, counter) in types {
+            let mut line = String::new();
+            push_field(&mut line, print.label("TYPE"), &print.key(key));
+            push_field(
+                &mut line,
+                print.label("DUPLICATES"),
+                &print.number(counter.count),
+            );
+            push_field(
+                &mut line,
+                print.label("BYTES"),
+                &print.number(counter.bytes),
+            );
+            println!("  {}", line);
+        }
+    }
+    if !results.outliers.is_empty() {
+        println!(
+            "{}: {} lines",
+            print.label("LONGEST LINES"),
+            print.number(results.outliers.len())
+        );
+        for outlier in results.outliers.sorted() {
+            let mut line = String::new();
+            push_field(&mut line, print.label("TYPE"), &print.key(&outlier.key));
+            push_field(
+                &mut line,
+                print.label("LINE"),
+                &print.number(outlier.position.line_number),
+            );
+            push_field(
+                &mut line,
+                print.label("OFFSET"),
+                &print.number(outlier.position.offset),
+            );
+            push_field(
+                &mut line,
+                print.label("BYTES"),
+                &print.number(outlier.length),
+            );
+            push_field(&mut line, print.label("PREVIEW"), &outlier.preview);
+            println!("  {}", line);
+        }
+    }
+    if !print.costs.is_empty() {
+        let bytes = results.types.values().map(|counter| counter.bytes).sum();
+        for rate in &print.costs {
+            println!(
+                "TOTAL {}: {}",
+                rate.label(),
+                print.decimal(rate.cost(bytes))
+            );
+        }
+    }
+    for limit in print.limits.iter().filter(|limit| limit.is_met(results)) {
+        println!(
+            "{}: {} | {}: {}",
+            print.label("LIMIT EXCEEDED"),
+            limit,
+            print.label("ACTUAL"),
+            print.number(limit.actual(results))
+        );
+    }
+    if let Some(format) = results.format {
+        println!("{}: {} (detected)", print.label("FORMAT"), format);
+    }
+    if let Some(compression) = results.compression {
+        let mut line = String::new();
+        push_field(
+            &mut line,
+            print.label("COMPRESSION"),
+            &format!("{:?}", compression.compression),
+        );
+        push_field(
+            &mut line,
+            print.label("COMPRESSED BYTES"),
+            &print.number(compression.compressed_bytes),
+        );
+        push_field(
+            &mut line,
+            print.label("DECOMPRESSED BYTES"),
+            &print.number(compression.decompressed_bytes),
+        );
+        push_field(
+            &mut line,
+            print.label("RATIO"),
+            &format!("{:.2}", compression.ratio()),
+        );
+        println!("{}", line);
+    }
+    if let Some(check) = &results.byte_check {
+        let mut line = String::new();
+        push_field(
+            &mut line,
+            print.label("READ"),
+            &print.number(check.read_bytes),
+        );
+        push_field(
+            &mut line,
+            print.label("IN LINES"),
+            &print.number(check.line_bytes),
+        );
+        push_field(
+            &mut line,
+            print.label("TERMINATORS"),
+            &print.number(check.terminator_bytes),
+        );
+        push_field(
+            &mut line,
+            print.label("COUNTED"),
+            &print.number(check.counted_bytes),
+        );
+        println!("{}: {}", print.label("BYTE CHECK"), line);
+        for (start, end) in &check.missing {
+            eprintln!(
+                "{}: the {} bytes from offset {} to {} were read but none of their lines made it into the results",
+                print.label("WARNING"),
+                print.number(end - start),
+                start,
+                end
+            );
+        }
+    }
+    if let Some(growth) = results.file_growth {
+        eprintln!(
+            "{}: the file grew by {} bytes during the analysis, the results reflect a moving target",
+            print.label("WARNING"),
+            print.number(growth)
+        );
+    }
+}
+
+/// The types of the report in the order asked for.
+fn ordered_types<'a>(
+    print: &PrintOptions,
+    results: &'a Report,
+) -> Vec<(&'a str, &'a TypeLineCounter)> {
+    let mut types: Vec<_> = results
+        .types
+        .iter()
+        .map(|(key, counter)| (key.as_ref(), counter))
+        .collect();
+    match print.order {
+        Some(Order::Key) => types.sort_by(|a, b| a.0.cmp(b.0)),
+        Some(Order::Count) => types.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0))),
+        Some(Order::FirstSeen) => types.sort_by_key(|(key, _)| {
+            // `None` sorts before `Some`, so the types without a position are flagged to go last
+            let position = results.first_seen.get(*key);
+            (position.is_none(), position.copied(), *key)
+        }),
+        None => {}
+    }
+    types
 }
 
-fn print_lean_table(results: &TypeLineResults) {
+fn print_pretty_table(print: &PrintOptions, results: &Report) {
+    let mut header = cells(print, ["TYPE", "TOTAL COUNT", "TOTAL BYTES"]);
+    if print.detailed {
+        header.extend(cells(print, ["FIRST LINE", "LAST LINE"]));
+    }
+    let durations = !results.durations.is_empty();
+    if durations {
+        header.extend(cells(print, ["TOTAL DURATION", "AVG DURATION"]));
+    }
+    header.extend(print.costs.iter().map(CostRate::label));
+    let mut rows = vec![header];
+    for (key, counter) in ordered_types(print, results) {
+        let mut row = vec![
+            print.key(key).into_owned(),
+            print.number(counter.count),
+            print.number(counter.bytes),
+        ];
+        if print.detailed {
+            row.push(print.position(results.first_seen.get(key)));
+            row.push(print.position(results.last_seen.get(key)));
+        }
+        if durations {
+            let (total, average) = match results.durations.get(key) {
+                Some(durations) => duration_cells(durations),
+                None => (String::new(), String::new()),
+            };
+            row.push(total);
+            row.push(average);
+        }
+        for rate in &print.costs {
+            row.push(print.decimal(rate.cost(counter.bytes)));
+        }
+        rows.push(row);
+    }
+    print_grid(&rows);
+}
+
+fn print_lean_table(print: &PrintOptions, results: &Report) {
     let mut table = String::new();
-    for (key, counter) in results {
-        table.push_str("TYPE: ");
-        table.push_str(&key);
-        table.push_str(" | TOTAL COUNT: ");
-        table.push_str(counter.count.to_string().as_str());
-        table.push_str(" | TOTAL BYTES: ");
-        table.push_str(counter.bytes.to_string().as_str());
-        table.push_str("\n");
+    for (key, counter) in ordered_types(print, results) {
+        push_field(&mut table, print.label("TYPE"), &print.key(key));
+        push_field(
+            &mut table,
+            print.label("TOTAL COUNT"),
+            &print.number(counter.count),
+        );
+        push_field(
+            &mut table,
+            print.label("TOTAL BYTES"),
+            &print.number(counter.bytes),
+        );
+        if let (true, Some(first), Some(last)) = (
+            print.detailed,
+            results.first_seen.get(key),
+            results.last_seen.get(key),
+        ) {
+            push_field(
+                &mut table,
+                print.label("FIRST LINE"),
+                &print.position(Some(first)),
+            );
+            push_field(
+                &mut table,
+                print.label("LAST LINE"),
+                &print.position(Some(last)),
+            );
+        }
+        if let Some(durations) = results.durations.get(key) {
+            let (total, average) = duration_cells(durations);
+            push_field(&mut table, print.label("TOTAL DURATION"), &total);
+            push_field(&mut table, print.label("AVG DURATION"), &average);
+        }
+        for rate in &print.costs {
+            push_field(
+                &mut table,
+                &rate.label(),
+                &print.decimal(rate.cost(counter.bytes)),
+            );
+        }
+        table.push('\n');
     }
     println!("{}", table);
 }
+
+/// Appends `LABEL: value` to the line being built at the end of the table, separating it
+/// from the previous field of the line with a bar.
+fn push_field(table: &mut String, label: &str, value: &str) {
+    if !table.is_empty() && !table.ends_with('\n') {
+        table.push_str(" | ");
+    }
+    table.push_str(label);
+    table.push_str(": ");
+    table.push_str(value);
+}
+
+/// The total and average durations of a type as shown in the tables.
+fn duration_cells(durations: &DurationCounter) -> (String, String) {
+    let average = durations.average().map_or_else(String::new, human_duration);
+    (human_duration(durations.seconds()), average)
+}
+
+/// The report laid out for golden files. Lines are sorted by type and the columns are
+/// padded to the widest value, so the same results always give the same bytes.
+pub fn snapshot(results: &Report) -> String {
+    let mut rows: Vec<(&str, String, String)> = results
+        .types
+        .iter()
+        .map(|(key, counter)| {
+            (
+                key.as_ref(),
+                counter.count.to_string(),
+                counter.bytes.to_string(),
+            )
+        })
+        .collect();
+    rows.sort();
+    let trailing = &results.trailing_garbage;
+    if trailing.count > 0 {
+        rows.push((
+            "TRAILING GARBAGE",
+            trailing.count.to_string(),
+            trailing.bytes.to_string(),
+        ));
+    }
+    let duplicates = results.total_duplicates();
+    if duplicates.count > 0 {
+        rows.push((
+            "EXACT DUPLICATES",
+            duplicates.count.to_string(),
+            duplicates.bytes.to_string(),
+        ));
+    }
+    let header = ("TYPE", "COUNT".to_string(), "BYTES".to_string());
+    let widths =
+        std::iter::once(&header)
+            .chain(&rows)
+            .fold((0, 0, 0), |(key, count, bytes), row| {
+                (
+                    key.max(row.0.chars().count()),
+                    count.max(row.1.len()),
+                    bytes.max(row.2.len()),
+                )
+            });
+    let mut snapshot = String::new();
+    for (key, count, bytes) in std::iter::once(&header).chain(&rows) {
+        let padding = widths.0 - key.chars().count();
+        snapshot.push_str(&format!(
+            "{}{}  {:>count_width$}  {:>bytes_width$}\n",
+            key,
+            " ".repeat(padding),
+            count,
+            bytes,
+            count_width = widths.1,
+            bytes_width = widths.2
+        ));
+    }
+    if let Some(format) = results.format {
+        snapshot.push_str(&format!("FORMAT: {}\n", format));
+    }
+    snapshot
+}
+
+fn tree_label(print: &PrintOptions, row: &TreeRow) -> String {
+    format!(
+        "{}{}{}",
+        "  ".repeat(row.depth),
+        print.key(&row.label),
+        if row.subtotal {
+            format!(" {}", print.label("SUBTOTAL"))
+        } else {
+            String::new()
+        }
+    )
+}
+
+fn print_pretty_tree(print: &PrintOptions, rows: &[TreeRow]) {
+    let mut grid = vec![cells(print, ["TYPE", "TOTAL COUNT", "TOTAL BYTES"])];
+    for row in rows {
+        let (count, bytes) = match &row.counter {
+            Some(counter) => (print.number(counter.count), print.number(counter.bytes)),
+            None => (String::new(), String::new()),
+        };
+        grid.push(vec![tree_label(print, row), count, bytes]);
+    }
+    print_grid(&grid);
+}
+
+fn print_lean_tree(print: &PrintOptions, rows: &[TreeRow]) {
+    let mut table = String::new();
+    for row in rows {
+        table.push_str(&tree_label(print, row));
+        if let Some(counter) = &row.counter {
+            push_field(
+                &mut table,
+                print.label("TOTAL COUNT"),
+                &print.number(counter.count),
+            );
+            push_field(
+                &mut table,
+                print.label("TOTAL BYTES"),
+                &print.number(counter.bytes),
+            );
+        }
+        table.push('\n');
+    }
+    println!("{}", table);
+}
+
+pub fn print_inspection(print: &PrintOptions, inspection: &Inspection, hints: &[String]) {
+    let average_line_length = inspection
+        .average_line_length
+        .map(|average| print.decimal(average))
+        .unwrap_or_else(|| "-".to_string());
+    let rows = [
+        ("SIZE", inspection.size.to_string()),
+        ("SAMPLED BYTES", inspection.sampled_bytes.to_string()),
+        ("ESTIMATED LINES", inspection.estimated_lines.to_string()),
+        ("AVERAGE LINE LENGTH", average_line_length),
+        (
+            "LONGEST SAMPLED LINE",
+            inspection.longest_sampled_line.to_string(),
+        ),
+        ("NEWLINE", format!("{:?}", inspection.newline)),
+        ("BOM", optional(inspection.bom)),
+        ("COMPRESSION", optional(inspection.compression)),
+        (
+            "ENDS WITH NEWLINE",
+            inspection.ends_with_newline.to_string(),
+        ),
+    ];
+    if print.pretty {
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|(name, value)| vec![print.label(name).to_string(), value.clone()])
+            .collect();
+        print_grid(&rows);
+    } else {
+        let mut table = String::new();
+        for (name, value) in &rows {
+            push_field(&mut table, print.label(name), value);
+            table.push('\n');
+        }
+        print!("{}", table);
+    }
+    for hint in hints {
+        println!("{}: {}", print.label("HINT"), hint);
+    }
+}
+
+fn optional(value: Option<impl std::fmt::Debug>) -> String {
+    value
+        .map(|value| format!("{:?}", value))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn print_estimate(print: &PrintOptions, estimate: &Estimate) {
+    if estimate.exact {
+        println!(
+            "The sample covered the whole file ({} bytes). The figures are exact.",
+            estimate.size
+        );
+    } else {
+        println!(
+            "ESTIMATE based on the first {} of {} bytes ({} lines). Ranges are 95% confidence bounds.",
+            estimate.sampled_bytes, estimate.size, estimate.sampled_lines
+        );
+    }
+    if print.pretty {
+        let mut rows = vec![cells(
+            print,
+            [
+                "TYPE",
+                "ESTIMATED COUNT",
+                "COUNT RANGE",
+                "ESTIMATED BYTES",
+                "BYTES RANGE",
+            ],
+        )];
+        for (key, e) in &estimate.types {
+            rows.push(vec![
+                print.key(key).into_owned(),
+                print.number(e.count),
+                format!(
+                    "{} - {}",
+                    print.number(e.count_low),
+                    print.number(e.count_high)
+                ),
+                print.number(e.bytes),
+                format!(
+                    "{} - {}",
+                    print.number(e.bytes_low),
+                    print.number(e.bytes_high)
+                ),
+            ]);
+        }
+        print_grid(&rows);
+    } else {
+        let mut table = String::new();
+        for (key, e) in &estimate.types {
+            push_field(&mut table, print.label("TYPE"), &print.key(key));
+            push_field(
+                &mut table,
+                print.label("ESTIMATED COUNT"),
+                &format!(
+                    "{} ({} - {})",
+                    print.number(e.count),
+                    print.number(e.count_low),
+                    print.number(e.count_high)
+                ),
+            );
+            push_field(
+                &mut table,
+                print.label("ESTIMATED BYTES"),
+                &format!(
+                    "{} ({} - {})",
+                    print.number(e.bytes),
+                    print.number(e.bytes_low),
+                    print.number(e.bytes_high)
+                ),
+            );
+            table.push('\n');
+        }
+        println!("{}", table);
+    }
+}
+
+/// Prints the compression ratios of the sample of every type, one column per compressor.
+pub fn print_compression_estimate(print: &PrintOptions, estimate: &CompressionEstimate) {
+    let gzip = estimate.values().any(|c| c.gzip.is_some());
+    let zstd = estimate.values().any(|c| c.zstd.is_some());
+    let ratio = |ratio: Option<f64>| ratio.map_or_else(|| "-".to_string(), |r| print.decimal(r));
+    let mut rows = vec![cells(print, ["TYPE", "SAMPLED LINES", "SAMPLED BYTES"])];
+    if gzip {
+        rows[0].push(print.label("GZIP RATIO").to_string());
+    }
+    if zstd {
+        rows[0].push(print.label("ZSTD RATIO").to_string());
+    }
+    for (key, compression) in estimate {
+        let mut row = vec![
+            print.key(key).into_owned(),
+            print.number(compression.sampled_lines),
+            print.number(compression.sampled_bytes),
+        ];
+        if gzip {
+            row.push(ratio(compression.gzip));
+        }
+        if zstd {
+            row.push(ratio(compression.zstd));
+        }
+        rows.push(row);
+    }
+    println!("COMPRESSION ESTIMATE based on a sample of the lines of every type.");
+    if print.pretty {
+        print_grid(&rows);
+    } else {
+        let (header, rows) = rows.split_first().expect("The header is always there");
+        let mut table = String::new();
+        for row in rows {
+            for (label, value) in header.iter().zip(row) {
+                push_field(&mut table, label, value);
+            }
+            table.push('\n');
+        }
+        println!("{}", table);
+    }
+}
+
+/// Prints to the stderr how the size, lines and timings of the chunks are distributed, and
+/// how long every worker was busy parsing, so it doesn't mix with the results.
+pub fn print_chunk_summary(print: &PrintOptions, summary: &ChunkSummary) {
+    eprintln!(
+        "{}: {}",
+        print.label("CHUNKS"),
+        print.number(summary.chunks)
+    );
+    let count = |value: f64| print.number(value.round() as u64);
+    let timing = |value: f64| format!("{:.2}", value);
+    let figures: [(&str, _, &dyn Fn(f64) -> String); 5] = [
+        ("BYTES", &summary.bytes, &count),
+        ("LINES", &summary.lines, &count),
+        ("READ MS", &summary.read_ms, &timing),
+        ("PARSE MS", &summary.parse_ms, &timing),
+        ("PARSE MB/S", &summary.parse_mb_per_second, &timing),
+    ];
+    for (figure, distribution, format) in figures.iter() {
+        let mut line = String::new();
+        push_field(&mut line, print.label("FIGURE"), print.label(figure));
+        for (label, value) in [
+            ("MIN", distribution.min),
+            ("P50", distribution.p50),
+            ("P90", distribution.p90),
+            ("MAX", distribution.max),
+            ("MEAN", distribution.mean),
+        ] {
+            push_field(&mut line, print.label(label), &format(value));
+        }
+        eprintln!("  {}", line);
+    }
+    let mut line = String::new();
+    for (worker, busy) in summary.busy_ms_by_worker.iter().enumerate() {
+        push_field(&mut line, &worker.to_string(), &timing(*busy));
+    }
+    eprintln!("{}: {}", print.label("BUSY MS BY WORKER"), line);
+}
+
+pub fn print_query_result(print: &PrintOptions, result: &QueryResult) {
+    let cell = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Number(n) if n.is_f64() => print.decimal(n.as_f64().unwrap_or_default()),
+        value => value.to_string(),
+    };
+    if print.pretty {
+        let header = result.columns.iter().map(|c| c.to_uppercase()).collect();
+        let rows: Vec<Vec<String>> = std::iter::once(header)
+            .chain(result.rows.iter().map(|row| row.iter().map(cell).collect()))
+            .collect();
+        print_grid(&rows);
+    } else {
+        let mut table = String::new();
+        for row in &result.rows {
+            let cells: Vec<String> = result
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(column, value)| format!("{}: {}", column.to_uppercase(), cell(value)))
+                .collect();
+            table.push_str(&cells.join(" | "));
+            table.push('\n');
+        }
+        println!("{}", table);
+    }
+    if result.errors > 0 {
+        println!(
+            "{} lines couldn't be parsed and were left out",
+            result.errors
+        );
+    }
+}
+
+pub fn print_pivot(print: &PrintOptions, pivot: &PivotTable) {
+    let total = print.label("TOTAL");
+    let header = std::iter::once(pivot.row_field.to_uppercase())
+        .chain(pivot.columns.iter().cloned())
+        .chain(std::iter::once(total.to_string()));
+    let rows = pivot
+        .rows
+        .iter()
+        .map(|(label, values, total)| (label.as_str(), values, *total))
+        .chain(std::iter::once((total, &pivot.column_totals, pivot.total)));
+    if print.pretty {
+        let mut grid = vec![header.collect()];
+        for (label, values, total) in rows {
+            grid.push(
+                std::iter::once(label.to_string())
+                    .chain(values.iter().map(u64::to_string))
+                    .chain(std::iter::once(total.to_string()))
+                    .collect(),
+            );
+        }
+        print_grid(&grid);
+    } else {
+        let columns: Vec<String> = header.skip(1).collect();
+        let mut table = String::new();
+        for (label, values, total) in rows {
+            let cells: Vec<String> = columns
+                .iter()
+                .zip(values.iter().chain(std::iter::once(&total)))
+                .map(|(column, value)| format!("{}: {}", column, value))
+                .collect();
+            table.push_str(&format!(
+                "{}: {} | {}\n",
+                pivot.row_field.to_uppercase(),
+                label,
+                cells.join(" | ")
+            ));
+        }
+        println!("{}", table);
+    }
+    if pivot.errors > 0 {
+        println!(
+            "{} lines couldn't be parsed and were left out",
+            pivot.errors
+        );
+    }
+}
+
+pub fn print_trend(print: &PrintOptions, trend: &Trend) {
+    match (trend.timestamps.first(), trend.timestamps.last()) {
+        (Some(first), Some(last)) => println!(
+            "LAST {} RUNS, FROM {} TO {}",
+            trend.timestamps.len(),
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(*first)),
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(*last))
+        ),
+        _ => {
+            println!("The history has no runs yet");
+            return;
+        }
+    }
+    let signed = |delta: i64| {
+        let sign = if delta < 0 { '-' } else { '+' };
+        format!("{}{}", sign, print.number(delta.unsigned_abs()))
+    };
+    if print.pretty {
+        let mut rows = vec![cells(
+            print,
+            [
+                "TYPE",
+                "COUNT",
+                "COUNT DELTA",
+                "COUNT TREND",
+                "BYTES",
+                "BYTES DELTA",
+                "BYTES TREND",
+            ],
+        )];
+        for row in &trend.types {
+            rows.push(vec![
+                print.key(&row.key).into_owned(),
+                print.number(row.counts.last().copied().unwrap_or_default()),
+                signed(row.count_delta()),
+                sparkline(&row.counts),
+                print.number(row.bytes.last().copied().unwrap_or_default()),
+                signed(row.bytes_delta()),
+                sparkline(&row.bytes),
+            ]);
+        }
+        print_grid(&rows);
+    } else {
+        let mut table = String::new();
+        for row in &trend.types {
+            push_field(&mut table, print.label("TYPE"), &print.key(&row.key));
+            push_field(
+                &mut table,
+                print.label("COUNT"),
+                &format!(
+                    "{} ({}) {}",
+                    print.number(row.counts.last().copied().unwrap_or_default()),
+                    signed(row.count_delta()),
+                    sparkline(&row.counts)
+                ),
+            );
+            push_field(
+                &mut table,
+                print.label("BYTES"),
+                &format!(
+                    "{} ({}) {}",
+                    print.number(row.bytes.last().copied().unwrap_or_default()),
+                    signed(row.bytes_delta()),
+                    sparkline(&row.bytes)
+                ),
+            );
+            table.push('\n');
+        }
+        println!("{}", table);
+    }
+}
+
+/// The headers as shown in the tables.
+fn cells<const N: usize>(print: &PrintOptions, names: [&str; N]) -> Vec<String> {
+    names
+        .iter()
+        .map(|name| print.label(name).to_string())
+        .collect()
+}
+
+/// Prints the rows in a grid, the first one usually being the header.
+#[cfg(feature = "tables")]
+fn print_grid(rows: &[Vec<String>]) {
+    let mut table = Table::new();
+    for row in rows {
+        table.add_row(Row::new(row.iter().map(|cell| Cell::new(cell)).collect()));
+    }
+    table.printstd();
+}
+
+/// Without the `tables` feature, the cells of every row are just separated by bars.
+#[cfg(not(feature = "tables"))]
+fn print_grid(rows: &[Vec<String>]) {
+    for row in rows {
+        println!("{}", row.join(" | "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_options_shorten_the_long_keys() {
+        let mut print = PrintOptions {
+            max_key_width: Some(12),
+            ..PrintOptions::default()
+        };
+        assert_eq!(print.key("payment.card"), "payment.card");
+        assert_eq!(print.key("payment.card.authorized"), "payment.car…");
+        assert_eq!(print.key("páyment.cárd.authorized").chars().count(), 12);
+
+        print.hash_long_keys = true;
+        let authorized = print.key("payment.card.authorized");
+        let declined = print.key("payment.card.declined");
+        assert!(authorized.starts_with("pay…"));
+        assert_eq!(authorized.chars().count(), 12);
+        assert_ne!(authorized, declined);
+        assert_eq!(authorized, print.key("payment.card.authorized"));
+
+        print.max_key_width = None;
+        assert_eq!(
+            print.key("payment.card.authorized"),
+            "payment.card.authorized"
+        );
+    }
+
+    #[test]
+    fn snapshot_is_sorted_and_fixed_width() {
+        let mut results = Report::new();
+        results.add_line("payment.card".into(), 1_000);
+        results.add_line("ERROR".into(), 7);
+        results.add_line("api".into(), 20);
+        results.add_line("api".into(), 20);
+        assert_eq!(
+            snapshot(&results),
+            "TYPE          COUNT  BYTES\n\
+             ERROR             1      7\n\
+             api               2     40\n\
+             payment.card      1   1000\n"
+        );
+        results.trailing_garbage.add_bytes(3);
+        assert!(snapshot(&results).ends_with("TRAILING GARBAGE      1      3\n"));
+    }
+
+    #[test]
+    fn types_are_ordered_as_asked() {
+        use crate::models::LinePosition;
+
+        let mut results = Report::new();
+        for (key, line_number) in [("c", 1), ("a", 2), ("b", 3), ("b", 4), ("d", 0)].iter() {
+            results.add_line(Cow::Borrowed(*key), 10);
+            if *line_number > 0 {
+                results.add_position(
+                    key,
+                    LinePosition {
+                        line_number: *line_number,
+                        offset: *line_number as u64 * 10,
+                    },
+                );
+            }
+        }
+        let keys = |order| {
+            let print = PrintOptions {
+                order: Some(order),
+                ..PrintOptions::default()
+            };
+            ordered_types(&print, &results)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(Order::Key), ["a", "b", "c", "d"]);
+        assert_eq!(keys(Order::Count), ["b", "a", "c", "d"]);
+        assert_eq!(keys(Order::FirstSeen), ["c", "a", "b", "d"]);
+        assert_eq!("first-seen".parse(), Ok(Order::FirstSeen));
+        assert!("seen".parse::<Order>().is_err());
+    }
+
+    #[test]
+    fn print_options_separate_the_thousands() {
+        let mut print = PrintOptions::default();
+        assert_eq!(print.number(12_345_678u64), "12345678");
+        print.thousands = Some(',');
+        assert_eq!(print.number(12_345_678u64), "12,345,678");
+        assert_eq!(print.number(123_456usize), "123,456");
+        assert_eq!(print.number(1_000u64), "1,000");
+        assert_eq!(print.number(999u64), "999");
+        assert_eq!(print.number(0u64), "0");
+        print.thousands = Some('.');
+        assert_eq!(print.number(1_234_567u64), "1.234.567");
+    }
+
+    #[test]
+    fn print_options_show_the_positions() {
+        use crate::models::LinePosition;
+
+        let print = PrintOptions {
+            thousands: Some(','),
+            ..PrintOptions::default()
+        };
+        let position = LinePosition {
+            line_number: 1_234,
+            offset: 567_890,
+        };
+        assert_eq!(print.position(Some(&position)), "1,234 (byte 567,890)");
+        assert_eq!(print.position(None), "");
+    }
+
+    #[test]
+    fn labels_replace_the_headers_and_the_categories() {
+        let print = PrintOptions {
+            max_key_width: Some(4),
+            labels: [("error", "ERREUR"), ("total_count", "NOMBRE")]
+                .iter()
+                .copied()
+                .collect(),
+            ..PrintOptions::default()
+        };
+        assert_eq!(print.key("ERROR"), "ERR…");
+        assert_eq!(print.key("error"), "err…");
+        assert_eq!(cells(&print, ["TYPE", "TOTAL COUNT"]), ["TYPE", "NOMBRE"]);
+
+        let mut table = String::new();
+        push_field(&mut table, print.label("TYPE"), &print.key("ERROR"));
+        push_field(&mut table, print.label("TOTAL COUNT"), "3");
+        table.push('\n');
+        push_field(&mut table, print.label("TYPE"), "A");
+        assert_eq!(table, "TYPE: ERR… | NOMBRE: 3\nTYPE: A");
+    }
+
+    #[test]
+    fn derived_statistics_are_rounded_half_to_even() {
+        assert_eq!(round_half_even(0.125, 2), 0.12);
+        assert_eq!(round_half_even(0.375, 2), 0.38);
+        assert_eq!(round_half_even(2.5, 0), 2.0);
+        assert_eq!(round_half_even(-1.5, 0), -2.0);
+        assert!(round_half_even(f64::NAN, 2).is_nan());
+
+        let mut print = PrintOptions::default();
+        assert_eq!(print.decimal(1.0 / 3.0), (1.0f64 / 3.0).to_string());
+        print.precision = Some(2);
+        assert_eq!(print.decimal(1.0 / 3.0), "0.33");
+        assert_eq!(print.decimal(0.125), "0.12");
+        assert_eq!(print.decimal(7.0), "7.00");
+        print.precision = Some(0);
+        assert_eq!(print.decimal(3.5), "4");
+    }
+}
@@ -0,0 +1,308 @@
+use crate::parser::split_path;
+use serde::de::IgnoredAny;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+// NOTE: Extracting a few fields from wide records doesn't need the whole line as a
+// `serde_json::Value`. The scanner below walks the line skipping the values of the other
+// fields without decoding them, only parses the values it's asked for, and stops as soon
+// as all of them are found. The flip side is that a line broken after the last field
+// needed isn't detected as invalid.
+
+/// Fields to extract from JSON object lines.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Projection {
+    root: Node,
+    /// Path given first, the one of `field`.
+    first: Vec<String>,
+}
+
+/// Fields wanted within an object. A node without fields takes the whole value.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Node {
+    fields: HashMap<String, Node>,
+}
+
+impl Projection {
+    /// Projection of the paths, their fields being nested. When a path is the beginning
+    /// of another one, its whole value is taken.
+    pub(crate) fn new<'a>(paths: impl IntoIterator<Item = &'a [String]>) -> Self {
+        let mut root = Node::default();
+        let mut paths = paths.into_iter().filter(|p| !p.is_empty()).peekable();
+        let first = paths.peek().map_or_else(Vec::new, |path| path.to_vec());
+        // the shorter paths go first, so they're known when their longer versions come
+        let mut paths: Vec<&[String]> = paths.collect();
+        paths.sort_by_key(|path| path.len());
+        for path in paths {
+            let mut node = &mut root;
+            for field in path {
+                let known = node.fields.contains_key(field);
+                node = node.fields.entry(field.clone()).or_default();
+                if known && node.fields.is_empty() {
+                    // a shorter path already takes the whole value
+                    break;
+                }
+            }
+        }
+        Projection { root, first }
+    }
+
+    /// Projection of a single field. Nested fields are separated by dots.
+    pub(crate) fn single(path: &str) -> Self {
+        Self::new([split_path(path).as_slice()])
+    }
+
+    /// The line as an object holding only the projected fields, or `None` if it isn't a
+    /// JSON object up to the last field needed. Without fields, the whole line is validated.
+    pub(crate) fn project(&self, line: &[u8]) -> Option<Value> {
+        if self.root.fields.is_empty() {
+            serde_json::from_slice::<IgnoredAny>(line).ok()?;
+            return Some(Value::Object(Map::new()));
+        }
+        let mut scanner = Scanner { line, position: 0 };
+        scanner.expect(b'{')?;
+        let (object, complete) = scanner.object(&self.root)?;
+        if !complete && !scanner.at_end() {
+            return None;
+        }
+        Some(Value::Object(object))
+    }
+
+    /// Value of the first path of the projection, `None` if the line has no such field.
+    pub(crate) fn field(&self, line: &[u8]) -> Option<Value> {
+        let mut value = self.project(line)?;
+        for field in &self.first {
+            value = value.as_object_mut()?.remove(field)?;
+        }
+        Some(value)
+    }
+}
+
+struct Scanner<'a> {
+    line: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .line
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.line.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.peek()? == byte).then(|| self.position += 1)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    /// Reads the members of an object, its opening brace already consumed. Returns the
+    /// fields of the node found and whether all of them were, in which case the scanning
+    /// stopped right after the last one.
+    fn object(&mut self, node: &Node) -> Option<(Map<String, Value>, bool)> {
+        let mut object = Map::new();
+        if self.peek()? == b'}' {
+            self.position += 1;
+            return Some((object, false));
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            match node.fields.get(key.as_ref()) {
+                Some(child) if !object.contains_key(key.as_ref()) => {
+                    let value = if child.fields.is_empty() {
+                        let start = self.value_start()?;
+                        self.skip_value()?;
+                        serde_json::from_slice(&self.line[start..self.position]).ok()?
+                    } else if self.peek()? == b'{' {
+                        self.position += 1;
+                        let (nested, complete) = self.object(child)?;
+                        if complete && object.len() + 1 < node.fields.len() {
+                            // other fields of this object are still needed
+                            self.finish_object()?;
+                        }
+                        Value::Object(nested)
+                    } else {
+                        // not an object, so the nested fields aren't there
+                        self.skip_value()?;
+                        Value::Null
+                    };
+                    object.insert(key.into_owned(), value);
+                    if object.len() == node.fields.len() {
+                        return Some((object, true));
+                    }
+                }
+                _ => self.skip_value()?,
+            }
+            match self.peek()? {
+                b',' => self.position += 1,
+                b'}' => {
+                    self.position += 1;
+                    return Some((object, false));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Skips the remaining members of an object, up to its closing brace.
+    fn finish_object(&mut self) -> Option<()> {
+        loop {
+            match self.peek()? {
+                b'}' => {
+                    self.position += 1;
+                    return Some(());
+                }
+                b',' => {
+                    self.position += 1;
+                    self.string()?;
+                    self.expect(b':')?;
+                    self.skip_value()?;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn value_start(&mut self) -> Option<usize> {
+        self.peek().map(|_| self.position)
+    }
+
+    /// Reads a string, decoding it only if it has escapes.
+    fn string(&mut self) -> Option<std::borrow::Cow<'a, str>> {
+        let start = self.value_start()?;
+        self.skip_string()?;
+        let raw = &self.line[start + 1..self.position - 1];
+        if raw.contains(&b'\\') {
+            serde_json::from_slice(&self.line[start..self.position]).ok()
+        } else {
+            std::str::from_utf8(raw)
+                .ok()
+                .map(std::borrow::Cow::Borrowed)
+        }
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        self.expect(b'"')?;
+        loop {
+            match self.line.get(self.position)? {
+                b'\\' => self.position += 2,
+                b'"' => {
+                    self.position += 1;
+                    return Some(());
+                }
+                _ => self.position += 1,
+            }
+        }
+    }
+
+    /// Skips a value checking only that its brackets and quotes are balanced.
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'"' => self.skip_string(),
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                loop {
+                    match self.line.get(self.position)? {
+                        b'"' => {
+                            self.skip_string()?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                self.position += 1;
+                                return Some(());
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.position += 1;
+                }
+            }
+            _ => {
+                let start = self.position;
+                while self
+                    .line
+                    .get(self.position)
+                    .is_some_and(|b| !matches!(b, b',' | b'}' | b']') && !b.is_ascii_whitespace())
+                {
+                    self.position += 1;
+                }
+                (self.position > start).then_some(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn projection(paths: &[&str]) -> Projection {
+        let paths: Vec<Vec<String>> = paths.iter().map(|path| split_path(path)).collect();
+        Projection::new(paths.iter().map(Vec::as_slice))
+    }
+
+    #[test]
+    fn projection_keeps_only_the_fields_asked_for() {
+        let line = r#" {"a": [1, {"x": "}"}], "t\"y": "páy", "n": {"m": 1.5, "k": {"z": true}}, "b": null} "#
+            .as_bytes();
+        let projection = projection(&["t\"y", "n.k", "n.m", "missing", "a.x"]);
+        assert_eq!(
+            projection.project(line),
+            Some(json!({"a": null, "t\"y": "páy", "n": {"m": 1.5, "k": {"z": true}}}))
+        );
+        assert_eq!(
+            self::projection(&["n", "n.m"]).project(line),
+            Some(json!({"n": {"m": 1.5, "k": {"z": true}}}))
+        );
+        assert_eq!(Projection::single("n.m").field(line), Some(json!(1.5)));
+        assert_eq!(Projection::single("b").field(line), Some(Value::Null));
+        assert_eq!(Projection::single("c").field(line), None);
+    }
+
+    #[test]
+    fn projection_stops_once_the_fields_are_found() {
+        let projection = projection(&["n.m", "t"]);
+        assert_eq!(
+            projection.project(br#"{"n": {"m": 1, "x": 2}, "t": "a", "rest": broken"#),
+            Some(json!({"n": {"m": 1}, "t": "a"}))
+        );
+        assert_eq!(
+            projection.project(br#"{"n": {"m": 1, "x": 2}, "z": 3}"#),
+            Some(json!({"n": {"m": 1}}))
+        );
+        // broken before the fields, or with missing fields
+        assert_eq!(
+            projection.project(br#"{"n": {"m": 1, "x" 2}, "t": "a"}"#),
+            None
+        );
+        assert_eq!(projection.project(br#"{"n": {"m": 1}, "z": "#), None);
+        assert_eq!(projection.project(br#"{"z": 1} trailing"#), None);
+        assert_eq!(projection.project(br#"{"t": tru, "n": {"m": 1}}"#), None);
+        assert_eq!(projection.project(b"not json"), None);
+        assert_eq!(projection.project(b"[1]"), None);
+        assert_eq!(projection.project(b"{}\r\n"), Some(json!({})));
+    }
+
+    #[test]
+    fn projection_without_fields_validates_the_line() {
+        let projection = Projection::default();
+        assert_eq!(projection.project(br#"{"a": 1}"#), Some(json!({})));
+        assert_eq!(projection.project(br#"{"a": 1"#), None);
+    }
+}
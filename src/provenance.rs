@@ -0,0 +1,92 @@
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+
+/// Fields appended to the lines written by the sinks (`Split`, `Rejects`) so downstream systems
+/// can trace them back to where they were read: `_source_file`, `_line_number` and
+/// `_byte_offset`. Clones share the same source, so it can be changed from the `on_source`
+/// callback as the files of an analysis are read one after the other.
+#[derive(Debug, Clone)]
+pub struct Provenance(Arc<RwLock<String>>);
+
+impl Provenance {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self(Arc::new(RwLock::new(source.into())))
+    }
+
+    /// Source of the lines written from now on. Its signature matches the `on_source` callback.
+    pub fn set_source(&self, source: &str) {
+        *self.0.write().expect("The provenance lock is poisoned") = source.to_string();
+    }
+
+    /// The line with the fields appended. JSON objects keep their bytes and get the fields after
+    /// theirs. Anything else, like a line that couldn't be parsed, is kept as a string under
+    /// `_line` next to them.
+    pub(crate) fn annotate(&self, line: &[u8], line_number: usize, offset: u64) -> Vec<u8> {
+        let fields = format!(
+            "\"_source_file\":{},\"_line_number\":{},\"_byte_offset\":{}",
+            json!(*self.0.read().expect("The provenance lock is poisoned")),
+            line_number,
+            offset
+        );
+        let object = trim_whitespace(line);
+        let mut annotated = Vec::with_capacity(line.len() + fields.len() + 16);
+        if object.first() == Some(&b'{')
+            && serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(object).is_ok()
+        {
+            annotated.extend_from_slice(&object[..object.len() - 1]);
+            if !trim_whitespace(&object[1..object.len() - 1]).is_empty() {
+                annotated.push(b',');
+            }
+            annotated.extend_from_slice(fields.as_bytes());
+        } else {
+            annotated.push(b'{');
+            annotated.extend_from_slice(fields.as_bytes());
+            annotated.extend_from_slice(b",\"_line\":");
+            annotated
+                .extend_from_slice(json!(String::from_utf8_lossy(line)).to_string().as_bytes());
+        }
+        annotated.push(b'}');
+        annotated
+    }
+}
+
+fn trim_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotate_appends_the_fields_to_the_lines() {
+        let provenance = Provenance::new("app-1.log");
+        let annotated =
+            |line: &str| String::from_utf8(provenance.annotate(line.as_bytes(), 3, 42)).unwrap();
+        assert_eq!(
+            annotated("{\"type\":\"A\", \"n\": 1} "),
+            "{\"type\":\"A\", \"n\": 1,\"_source_file\":\"app-1.log\",\"_line_number\":3,\"_byte_offset\":42}"
+        );
+        assert_eq!(
+            annotated("{ }"),
+            "{ \"_source_file\":\"app-1.log\",\"_line_number\":3,\"_byte_offset\":42}"
+        );
+        provenance.set_source("app-2.log");
+        assert_eq!(
+            annotated("{\"type\":\"B\""),
+            "{\"_source_file\":\"app-2.log\",\"_line_number\":3,\"_byte_offset\":42,\"_line\":\"{\\\"type\\\":\\\"B\\\"\"}"
+        );
+        assert_eq!(
+            annotated("[1]"),
+            "{\"_source_file\":\"app-2.log\",\"_line_number\":3,\"_byte_offset\":42,\"_line\":\"[1]\"}"
+        );
+    }
+}
@@ -1,314 +1,3118 @@
-use crate::models::{IntermediateTypeLineCounter, TypeLine, TypeLineCounter, TypeLineResults};
-use crate::printer;
-use std::io::Read;
+use crate::advice::{advise_sequential, drop_cache};
+use crate::aggregator::Record;
+use crate::chunk_stats::ChunkStats;
+use crate::direct::InputFile;
+use crate::durations::duration_seconds;
+use crate::error::AnalyzeError;
+use crate::error_report::describe_error;
+use crate::format::{sniff, Format, JsonLinesReader};
+use crate::index::{intersect, ByteRange, Index, RangesReader};
+use crate::inspect::detect_binary;
+use crate::models::{ByteCheck, LinePosition, Report, ReportHasher};
+use crate::options::{
+    AnalyzeOptions, ChunkSizeCheck, ExplodeBytes, InvalidUtf8, KeyHasher, Strategy, WorkerPanic,
+};
+#[cfg(feature = "relaxed")]
+use crate::parser::parse_key_relaxed;
+use crate::parser::{is_truncated, nesting_depth, parse_key, replace_non_finite, Key};
+use crate::printer::{self, Output, PrintOptions};
+use crate::retry::RetryingReader;
+use crate::sources::SourceReports;
+use crate::throttle::ThrottledReader;
+#[cfg(feature = "affinity")]
+use core_affinity::CoreId;
+use std::io::{self, Read};
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    cell::Cell,
+    collections::{
+        hash_map::{DefaultHasher, RandomState},
+        HashMap, VecDeque,
+    },
     fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    sync::mpsc::channel,
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, Sender},
+        Arc,
+    },
     thread::spawn,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-const ERROR_TYPE: &'static str = "ERROR";
+const ERROR_TYPE: &str = "ERROR";
+const INVALID_UTF8_TYPE: &str = "INVALID_UTF8";
+const TRUNCATED_TYPE: &str = "TRUNCATED";
+const PANIC_TYPE: &str = "PANIC";
+const TOO_LONG_TYPE: &str = "TOO_LONG";
+const TOO_DEEP_TYPE: &str = "TOO_DEEP";
+/// Lines of the types past `max_types`.
+const OTHER_TYPE: &str = "OTHER";
+#[cfg(feature = "raw")]
+const UNMATCHED_TYPE: &str = "UNMATCHED";
+/// Bytes at the beginning of the input checked for binary content and sniffed for the format.
+const SAMPLE_SIZE: u64 = 8 * 1024;
+/// Bounds of the chunk size when it's tuned during the run.
+const MIN_ADAPTIVE_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_ADAPTIVE_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+/// Lines to parse before the error rate is checked during the run,
+/// so a few errors at the beginning of the file don't abort it.
+const MIN_LINES_FOR_ERROR_RATE: u64 = 1_000;
+
+thread_local! {
+    /// Callbacks notified by the current thread since its chunk began, and how many of them a
+    /// run of the chunk that panicked already notified, so its retry leaves them out.
+    static NOTIFIED: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+pub fn start(
+    path: PathBuf,
+    print: &PrintOptions,
+    options: &AnalyzeOptions,
+) -> Result<Report, AnalyzeError> {
+    start_with(&format!("the file {:?}", path), print, || {
+        analyze(&path, options)
+    })
+}
+
+/// Runs the given analysis of `source`, prints its results and returns them.
+pub fn start_with(
+    source: &str,
+    print: &PrintOptions,
+    analysis: impl FnOnce() -> Result<Report, AnalyzeError>,
+) -> Result<Report, AnalyzeError> {
+    let init = Instant::now();
+    let outcome = match analysis() {
+        Ok(results) => {
+            printer::print_table(print, &results);
+            Ok(results)
+        }
+        Err(AnalyzeError::Cancelled(results)) => {
+            eprintln!("The analysis was cancelled. Showing partial results.");
+            printer::print_table(print, &results);
+            Ok(*results)
+        }
+        Err(AnalyzeError::Incomplete {
+            results,
+            lost_bytes,
+        }) => {
+            eprintln!(
+                "The results of at least {} bytes were lost. Showing the INCOMPLETE results.",
+                lost_bytes
+            );
+            printer::print_table(print, &results);
+            Err(AnalyzeError::Incomplete {
+                results,
+                lost_bytes,
+            })
+        }
+        Err(e) => {
+            eprintln!("Error trying to read {} - {}", source, e);
+            Err(e)
+        }
+    };
+    if print.output == Output::Table && print.template.is_none() {
+        println!("Took {:?} microseconds", init.elapsed().as_micros());
+    }
+    outcome
+}
+
+/// Analyzes every file keeping their reports apart, by path.
+pub fn analyze_files(
+    paths: &[PathBuf],
+    options: &AnalyzeOptions,
+) -> Result<SourceReports, AnalyzeError> {
+    let mut sources = SourceReports::new();
+    for path in paths {
+        let source = path.display().to_string();
+        if let Some(on_source) = &options.on_source {
+            (on_source.0)(&source);
+        }
+        sources.insert(source, analyze(path, options)?);
+    }
+    Ok(sources)
+}
+
+/// Analyzes the file found in `path` using the given options.
+/// If the file grows while it's analyzed, the growth is kept in `Report::file_growth`
+/// unless the end is pinned with `snapshot`.
+pub fn analyze(path: &Path, options: &AnalyzeOptions) -> Result<Report, AnalyzeError> {
+    let initial_size = std::fs::metadata(path).ok().map(|m| m.len());
+    let mut end = match options.snapshot_size {
+        Some(size) => Some(size),
+        None if options.snapshot => initial_size,
+        None => None,
+    };
+    let mut start = 0;
+    if let (Some(time_range), Some(size)) = (&options.time_range, initial_size) {
+        let size = end.map_or(size, |end| end.min(size));
+        let (range_start, range_end) = time_range.locate(&mut File::open(path)?, size)?;
+        start = range_start;
+        end = Some(range_end);
+    }
+    // with an up to date index, only the blocks holding the types asked for are read
+    let ranges = match (&options.only, initial_size) {
+        (Some(only), Some(size)) => Index::read_from(&Index::path_of(path))
+            .ok()
+            .filter(|index| index.fits(size, options))
+            .map(|index| {
+                let ranges = index.ranges(only, options.time_range.as_ref());
+                intersect(&ranges, &[(start, end.unwrap_or(size))])
+            }),
+        _ => None,
+    };
+    let pinned = end.is_some() || ranges.is_some();
+    let analysis = if options.io_retries > 0 {
+        // the file is opened again on every retry as the old handle might be stale
+        let reader = RetryingReader::new(
+            || open_input(path, options),
+            options.io_retries,
+            options.io_retry_delay,
+        )?;
+        analyze_until(reader, start, end, ranges, initial_size, options)
+    } else {
+        analyze_until(
+            open_input(path, options)?,
+            start,
+            end,
+            ranges,
+            initial_size,
+            options,
+        )
+    };
+    if options.drop_cache_after {
+        if let Ok(file) = File::open(path) {
+            drop_cache(&file);
+        }
+    }
+    let mut results = analysis?;
+    if !pinned {
+        let final_size = std::fs::metadata(path).ok().map(|m| m.len());
+        if let (Some(initial), Some(last)) = (initial_size, final_size) {
+            results.file_growth = (last > initial).then(|| last - initial);
+        }
+    }
+    Ok(results)
+}
+
+/// Opens the file hinting the kernel that it's read from beginning to end,
+/// or bypassing the page cache with `direct_io`.
+fn open_input(path: &Path, options: &AnalyzeOptions) -> io::Result<InputFile> {
+    let input = InputFile::open(path, options.direct_io)?;
+    if let Some(file) = input.cached() {
+        advise_sequential(file);
+    }
+    Ok(input)
+}
+
+fn analyze_until(
+    mut reader: impl Read + Seek,
+    start: u64,
+    end: Option<u64>,
+    ranges: Option<Vec<ByteRange>>,
+    size: Option<u64>,
+    options: &AnalyzeOptions,
+) -> Result<Report, AnalyzeError> {
+    if let Some(ranges) = ranges {
+        let length = ranges.iter().map(|(start, end)| end - start).sum();
+        return analyze_source(
+            RangesReader::new(reader, ranges),
+            Some(length),
+            options,
+            None,
+        );
+    }
+    if start > 0 {
+        reader.seek(SeekFrom::Start(start))?;
+    }
+    match end {
+        Some(end) => {
+            let length = end.saturating_sub(start);
+            analyze_source(reader.take(length), Some(length), options, None)
+        }
+        None => analyze_source(
+            reader,
+            size.map(|size| size.saturating_sub(start)),
+            options,
+            None,
+        ),
+    }
+}
+
+/// Analyzes any source of bytes using the given options.
+pub fn analyze_reader(reader: impl Read, options: &AnalyzeOptions) -> Result<Report, AnalyzeError> {
+    analyze_source(reader, None, options, None)
+}
+
+/// Analyzes any source of bytes counting the types with the hasher `S`,
+/// like `fxhash::FxBuildHasher`. The `hasher` option is ignored.
+pub fn analyze_reader_with_hasher<S: ReportHasher>(
+    reader: impl Read,
+    options: &AnalyzeOptions,
+) -> Result<Report<S>, AnalyzeError> {
+    analyze_source_with_hasher(reader, None, options, None)
+}
+
+pub(crate) fn analyze_source(
+    reader: impl Read,
+    total: Option<u64>,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report, AnalyzeError> {
+    // the results are few, so going back to the default hasher is cheap
+    match options.hasher {
+        KeyHasher::Std => {
+            analyze_source_with_hasher::<RandomState>(reader, total, options, partials)
+        }
+        #[cfg(feature = "fast-hashers")]
+        KeyHasher::Fx => {
+            analyze_source_with_hasher::<fxhash::FxBuildHasher>(reader, total, options, partials)
+                .map(Report::with_hasher)
+        }
+        #[cfg(feature = "fast-hashers")]
+        KeyHasher::AHash => analyze_source_with_hasher::<
+            std::hash::BuildHasherDefault<ahash::AHasher>,
+        >(reader, total, options, partials)
+        .map(Report::with_hasher),
+    }
+}
+
+fn analyze_source_with_hasher<S: ReportHasher>(
+    reader: impl Read,
+    total: Option<u64>,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    match options.max_throughput {
+        Some(bytes_per_second) => run_analysis(
+            ThrottledReader::new(reader, bytes_per_second),
+            total,
+            options,
+            partials,
+        ),
+        None => run_analysis(reader, total, options, partials),
+    }
+}
+
+fn run_analysis<S: ReportHasher>(
+    reader: impl Read,
+    total: Option<u64>,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    #[cfg(feature = "gzip")]
+    let reader = {
+        let mut reader = reader;
+        let mut header = Vec::new();
+        (&mut reader)
+            .take(crate::gzip::BGZF_HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+        let is_gzip = header.starts_with(&crate::gzip::GZIP_MAGIC);
+        let bgzf = crate::gzip::is_bgzf(&header);
+        #[cfg(feature = "zstd")]
+        let is_zstd = header.starts_with(&crate::gzip::ZSTD_MAGIC);
+        let reader = std::io::Cursor::new(header).chain(reader);
+        if is_gzip {
+            return crate::gzip::analyze_gzip(reader, bgzf, options, partials);
+        }
+        #[cfg(feature = "zstd")]
+        if is_zstd {
+            return crate::gzip::analyze_zstd(reader, options, partials);
+        }
+        reader
+    };
+    analyze_decoded(reader, total, options, partials)
+}
+
+/// Analyzes an input that isn't compressed, or that's already being decompressed.
+pub(crate) fn analyze_decoded<S: ReportHasher>(
+    mut reader: impl Read,
+    total: Option<u64>,
+    options: &AnalyzeOptions,
+    partials: Option<Sender<Report>>,
+) -> Result<Report<S>, AnalyzeError> {
+    // the sample is read ahead and put back in front of the rest of the input
+    let mut sample = Vec::new();
+    if options.check_binary || options.format == Format::Auto {
+        (&mut reader).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+    }
+    if options.check_binary {
+        let check_utf8 = options.invalid_utf8 == InvalidUtf8::Error;
+        if let Some(compression) = detect_binary(&sample, check_utf8) {
+            return Err(AnalyzeError::BinaryContent { compression });
+        }
+    }
+    let format = match options.format {
+        Format::Auto => sniff(&sample),
+        format => format,
+    };
+    let reader = std::io::Cursor::new(sample).chain(reader);
+    let context = Context::new(options, total, partials);
+    // the bytes read are the ones handed to the strategy, so the offsets of both match
+    let read_bytes;
+    let mut results = match format {
+        format if format.is_lines() => {
+            let reader = CountingReader::new(reader);
+            read_bytes = reader.count.clone();
+            run_strategy(reader, options, &context)?
+        }
+        format => {
+            let reader = CountingReader::new(JsonLinesReader::new(BufReader::new(reader), format));
+            read_bytes = reader.count.clone();
+            run_strategy(reader, options, &context)?
+        }
+    };
+    // the digests are only needed to merge the partial results
+    results.digests = HashMap::new();
+    if let Some((depth, separator)) = options.rollup() {
+        results = results.rollup(depth, separator);
+    }
+    if options.check_bytes {
+        let counted_bytes = results.types.values().map(|c| c.bytes as u64).sum();
+        results
+            .byte_check
+            .get_or_insert_with(ByteCheck::default)
+            .finish(read_bytes.load(Ordering::Relaxed), counted_bytes);
+    }
+    // JSON lines are the norm, only the other formats are worth reporting
+    if options.format == Format::Auto && format != Format::Jsonl {
+        results.format = Some(format);
+    }
+    if options.is_cancelled() {
+        return Err(AnalyzeError::Cancelled(Box::new(results.with_hasher())));
+    }
+    let lost_bytes = context.lost_bytes.load(Ordering::Relaxed);
+    if lost_bytes > 0 {
+        return Err(AnalyzeError::Incomplete {
+            results: Box::new(results.with_hasher()),
+            lost_bytes,
+        });
+    }
+    context.check_error_rate(
+        context.lines.load(Ordering::Relaxed),
+        context.errors.load(Ordering::Relaxed),
+    )?;
+    Ok(results)
+}
+
+/// Reader keeping the count of the bytes read through it.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    pub(crate) count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+fn run_strategy<S: ReportHasher>(
+    reader: impl Read,
+    options: &AnalyzeOptions,
+    context: &Context,
+) -> Result<Report<S>, AnalyzeError> {
+    Ok(match options.strategy {
+        Strategy::Chunks => calculate_results(reader, options, context.clone())?,
+        Strategy::Naive => {
+            calculate_results_naive(&mut BufReader::new(reader), options, context.clone())?
+        }
+        Strategy::Batches => {
+            calculate_results_batches(&mut BufReader::new(reader), options, context)?
+        }
+    })
+}
+
+/// State shared by the workers of an analysis.
+/// It keeps track of the processed bytes and notifies the embedder about them.
+#[derive(Clone)]
+struct Context {
+    bytes_done: Arc<AtomicU64>,
+    total: Option<u64>,
+    options: Arc<AnalyzeOptions>,
+    partials: Option<Sender<Report>>,
+    aborted: Arc<AtomicBool>,
+    /// Lines parsed so far and how many of them were errors.
+    lines: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+    /// Time spent by the workers parsing, used to tune the chunk size.
+    parse_nanos: Arc<AtomicU64>,
+    /// Bytes whose results couldn't make it into the report.
+    lost_bytes: Arc<AtomicU64>,
+    /// Cores the workers are pinned to. Empty if they're not pinned.
+    #[cfg(feature = "affinity")]
+    cores: Arc<Vec<CoreId>>,
+}
+
+impl Context {
+    fn new(options: &AnalyzeOptions, total: Option<u64>, partials: Option<Sender<Report>>) -> Self {
+        Self {
+            bytes_done: Arc::new(AtomicU64::new(0)),
+            total,
+            options: Arc::new(options.clone()),
+            partials,
+            aborted: Arc::new(AtomicBool::new(false)),
+            lines: Arc::new(AtomicU64::new(0)),
+            errors: Arc::new(AtomicU64::new(0)),
+            parse_nanos: Arc::new(AtomicU64::new(0)),
+            lost_bytes: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "affinity")]
+            cores: Arc::new(if options.pin_threads {
+                core_affinity::get_core_ids().unwrap_or_default()
+            } else {
+                Vec::new()
+            }),
+        }
+    }
+
+    /// Pins the current thread to a core chosen by the index of the work it does.
+    #[cfg(feature = "affinity")]
+    fn pin(&self, worker: usize) {
+        if !self.cores.is_empty() {
+            // not being able to pin a thread doesn't affect the results
+            core_affinity::set_for_current(self.cores[worker % self.cores.len()]);
+        }
+    }
+
+    /// Threads can't be pinned without the `affinity` feature.
+    #[cfg(not(feature = "affinity"))]
+    fn pin(&self, _worker: usize) {}
+
+    /// Notifies the `on_chunk` callback about a parsed chunk, and logs it as a `tracing` event.
+    fn chunk_parsed(&self, stats: ChunkStats) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            chunk = stats.index,
+            worker = stats.worker,
+            offset = stats.offset,
+            bytes = stats.bytes,
+            lines = stats.lines,
+            read_us = stats.read.as_micros() as u64,
+            parse_us = stats.parse.as_micros() as u64,
+            "chunk parsed"
+        );
+        if let Some(on_chunk) = &self.options.on_chunk {
+            (on_chunk.0)(&stats);
+        }
+    }
+
+    /// Lets the rest of the workers know that a fatal error happened.
+    fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+    }
+
+    /// Records that the results of some bytes won't make it into the report.
+    fn lose(&self, bytes: usize) {
+        self.lost_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Whether the work should stop, either because of a cancellation or a fatal error.
+    fn is_stopped(&self) -> bool {
+        self.options.is_cancelled() || self.aborted.load(Ordering::Relaxed)
+    }
+
+    fn publish<S: ReportHasher>(&self, partial_results: &Report<S>) {
+        if let Some(partials) = &self.partials {
+            // nobody listening anymore is not an issue for the analysis itself
+            let _ = partials.send(partial_results.clone().with_hasher());
+        }
+    }
+
+    /// Accounts the lines parsed by a worker and checks the error rate so far.
+    fn track_lines<S: ReportHasher>(
+        &self,
+        lines: usize,
+        partial_results: &Report<S>,
+    ) -> Result<(), AnalyzeError> {
+        let lines = lines as u64;
+        let errors = [ERROR_TYPE, TRUNCATED_TYPE]
+            .iter()
+            .map(|key| partial_results.get(key).map_or(0, |c| c.count) as u64)
+            .sum();
+        let lines = self.lines.fetch_add(lines, Ordering::Relaxed) + lines;
+        let errors = self.errors.fetch_add(errors, Ordering::Relaxed) + errors;
+        if lines < MIN_LINES_FOR_ERROR_RATE {
+            return Ok(());
+        }
+        self.check_error_rate(lines, errors)
+    }
+
+    fn check_error_rate(&self, lines: u64, errors: u64) -> Result<(), AnalyzeError> {
+        match self.options.max_error_rate {
+            Some(max_error_rate) if errors as f64 > max_error_rate * lines as f64 => {
+                self.abort();
+                Err(AnalyzeError::TooManyErrors { errors, lines })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn advance(&self, bytes: usize) {
+        let bytes = bytes as u64;
+        let bytes_done = self.bytes_done.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(on_progress) = &self.options.on_progress {
+            (on_progress.0)(bytes_done, self.total);
+        }
+    }
+}
+
+/// Adds the line to the results unless its type is left out, notifying the `on_line` callback and
+/// looking for an identical earlier line, keeping its position or summing its duration if asked to.
+/// The duration is looked for in `fields`, the line itself unless fields were extracted from it.
+fn count_line<S: ReportHasher>(
+    results: &mut Report<S>,
+    key: Cow<'static, str>,
+    bytes: usize,
+    line: &[u8],
+    fields: &[u8],
+    position: LinePosition,
+    options: &AnalyzeOptions,
+) {
+    if let Some(only) = &options.only {
+        if !only.contains(key.as_ref()) {
+            return;
+        }
+    }
+    let key = match options.max_types {
+        Some(max) if results.get(&key).is_none() && results.len() + 1 >= max => {
+            Cow::Borrowed(OTHER_TYPE)
+        }
+        _ => key,
+    };
+    if let Some(on_line) = &options.on_line {
+        notify(|| (on_line.0)(&key, position.line_number, position.offset, line));
+    }
+    if options.positions {
+        results.add_position(&key, position);
+    }
+    if options.outliers > 0 {
+        let length = line_bytes(line, false);
+        results
+            .outliers
+            .add(options.outliers, &key, length, line, position);
+    }
+    if !options.aggregators.is_empty() {
+        results.observe(&Record::new(&key, fields, bytes), &options.aggregators);
+    }
+    if options.exact_duplicates {
+        results.add_digest(&key, line_digest(line), bytes);
+    }
+    if let Some(field) = &options.duration_projection {
+        let duration = field.field(fields);
+        if let Some(seconds) = duration.and_then(|d| duration_seconds(&d, options.duration_unit)) {
+            results.add_duration(&key, seconds);
+        }
+    }
+    results.add_line(key, bytes);
+}
+
+/// Records the lines of a chunk, batch or line found at `offset`, if the analysis checks its bytes.
+fn check_span<S: ReportHasher>(
+    results: &mut Report<S>,
+    span: &[u8],
+    offset: u64,
+    options: &AnalyzeOptions,
+) {
+    if !options.check_bytes {
+        return;
+    }
+    let terminator_bytes = span
+        .split_inclusive(|c| *c == b'\n')
+        .map(|line| (line.len() - line_bytes(line, false)) as u64)
+        .sum();
+    results
+        .byte_check
+        .get_or_insert_with(ByteCheck::default)
+        .add_span(offset, span.len() as u64, terminator_bytes);
+}
+
+/// Digest of the line without its terminator. Some strategies leave the `\r` of `\r\n` in the line.
+fn line_digest(line: &[u8]) -> u64 {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn notify_error(
+    options: &AnalyzeOptions,
+    line_number: usize,
+    offset: u64,
+    line: &[u8],
+    e: &serde_json::Error,
+) {
+    if let Some(on_error) = &options.on_error {
+        notify(|| (on_error.0)(line_number, offset, line, e));
+    } else if options.verbose_errors {
+        notify(|| {
+            eprintln!(
+                "Error found parsing line {} (byte offset {}) - {}",
+                line_number,
+                offset,
+                describe_error(line, e)
+            )
+        });
+    }
+}
+
+/// Calls the callback unless a run of the chunk that panicked already did. It only counts as
+/// notified once it returns, so a callback that panicked is called again by the retry.
+fn notify(callback: impl FnOnce()) {
+    let (notified, replayed) = NOTIFIED.with(Cell::get);
+    if notified >= replayed {
+        callback();
+    }
+    NOTIFIED.with(|n| n.set((notified + 1, replayed)));
+}
+
+/// Classifies a single line and adds it to the results.
+/// `line` is the raw line, with or without its terminator.
+fn process_line<S: ReportHasher>(
+    results: &mut Report<S>,
+    line: &[u8],
+    num_bytes: usize,
+    line_number: usize,
+    offset: u64,
+    options: &AnalyzeOptions,
+) -> Result<(), AnalyzeError> {
+    if is_skipped(line, line_number, options) {
+        return Ok(());
+    }
+    let position = LinePosition {
+        line_number,
+        offset,
+    };
+    if let Some(key) = exceeded_limit(line, options) {
+        count_line(
+            results,
+            Cow::Borrowed(key),
+            num_bytes,
+            line,
+            line,
+            position,
+            options,
+        );
+        return Ok(());
+    }
+    let line = match std::str::from_utf8(line) {
+        Ok(_) => Cow::Borrowed(line),
+        Err(_) => match options.invalid_utf8 {
+            InvalidUtf8::Error => {
+                return Err(AnalyzeError::InvalidUtf8 {
+                    line_number,
+                    offset,
+                })
+            }
+            InvalidUtf8::Replace => Cow::Owned(String::from_utf8_lossy(line).into_owned().into()),
+            InvalidUtf8::SkipLine => {
+                count_line(
+                    results,
+                    Cow::Borrowed(INVALID_UTF8_TYPE),
+                    num_bytes,
+                    line,
+                    line,
+                    position,
+                    options,
+                );
+                return Ok(());
+            }
+        },
+    };
+
+    #[cfg(feature = "raw")]
+    let extracted = options
+        .extract_regex
+        .as_ref()
+        .and_then(|extract| extract.enrich(&line, options.format == Format::Raw));
+    #[cfg(feature = "raw")]
+    let fields: &[u8] = extracted.as_deref().unwrap_or(&line);
+    #[cfg(not(feature = "raw"))]
+    let fields: &[u8] = &line;
+
+    #[cfg(feature = "raw")]
+    if options.format == Format::Raw {
+        // the regex grouping the lines goes first, then the fields extracted from them
+        let key = match (&options.group_by_regex, &extracted) {
+            (None, Some(extracted)) => parse_line_key(extracted, options).ok(),
+            _ => None,
+        };
+        match key {
+            Some((key, _)) => add_key(results, key, &line, fields, position, num_bytes, options),
+            None => {
+                let key = raw_key(&line, options.group_by_regex.as_ref());
+                count_line(results, key, num_bytes, &line, fields, position, options);
+            }
+        }
+        return Ok(());
+    }
+
+    // I used serde in order to validate that the text is valid JSON
+    // while only caring about the property we're grouping by.
+    // In case bad formatted JSON I decided to go on and count the error as a new
+    // category and also output the error in stderr.
+    let parsed = match &options.unwrap_path {
+        Some(path) => {
+            unwrap_line(fields, path).and_then(|inner| parse_line_key(inner.as_bytes(), options))
+        }
+        None => parse_line_key(fields, options),
+    };
+    match parsed {
+        Ok((key, trailing)) => {
+            if trailing > 0 {
+                results.trailing_garbage.add_bytes(trailing);
+            }
+            add_key(results, key, &line, fields, position, num_bytes, options);
+        }
+        Err(e) => {
+            notify_error(options, line_number, offset, &line, &e);
+            let key = if options.detect_truncated && is_truncated(&line) {
+                TRUNCATED_TYPE
+            } else {
+                ERROR_TYPE
+            };
+            count_line(
+                results,
+                Cow::Borrowed(key),
+                num_bytes,
+                &line,
+                &line,
+                position,
+                options,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Type of a raw line: the first capture group of the regex, or its whole match if it has no groups.
+#[cfg(feature = "raw")]
+fn raw_key(line: &[u8], regex: Option<&regex::Regex>) -> Cow<'static, str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    // the encoding has already been validated or replaced
+    let line = String::from_utf8_lossy(line);
+    let group = regex.and_then(|regex| {
+        let captures = regex.captures(&line)?;
+        captures.get(if regex.captures_len() > 1 { 1 } else { 0 })
+    });
+    match group {
+        Some(group) => Cow::Owned(group.as_str().to_string()),
+        None => Cow::Borrowed(UNMATCHED_TYPE),
+    }
+}
+
+/// Decodes the JSON line embedded as a string in the `path` field of an envelope,
+/// like the `log` field of Docker logs.
+fn unwrap_line(line: &[u8], path: &[String]) -> serde_json::Result<String> {
+    match parse_key(
+        line,
+        std::slice::from_ref(&path.to_vec()),
+        true,
+        false,
+        false,
+    )? {
+        (Key::Single(inner), _) => Ok(inner),
+        (Key::Exploded(_), _) => unreachable!("Keys are never exploded when explode is not set"),
+    }
+}
+
+fn parse_line_key(line: &[u8], options: &AnalyzeOptions) -> serde_json::Result<(Key, usize)> {
+    #[cfg(feature = "relaxed")]
+    if options.relaxed {
+        // the encoding has already been validated or replaced
+        let line = std::str::from_utf8(line).map_err(serde::de::Error::custom)?;
+        return parse_key_relaxed(
+            line,
+            &options.key_paths,
+            options.strict_keys,
+            options.explode,
+        )
+        .map(|key| (key, 0));
+    }
+    let parse = |line: &[u8]| {
+        parse_key(
+            line,
+            &options.key_paths,
+            options.strict_keys,
+            options.explode,
+            options.lenient_trailing,
+        )
+    };
+    match parse(line) {
+        // the literals are only looked for when the line fails, so valid lines don't pay for it
+        Err(e) if options.allow_non_finite => match replace_non_finite(line) {
+            Some(replaced) => parse(&replaced).map_err(|_| e),
+            None => Err(e),
+        },
+        parsed => parsed,
+    }
+}
+
+fn add_key<S: ReportHasher>(
+    results: &mut Report<S>,
+    key: Key,
+    line: &[u8],
+    fields: &[u8],
+    position: LinePosition,
+    num_bytes: usize,
+    options: &AnalyzeOptions,
+) {
+    match key {
+        Key::Single(key) => count_line(
+            results,
+            Cow::Owned(key),
+            num_bytes,
+            line,
+            fields,
+            position,
+            options,
+        ),
+        Key::Exploded(keys) => {
+            let elements = keys.len();
+            for (i, key) in keys.into_iter().enumerate() {
+                let bytes = match options.explode_bytes {
+                    ExplodeBytes::Full => num_bytes,
+                    // the remainder goes to the first elements so the totals still add up
+                    ExplodeBytes::Divided => {
+                        num_bytes / elements + usize::from(i < num_bytes % elements)
+                    }
+                };
+                count_line(
+                    results,
+                    Cow::Owned(key),
+                    bytes,
+                    line,
+                    fields,
+                    position,
+                    options,
+                );
+            }
+        }
+    }
+}
+
+/// Type of the lines going over the length or depth limits, which aren't parsed.
+fn exceeded_limit(line: &[u8], options: &AnalyzeOptions) -> Option<&'static str> {
+    if options
+        .max_line_length
+        .is_some_and(|max| line_bytes(line, false) > max)
+    {
+        Some(TOO_LONG_TYPE)
+    } else if options
+        .max_depth
+        .is_some_and(|max| nesting_depth(line) > max)
+    {
+        Some(TOO_DEEP_TYPE)
+    } else {
+        None
+    }
+}
+
+/// Whether the line is a header or a comment that must not be counted.
+fn is_skipped(line: &[u8], line_number: usize, options: &AnalyzeOptions) -> bool {
+    line_number <= options.skip_lines
+        || options
+            .comment_prefix
+            .as_ref()
+            .is_some_and(|prefix| line.starts_with(prefix.as_bytes()))
+}
+
+/// Number of bytes a line accounts for.
+/// `line` must include its terminator, if any. Both `\n` and `\r\n` terminators
+/// are taken into account and they're only counted when `count_newlines` is set.
+fn line_bytes(line: &[u8], count_newlines: bool) -> usize {
+    if count_newlines {
+        return line.len();
+    }
+    match line {
+        [rest @ .., b'\r', b'\n'] | [rest @ .., b'\n'] => rest.len(),
+        _ => line.len(),
+    }
+}
+
+/// A line of a chunk parsed by a worker.
+#[derive(Clone, Copy)]
+struct ChunkLine<'a> {
+    line: &'a [u8],
+    num_bytes: usize,
+    line_number: usize,
+    offset: u64,
+}
+
+/// Lines of a chunk beginning at line `first_line_number` and byte `offset`, with their terminators.
+fn chunk_lines(
+    chunk: &[u8],
+    first_line_number: usize,
+    mut offset: u64,
+    count_newlines: bool,
+) -> impl Iterator<Item = ChunkLine<'_>> {
+    chunk
+        .split_inclusive(|c| *c == b'\n')
+        .enumerate()
+        .map(move |(i, line)| {
+            let chunk_line = ChunkLine {
+                line,
+                num_bytes: line_bytes(line, count_newlines),
+                line_number: first_line_number + i,
+                offset,
+            };
+            offset += line.len() as u64;
+            chunk_line
+        })
+}
+
+/// Parses the lines of a chunk in a worker, returning the results and the number of lines.
+/// If the parsing panics, the lines are handled as `options.worker_panic` says. The callbacks
+/// notified before the panic aren't notified again, whatever the lines end up counted as.
+fn parse_chunk<'a, S: ReportHasher, I: Iterator<Item = ChunkLine<'a>>>(
+    lines: impl Fn() -> I,
+    options: &AnalyzeOptions,
+) -> Result<(Report<S>, usize), AnalyzeError> {
+    let parse = |results: &mut Report<S>, line: ChunkLine| {
+        process_line(
+            results,
+            line.line,
+            line.num_bytes,
+            line.line_number,
+            line.offset,
+            options,
+        )
+    };
+    NOTIFIED.with(|n| n.set((0, 0)));
+    let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut results = Report::default();
+        let mut count = 0;
+        for line in lines() {
+            parse(&mut results, line)?;
+            count += 1;
+        }
+        Ok((results, count))
+    }));
+    let payload = match parsed {
+        Ok(parsed) => return parsed,
+        Err(payload) => payload,
+    };
+    if options.worker_panic == WorkerPanic::Abort {
+        panic::resume_unwind(payload);
+    }
+    // whatever the panicking run counted is discarded, but not what it notified
+    NOTIFIED.with(|n| n.set((0, n.get().0)));
+    let mut results = Report::default();
+    let mut count = 0;
+    for line in lines() {
+        count += 1;
+        if options.worker_panic == WorkerPanic::Retry {
+            let mut line_results = Report::default();
+            if let Ok(parsed) =
+                panic::catch_unwind(AssertUnwindSafe(|| parse(&mut line_results, line)))
+            {
+                parsed?;
+                results.merge(line_results);
+                continue;
+            }
+        }
+        if is_skipped(line.line, line.line_number, options) {
+            continue;
+        }
+        if options.verbose_errors {
+            eprintln!(
+                "Line {} (byte offset {}) couldn't be parsed because the worker panicked",
+                line.line_number, line.offset
+            );
+        }
+        if options.positions {
+            results.add_position(
+                PANIC_TYPE,
+                LinePosition {
+                    line_number: line.line_number,
+                    offset: line.offset,
+                },
+            );
+        }
+        results.add_line(Cow::Borrowed(PANIC_TYPE), line.num_bytes);
+    }
+    Ok((results, count))
+}
+
+/// Position right after the last newline of the buffer.
+fn find_last_newline_position(buf: &[u8]) -> Option<usize> {
+    buf.iter().rposition(|c| *c == b'\n').map(|i| i + 1)
+}
+
+/// A line over `max_line_length` of which only the beginning was kept in memory.
+struct LongLine {
+    /// Bytes of the whole line, with its terminator.
+    read_bytes: usize,
+    terminator_bytes: usize,
+}
+
+/// Reads a line like `read_until`, unless it's longer than `max` bytes. Then the beginning
+/// of the line is kept in `buf` and the rest skipped, so it doesn't take all the memory.
+fn read_line_capped(
+    reader: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+    max: Option<usize>,
+) -> io::Result<(usize, Option<LongLine>)> {
+    let max = match max {
+        Some(max) => max,
+        None => return Ok((reader.read_until(b'\n', buf)?, None)),
+    };
+    // room for a `\r\n` terminator
+    let limit = max.saturating_add(2);
+    let read_bytes = reader.by_ref().take(limit as u64).read_until(b'\n', buf)?;
+    if read_bytes < limit || buf.ends_with(b"\n") {
+        return Ok((read_bytes, None));
+    }
+    let (skipped, terminator_bytes) = skip_line(reader, buf.last().copied())?;
+    let long = LongLine {
+        read_bytes: read_bytes + skipped,
+        terminator_bytes,
+    };
+    Ok((long.read_bytes, Some(long)))
+}
+
+/// Skips the rest of a line, returning the bytes skipped and those of its terminator.
+/// `last` is the byte read right before.
+fn skip_line(reader: &mut impl BufRead, mut last: Option<u8>) -> io::Result<(usize, usize)> {
+    let mut skipped = 0;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok((skipped, 0));
+        }
+        if let Some(i) = available.iter().position(|c| *c == b'\n') {
+            let carriage_return = if i > 0 {
+                available[i - 1]
+            } else {
+                last.unwrap_or(0)
+            };
+            reader.consume(i + 1);
+            return Ok((skipped + i + 1, 1 + usize::from(carriage_return == b'\r')));
+        }
+        let n = available.len();
+        last = available.last().copied();
+        reader.consume(n);
+        skipped += n;
+    }
+}
+
+/// Skips the rest of the line `buf` begins with, which has no newline yet, reading `chunk_size`
+/// bytes at a time. What comes after the line is left in `buf`.
+fn skip_long_line(f: &mut impl Read, buf: &mut Vec<u8>, chunk_size: usize) -> io::Result<LongLine> {
+    let mut read_bytes = 0;
+    let mut last = None;
+    loop {
+        if let Some(i) = buf.iter().position(|c| *c == b'\n') {
+            let carriage_return = if i > 0 { Some(buf[i - 1]) } else { last };
+            buf.drain(..=i);
+            return Ok(LongLine {
+                read_bytes: read_bytes + i + 1,
+                terminator_bytes: 1 + usize::from(carriage_return == Some(b'\r')),
+            });
+        }
+        read_bytes += buf.len();
+        last = buf.last().copied().or(last);
+        buf.clear();
+        if f.by_ref().take(chunk_size as u64).read_to_end(buf)? == 0 {
+            return Ok(LongLine {
+                read_bytes,
+                terminator_bytes: 0,
+            });
+        }
+    }
+}
+
+/// Counts a line over `max_line_length` as `TOO_LONG` from its beginning, `prefix`.
+/// Its results are left to be merged in the order of the input, like the rest.
+fn count_long_line<S: ReportHasher>(
+    prefix: &[u8],
+    long: &LongLine,
+    line_number: usize,
+    offset: u64,
+    context: &Context,
+) -> Result<Report<S>, AnalyzeError> {
+    let options = &context.options;
+    let mut line_results = Report::default();
+    if !is_skipped(prefix, line_number, options) {
+        let num_bytes = if options.count_newlines {
+            long.read_bytes
+        } else {
+            long.read_bytes - long.terminator_bytes
+        };
+        let position = LinePosition {
+            line_number,
+            offset,
+        };
+        let key = Cow::Borrowed(TOO_LONG_TYPE);
+        count_line(
+            &mut line_results,
+            key,
+            num_bytes,
+            prefix,
+            prefix,
+            position,
+            options,
+        );
+        if !line_results.outliers.is_empty() {
+            // only the beginning of the line was read
+            let length = long.read_bytes - long.terminator_bytes;
+            line_results
+                .outliers
+                .add(options.outliers, TOO_LONG_TYPE, length, prefix, position);
+        }
+    }
+    if options.check_bytes {
+        line_results
+            .byte_check
+            .get_or_insert_with(ByteCheck::default)
+            .add_span(offset, long.read_bytes as u64, long.terminator_bytes as u64);
+    }
+    context.track_lines(1, &line_results)?;
+    context.advance(long.read_bytes);
+    context.publish(&line_results);
+    Ok(line_results)
+}
+
+/// Merges the partial results, counting the types past `max_types` as `OTHER`.
+fn merge_capped<S: ReportHasher>(
+    results: &mut Report<S>,
+    partial_results: Report<S>,
+    options: &AnalyzeOptions,
+) {
+    let partial_results = match options.max_types {
+        Some(max) => partial_results.cap_types(results, max, OTHER_TYPE),
+        None => partial_results,
+    };
+    results.merge(partial_results);
+}
+
+fn calculate_results<S: ReportHasher>(
+    mut f: impl Read,
+    options: &AnalyzeOptions,
+    context: Context,
+) -> Result<Report<S>, AnalyzeError> {
+    let mut chunk_size = if options.adaptive_chunk_size {
+        MIN_ADAPTIVE_CHUNK_SIZE
+    } else {
+        options.chunk_size
+    };
+    let mut read_nanos = 0;
+    let mut read_bytes = 0;
+    // time spent reading the chunk being filled
+    let mut chunk_read = Duration::ZERO;
+    let mut results = Report::default();
+    let mut buf = Vec::with_capacity(chunk_size);
+    let (tx, rx) = channel();
+    let mut threads = VecDeque::with_capacity(options.threads);
+    // position of the first line of the next chunk
+    let mut line_number = 1;
+    let mut offset = 0;
+    let mut chunks = 0;
+    let mut raised_chunk_size = false;
+    // offset and chunk size of the line the chunks were made bigger for, until it fits
+    let mut growing = None;
+    // an error stops the reading, but it's returned once all the threads are joined,
+    // so no worker is left notifying the callbacks after the analysis
+    let reading = (|| {
+        loop {
+            if context.is_stopped() {
+                break;
+            }
+
+            // read what we need
+            let read_start = Instant::now();
+            let bytes = f
+                .by_ref()
+                .take(chunk_size.saturating_sub(buf.len()) as u64)
+                .read_to_end(&mut buf)?;
+            let read = read_start.elapsed();
+            read_nanos += read.as_nanos() as u64;
+            read_bytes += bytes as u64;
+            chunk_read += read;
+
+            // short circuit check
+            if buf.is_empty() {
+                break;
+            }
+
+            // copy incomplete lines to the next buffer.
+            if let Some(last_newline_position) = find_last_newline_position(&buf) {
+                if let Some((offset, chunk_size)) = growing.take() {
+                    if !raised_chunk_size {
+                        eprintln!(
+                            "WARNING: the line at byte offset {} is longer than the chunk size of {} bytes, the chunks are made bigger to fit it.",
+                            offset, chunk_size
+                        );
+                        raised_chunk_size = true;
+                    }
+                }
+                let mut next_buf = Vec::with_capacity(chunk_size);
+                next_buf.extend_from_slice(&buf[last_newline_position..]);
+                buf.truncate(last_newline_position);
+
+                // wait for the oldest thread if we already have as many as allowed running
+                if threads.len() == options.threads {
+                    if let Some((thread, bytes)) = threads.pop_front() {
+                        join_thread(thread, bytes, &context);
+                    }
+                }
+
+                // start threads and capture the results
+                let thread_tx = tx.clone();
+                let thread_buf = buf;
+                let thread_context = context.clone();
+                let first_line_number = line_number;
+                let chunk_offset = offset;
+                let worker = chunks;
+                let read = std::mem::take(&mut chunk_read);
+                chunks += 1;
+                line_number += thread_buf.iter().filter(|c| **c == b'\n').count();
+                offset += last_newline_position as u64;
+                let thread = spawn(move || {
+                    thread_context.pin(worker);
+                    let options = &thread_context.options;
+                    // chunks still waiting to be parsed are discarded on cancellation
+                    if thread_context.is_stopped() {
+                        return;
+                    }
+                    let parse_start = Instant::now();
+                    // the chunk always ends with a newline char, which isn't part of the lines
+                    let lines = || {
+                        chunk_lines(
+                            &thread_buf[..last_newline_position],
+                            first_line_number,
+                            chunk_offset,
+                            options.count_newlines,
+                        )
+                        .map(|line| ChunkLine {
+                            line: line.line.strip_suffix(b"\n").unwrap_or(line.line),
+                            ..line
+                        })
+                    };
+                    let (mut partial_results, parsed_lines) = match parse_chunk(lines, options) {
+                        Ok(parsed) => parsed,
+                        Err(e) => {
+                            thread_context.abort();
+                            // the receiver lives until all the threads are joined
+                            let _ = thread_tx.send((chunk_offset, Err(e)));
+                            return;
+                        }
+                    };
+                    check_span(
+                        &mut partial_results,
+                        &thread_buf[..last_newline_position],
+                        chunk_offset,
+                        options,
+                    );
+                    if let Err(e) = thread_context.track_lines(parsed_lines, &partial_results) {
+                        let _ = thread_tx.send((chunk_offset, Err(e)));
+                        return;
+                    }
+
+                    let parse = parse_start.elapsed();
+                    thread_context
+                        .parse_nanos
+                        .fetch_add(parse.as_nanos() as u64, Ordering::Relaxed);
+                    thread_context.chunk_parsed(ChunkStats {
+                        index: worker,
+                        worker: worker % options.threads,
+                        offset: chunk_offset,
+                        bytes: last_newline_position,
+                        lines: parsed_lines,
+                        read,
+                        parse,
+                    });
+                    thread_context.advance(last_newline_position);
+                    thread_context.publish(&partial_results);
+
+                    let _ = thread_tx.send((chunk_offset, Ok(partial_results)));
+                });
+                threads.push_back((thread, last_newline_position));
+                buf = next_buf;
+                if options.adaptive_chunk_size {
+                    let parsed_bytes = context.bytes_done.load(Ordering::Relaxed);
+                    if parsed_bytes > 0 && read_bytes > 0 {
+                        chunk_size = adapt_chunk_size(
+                            chunk_size,
+                            read_nanos as f64 / read_bytes as f64,
+                            context.parse_nanos.load(Ordering::Relaxed) as f64
+                                / parsed_bytes as f64,
+                            options.threads,
+                        );
+                    }
+                }
+            } else if let Some(limit) = options
+                .max_line_length
+                .map(|max| max.saturating_add(2))
+                .filter(|limit| buf.len() >= *limit)
+            {
+                // the line is over the maximum length even with a `\r\n` terminator,
+                // so its beginning is kept and the rest skipped
+                let prefix = buf[..limit].to_vec();
+                let long = skip_long_line(&mut f, &mut buf, chunk_size)?;
+                // it wasn't worth making the chunks bigger for it
+                growing = None;
+                let line_results = count_long_line(&prefix, &long, line_number, offset, &context)?;
+                let _ = tx.send((offset, Ok(line_results)));
+                line_number += 1;
+                offset += long.read_bytes as u64;
+            } else if buf.len() >= chunk_size {
+                // the line doesn't fit in the chunk yet
+                if options.adaptive_chunk_size && chunk_size < MAX_ADAPTIVE_CHUNK_SIZE {
+                    chunk_size = (chunk_size * 2).min(MAX_ADAPTIVE_CHUNK_SIZE);
+                } else {
+                    // a long line makes the chunks grow up to `max_line_length`, past which it's
+                    // skipped, or to the largest chunk the workers can hold
+                    let max_chunk_size = options
+                        .max_line_length
+                        .map_or(usize::MAX, |max| max.saturating_add(2))
+                        .min(ChunkSizeCheck::max(options.threads));
+                    if chunk_size >= max_chunk_size {
+                        return Err(AnalyzeError::LineTooLong {
+                            offset,
+                            max_chunk_size,
+                        });
+                    }
+                    // the warning waits for the line to fit, as it might be skipped instead
+                    growing.get_or_insert((offset, chunk_size));
+                    chunk_size = chunk_size.saturating_mul(2).min(max_chunk_size);
+                }
+            } else if bytes == 0 {
+                // nothing read with room left in the chunk means EOF, so what's left is the last
+                // line, without a trailing newline
+                let line = ChunkLine {
+                    line: &buf,
+                    num_bytes: line_bytes(&buf, options.count_newlines),
+                    line_number,
+                    offset,
+                };
+                let (mut line_results, parsed_lines) =
+                    parse_chunk(|| std::iter::once(line), options)?;
+                check_span(&mut line_results, &buf, offset, options);
+                context.track_lines(parsed_lines, &line_results)?;
+                context.advance(buf.len());
+                context.publish(&line_results);
+                let _ = tx.send((offset, Ok(line_results)));
+                break;
+            }
+        }
+        Ok(())
+    })();
+    if reading.is_err() {
+        context.abort();
+    }
+
+    for (thread, bytes) in threads {
+        join_thread(thread, bytes, &context);
+    }
+    // all the senders are gone once the threads are joined and this one dropped
+    drop(tx);
+    reading?;
+    // merged in the order of the input, not the order the chunks finished in,
+    // so every run gives the same report and keeps the same types past `max_types`
+    let mut partials: Vec<_> = rx.into_iter().collect();
+    partials.sort_by_key(|(offset, _)| *offset);
+    for (_, partial_results) in partials {
+        merge_capped(&mut results, partial_results?, options);
+    }
+
+    Ok(results)
+}
+
+/// Next chunk size given the time per byte spent reading and parsing.
+/// If the workers parse faster than the file is read the chunks grow, so the per chunk
+/// overhead is reduced. Otherwise they shrink, so the work is spread more evenly among the workers.
+fn adapt_chunk_size(
+    chunk_size: usize,
+    read_nanos_per_byte: f64,
+    parse_nanos_per_byte: f64,
+    threads: usize,
+) -> usize {
+    let next = if parse_nanos_per_byte / threads as f64 <= read_nanos_per_byte {
+        chunk_size * 2
+    } else {
+        chunk_size / 2
+    };
+    next.clamp(MIN_ADAPTIVE_CHUNK_SIZE, MAX_ADAPTIVE_CHUNK_SIZE)
+}
+
+/// Waits for the worker of a chunk of `bytes`. If it died before sending its results back,
+/// as when a callback panics, the results of the chunk are lost, unless the panic should stop
+/// the analysis.
+fn join_thread(thread: std::thread::JoinHandle<()>, bytes: usize, context: &Context) {
+    if let Err(payload) = thread.join() {
+        if context.options.worker_panic == WorkerPanic::Abort {
+            panic::resume_unwind(payload);
+        }
+        context.lose(bytes);
+    }
+}
+
+// NOTE: I chose to use a BufRead impl because I didn't want to have all the file in memory.
+// I chose the impl to allow me to pass a &[u8] from the tests while avoiding dynamic dispatching.
+fn calculate_results_naive<S: ReportHasher>(
+    buffer_reader: &mut impl BufRead,
+    options: &AnalyzeOptions,
+    context: Context,
+) -> Result<Report<S>, AnalyzeError> {
+    let mut buf = Vec::new();
+    let mut results = Report::default();
+    let mut partial_results = Report::default();
+    let mut partial_bytes = 0;
+    let mut partial_lines = 0;
+    let mut line_number = 1;
+    let mut offset = 0;
+
+    while !options.is_cancelled() {
+        // using read_until instead of the lines iterator as this is slighly faster
+        // it also includes the end line char and computes the number of bytes.
+        // The encoding is validated afterwards so invalid UTF-8 doesn't stop the reading.
+        // NOTE: I tried parallelizing the reading of the lines by using Rayon `par_bridge`
+        // over the `lines` iterator but it was significantly slower.
+        // Probably due to the mutex penalty I was unable to overcome.
+        // I also tried to read the file by chunks and do the parsing in several rayon
+        // spawned jobs but pretty much the same.
+        let (read_bytes, long_line) =
+            read_line_capped(buffer_reader, &mut buf, options.max_line_length)?;
+        // short circuit check
+        if read_bytes == 0 {
+            break;
+        }
+
+        if let Some(long) = long_line {
+            // the lines before it are merged first, so the types are kept in the order of the input
+            if partial_lines > 0 {
+                context.track_lines(partial_lines, &partial_results)?;
+                context.publish(&partial_results);
+                merge_capped(&mut results, std::mem::take(&mut partial_results), options);
+                partial_bytes = 0;
+                partial_lines = 0;
+            }
+            let line_results = count_long_line(&buf, &long, line_number, offset, &context)?;
+            merge_capped(&mut results, line_results, options);
+            buf.clear();
+            line_number += 1;
+            offset += read_bytes as u64;
+            continue;
+        }
+        let num_bytes = line_bytes(&buf, options.count_newlines);
+        if options.sandbox {
+            // lines panicking are counted as the workers of the other strategies do
+            let line = ChunkLine {
+                line: &buf,
+                num_bytes,
+                line_number,
+                offset,
+            };
+            let (line_results, _) = parse_chunk(|| std::iter::once(line), options)?;
+            partial_results.merge(line_results);
+        } else {
+            process_line(
+                &mut partial_results,
+                &buf,
+                num_bytes,
+                line_number,
+                offset,
+                options,
+            )?;
+        }
+        check_span(&mut partial_results, &buf, offset, options);
+        context.advance(read_bytes);
+
+        // there are no chunks here, so partial results are published every `chunk_size` bytes
+        partial_bytes += read_bytes;
+        partial_lines += 1;
+        if partial_bytes >= options.chunk_size {
+            context.track_lines(partial_lines, &partial_results)?;
+            context.publish(&partial_results);
+            merge_capped(&mut results, std::mem::take(&mut partial_results), options);
+            partial_bytes = 0;
+            partial_lines = 0;
+        }
+        // clear buffer and update line number and offset (used in case of error)
+        buf.clear();
+        line_number += 1;
+        offset += read_bytes as u64;
+    }
+    if !partial_results.is_empty() {
+        context.track_lines(partial_lines, &partial_results)?;
+        context.publish(&partial_results);
+    }
+    merge_capped(&mut results, partial_results, options);
+    Ok(results)
+}
+
+/// Parallel version of the naive strategy.
+/// Lines are read the same way but they're parsed in batches by scoped threads.
+fn calculate_results_batches<S: ReportHasher>(
+    buffer_reader: &mut impl BufRead,
+    options: &AnalyzeOptions,
+    context: &Context,
+) -> Result<Report<S>, AnalyzeError> {
+    let mut results = Report::default();
+    // position of the first line of the next batch
+    let mut line_number = 1;
+    let mut offset = 0;
+    let mut batches = 0;
+
+    std::thread::scope(|scope| {
+        let mut threads = VecDeque::with_capacity(options.threads);
+        while !context.is_stopped() {
+            let mut batch = Vec::new();
+            let mut lines = 0;
+            // a line over the maximum length ends the batch, and it's counted apart
+            let mut long_line = None;
+            let read_start = Instant::now();
+            while lines < options.batch_lines {
+                let start = batch.len();
+                let (read_bytes, long) =
+                    read_line_capped(buffer_reader, &mut batch, options.max_line_length)?;
+                if read_bytes == 0 {
+                    break;
+                }
+                if let Some(long) = long {
+                    long_line = Some((batch.split_off(start), long));
+                    break;
+                }
+                lines += 1;
+            }
+            let read = read_start.elapsed();
+            // short circuit check
+            if lines == 0 && long_line.is_none() {
+                break;
+            }
+
+            // wait for the oldest thread if we already have as many as allowed running
+            if threads.len() == options.threads {
+                if let Some(batch) = threads.pop_front() {
+                    merge_batch(&mut results, batch, context)?;
+                }
+            }
+
+            let first_line_number = line_number;
+            let batch_offset = offset;
+            line_number += lines;
+            offset += batch.len() as u64;
+            // the results of the long line wait behind the ones of the batch,
+            // so the types are kept in the order of the input
+            let long_line_results = match long_line {
+                Some((prefix, long)) => {
+                    let line_results =
+                        count_long_line(&prefix, &long, line_number, offset, context)?;
+                    line_number += 1;
+                    offset += long.read_bytes as u64;
+                    Some(line_results)
+                }
+                None => None,
+            };
+            if lines == 0 {
+                threads.push_back((None, 0, long_line_results));
+                continue;
+            }
+            let worker = batches;
+            let batch_bytes = batch.len();
+            batches += 1;
+            let thread = scope.spawn(move || -> Result<Report<S>, AnalyzeError> {
+                context.pin(worker);
+                // batches still waiting to be parsed are discarded on cancellation
+                if context.is_stopped() {
+                    return Ok(Report::default());
+                }
+                // the last line of the file might not have a newline char
+                let batch_lines = || {
+                    chunk_lines(
+                        &batch,
+                        first_line_number,
+                        batch_offset,
+                        options.count_newlines,
+                    )
+                };
+                let parse_start = Instant::now();
+                let (mut partial_results, _) =
+                    parse_chunk(batch_lines, options).inspect_err(|_| {
+                        context.abort();
+                    })?;
+                check_span(&mut partial_results, &batch, batch_offset, options);
+                context.track_lines(lines, &partial_results)?;
+                context.chunk_parsed(ChunkStats {
+                    index: worker,
+                    worker: worker % options.threads,
+                    offset: batch_offset,
+                    bytes: batch.len(),
+                    lines,
+                    read,
+                    parse: parse_start.elapsed(),
+                });
+                context.advance(batch.len());
+                context.publish(&partial_results);
+                Ok(partial_results)
+            });
+            threads.push_back((Some(thread), batch_bytes, long_line_results));
+        }
+        for batch in threads {
+            merge_batch(&mut results, batch, context)?;
+        }
+        Ok(results)
+    })
+}
+
+/// A batch being parsed and its size, if it has any lines, followed by the results of the long
+/// line that ended it, if any.
+type PendingBatch<'scope, S> = (
+    Option<std::thread::ScopedJoinHandle<'scope, Result<Report<S>, AnalyzeError>>>,
+    usize,
+    Option<Report<S>>,
+);
+
+/// Merges the results of a batch and then the ones of the long line that ended it.
+fn merge_batch<S: ReportHasher>(
+    results: &mut Report<S>,
+    (thread, bytes, long_line_results): PendingBatch<'_, S>,
+    context: &Context,
+) -> Result<(), AnalyzeError> {
+    if let Some(thread) = thread {
+        let partial_results = join_scoped_thread(thread, bytes, context)?;
+        merge_capped(results, partial_results, &context.options);
+    }
+    if let Some(line_results) = long_line_results {
+        merge_capped(results, line_results, &context.options);
+    }
+    Ok(())
+}
+
+/// Same as `join_thread`, with the results of a lost batch left empty.
+fn join_scoped_thread<S: ReportHasher>(
+    thread: std::thread::ScopedJoinHandle<'_, Result<Report<S>, AnalyzeError>>,
+    bytes: usize,
+    context: &Context,
+) -> Result<Report<S>, AnalyzeError> {
+    match thread.join() {
+        Ok(partial_results) => partial_results,
+        Err(payload) => {
+            if context.options.worker_panic == WorkerPanic::Abort {
+                panic::resume_unwind(payload);
+            }
+            context.lose(bytes);
+            Ok(Report::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_stats::ChunkTimings;
+    use crate::index::Indexer;
+    use crate::inspect::Compression;
+    use crate::models::{TypeLineCounter, PREVIEW_CHARS};
+    use crate::options::CancellationToken;
+    use crate::replay::TimeUnit;
+    use crate::time_range::{parse_timestamp, TimeRange};
+
+    fn chunks_options(chunk_size: usize) -> AnalyzeOptions {
+        AnalyzeOptions::builder()
+            .strategy(Strategy::Chunks)
+            .chunk_size(chunk_size)
+            .build()
+    }
+
+    #[test]
+    fn calculate_results_correctly_creates_the_sets() {
+        let mut file_content = r#"{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"A","foo":"bar","items":["one","two"]}
+{"type":"C","foo":"bar","items":["one","two"]}
+"#
+        .as_bytes();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn calculate_results_adds_empty_lines_as_errors_creating_the_sets() {
+        let mut file_content = r#"{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"A","foo":"bar","items":["one","two"]}
+
+{"type":"C","foo":"bar","items":["one","two"]}
+"#
+        .as_bytes();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        assert_eq!(result.len(), 4);
+        assert!(result.get(ERROR_TYPE).is_some())
+    }
+
+    #[test]
+    fn calculate_results_adds_bad_formatted_json_as_errors_creating_the_sets() {
+        let mut file_content = r#"{"type":"B" "foo":"bar","items":["one","two"]}
+{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"A","foo":"bar","items":["one","two"]}
+{"type":"C","foo":"bar","items":["one","two"]}
+"#
+        .as_bytes();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        assert_eq!(result.len(), 4);
+        assert!(result.get(ERROR_TYPE).is_some())
+    }
+
+    #[test]
+    fn calculate_results_adds_json_with_no_type_as_errors_creating_the_sets() {
+        let mut file_content = r#"{"type1":"B" "foo":"bar","items":["one","two"]}
+{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"A","foo":"bar","items":["one","two"]}
+{"type":"C","foo":"bar","items":["one","two"]}
+"#
+        .as_bytes();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        assert_eq!(result.len(), 4);
+        assert!(result.get(ERROR_TYPE).is_some())
+    }
+
+    #[test]
+    fn calculate_results_takes_into_account_spaces_when_counting_bytes() {
+        let mut file_content = r#"  {  "type":"B", "foo":"bar","items":["one","two"]}  
+"#
+        .as_bytes();
+        let num_bytes = file_content.len();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result.get(ERROR_TYPE).is_none());
+        assert_eq!(result.get("B").map(|r| r.bytes), Some(num_bytes));
+    }
+
+    #[test]
+    fn calculate_results_takes_into_account_spaces_when_counting_bytes_even_when_invalid_json() {
+        let mut file_content = r#"  {  "type":"B" "foo":"bar","items":["one","two"]}  
+"#
+        .as_bytes();
+        let num_bytes = file_content.len();
+        let result = analyze_reader(&mut file_content, &chunks_options(1_000)).unwrap();
+        let error = result.get(ERROR_TYPE).map(|r| r.bytes);
+        assert_eq!(result.len(), 1);
+        assert!(error.is_some());
+        assert_eq!(error, Some(num_bytes));
+    }
+
+    #[test]
+    fn every_strategy_counts_the_last_line_without_a_trailing_newline() {
+        for content in [
+            "{\"type\":\"A\"}\n{\"type\":\"B\",\"items\":[\"one\",\"two\"]}",
+            "{\"type\":\"B\"}",
+            "{\"type\":\"A\"}\r\n{\"type\":\"B\"}\r",
+            "{\"type\":\"A\"}\nnot json",
+        ] {
+            for count_newlines in [true, false] {
+                let results: Vec<_> = [Strategy::Naive, Strategy::Chunks, Strategy::Batches]
+                    .iter()
+                    .map(|&strategy| {
+                        let options = AnalyzeOptions::builder()
+                            .strategy(strategy)
+                            .chunk_size(8)
+                            .batch_lines(1)
+                            .count_newlines(count_newlines)
+                            .check_bytes(true)
+                            .positions(true)
+                            .build();
+                        analyze_reader(content.as_bytes(), &options).unwrap()
+                    })
+                    .collect();
+                assert_eq!(results[0], results[1], "{:?}", content);
+                assert_eq!(results[0], results[2], "{:?}", content);
+                let lines: usize = results[1].types.values().map(|r| r.count).sum();
+                assert_eq!(lines, content.split('\n').count(), "{:?}", content);
+                assert!(results[1].byte_check.as_ref().unwrap().is_complete());
+            }
+        }
+    }
+
+    #[test]
+    fn calculate_results_raises_the_chunks_smaller_than_a_line() {
+        let file_content = "{\"type\":\"B\",\"items\":[\"one\",\"two\"]}\n{\"type\":\"A\"}\n\n";
+        let expected = analyze_reader(file_content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        for chunk_size in [0, 1, 2, 13] {
+            let result = analyze_reader(file_content.as_bytes(), &chunks_options(chunk_size));
+            assert_eq!(result.unwrap(), expected, "chunks of {} bytes", chunk_size);
+        }
+    }
+
+    #[test]
+    fn find_last_newline_position_finds_a_newline_at_the_beginning() {
+        assert_eq!(find_last_newline_position(b"\nabc"), Some(1));
+        assert_eq!(find_last_newline_position(b"a\nb\n"), Some(4));
+        assert_eq!(find_last_newline_position(b"abc"), None);
+        assert_eq!(find_last_newline_position(b""), None);
+    }
+
+    #[test]
+    fn byte_check_compares_the_bytes_read_with_the_lines() {
+        let file_content = "{\"type\":\"A\"}\r\n# comment\n{\"type\":\"B\"}\n{\"type\":\"A\"}\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .batch_lines(2)
+                .count_newlines(false)
+                .skip_comments("#")
+                .check_bytes(true)
+                .build();
+            let check = analyze_reader(file_content.as_bytes(), &options)
+                .unwrap()
+                .byte_check
+                .unwrap();
+            assert!(check.is_complete(), "{:?}", strategy);
+            assert_eq!(check.read_bytes, file_content.len() as u64);
+            assert_eq!(check.terminator_bytes, 5);
+            // the comment is read but not counted
+            assert_eq!(check.expected_bytes(false) - check.counted_bytes, 9);
+        }
+
+        let options = AnalyzeOptions::builder()
+            .strategy(Strategy::Chunks)
+            .chunk_size(14)
+            .check_bytes(true)
+            .build();
+        let truncated = "{\"type\":\"A\"}\n{\"type\":\"A\"}\n{\"type\":\"B\"}";
+        let check = analyze_reader(truncated.as_bytes(), &options)
+            .unwrap()
+            .byte_check
+            .unwrap();
+        assert!(check.is_complete());
+        assert_eq!(check.read_bytes, 38);
+        assert_eq!(check.line_bytes, 38);
+        assert!(check.missing.is_empty());
+        let report = analyze_reader(truncated.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert!(report.byte_check.is_none());
+    }
+
+    #[test]
+    fn calculate_results_counts_the_last_line_after_the_chunks() {
+        let mut file_content = "{\"type\":\"A\"}\n{\"type\":\"A\"}\n{\"type\":\"B\"}".as_bytes();
+        let results = analyze_reader(&mut file_content, &chunks_options(14)).unwrap();
+        assert_eq!(results.get("A").map(|r| r.count), Some(2));
+        assert_eq!(results.get("B").map(|r| (r.count, r.bytes)), Some((1, 12)));
+    }
+
+    #[test]
+    fn calculate_results_limits_the_threads_without_losing_chunks() {
+        let mut file_content = r#"{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"B","foo":"bar","items":["one","two"]}
+{"type":"A","foo":"bar","items":["one","two"]}
+{"type":"C","foo":"bar","items":["one","two"]}
+"#
+        .as_bytes();
+        let options = AnalyzeOptions::builder()
+            .strategy(Strategy::Chunks)
+            .chunk_size(50)
+            .threads(1)
+            .build();
+        let result = analyze_reader(&mut file_content, &options).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result.get("B").map(|r| r.count), Some(2));
+    }
+
+    #[test]
+    fn analyze_reader_groups_by_the_configured_field() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"B","foo":"baz"}
+{"type":"A","foo":"bar"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .group_by("foo")
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get("bar").map(|r| r.count), Some(2));
+            assert_eq!(result.get("baz").map(|r| r.count), Some(1));
+        }
+    }
+
+    #[test]
+    fn analyze_reader_reports_progress_and_errors_through_callbacks() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"B" "foo":"baz"}
+{"type":"A","foo":"bar"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let bytes_done = Arc::new(AtomicU64::new(0));
+            let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let progress_bytes = bytes_done.clone();
+            let collected_errors = errors.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(30)
+                .batch_lines(1)
+                .on_progress(move |done, total| {
+                    assert_eq!(total, None);
+                    progress_bytes.fetch_max(done, Ordering::SeqCst);
+                })
+                .on_error(move |_, _, line, _| {
+                    collected_errors
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(line).trim_end().to_string());
+                })
+                .build();
+            analyze_reader(file_content, &options).unwrap();
+            assert_eq!(bytes_done.load(Ordering::SeqCst), file_content.len() as u64);
+            assert_eq!(
+                *errors.lock().unwrap(),
+                vec![r#"{"type":"B" "foo":"baz"}"#.to_string()]
+            );
+        }
+    }
+
+    #[test]
+    fn analyze_reader_reports_the_same_error_positions_in_every_strategy() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"B" "foo":"baz"}
+{"type":"A","foo":"bar"}
+{"type":"A" "foo":"bar"}
+"#
+        .as_bytes();
+        let mut positions = Vec::new();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let collected_errors = errors.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(60)
+                .batch_lines(1)
+                .on_error(move |line_number, offset, _, _| {
+                    collected_errors.lock().unwrap().push((line_number, offset));
+                })
+                .build();
+            analyze_reader(file_content, &options).unwrap();
+            let mut errors = errors.lock().unwrap().clone();
+            errors.sort_unstable();
+            positions.push(errors);
+        }
+        assert_eq!(positions[0], vec![(2, 25), (4, 75)]);
+        assert_eq!(positions[0], positions[1]);
+        assert_eq!(positions[0], positions[2]);
+    }
+
+    #[test]
+    fn analyze_reader_returns_partial_results_when_cancelled() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"B","foo":"baz"}
+{"type":"A","foo":"bar"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let token = CancellationToken::new();
+            let progress_token = token.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(30)
+                .batch_lines(1)
+                .threads(1)
+                .cancellation_token(token)
+                .on_progress(move |_, _| progress_token.cancel())
+                .build();
+            match analyze_reader(file_content, &options) {
+                Err(AnalyzeError::Cancelled(partial)) => {
+                    assert_eq!(partial.get("B").map(|r| r.count), Some(1));
+                    assert!(partial.get("A").is_none());
+                }
+                other => panic!("expected a cancelled analysis, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn calculate_results_handles_chunks_ending_exactly_on_a_line_boundary() {
+        let line = r#"{"type":"B","foo":"bar"}
+"#;
+        let file_content = line.repeat(3);
+        for chunk_size in [line.len(), line.len() * 2, line.len() * 3] {
+            let result =
+                analyze_reader(file_content.as_bytes(), &chunks_options(chunk_size)).unwrap();
+            assert_eq!(result.len(), 1, "chunk size {}", chunk_size);
+            assert_eq!(result.get("B").map(|r| r.count), Some(3));
+            assert_eq!(result.get("B").map(|r| r.bytes), Some(file_content.len()));
+        }
+    }
+
+    #[test]
+    fn calculate_results_counts_empty_lines_at_the_edges_of_the_chunks() {
+        let file_content = "\n{\"type\":\"B\"}\n\n\n{\"type\":\"A\"}\n";
+        for chunk_size in 14..=file_content.len() {
+            let result =
+                analyze_reader(file_content.as_bytes(), &chunks_options(chunk_size)).unwrap();
+            let error = result.get(ERROR_TYPE).copied().unwrap_or_default();
+            assert_eq!(error.count, 3, "chunk size {}", chunk_size);
+            assert_eq!(error.bytes, 3, "chunk size {}", chunk_size);
+            assert_eq!(result.get("B").map(|r| r.count), Some(1));
+        }
+    }
+
+    #[test]
+    fn calculate_results_does_not_report_errors_for_files_without_them() {
+        let file_content = r#"{"type":"B","foo":"bar"}
+{"type":"A","foo":"bar"}
+"#;
+        for chunk_size in 25..=file_content.len() {
+            let result =
+                analyze_reader(file_content.as_bytes(), &chunks_options(chunk_size)).unwrap();
+            assert!(
+                result.get(ERROR_TYPE).is_none(),
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn every_strategy_agrees_on_the_bytes_of_crlf_files() {
+        let file_content = "{\"type\":\"B\"}\r\n{\"type\":\"B\"}\r\n\r\n{\"type\":\"A\"}\n";
+        for count_newlines in [true, false] {
+            let results: Vec<Report> = [Strategy::Naive, Strategy::Chunks, Strategy::Batches]
+                .iter()
+                .map(|strategy| {
+                    let options = AnalyzeOptions::builder()
+                        .strategy(*strategy)
+                        .chunk_size(20)
+                        .count_newlines(count_newlines)
+                        .build();
+                    analyze_reader(file_content.as_bytes(), &options).unwrap()
+                })
+                .collect();
+            assert_eq!(results[0], results[1]);
+            assert_eq!(results[0], results[2]);
+            let expected = if count_newlines {
+                (28, 2, 13)
+            } else {
+                (24, 0, 12)
+            };
+            assert_eq!(results[0].get("B").map(|r| r.bytes), Some(expected.0));
+            assert_eq!(
+                results[0].get(ERROR_TYPE).map(|r| r.bytes),
+                Some(expected.1)
+            );
+            assert_eq!(results[0].get("A").map(|r| r.bytes), Some(expected.2));
+        }
+    }
+
+    #[test]
+    fn batches_handle_long_lines_and_a_missing_trailing_newline() {
+        let file_content = format!(
+            "{{\"type\":\"B\",\"foo\":\"{}\"}}\n{{\"type\":\"A\"}}\n{{\"type\":\"B\"}}",
+            "x".repeat(100)
+        );
+        for batch_lines in 1..=4 {
+            let options = AnalyzeOptions::builder()
+                .strategy(Strategy::Batches)
+                .batch_lines(batch_lines)
+                .threads(2)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get("B").map(|r| (r.count, r.bytes)), Some((2, 134)));
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
+            assert!(result.get(ERROR_TYPE).is_none());
+        }
+    }
+
+    #[test]
+    fn adaptive_chunk_size_grows_to_fit_long_lines() {
+        let long_line = format!(
+            "{{\"type\":\"B\",\"foo\":\"{}\"}}\n",
+            "x".repeat(MIN_ADAPTIVE_CHUNK_SIZE * 3)
+        );
+        let file_content = format!("{{\"type\":\"A\"}}\n{}{{\"type\":\"A\"}}\n", long_line);
+        let options = AnalyzeOptions::builder()
+            .strategy(Strategy::Chunks)
+            .adaptive_chunk_size(true)
+            .build();
+        let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+        assert_eq!(result.get("A").map(|r| r.count), Some(2));
+        assert_eq!(result.get("B").map(|r| r.bytes), Some(long_line.len()));
+    }
+
+    #[test]
+    fn adapt_chunk_size_follows_the_bottleneck() {
+        let size = MIN_ADAPTIVE_CHUNK_SIZE * 4;
+        // parsing is faster than reading
+        assert_eq!(adapt_chunk_size(size, 2.0, 4.0, 4), size * 2);
+        // parsing is slower than reading
+        assert_eq!(adapt_chunk_size(size, 1.0, 8.0, 4), size / 2);
+        assert_eq!(
+            adapt_chunk_size(MIN_ADAPTIVE_CHUNK_SIZE, 1.0, 8.0, 4),
+            MIN_ADAPTIVE_CHUNK_SIZE
+        );
+        assert_eq!(
+            adapt_chunk_size(MAX_ADAPTIVE_CHUNK_SIZE, 2.0, 1.0, 4),
+            MAX_ADAPTIVE_CHUNK_SIZE
+        );
+    }
+
+    #[test]
+    fn analyze_reader_aborts_when_the_error_rate_is_too_high() {
+        let mut file_content = "{\"type\":\"A\"}\n".repeat(900);
+        file_content.push_str(&"{\"type\":\n".repeat(100));
+        file_content.push_str(&"{\"type\":\"A\"}\n".repeat(1_000));
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(1_000)
+                .batch_lines(100)
+                .max_error_rate(0.05)
+                .build();
+            match analyze_reader(file_content.as_bytes(), &options) {
+                Err(AnalyzeError::TooManyErrors { errors, lines }) => {
+                    assert!(errors as f64 > lines as f64 * 0.05)
+                }
+                other => panic!("expected too many errors, got {:?}", other),
+            }
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(1_000)
+                .batch_lines(100)
+                .max_error_rate(0.1)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(100));
+        }
+    }
+
+    #[test]
+    fn analyze_reader_checks_the_error_rate_of_small_files_at_the_end() {
+        let file_content = "{\"type\":\"A\"}\n{\"type\":\n";
+        let options = AnalyzeOptions::builder().max_error_rate(0.4).build();
+        match analyze_reader(file_content.as_bytes(), &options) {
+            Err(AnalyzeError::TooManyErrors { errors, lines }) => {
+                assert_eq!((errors, lines), (1, 2))
+            }
+            other => panic!("expected too many errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn analyze_reader_skips_header_and_comment_lines() {
+        let file_content = r#"{"source":"metadata"}
+# comment
+{"type":"B"}
+#{"type":"B"}
+{"type":"A"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(30)
+                .skip_lines(1)
+                .skip_comments("#")
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.len(), 2);
+            assert_eq!(result.get("B").map(|r| r.count), Some(1));
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
+        }
+    }
+
+    #[test]
+    fn analyze_reader_counts_trailing_garbage_separately_when_lenient() {
+        let file_content = "{\"type\":\"B\"},\r\n{\"type\":\"B\"}\n{\"type\":\"A\"} xx\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(2));
 
-pub fn start(
-    path: PathBuf,
-    pretty_print: bool,
-    use_chunks: bool,
-    chunk_size: usize,
-    verbose_errors: bool,
-) {
-    let init = Instant::now();
-    if let Ok(f) = File::open(&path) {
-        if use_chunks {
-            let results = calculate_results(f, chunk_size, verbose_errors);
-            printer::print_table(pretty_print, &results);
-        } else {
-            let mut br = BufReader::new(f);
-            let results = calculate_results_naive(&mut br, verbose_errors);
-            printer::print_table(pretty_print, &results);
-        };
-    } else {
-        eprintln!("Error trying to open the file {:?}", path);
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .lenient_trailing(true)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            assert!(result.get(ERROR_TYPE).is_none());
+            assert_eq!(result.get("B").map(|r| (r.count, r.bytes)), Some((2, 28)));
+            assert_eq!(result.get("A").map(|r| (r.count, r.bytes)), Some((1, 16)));
+            assert_eq!(
+                result.trailing_garbage,
+                TypeLineCounter { count: 2, bytes: 3 }
+            );
+        }
     }
-    println!("Took {:?} microseconds", init.elapsed().as_micros());
-}
 
-fn find_last_newline_position(buf: &[u8]) -> Option<usize> {
-    let mut i = buf.len() - 1;
-    while i > 0 {
-        if buf[i] == b'\n' {
-            return Some(i + 1);
+    #[test]
+    fn analyze_reader_accepts_non_finite_numbers_when_asked_to() {
+        let file_content = r#"{"type":"B","value":NaN}
+{"type":"B","value":-Infinity}
+{"type":NaN}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(3));
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .allow_non_finite(true)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert!(result.get(ERROR_TYPE).is_none());
+            assert_eq!(result.get("B").map(|r| (r.count, r.bytes)), Some((2, 56)));
+            assert_eq!(
+                result.get("null").map(|r| (r.count, r.bytes)),
+                Some((1, 13))
+            );
         }
-        i -= 1;
     }
-    None
-}
 
-fn calculate_results(
-    mut f: impl Read,
-    chunk_size: usize,
-    verbose_errors: bool,
-) -> TypeLineResults<'static> {
-    let mut results = HashMap::new();
-    let mut buf = Vec::with_capacity(chunk_size);
-    let mut fatal_error = None;
-    let (tx, rx) = channel();
-    let mut threads = Vec::new();
-    loop {
-        // read what we need
-        f.by_ref()
-            .take((chunk_size - buf.len()) as u64)
-            .read_to_end(&mut buf)
-            .unwrap();
+    #[test]
+    fn analyze_reader_unwraps_envelopes_when_asked_to() {
+        let file_content = r#"{"log":"{\"type\":\"B\"}\n","stream":"stdout"}
+{"log":"{\"type\":\"A\"}\n","stream":"stderr"}
+{"log":"plain text\n","stream":"stdout"}
+{"type":"A"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(60)
+                .unwrap_field("log")
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get("B").map(|r| (r.count, r.bytes)), Some((1, 47)));
+            assert_eq!(result.get("A").map(|r| (r.count, r.bytes)), Some((1, 47)));
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(2));
 
-        // short circuit check
-        if buf.len() == 0 {
-            break;
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(60)
+                .unwrap_field("log")
+                .group_by("stream")
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(4));
         }
+    }
 
-        // copy incomplete lines to the next buffer.
-        if let Some(last_newline_position) = find_last_newline_position(&buf) {
-            let mut next_buf = Vec::with_capacity(chunk_size);
-            next_buf.extend_from_slice(&buf[last_newline_position..]);
-            buf.truncate(last_newline_position);
-
-            // start threads and capture the results
-            let thread_tx = tx.clone();
-            let thread_buf = buf;
-            let thread = spawn(move || {
-                let mut intermediate_counters = Vec::new();
-                thread_buf[..last_newline_position]
-                    .split(|c| *c == b'\n')
-                    .into_iter()
-                    .for_each(|line| {
-                        let num_bytes = line.len() + 1; // adding the end line char
-                        match serde_json::from_slice::<TypeLine>(line) {
-                            Ok(typeline) => {
-                                intermediate_counters.push(IntermediateTypeLineCounter {
-                                    key: Cow::Owned(typeline.linetype),
-                                    bytes: num_bytes,
-                                });
-                            }
-                            Err(e) => {
-                                if verbose_errors {
-                                    eprintln!(
-                                        "Error found parsing line: {} bytes - {:?}",
-                                        num_bytes, e
-                                    );
-                                }
-
-                                intermediate_counters.push(IntermediateTypeLineCounter {
-                                    key: Cow::Borrowed(ERROR_TYPE),
-                                    bytes: num_bytes,
-                                });
-                            }
-                        }
-                    });
-
-                if let Err(e) = thread_tx.send(intermediate_counters) {
-                    if verbose_errors {
-                        eprintln!("{:?}", e);
-                    }
-                }
-            });
-            threads.push(thread);
-            buf = next_buf;
-        } else {
-            fatal_error = Some(
-                r#"FATAL ERROR: Either the chunk size is smaller than the lines you want to parse or your file doesn't end with a newline char."#,
+    #[test]
+    fn analyze_reader_counts_truncated_lines_apart_when_asked_to() {
+        let file_content = r#"{"type":"A","msg":"complete"}
+{"type":"A","msg":"cut in the mid
+{"type":"B","payload":{"items":[1,2,
+{"type":"B" "msg":"broken"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .detect_truncated(true)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
+            assert_eq!(
+                result.get(TRUNCATED_TYPE).map(|r| (r.count, r.bytes)),
+                Some((2, 71))
             );
-            break;
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(1));
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert!(result.get(TRUNCATED_TYPE).is_none());
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(3));
         }
     }
 
-    if let Some(error) = fatal_error {
-        eprintln!("{}", error);
-    } else {
-        let threads_len = threads.len();
-
-        for t in threads {
-            t.join().expect("The thread panicked");
-        }
-        for _ in 0..threads_len {
-            match rx.recv() {
-                Ok(intermediate_counters) => {
-                    for ic in intermediate_counters {
-                        results
-                            .entry(ic.key)
-                            .or_insert(TypeLineCounter::default())
-                            .add_bytes(ic.bytes);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Something went wrong with the file reading {:?}", e);
-                }
-            }
+    #[test]
+    fn analyze_reader_rejects_binary_content() {
+        let bzip2 = b"BZh91AY&SY\0\0\n";
+        let binary = b"{\"type\":\"A\"}\n\0\0\0\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder().strategy(strategy).build();
+            // gzip and zstd inputs are decompressed with their features, bzip2 ones never are
+            let result = analyze_reader(&bzip2[..], &options);
+            assert!(matches!(
+                result,
+                Err(AnalyzeError::BinaryContent {
+                    compression: Some(Compression::Bzip2)
+                })
+            ));
+            let result = analyze_reader(&binary[..], &options);
+            assert!(matches!(
+                result,
+                Err(AnalyzeError::BinaryContent { compression: None })
+            ));
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .check_binary(false)
+                .build();
+            let result = analyze_reader(&binary[..], &options).unwrap();
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(1));
         }
+    }
 
-        // rectify the end of line error for each thread
-        if let Some((key, mut counter)) = results.remove_entry(ERROR_TYPE) {
-            counter.bytes -= threads_len;
-            counter.count -= threads_len;
-            if counter.bytes > 0 {
-                results.insert(key, counter);
+    #[test]
+    fn analyze_reader_sniffs_and_converts_other_formats() {
+        let inputs = [
+            (
+                "[\n  {\"type\": \"A\"},\n  {\"type\": \"B\"}\n]\n",
+                Format::JsonArray,
+            ),
+            ("type,size\nA,1\nB,2\n", Format::Csv),
+            ("type=A size=1\ntype=B\n", Format::Logfmt),
+        ];
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder().strategy(strategy).build();
+            for (input, format) in inputs {
+                let result = analyze_reader(input.as_bytes(), &options).unwrap();
+                assert_eq!(result.format, Some(format));
+                assert_eq!(result.get("A").map(|r| r.count), Some(1));
+                assert_eq!(result.get("B").map(|r| r.count), Some(1));
+                assert!(result.get(ERROR_TYPE).is_none());
             }
+            let result = analyze_reader(&b"{\"type\":\"A\"}\n"[..], &options).unwrap();
+            assert_eq!(result.format, None);
+
+            // a forced format is not reported
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .format(Format::JsonSeq)
+                .build();
+            let result = analyze_reader(&b"\x1e{\"type\":\"A\"}\n"[..], &options).unwrap();
+            assert_eq!(result.format, None);
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
         }
     }
 
-    results
-}
+    #[test]
+    #[cfg(feature = "raw")]
+    fn raw_lines_are_grouped_by_a_regex() {
+        let content = "web: GET /\r\nweb: GET /about\ndb: slow query\n{\"type\":\"A\"}\n";
+        let prefix = regex::Regex::new(r"^(\w+):").unwrap();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .group_by_regex(prefix.clone())
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get("web").map(|r| (r.count, r.bytes)), Some((2, 28)));
+            assert_eq!(result.get("db").map(|r| r.count), Some(1));
+            assert_eq!(result.get(UNMATCHED_TYPE).map(|r| r.count), Some(1));
+            assert!(result.get(ERROR_TYPE).is_none());
+            assert_eq!(result.format, None);
+        }
+        // without groups, the whole match is the type
+        let options = AnalyzeOptions::builder()
+            .group_by_regex(regex::Regex::new(r"GET|slow").unwrap())
+            .build();
+        let result = analyze_reader(content.as_bytes(), &options).unwrap();
+        assert_eq!(result.get("GET").map(|r| r.count), Some(2));
+        assert_eq!(result.get("slow").map(|r| r.count), Some(1));
+    }
 
-// NOTE: I chose to use a BufRead impl because I didn't want to have all the file in memory.
-// I chose the impl to allow me to pass a &[u8] from the tests while avoiding dynamic dispatching.
-fn calculate_results_naive(
-    buffer_reader: &mut impl BufRead,
-    verbose_errors: bool,
-) -> TypeLineResults {
-    let mut buf = String::new();
-    let mut results = HashMap::new();
-    let mut line_number = 1;
+    #[test]
+    #[cfg(feature = "raw")]
+    fn extracted_fields_group_the_lines_and_hold_their_durations() {
+        let content = "{\"message\":\"status=200 took=5\"}\n{\"message\":\"status=500 took=20\"}\n{\"message\":\"status=200 took=1\"}\n{\"message\":\"down\"}\n";
+        let extract: crate::ExtractRegex = r"status=(?P<status>\d+) took=(?P<took>\d+):message"
+            .parse()
+            .unwrap();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .group_by("status")
+                .duration_field("took")
+                .extract_regex(extract.clone())
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get("200").map(|r| r.count), Some(2));
+            assert_eq!(result.get("500").map(|r| r.count), Some(1));
+            // a line the regex doesn't match keeps its own fields
+            assert_eq!(result.get(ERROR_TYPE).map(|r| r.count), Some(1));
+            assert_eq!(result.durations["200"].nanos, 6_000_000);
+        }
 
-    loop {
-        // using read_line instead of the lines iterator as this is slighly faster
-        // it also includes the end line char and computes the number of bytes
-        // I decided to panic in case there were issues with the encoding.
-        // NOTE: I tried parallelizing the reading of the lines by using Rayon `par_bridge`
-        // over the `lines` iterator but it was significantly slower.
-        // Probably due to the mutex penalty I was unable to overcome.
-        // I also tried to read the file by chunks and do the parsing in several rayon
-        // spawned jobs but pretty much the same.
-        let num_bytes = buffer_reader.read_line(&mut buf).expect("Not UTF-8 found");
-
-        // I used serde in order to validate that the text is valid JSON
-        // and used a simple struct which only cares about the `type` property.
-        // In case bad formatted JSON I decided to go on and count the error as a new
-        // category and also output the error in stderr.
-        match serde_json::from_str::<TypeLine>(&buf) {
-            Ok(typeline) => {
-                results
-                    .entry(Cow::Owned(typeline.linetype))
-                    .or_insert(TypeLineCounter::default())
-                    .add_bytes(num_bytes);
-            }
-            Err(e) if num_bytes != 0 => {
-                if verbose_errors {
-                    eprintln!("Error found parsing line {} - {:?}", line_number, e);
+        let options = AnalyzeOptions::builder()
+            .format(Format::Raw)
+            .group_by("service")
+            .extract_regex(r"^(?P<service>\w+):".parse().unwrap())
+            .build();
+        let result =
+            analyze_reader(&b"web: GET /\ndb: slow\nweb: POST /\n???\n"[..], &options).unwrap();
+        assert_eq!(result.get("web").map(|r| r.count), Some(2));
+        assert_eq!(result.get("db").map(|r| r.count), Some(1));
+        assert_eq!(result.get(UNMATCHED_TYPE).map(|r| r.count), Some(1));
+    }
+
+    #[test]
+    fn aggregators_are_computed_by_type_across_the_workers() {
+        let content = "{\"type\":\"a.x\",\"ms\":10,\"user\":\"ann\"}\n\
+                       {\"type\":\"a.y\",\"ms\":30,\"user\":\"bob\"}\n\
+                       {\"type\":\"b\",\"ms\":5,\"user\":\"ann\"}\n\
+                       {\"type\":\"a.x\",\"ms\":20,\"user\":\"ann\"}\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .batch_lines(1)
+                .rollup(1, ".")
+                .aggregator("took", crate::Sum::new("ms"))
+                .aggregator("users", crate::Distinct::new("user"))
+                .aggregator("slowest", crate::Quantile::new("ms", 1.0).unwrap())
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            let report = |key: &str, name: &str| result.aggregates[key][name].report();
+            assert_eq!(report("a", "took"), serde_json::json!(60.0));
+            assert_eq!(report("a", "users"), serde_json::json!(2));
+            assert_eq!(report("a", "slowest"), serde_json::json!(30.0));
+            assert_eq!(report("b", "users"), serde_json::json!(1));
+
+            let json = serde_json::to_value(&result).unwrap();
+            assert_eq!(json["aggregates"]["b"]["took"], serde_json::json!(5.0));
+        }
+    }
+
+    #[test]
+    fn dropping_the_page_cache_leaves_the_results_untouched() {
+        let path = std::env::temp_dir().join(format!("fr-cache-{}.jsonl", std::process::id()));
+        std::fs::write(&path, "{\"type\":\"A\"}\n{\"type\":\"B\"}\n").unwrap();
+        let expected = analyze(&path, &AnalyzeOptions::default()).unwrap();
+        let options = AnalyzeOptions::builder().drop_cache_after(true).build();
+        assert_eq!(analyze(&path, &options).unwrap(), expected);
+        std::fs::remove_file(&path).unwrap();
+        assert!(analyze(&path, &options).is_err());
+    }
+
+    #[test]
+    fn analyze_warns_about_files_growing_during_the_analysis() {
+        let content = "{\"type\":\"A\"}\n{\"type\":\"B\"}\n";
+        let appended = "{\"type\":\"C\"}\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let path = std::env::temp_dir().join(format!(
+                "fr-growth-{}-{:?}.jsonl",
+                std::process::id(),
+                strategy
+            ));
+            for snapshot in [false, true] {
+                std::fs::write(&path, content).unwrap();
+                let appended_once = Arc::new(AtomicBool::new(false));
+                let growing_path = path.clone();
+                let options = AnalyzeOptions::builder()
+                    .strategy(strategy)
+                    .snapshot(snapshot)
+                    .on_progress(move |_, _| {
+                        if !appended_once.swap(true, Ordering::SeqCst) {
+                            let mut file = std::fs::OpenOptions::new()
+                                .append(true)
+                                .open(&growing_path)
+                                .unwrap();
+                            std::io::Write::write_all(&mut file, appended.as_bytes()).unwrap();
+                        }
+                    })
+                    .build();
+                let result = analyze(&path, &options).unwrap();
+                assert_eq!(result.get("A").map(|r| r.count), Some(1));
+                if snapshot {
+                    assert_eq!(result.file_growth, None);
+                    assert!(result.get("C").is_none());
+                } else {
+                    assert_eq!(result.file_growth, Some(appended.len() as u64));
                 }
-                results
-                    .entry(Cow::Borrowed(ERROR_TYPE))
-                    .or_insert(TypeLineCounter::default())
-                    .add_bytes(num_bytes);
             }
-            Err(_) => (), // end of line
+
+            std::fs::write(&path, format!("{}{}", content, appended)).unwrap();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .snapshot_size(content.len() as u64)
+                .build();
+            let result = analyze(&path, &options).unwrap();
+            assert_eq!(result.get("B").map(|r| r.count), Some(1));
+            assert!(result.get("C").is_none());
+            std::fs::remove_file(&path).unwrap();
         }
-        // clear buffer and update line number (used in case of error)
-        buf.clear();
-        line_number += 1;
-        // short circuit check
-        if num_bytes == 0 {
-            break;
+    }
+
+    #[test]
+    fn analyze_skips_to_the_time_range() {
+        let mut content = String::new();
+        for minute in 0..600 {
+            let kind = if minute % 100 == 0 { "B" } else { "A" };
+            content.push_str(&format!(
+                "{{\"ts\":\"2024-02-29T{:02}:{:02}:00Z\",\"type\":\"{}\"}}\n",
+                minute / 60,
+                minute % 60,
+                kind
+            ));
+        }
+        let path = std::env::temp_dir().join(format!("fr-range-{}.jsonl", std::process::id()));
+        std::fs::write(&path, &content).unwrap();
+        for io_retries in [0, 2] {
+            let options = AnalyzeOptions::builder()
+                .io_retries(io_retries)
+                .time_range(TimeRange {
+                    field: "ts".to_string(),
+                    since: Some(parse_timestamp("2024-02-29T01:30:00Z").unwrap()),
+                    until: Some(parse_timestamp("2024-02-29T03:20:00Z").unwrap()),
+                    unit: TimeUnit::Auto,
+                })
+                .build();
+            let result = analyze(&path, &options).unwrap();
+            assert_eq!(result.get("A").map(|r| r.count), Some(109));
+            assert_eq!(result.get("B").map(|r| r.count), Some(2));
         }
+        std::fs::remove_file(&path).unwrap();
     }
-    results
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn analyze_only_reads_the_indexed_blocks_of_the_types() {
+        let mut content = String::new();
+        for i in 0..1_000 {
+            let kind = if i % 250 == 0 { "B" } else { "A" };
+            content.push_str(&format!("{{\"type\":\"{}\",\"n\":\"{:03}\"}}\n", kind, i));
+        }
+        let path = std::env::temp_dir().join(format!("fr-index-{}.jsonl", std::process::id()));
+        std::fs::write(&path, &content).unwrap();
+        let totals = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let analyze_b = |totals: Arc<std::sync::Mutex<Vec<Option<u64>>>>| {
+            let options = AnalyzeOptions::builder()
+                .only(vec!["B"])
+                .on_progress(move |_, total| totals.lock().unwrap().push(total))
+                .build();
+            analyze(&path, &options).unwrap()
+        };
+
+        let result = analyze_b(totals.clone());
+        assert_eq!(result.get("B").map(|r| r.count), Some(4));
+        assert!(result.get("A").is_none());
+        assert_eq!(
+            totals.lock().unwrap().last(),
+            Some(&Some(content.len() as u64))
+        );
+
+        let index = Indexer {
+            block_size: 1_000,
+            ..Indexer::default()
+        }
+        .build(content.as_bytes())
+        .unwrap();
+        index.write_to(&Index::path_of(&path)).unwrap();
+        totals.lock().unwrap().clear();
+        assert_eq!(analyze_b(totals.clone()), result);
+        let read = totals.lock().unwrap().last().copied().flatten().unwrap();
+        assert!(read <= 4 * 1_020, "{} bytes read", read);
+
+        // a stale index is ignored
+        std::fs::write(&path, format!("{}{{\"type\":\"B\"}}\n", content)).unwrap();
+        assert_eq!(analyze_b(totals).get("B").map(|r| r.count), Some(5));
+        std::fs::remove_file(Index::path_of(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
 
     #[test]
-    fn calculate_results_correctly_creates_the_sets() {
-        let mut file_content = r#"{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"A","foo":"bar","items":["one","two"]}
-{"type":"C","foo":"bar","items":["one","two"]}
-"#
-        .as_bytes();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 3);
+    fn line_bytes_only_counts_the_terminator_when_asked_to() {
+        assert_eq!(line_bytes(b"abc\r\n", true), 5);
+        assert_eq!(line_bytes(b"abc\r\n", false), 3);
+        assert_eq!(line_bytes(b"abc\n", false), 3);
+        assert_eq!(line_bytes(b"abc", false), 3);
+        assert_eq!(line_bytes(b"abc\r", false), 4);
     }
 
     #[test]
-    fn calculate_results_adds_empty_lines_as_errors_creating_the_sets() {
-        let mut file_content = r#"{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"A","foo":"bar","items":["one","two"]}
+    fn analyze_reader_handles_invalid_utf8_as_configured() {
+        let file_content = b"{\"type\":\"B\"}\n{\"type\":\"B\xff\"}\n{\"type\":\"A\"}\n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .build();
+            match analyze_reader(&file_content[..], &options) {
+                Err(AnalyzeError::InvalidUtf8 {
+                    line_number,
+                    offset,
+                }) => assert_eq!((line_number, offset), (2, 13)),
+                other => panic!("expected an invalid UTF-8 error, got {:?}", other),
+            }
 
-{"type":"C","foo":"bar","items":["one","two"]}
-"#
-        .as_bytes();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 4);
-        assert!(result.get(ERROR_TYPE).is_some())
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .invalid_utf8(InvalidUtf8::Replace)
+                .build();
+            let result = analyze_reader(&file_content[..], &options).unwrap();
+            assert_eq!(result.get("B\u{FFFD}").map(|r| r.bytes), Some(14));
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(20)
+                .invalid_utf8(InvalidUtf8::SkipLine)
+                .build();
+            let result = analyze_reader(&file_content[..], &options).unwrap();
+            assert_eq!(result.get(INVALID_UTF8_TYPE).map(|r| r.count), Some(1));
+            assert_eq!(result.get("B").map(|r| r.count), Some(1));
+            assert_eq!(result.get("A").map(|r| r.count), Some(1));
+        }
     }
 
     #[test]
-    fn calculate_results_adds_bad_formatted_json_as_errors_creating_the_sets() {
-        let mut file_content = r#"{"type":"B" "foo":"bar","items":["one","two"]}
-{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"A","foo":"bar","items":["one","two"]}
-{"type":"C","foo":"bar","items":["one","two"]}
+    fn analyze_reader_sums_the_durations_by_type() {
+        let file_content = r#"{"type":"A","took":{"ms":1500}}
+{"type":"A","took":{"ms":"2m"}}
+{"type":"A"}
+{"type":"B","took":{"ms":250}}
 "#
         .as_bytes();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 4);
-        assert!(result.get(ERROR_TYPE).is_some())
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .batch_lines(1)
+                .duration_field("took.ms")
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            let a = result.durations["A"];
+            assert_eq!((a.count, a.seconds(), a.average()), (2, 121.5, Some(60.75)));
+            assert_eq!(result.durations["B"].seconds(), 0.25);
+            assert_eq!(result.get("A").map(|r| r.count), Some(3));
+        }
+    }
+
+    /// Lines of several types with fractional durations, some of them repeated or broken.
+    fn generated_corpus(lines: usize) -> String {
+        let mut seed: u64 = 42;
+        let mut next = move || {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            seed >> 33
+        };
+        (0..lines)
+            .map(|_| match next() % 20 {
+                0 => "{\"type\":\"broken\"\n".to_string(),
+                1 => "{\"type\":\"dup\",\"took\":0.1}\n".to_string(),
+                n => format!(
+                    "{{\"type\":\"T{}\",\"took\":{}.{},\"pad\":\"{}\"}}\n",
+                    n % 7,
+                    next() % 1_000,
+                    next() % 10,
+                    "x".repeat((next() % 40) as usize)
+                ),
+            })
+            .collect()
     }
 
     #[test]
-    fn calculate_results_adds_json_with_no_type_as_errors_creating_the_sets() {
-        let mut file_content = r#"{"type1":"B" "foo":"bar","items":["one","two"]}
-{"type":"B","foo":"bar","items":["one","two"]}
-{"type":"A","foo":"bar","items":["one","two"]}
-{"type":"C","foo":"bar","items":["one","two"]}
-"#
-        .as_bytes();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 4);
-        assert!(result.get(ERROR_TYPE).is_some())
+    fn parallel_strategies_give_byte_identical_reports() {
+        let corpus = generated_corpus(5_000);
+        let options = |strategy, threads, chunk_size| {
+            AnalyzeOptions::builder()
+                .strategy(strategy)
+                .threads(threads)
+                .chunk_size(chunk_size)
+                .batch_lines(chunk_size / 64)
+                .duration_field("took")
+                .exact_duplicates(true)
+                .positions(true)
+                .build()
+        };
+        let json = |options| {
+            serde_json::to_string(&analyze_reader(corpus.as_bytes(), &options).unwrap()).unwrap()
+        };
+        let expected = json(options(Strategy::Naive, 1, 1_024));
+        for strategy in [Strategy::Chunks, Strategy::Batches] {
+            for threads in [1, 2, 8] {
+                for chunk_size in [256, 4_096] {
+                    assert_eq!(
+                        json(options(strategy, threads, chunk_size)),
+                        expected,
+                        "{:?} with {} threads and chunks of {} bytes",
+                        strategy,
+                        threads,
+                        chunk_size
+                    );
+                }
+            }
+        }
     }
 
     #[test]
-    fn calculate_results_takes_into_account_spaces_when_counting_bytes() {
-        let mut file_content = r#"  {  "type":"B", "foo":"bar","items":["one","two"]}  
-"#
-        .as_bytes();
-        let num_bytes = file_content.len();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 1);
-        assert!(result.get(ERROR_TYPE).is_none());
-        assert_eq!(result.get("B").map(|r| r.bytes), Some(num_bytes));
+    fn worker_panics_are_recovered_as_configured() {
+        let file_content = b"{\"type\":\"A\"}\n{\"type\":\"boom\"}\n{\"type\":\"A\"}\n";
+        let options = |strategy, worker_panic| {
+            AnalyzeOptions::builder()
+                .strategy(strategy)
+                .worker_panic(worker_panic)
+                .on_line(|key, _, _, _| assert_ne!(key, "boom", "a bad record"))
+                .build()
+        };
+        for strategy in [Strategy::Chunks, Strategy::Batches] {
+            let result = analyze_reader(&file_content[..], &options(strategy, WorkerPanic::Count));
+            let result = result.unwrap();
+            assert_eq!(
+                result.get(PANIC_TYPE).map(|r| (r.count, r.bytes)),
+                Some((3, 42))
+            );
+            assert!(result.get("A").is_none());
+
+            let result = analyze_reader(&file_content[..], &options(strategy, WorkerPanic::Retry));
+            let result = result.unwrap();
+            assert_eq!(
+                result.get(PANIC_TYPE).map(|r| (r.count, r.bytes)),
+                Some((1, 16))
+            );
+            assert_eq!(result.get("A").map(|r| r.count), Some(2));
+
+            let aborted = panic::catch_unwind(|| {
+                analyze_reader(&file_content[..], &options(strategy, WorkerPanic::Abort))
+            });
+            assert!(aborted.is_err());
+        }
     }
 
     #[test]
-    fn calculate_results_takes_into_account_spaces_when_counting_bytes_even_when_invalid_json() {
-        let mut file_content = r#"  {  "type":"B" "foo":"bar","items":["one","two"]}  
-"#
-        .as_bytes();
-        let num_bytes = file_content.len();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        let error = result.get(ERROR_TYPE).map(|r| r.bytes);
-        assert_eq!(result.len(), 1);
-        assert!(error.is_some());
-        assert_eq!(error, Some(num_bytes));
+    fn workers_dying_outside_the_parsing_make_the_results_incomplete() {
+        let file_content = "{\"type\":\"A\"}\n{\"type\":\"B\"}\n{\"type\":\"C\"}\n";
+        for strategy in [Strategy::Chunks, Strategy::Batches] {
+            let options = |worker_panic| {
+                AnalyzeOptions::builder()
+                    .strategy(strategy)
+                    .worker_panic(worker_panic)
+                    .chunk_size(14)
+                    .batch_lines(1)
+                    .on_chunk(|stats| assert_ne!(stats.index, 1, "a bad callback"))
+                    .build()
+            };
+            match analyze_reader(file_content.as_bytes(), &options(WorkerPanic::Count)) {
+                Err(AnalyzeError::Incomplete {
+                    results,
+                    lost_bytes,
+                }) => {
+                    assert_eq!(lost_bytes, 13, "{:?}", strategy);
+                    assert_eq!(results.get("A").map(|r| r.count), Some(1));
+                    assert!(results.get("B").is_none());
+                    assert_eq!(results.get("C").map(|r| r.count), Some(1));
+                }
+                other => panic!("{:?}: unexpected outcome {:?}", strategy, other.map(|_| ())),
+            }
+
+            let aborted = panic::catch_unwind(|| {
+                analyze_reader(file_content.as_bytes(), &options(WorkerPanic::Abort))
+            });
+            assert!(aborted.is_err());
+        }
+    }
+
+    #[test]
+    fn workers_are_joined_before_the_errors_are_returned() {
+        /// Fails once a worker is notifying the lines, like a device gone after the first chunk.
+        struct Gone(std::sync::mpsc::Receiver<()>);
+
+        impl Read for Gone {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                let _ = self.0.recv();
+                Err(io::Error::other("device gone"))
+            }
+        }
+
+        let file_content = b"{\"type\":\"A\"}\n{\"type\":\"B\"}\n";
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collector = lines.clone();
+        let (notifying, notified) = channel();
+        let options = AnalyzeOptions::builder()
+            .strategy(Strategy::Chunks)
+            .chunk_size(file_content.len())
+            // nothing is read ahead, so the read fails after the first chunk
+            .format(Format::Jsonl)
+            .check_binary(false)
+            .on_line(move |key, _, _, _| {
+                let _ = notifying.send(());
+                // the worker is still notifying when the read fails
+                std::thread::sleep(Duration::from_millis(50));
+                collector.lock().unwrap().push(key.to_string());
+            })
+            .build();
+        let result = analyze_reader(file_content.chain(Gone(notified)), &options);
+        assert!(matches!(result, Err(AnalyzeError::Io(_))));
+        assert_eq!(*lines.lock().unwrap(), vec!["A", "B"]);
+    }
+
+    #[test]
+    fn callbacks_are_notified_once_when_a_chunk_panics() {
+        /// Panics on the lines of the `BOOM` type, after `on_line` was notified.
+        #[derive(Clone)]
+        struct Boom;
+
+        impl crate::aggregator::Aggregator for Boom {
+            fn observe(&mut self, record: &Record) {
+                assert_ne!(record.key(), "BOOM", "a bad record");
+            }
+
+            fn merge(&mut self, _: &dyn crate::aggregator::Aggregator) {}
+
+            fn report(&self) -> serde_json::Value {
+                serde_json::Value::Null
+            }
+        }
+
+        let file_content = "{\"type\":\"A\"}\nnot json\n{\"type\":\"B\"}\n\
+                            {\"type\":\"BOOM\"}\n{\"type\":\"C\"}\n";
+        for strategy in [Strategy::Chunks, Strategy::Batches] {
+            for worker_panic in [WorkerPanic::Retry, WorkerPanic::Count] {
+                let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+                let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+                let (line_collector, error_collector) = (lines.clone(), errors.clone());
+                let options = AnalyzeOptions::builder()
+                    .strategy(strategy)
+                    .worker_panic(worker_panic)
+                    .aggregator("boom", Boom)
+                    .on_line(move |key, _, _, _| {
+                        line_collector.lock().unwrap().push(key.to_string())
+                    })
+                    .on_error(move |line_number, _, _, _| {
+                        error_collector.lock().unwrap().push(line_number)
+                    })
+                    .build();
+                let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+                let lines = lines.lock().unwrap().clone();
+                let errors = errors.lock().unwrap().clone();
+                assert_eq!(errors, vec![2], "{:?} {:?}", strategy, worker_panic);
+                if worker_panic == WorkerPanic::Retry {
+                    assert_eq!(
+                        lines,
+                        vec!["A", "ERROR", "B", "BOOM", "C"],
+                        "{:?}",
+                        strategy
+                    );
+                    assert_eq!(result.get(PANIC_TYPE).map(|r| r.count), Some(1));
+                } else {
+                    // the lines after the panic are only counted as PANIC
+                    assert_eq!(lines, vec!["A", "ERROR", "B", "BOOM"], "{:?}", strategy);
+                    assert_eq!(result.get(PANIC_TYPE).map(|r| r.count), Some(5));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lines_over_the_limits_are_counted_apart_without_being_parsed() {
+        let long = format!("{{\"type\":\"A\",\"pad\":\"{}\"}}\r\n", "x".repeat(60));
+        let deep = "{\"type\":\"B\",\"a\":[[[[1]]]]}\n";
+        let file_content = format!(
+            "{}{}{}{}{}",
+            "{\"type\":\"A\"}\n", long, deep, "{\"type\":\"C\"}\n", long
+        );
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(32)
+                .batch_lines(2)
+                .max_line_length(30)
+                .max_depth(4)
+                .check_bytes(true)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            assert_eq!(result.get("A").map(|r| r.count), Some(1), "{:?}", strategy);
+            assert_eq!(result.get("C").map(|r| r.count), Some(1));
+            assert_eq!(
+                result.get(TOO_LONG_TYPE).map(|r| (r.count, r.bytes)),
+                Some((2, 2 * long.len()))
+            );
+            assert_eq!(
+                result.get(TOO_DEEP_TYPE).map(|r| (r.count, r.bytes)),
+                Some((1, deep.len()))
+            );
+            assert!(result.byte_check.unwrap().is_complete());
+        }
+
+        let types: String = (0..20)
+            .map(|i| format!("{{\"type\":\"T{}\"}}\n", i % 5 + usize::from(i < 10)))
+            .collect();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .batch_lines(3)
+                .max_types(3)
+                .build();
+            let result = analyze_reader(types.as_bytes(), &options).unwrap();
+            assert!(result.len() <= 3, "{:?}: {:?}", strategy, result.types);
+            assert_eq!(result.types.values().map(|r| r.count).sum::<usize>(), 20);
+            assert!(result.get(OTHER_TYPE).is_some());
+        }
+
+        // the long lines are merged where they are in the input, so every strategy keeps `A`
+        let long = format!("{{\"type\":\"L\",\"pad\":\"{}\"}}\n", "x".repeat(200));
+        let file_content = format!(
+            "{}{}{}",
+            "{\"type\":\"A\"}\n".repeat(40),
+            long.repeat(3),
+            "{\"type\":\"B\"}\n".repeat(40)
+        );
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(128)
+                .batch_lines(16)
+                .max_line_length(100)
+                .max_types(2)
+                .build();
+            let result = analyze_reader(file_content.as_bytes(), &options).unwrap();
+            let counts: HashMap<_, _> = result
+                .types
+                .iter()
+                .map(|(key, r)| (key.to_string(), r.count))
+                .collect();
+            let expected = HashMap::from([("A".to_string(), 40), (OTHER_TYPE.to_string(), 43)]);
+            assert_eq!(counts, expected, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn sandboxed_analyses_survive_arbitrary_bytes() {
+        // inputs known to stress parsers, on top of the generated ones
+        let mut corpus: Vec<Vec<u8>> = vec![
+            format!("{}\n", "[".repeat(100_000)).into_bytes(),
+            format!("{{\"type\":{}}}\n", "[".repeat(10_000)).into_bytes(),
+            format!("{{\"type\":\"{}\"}}\n", "x".repeat(3_000_000)).into_bytes(),
+            b"{\"type\":\"\xff\xfe\"}\n\xc3\x28\n".to_vec(),
+            b"{\"type\":\"A\0\"}\n\0\0\0\0\n".to_vec(),
+            b"{\"type\":\"A\"}\r{\"type\":\"B\"}\r\r\n\r".to_vec(),
+            b"{\"type\":\"unterminated\n{\"type\":\"\\".to_vec(),
+            b"{\"type\":1e999999999,\"n\":-0.0000000000000000000000001e-99999}\n".to_vec(),
+            b"{\"type\":\"\\ud800\\udc00\\ud800\"}\n{\"type\":\"\\u0000\"}\n".to_vec(),
+            b"{\"type\":{\"type\":{}},\"type\":[]}\n{}\n[]\nnull\n\n\n".to_vec(),
+            (0..20_000)
+                .map(|i| format!("{{\"type\":\"T{}\"}}\n", i))
+                .collect::<String>()
+                .into_bytes(),
+        ];
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let alphabet = b"{}[]\":,\\ \n\r\ttype0123456789.eE-+truefalsnu\xff\xc3\x80\0";
+        for _ in 0..200 {
+            let len = (next() % 2_000) as usize;
+            let input = (0..len)
+                .map(|_| alphabet[(next() % alphabet.len() as u64) as usize])
+                .collect();
+            corpus.push(input);
+        }
+
+        for input in &corpus {
+            for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+                let options = AnalyzeOptions::builder()
+                    .strategy(strategy)
+                    .chunk_size(64 * 1024)
+                    .batch_lines(100)
+                    .invalid_utf8(InvalidUtf8::SkipLine)
+                    .check_binary(false)
+                    .detect_truncated(true)
+                    .exact_duplicates(true)
+                    .sandbox(true)
+                    .build();
+                // errors are fine, panics aren't
+                if let Ok(result) = analyze_reader(&input[..], &options) {
+                    assert!(result.len() <= 10_000);
+                }
+            }
+        }
     }
 
     #[test]
-    fn calculate_results_does_not_work_when_file_does_not_end_with_newline() {
-        let mut file_content = r#"{ "type":"B", "foo":"bar","items":["one","two"]}"#.as_bytes();
-        let result = calculate_results(&mut file_content, 1_000, false);
-        assert_eq!(result.len(), 0);
+    fn sandboxed_analyses_keep_the_outputs_within_the_limits() {
+        let input: String = (0..2_000)
+            .map(|i| {
+                format!(
+                    "{{\"type\":\"T{}\",\"took\":1,\"pad\":\"{}\"}}\n",
+                    i,
+                    "x".repeat(i % 50)
+                )
+            })
+            .collect();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(8 * 1024)
+                .batch_lines(100)
+                .positions(true)
+                .duration_field("took")
+                .exact_duplicates(true)
+                .outliers(10_000)
+                .aggregator("pads", crate::Distinct::new("pad"))
+                .max_types(50)
+                .sandbox(true)
+                .build();
+            let result = analyze_reader(input.as_bytes(), &options).unwrap();
+            assert!(result.len() <= 50, "{:?}", strategy);
+            assert!(result.first_seen.len() <= 50, "{:?}", strategy);
+            assert!(result.last_seen.len() <= 50, "{:?}", strategy);
+            assert!(result.durations.len() <= 50, "{:?}", strategy);
+            assert_eq!(
+                result.durations.values().map(|d| d.count).sum::<usize>(),
+                2_000
+            );
+            assert!(result.digests.is_empty(), "{:?}", strategy);
+            assert!(result.aggregates.is_empty(), "{:?}", strategy);
+            assert_eq!(result.outliers.sorted().len(), 100, "{:?}", strategy);
+        }
     }
 
     #[test]
-    fn calculate_results_does_not_work_when_the_chunks_are_smaller_than_a_line() {
-        let mut file_content = r#"{ "type":"B", "foo":"bar","items":["one","two"]}"#.as_bytes();
-        let result = calculate_results(&mut file_content, 2, false);
-        assert_eq!(result.len(), 0);
+    fn analyze_reader_explodes_array_fields() {
+        let file_content = r#"{"tags":["a","b","a"]}
+{"tags":"b"}
+"#
+        .as_bytes();
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .group_by("tags")
+                .explode(true)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get("a").map(|r| (r.count, r.bytes)), Some((2, 46)));
+            assert_eq!(result.get("b").map(|r| (r.count, r.bytes)), Some((2, 36)));
+
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .group_by("tags")
+                .explode(true)
+                .explode_bytes(ExplodeBytes::Divided)
+                .build();
+            let result = analyze_reader(file_content, &options).unwrap();
+            assert_eq!(result.get("a").map(|r| (r.count, r.bytes)), Some((2, 15)));
+            assert_eq!(result.get("b").map(|r| (r.count, r.bytes)), Some((2, 21)));
+            let total: usize = result.types.values().map(|r| r.bytes).sum();
+            assert_eq!(total, file_content.len());
+        }
     }
 
     // -- naive
@@ -321,7 +3125,7 @@ mod tests {
 {"type":"C","foo":"bar","items":["one","two"]}
 "#
         .as_bytes();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         assert_eq!(result.len(), 3);
     }
 
@@ -334,7 +3138,7 @@ mod tests {
 {"type":"C","foo":"bar","items":["one","two"]}
 "#
         .as_bytes();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         assert_eq!(result.len(), 4);
         assert!(result.get(ERROR_TYPE).is_some())
     }
@@ -347,7 +3151,7 @@ mod tests {
 {"type":"C","foo":"bar","items":["one","two"]}
 "#
         .as_bytes();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         assert_eq!(result.len(), 4);
         assert!(result.get(ERROR_TYPE).is_some())
     }
@@ -360,7 +3164,7 @@ mod tests {
 {"type":"C","foo":"bar","items":["one","two"]}
 "#
         .as_bytes();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         assert_eq!(result.len(), 4);
         assert!(result.get(ERROR_TYPE).is_some())
     }
@@ -371,7 +3175,7 @@ mod tests {
 "#
         .as_bytes();
         let num_bytes = file_content.len();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         assert_eq!(result.len(), 1);
         assert!(result.get(ERROR_TYPE).is_none());
         assert_eq!(result.get("B").map(|r| r.bytes), Some(num_bytes));
@@ -384,10 +3188,188 @@ mod tests {
 "#
         .as_bytes();
         let num_bytes = file_content.len();
-        let result = calculate_results_naive(&mut file_content, false);
+        let result = analyze_reader(&mut file_content, &AnalyzeOptions::default()).unwrap();
         let error = result.get(ERROR_TYPE).map(|r| r.bytes);
         assert_eq!(result.len(), 1);
         assert!(error.is_some());
         assert_eq!(error, Some(num_bytes));
     }
+
+    #[test]
+    fn exact_duplicates_are_found_across_chunks_and_threads() {
+        let content = "{\"type\":\"A\",\"n\":1}\n{\"type\":\"A\",\"n\":2}\n{\"type\":\"A\",\"n\":1}\r\n\
+                       {\"type\":\"B\"}\n{\"type\":\"B\" \n{\"type\":\"A\",\"n\":1}\n{\"type\":\"B\" \n";
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches].iter() {
+            let options = AnalyzeOptions::builder()
+                .strategy(*strategy)
+                .chunk_size(40)
+                .batch_lines(2)
+                .threads(3)
+                .exact_duplicates(true)
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(result.duplicates.len(), 2, "{:?}", strategy);
+            assert_eq!(result.duplicates.get("A").map(|c| c.count), Some(2));
+            assert_eq!(result.duplicates.get(ERROR_TYPE).map(|c| c.count), Some(1));
+            assert!(result.digests.is_empty());
+        }
+        let result = analyze_reader(content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert!(result.duplicates.is_empty());
+    }
+
+    #[test]
+    fn positions_keep_the_first_and_last_lines_of_every_type() {
+        let content = "{\"type\":\"B\"}\n{\"type\":\"A\"}\r\n{\"type\":\"B\"}\n\
+                       not json\n{\"type\":\"C\"}\n{\"type\":\"A\"}\n";
+        let position = |line_number, offset| LinePosition {
+            line_number,
+            offset,
+        };
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches].iter() {
+            let options = AnalyzeOptions::builder()
+                .strategy(*strategy)
+                .chunk_size(30)
+                .batch_lines(2)
+                .threads(3)
+                .positions(true)
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(result.first_seen.len(), 4, "{:?}", strategy);
+            assert_eq!(result.first_seen["B"], position(1, 0), "{:?}", strategy);
+            assert_eq!(result.first_seen["A"], position(2, 13), "{:?}", strategy);
+            assert_eq!(
+                result.first_seen[ERROR_TYPE],
+                position(4, 40),
+                "{:?}",
+                strategy
+            );
+            assert_eq!(result.first_seen["C"], position(5, 49), "{:?}", strategy);
+            assert_eq!(result.last_seen.len(), 4, "{:?}", strategy);
+            assert_eq!(result.last_seen["B"], position(3, 27), "{:?}", strategy);
+            assert_eq!(result.last_seen["A"], position(6, 62), "{:?}", strategy);
+            assert_eq!(result.last_seen["C"], position(5, 49), "{:?}", strategy);
+        }
+        let result = analyze_reader(content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert!(result.first_seen.is_empty());
+        assert!(result.last_seen.is_empty());
+    }
+
+    #[test]
+    fn every_chunk_is_reported_once_parsed() {
+        let content = "{\"type\":\"A\"}\n{\"type\":\"B\"}\n".repeat(20);
+        for strategy in [Strategy::Chunks, Strategy::Batches] {
+            let timings = ChunkTimings::new();
+            let recorder = timings.clone();
+            let chunks = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let collected = chunks.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(100)
+                .batch_lines(7)
+                .threads(3)
+                .on_chunk(move |stats| {
+                    recorder.record(stats);
+                    collected.lock().unwrap().push(*stats);
+                })
+                .build();
+            analyze_reader(content.as_bytes(), &options).unwrap();
+            let mut chunks = chunks.lock().unwrap().clone();
+            chunks.sort_by_key(|chunk| chunk.index);
+            assert!(chunks.len() > 3, "{:?}", strategy);
+            let mut offset = 0;
+            for (i, chunk) in chunks.iter().enumerate() {
+                assert_eq!((chunk.index, chunk.offset), (i, offset), "{:?}", strategy);
+                assert_eq!(chunk.worker, i % 3);
+                offset += chunk.bytes as u64;
+            }
+            assert_eq!(offset, content.len() as u64);
+            assert_eq!(chunks.iter().map(|chunk| chunk.lines).sum::<usize>(), 40);
+            assert_eq!(timings.summary().unwrap().chunks, chunks.len());
+        }
+    }
+
+    #[test]
+    fn outliers_are_the_longest_lines() {
+        let huge = format!("{{\"type\":\"C\",\"pad\":\"{}\"}}\n", "x".repeat(200));
+        let content = format!(
+            "{}{}{}{}{}",
+            "{\"type\":\"A\",\"pad\":\"xxxx\"}\n{\"type\":\"B\"}\r\n",
+            "{\"type\":\"A\",\"pad\":\"xxxxxxxx\"}\n",
+            huge,
+            "{\"type\":\"B\",\"pad\":\"xxxx\"}\n",
+            "{\"type\":\"A\"}\n"
+        );
+        let outliers = |result: &Report| -> Vec<_> {
+            result
+                .outliers
+                .sorted()
+                .into_iter()
+                .map(|o| (o.key.to_string(), o.position.line_number, o.length))
+                .collect()
+        };
+        for strategy in [Strategy::Naive, Strategy::Chunks, Strategy::Batches] {
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .batch_lines(2)
+                .threads(3)
+                .outliers(3)
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            // lines of the same length are ranked by position
+            assert_eq!(
+                outliers(&result),
+                vec![
+                    ("C".to_string(), 4, huge.len() - 1),
+                    ("A".to_string(), 3, 29),
+                    ("A".to_string(), 1, 25),
+                ],
+                "{:?}",
+                strategy
+            );
+            let preview = &result.outliers.sorted()[0].preview;
+            assert_eq!(preview.chars().count(), PREVIEW_CHARS + 3);
+            assert!(preview.ends_with("xxx..."));
+
+            // the lines over the limit are measured whole, even if they aren't read whole
+            let options = AnalyzeOptions::builder()
+                .strategy(strategy)
+                .chunk_size(40)
+                .batch_lines(2)
+                .max_line_length(100)
+                .outliers(1)
+                .build();
+            let result = analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(
+                outliers(&result),
+                vec![(TOO_LONG_TYPE.to_string(), 4, huge.len() - 1)],
+                "{:?}",
+                strategy
+            );
+        }
+        let result = analyze_reader(content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert!(result.outliers.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "fast-hashers")]
+    fn every_hasher_gives_the_same_results() {
+        let content = "{\"type\":\"A\"}\n{\"type\":\"B\"}\n{\"type\":\"A\"}\nnot json\n";
+        let expected = analyze_reader(content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        for hasher in [KeyHasher::Fx, KeyHasher::AHash].iter() {
+            let options = AnalyzeOptions::builder()
+                .strategy(Strategy::Batches)
+                .batch_lines(1)
+                .hasher(*hasher)
+                .build();
+            assert_eq!(
+                analyze_reader(content.as_bytes(), &options).unwrap(),
+                expected
+            );
+        }
+        let fx: Report<fxhash::FxBuildHasher> =
+            analyze_reader_with_hasher(content.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert_eq!(fx.get("A").map(|c| c.count), Some(2));
+        assert_eq!(fx.with_hasher::<RandomState>(), expected);
+    }
 }
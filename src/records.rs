@@ -0,0 +1,142 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+/// Bytes read at once when scanning a file backwards.
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Writes the first `n` lines holding a whole JSON document. Returns how many were written.
+/// Broken lines are skipped, so a record is never cut.
+pub fn head(mut reader: impl BufRead, n: usize, mut writer: impl Write) -> io::Result<usize> {
+    let mut written = 0;
+    let mut line = Vec::new();
+    while written < n {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let record = trim_newline(&line);
+        if is_record(record) {
+            writer.write_all(record)?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Writes the last `n` lines holding a whole JSON document. Returns how many were written.
+/// The input is scanned backwards from its end, so only the last chunks of a big file are read.
+pub fn tail(mut reader: impl Read + Seek, n: usize, mut writer: impl Write) -> io::Result<usize> {
+    let mut records: Vec<Vec<u8>> = Vec::new();
+    let mut position = reader.seek(SeekFrom::End(0))?;
+    // pieces of the line cut by the start of the last chunk read, the last piece first. They're
+    // joined once the beginning of the line is found, so long lines aren't copied on every chunk
+    let mut pieces: Vec<Vec<u8>> = Vec::new();
+    while records.len() < n && position > 0 {
+        let start = position.saturating_sub(TAIL_CHUNK_SIZE);
+        let mut chunk = vec![0; (position - start) as usize];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut chunk)?;
+        position = start;
+
+        // the first line may continue in the previous chunk, unless this is the beginning
+        let mut lines = if position > 0 {
+            match chunk.iter().position(|b| *b == b'\n') {
+                Some(end) => {
+                    let lines = chunk.split_off(end + 1);
+                    chunk.pop();
+                    lines
+                }
+                None => {
+                    pieces.push(chunk);
+                    continue;
+                }
+            }
+        } else {
+            std::mem::take(&mut chunk)
+        };
+        for piece in pieces.drain(..).rev() {
+            lines.extend_from_slice(&piece);
+        }
+        pieces.push(chunk);
+
+        for line in lines.split(|b| *b == b'\n').rev() {
+            let record = trim_newline(line);
+            if is_record(record) {
+                records.push(record.to_vec());
+                if records.len() == n {
+                    break;
+                }
+            }
+        }
+    }
+    for record in records.iter().rev() {
+        writer.write_all(record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(records.len())
+}
+
+/// The line without its `\n` or `\r\n` terminator.
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn is_record(line: &[u8]) -> bool {
+    !line.iter().all(u8::is_ascii_whitespace)
+        && serde_json::from_slice::<serde::de::IgnoredAny>(line).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const INPUT: &str = "{\"n\":1}\n{\"n\":2\n\n{\"n\":3}\r\n{\"n\":4}\nnot json\n{\"n\":5}";
+
+    #[test]
+    fn head_skips_broken_records() {
+        let mut output = Vec::new();
+        assert_eq!(head(INPUT.as_bytes(), 3, &mut output).unwrap(), 3);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"n\":1}\n{\"n\":3}\n{\"n\":4}\n"
+        );
+    }
+
+    #[test]
+    fn tail_scans_backwards_across_chunks() {
+        let mut output = Vec::new();
+        assert_eq!(tail(Cursor::new(INPUT), 2, &mut output).unwrap(), 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "{\"n\":4}\n{\"n\":5}\n");
+
+        let mut output = Vec::new();
+        assert_eq!(tail(Cursor::new(INPUT), 10, &mut output).unwrap(), 4);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"n\":1}\n{\"n\":3}\n{\"n\":4}\n{\"n\":5}\n"
+        );
+
+        // lines much longer than a chunk
+        let long = format!("{{\"s\":\"{}\"}}", "x".repeat(TAIL_CHUNK_SIZE as usize * 2));
+        let input = format!("{}\n{{\"n\":1}}\n{}\n", long, long);
+        let mut output = Vec::new();
+        assert_eq!(tail(Cursor::new(&input), 3, &mut output).unwrap(), 3);
+        assert_eq!(output, input.as_bytes());
+
+        // a line over many chunks, cut in the middle of one
+        let long = format!(
+            "{{\"s\":\"{}\"}}",
+            "x".repeat(TAIL_CHUNK_SIZE as usize * 20 + 100)
+        );
+        let input = format!("{}\n{}", long, long);
+        let mut output = Vec::new();
+        assert_eq!(tail(Cursor::new(&input), 1, &mut output).unwrap(), 1);
+        assert_eq!(output, format!("{}\n", long).as_bytes());
+        let mut output = Vec::new();
+        assert_eq!(tail(Cursor::new(&input), 2, &mut output).unwrap(), 2);
+        assert_eq!(output, format!("{}\n{}\n", long, long).as_bytes());
+    }
+}
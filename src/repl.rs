@@ -0,0 +1,373 @@
+use crate::models::Report;
+use crate::parser::{parse_key, split_path, Key};
+use crate::sql::Predicate;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+    ops::Range,
+};
+
+const ERROR_TYPE: &str = "ERROR";
+const DEFAULT_SAMPLE: usize = 5;
+
+const HELP: &str = "\
+group <field>         groups the lines by the field (nested fields separated by dots)
+filter <expression>   keeps the lines matching it, like level=error or 'status >= 500 && level == \"error\"'
+filter                removes the filter
+types                 shows every type of the lines kept
+top <n> [count|bytes] shows the n biggest types by count (default) or bytes
+sample <type> [n]     prints the first n lines of the type (5 by default)
+help                  shows this help
+quit                  leaves the prompt
+";
+
+/// Interactive exploration of a file. The lines are loaded once and their types are kept
+/// in memory, so grouping, filtering and sampling them again doesn't read the file anymore.
+pub struct Repl {
+    content: Vec<u8>,
+    /// Bytes of every line, with its terminator.
+    lines: Vec<Range<usize>>,
+    group_by: String,
+    /// Types seen with the current grouping and the type of every line, as an index into them.
+    types: Vec<String>,
+    keys: Vec<usize>,
+    /// Lines matching the filter, all of them without one.
+    selected: Vec<usize>,
+}
+
+/// Command accepted by the prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplCommand {
+    Group(String),
+    Filter(Option<Predicate>),
+    Types,
+    Top(usize, Ranking),
+    Sample(String, usize),
+    Help,
+    Quit,
+}
+
+/// Figure the types are ranked by in `top`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    Count,
+    Bytes,
+}
+
+impl Repl {
+    /// Loads every line of the reader and groups them by `group_by`.
+    pub fn load(mut reader: impl Read, group_by: &str) -> io::Result<Self> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in content.split_inclusive(|b| *b == b'\n') {
+            lines.push(start..start + line.len());
+            start += line.len();
+        }
+        let mut repl = Repl {
+            selected: (0..lines.len()).collect(),
+            content,
+            lines,
+            group_by: String::new(),
+            types: Vec::new(),
+            keys: Vec::new(),
+        };
+        repl.group(group_by);
+        Ok(repl)
+    }
+
+    /// Reads commands from `input` until it ends or `quit` is entered, writing the answers
+    /// to `output`. Invalid commands are reported without leaving the prompt.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(ReplCommand::Quit) => return Ok(()),
+                Ok(command) => self.execute(&command, &mut output)?,
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+        }
+    }
+
+    /// Runs a command, writing its answer to `output`.
+    pub fn execute(&mut self, command: &ReplCommand, mut output: impl Write) -> io::Result<()> {
+        match command {
+            ReplCommand::Group(field) => {
+                self.group(field);
+                self.write_types(usize::MAX, Ranking::Count, &mut output)?;
+            }
+            ReplCommand::Filter(predicate) => {
+                self.filter(predicate.as_ref());
+                writeln!(
+                    output,
+                    "{} of {} lines kept",
+                    self.selected.len(),
+                    self.lines.len()
+                )?;
+            }
+            ReplCommand::Types => self.write_types(usize::MAX, Ranking::Count, &mut output)?,
+            ReplCommand::Top(n, ranking) => self.write_types(*n, *ranking, &mut output)?,
+            ReplCommand::Sample(name, n) => {
+                for line in self.sample(name, *n) {
+                    output.write_all(line)?;
+                    output.write_all(b"\n")?;
+                }
+            }
+            ReplCommand::Help => output.write_all(HELP.as_bytes())?,
+            ReplCommand::Quit => {}
+        }
+        output.flush()
+    }
+
+    /// Report of the lines kept with the current grouping.
+    pub fn report(&self) -> Report {
+        let mut report = Report::default();
+        for &index in &self.selected {
+            let name = &self.types[self.keys[index]];
+            report.add_line(Cow::Owned(name.clone()), self.lines[index].len());
+        }
+        report
+    }
+
+    /// Parses every line again to find its value of `field`.
+    fn group(&mut self, field: &str) {
+        let paths = vec![split_path(field)];
+        let mut indexes: HashMap<String, usize> = HashMap::new();
+        let mut types = Vec::new();
+        let mut keys = Vec::with_capacity(self.lines.len());
+        for index in 0..self.lines.len() {
+            let name = match parse_key(self.line(index), &paths, false, false, false) {
+                Ok((Key::Single(key), _)) => key,
+                Ok((Key::Exploded(_), _)) => unreachable!("Keys are never exploded in the prompt"),
+                Err(_) => ERROR_TYPE.to_string(),
+            };
+            keys.push(*indexes.entry(name).or_insert_with_key(|name| {
+                types.push(name.clone());
+                types.len() - 1
+            }));
+        }
+        self.types = types;
+        self.keys = keys;
+        self.group_by = field.to_string();
+    }
+
+    /// The line without its terminator.
+    fn line(&self, index: usize) -> &[u8] {
+        trim_newline(&self.content[self.lines[index].clone()])
+    }
+
+    fn filter(&mut self, predicate: Option<&Predicate>) {
+        self.selected = match predicate {
+            Some(predicate) => (0..self.lines.len())
+                .filter(|&index| predicate.matches(self.line(index)))
+                .collect(),
+            None => (0..self.lines.len()).collect(),
+        };
+    }
+
+    /// First `n` lines kept of the type.
+    fn sample(&self, name: &str, n: usize) -> Vec<&[u8]> {
+        self.selected
+            .iter()
+            .filter(|&&index| self.types[self.keys[index]] == name)
+            .take(n)
+            .map(|&index| self.line(index))
+            .collect()
+    }
+
+    fn write_types(&self, n: usize, ranking: Ranking, mut output: impl Write) -> io::Result<()> {
+        let report = self.report();
+        let mut types: Vec<_> = report.types.iter().collect();
+        types.sort_by(|(a_key, a), (b_key, b)| {
+            let (a_value, b_value) = match ranking {
+                Ranking::Count => (a.count, b.count),
+                Ranking::Bytes => (a.bytes, b.bytes),
+            };
+            b_value.cmp(&a_value).then_with(|| a_key.cmp(b_key))
+        });
+        writeln!(output, "{} ({} lines)", self.group_by, self.selected.len())?;
+        for (key, counter) in types.into_iter().take(n) {
+            writeln!(
+                output,
+                "TYPE: {} | TOTAL COUNT: {} | TOTAL BYTES: {}",
+                key, counter.count, counter.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for ReplCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let number = |arg: &str| {
+            arg.parse::<usize>()
+                .map_err(|_| format!("invalid number `{}`", arg))
+        };
+        match (name, args.as_slice()) {
+            ("group", [field]) => Ok(ReplCommand::Group(field.to_string())),
+            ("filter", []) => Ok(ReplCommand::Filter(None)),
+            ("filter", _) => shorthand(rest)
+                .unwrap_or_else(|| rest.to_string())
+                .parse()
+                .map(|predicate| ReplCommand::Filter(Some(predicate))),
+            ("types", []) => Ok(ReplCommand::Types),
+            ("top", [n]) => Ok(ReplCommand::Top(number(n)?, Ranking::Count)),
+            ("top", [n, ranking]) => Ok(ReplCommand::Top(number(n)?, ranking.parse()?)),
+            ("sample", [name]) => Ok(ReplCommand::Sample(name.to_string(), DEFAULT_SAMPLE)),
+            ("sample", [name, n]) => Ok(ReplCommand::Sample(name.to_string(), number(n)?)),
+            ("help", []) => Ok(ReplCommand::Help),
+            ("quit" | "exit", []) => Ok(ReplCommand::Quit),
+            ("group" | "types" | "top" | "sample" | "help" | "quit" | "exit", _) => Err(format!(
+                "wrong arguments for `{}`, type `help` to see the commands",
+                name
+            )),
+            _ => Err(format!(
+                "unknown command `{}`, expected one of: group, filter, types, top, sample, help, quit",
+                name
+            )),
+        }
+    }
+}
+
+impl std::str::FromStr for Ranking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Ranking::Count),
+            "bytes" => Ok(Ranking::Bytes),
+            _ => Err(format!(
+                "unknown ranking `{}`, expected one of: count, bytes",
+                s
+            )),
+        }
+    }
+}
+
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Expression of a `field=value` filter, the value being compared as a string unless it's
+/// a number, a boolean or null. `None` if the filter isn't written that way.
+fn shorthand(filter: &str) -> Option<String> {
+    let (field, value) = filter.split_once('=')?;
+    let is_field = |field: &str| {
+        !field.is_empty()
+            && field
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    };
+    if !is_field(field) || value.is_empty() || value.contains(['=', ' ', '\'', '"']) {
+        return None;
+    }
+    let literal = match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::String(_)) | Err(_) => format!("'{}'", value),
+        Ok(_) => value.to_string(),
+    };
+    Some(format!("{} == {}", field, literal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AnalyzeOptions;
+    use crate::reader::analyze_reader;
+
+    const LINES: &str = r#"{"type":"payment","level":"error","amount":30}
+{"type":"login","level":"info"}
+{"type":"payment","level":"info","amount":5}
+not json
+{"type":"payment","level":"error","amount":120}
+{"type":"logout","level":"error"}
+"#;
+
+    fn run(repl: &mut Repl, command: &str) -> String {
+        let mut output = Vec::new();
+        repl.execute(&command.parse().unwrap(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn repl_groups_filters_and_samples_the_lines_in_memory() {
+        let mut repl = Repl::load(LINES.as_bytes(), "type").unwrap();
+        let report = analyze_reader(LINES.as_bytes(), &AnalyzeOptions::default()).unwrap();
+        assert_eq!(repl.report(), report);
+
+        assert_eq!(
+            run(&mut repl, "top 2 bytes"),
+            "type (6 lines)\n\
+             TYPE: payment | TOTAL COUNT: 3 | TOTAL BYTES: 140\n\
+             TYPE: logout | TOTAL COUNT: 1 | TOTAL BYTES: 34\n"
+        );
+        assert_eq!(run(&mut repl, "filter level=error"), "3 of 6 lines kept\n");
+        assert_eq!(
+            run(&mut repl, "sample payment 1"),
+            "{\"type\":\"payment\",\"level\":\"error\",\"amount\":30}\n"
+        );
+        assert_eq!(
+            run(&mut repl, "group level"),
+            "level (3 lines)\nTYPE: error | TOTAL COUNT: 3 | TOTAL BYTES: 129\n"
+        );
+        assert_eq!(run(&mut repl, "filter amount > 100"), "1 of 6 lines kept\n");
+        assert_eq!(run(&mut repl, "filter"), "6 of 6 lines kept\n");
+        assert_eq!(
+            run(&mut repl, "top 1"),
+            "level (6 lines)\nTYPE: error | TOTAL COUNT: 3 | TOTAL BYTES: 129\n"
+        );
+    }
+
+    #[test]
+    fn repl_commands_are_parsed() {
+        assert_eq!(
+            "top 5 bytes".parse(),
+            Ok(ReplCommand::Top(5, Ranking::Bytes))
+        );
+        assert_eq!(
+            "sample payment".parse(),
+            Ok(ReplCommand::Sample("payment".to_string(), DEFAULT_SAMPLE))
+        );
+        assert_eq!(
+            "filter status=500".parse::<ReplCommand>(),
+            "filter status == 500".parse()
+        );
+        assert_eq!(
+            shorthand("level=error"),
+            Some("level == 'error'".to_string())
+        );
+        assert_eq!(shorthand("status >= 500"), None);
+        assert!("top five".parse::<ReplCommand>().is_err());
+        assert!("group".parse::<ReplCommand>().is_err());
+        assert!("select *".parse::<ReplCommand>().is_err());
+
+        let mut repl = Repl::load(LINES.as_bytes(), "type").unwrap();
+        let mut output = Vec::new();
+        repl.run(
+            "bogus\n\nsample logout\nquit\ntypes\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("> unknown command `bogus`"));
+        assert!(output.ends_with("> {\"type\":\"logout\",\"level\":\"error\"}\n> "));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::projection::Projection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::{self, BufRead, Write},
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Re-emits JSON lines keeping the pacing of their timestamps, scaled by `speed`,
+/// so captured logs become a realistic load for downstream consumers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// Field holding the timestamp. Nested fields are separated by dots.
+    pub timestamp_field: String,
+    /// How many times faster than the original the lines are emitted.
+    pub speed: f64,
+    /// Unit of the numeric timestamps.
+    pub time_unit: TimeUnit,
+}
+
+/// Unit of the numeric timestamps, counted since the epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    /// Guessed for every line from the magnitude of the number, so the same field may mix units.
+    /// Anything up to the year 5138 is told apart.
+    #[default]
+    Auto,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl FromStr for TimeUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TimeUnit::Auto),
+            "s" => Ok(TimeUnit::Seconds),
+            "ms" => Ok(TimeUnit::Milliseconds),
+            "us" => Ok(TimeUnit::Microseconds),
+            "ns" => Ok(TimeUnit::Nanoseconds),
+            _ => Err(format!(
+                "unknown time unit `{}`, expected one of: auto, s, ms, us, ns",
+                s
+            )),
+        }
+    }
+}
+
+impl TimeUnit {
+    /// Seconds of a timestamp in this unit.
+    pub fn seconds(self, n: f64) -> f64 {
+        let unit = match self {
+            // 1e11 seconds are in the year 5138, as are 1e14 ms and 1e17 µs
+            TimeUnit::Auto if n.abs() < 1e11 => TimeUnit::Seconds,
+            TimeUnit::Auto if n.abs() < 1e14 => TimeUnit::Milliseconds,
+            TimeUnit::Auto if n.abs() < 1e17 => TimeUnit::Microseconds,
+            TimeUnit::Auto => TimeUnit::Nanoseconds,
+            unit => unit,
+        };
+        match unit {
+            TimeUnit::Milliseconds => n / 1e3,
+            TimeUnit::Microseconds => n / 1e6,
+            TimeUnit::Nanoseconds => n / 1e9,
+            _ => n,
+        }
+    }
+}
+
+/// Lines emitted by a replay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub lines: usize,
+    /// Lines without a timestamp, emitted right after the previous one.
+    pub untimed: usize,
+}
+
+impl Replay {
+    /// Writes every line of the reader into the writer when its time comes, relative to the
+    /// timestamp of the first line. Lines going back in time are emitted right away.
+    pub fn run(&self, reader: impl BufRead, mut writer: impl Write) -> io::Result<ReplayStats> {
+        let field = Projection::single(&self.timestamp_field);
+        let unit = self.time_unit;
+        let speed = if self.speed > 0.0 { self.speed } else { 1.0 };
+        let mut stats = ReplayStats::default();
+        let mut first: Option<(f64, Instant)> = None;
+        for line in reader.lines() {
+            let line = line?;
+            match timestamp(line.as_bytes(), &field, unit) {
+                Some(seconds) => {
+                    let (first_seconds, start) = *first.get_or_insert((seconds, Instant::now()));
+                    let offset = (seconds - first_seconds) / speed;
+                    if offset > 0.0 {
+                        let due = start + Duration::from_secs_f64(offset);
+                        let now = Instant::now();
+                        if due > now {
+                            // the pending lines are delivered before waiting
+                            writer.flush()?;
+                            sleep(due - now);
+                        }
+                    }
+                }
+                None => stats.untimed += 1,
+            }
+            writeln!(writer, "{}", line)?;
+            stats.lines += 1;
+        }
+        writer.flush()?;
+        Ok(stats)
+    }
+}
+
+/// Seconds since the epoch of the timestamp field of the line.
+/// Numbers are in the given unit. Strings are RFC 3339 timestamps, like `2024-02-29T12:34:56.789Z`.
+pub(crate) fn timestamp(line: &[u8], field: &Projection, unit: TimeUnit) -> Option<f64> {
+    match field.field(line)? {
+        Value::Number(n) => Some(unit.seconds(n.as_f64()?)),
+        Value::String(s) => parse_rfc3339(&s),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_rfc3339(s: &str) -> Option<f64> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !matches!(s.as_bytes().get(10), Some(b'T') | Some(b't') | Some(b' ')) {
+        return None;
+    }
+    let mut rest = &s[19..];
+    let mut fraction = 0.0;
+    if let Some(digits) = rest.strip_prefix('.') {
+        let len = digits.bytes().take_while(u8::is_ascii_digit).count();
+        fraction = format!("0.{}", &digits[..len]).parse().ok()?;
+        rest = &digits[len..];
+    }
+    let offset = match rest {
+        "Z" | "z" | "" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours: i64 = rest.get(1..3)?.parse().ok()?;
+            let minutes: i64 = rest.get(4..6)?.parse().ok()?;
+            sign * (hours * 3_600 + minutes * 60)
+        }
+    };
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second - offset;
+    Some(seconds as f64 + fraction)
+}
+
+/// Days since the epoch of the civil date, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses speeds like `10x`, `0.5x` or `2`.
+pub fn parse_speed(s: &str) -> Result<f64, String> {
+    let trimmed = s.trim();
+    let number = trimmed.strip_suffix('x').unwrap_or(trimmed);
+    match number.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!(
+            "invalid speed `{}`, expected a positive number like 10x or 0.5x",
+            s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_keeps_the_scaled_pacing_of_the_timestamps() {
+        let input = "{\"ts\":\"2024-02-29T12:34:56Z\",\"type\":\"A\"}\n\
+                     not json\n\
+                     {\"ts\":\"2024-02-29T12:34:57.5+00:00\",\"type\":\"B\"}\n\
+                     {\"ts\":\"2024-02-29T12:34:50Z\",\"type\":\"C\"}\n";
+        let replay = Replay {
+            timestamp_field: "ts".to_string(),
+            speed: 10.0,
+            time_unit: TimeUnit::Auto,
+        };
+        let mut output = Vec::new();
+        let init = Instant::now();
+        let stats = replay.run(input.as_bytes(), &mut output).unwrap();
+        let elapsed = init.elapsed();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+        assert_eq!(
+            stats,
+            ReplayStats {
+                lines: 4,
+                untimed: 1
+            }
+        );
+        // 1.5 seconds at 10x, the line going back in time doesn't wait
+        assert!(elapsed >= Duration::from_millis(150));
+        assert!(elapsed < Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn timestamps_can_be_numbers_or_rfc3339_strings() {
+        let field = Projection::single("meta.ts");
+        let at = |line: &str| timestamp(line.as_bytes(), &field, TimeUnit::Auto);
+        assert_eq!(at(r#"{"meta":{"ts":1709210096}}"#), Some(1_709_210_096.0));
+        assert_eq!(
+            at(r#"{"meta":{"ts":1709210096500}}"#),
+            Some(1_709_210_096.5)
+        );
+        assert_eq!(
+            at(r#"{"meta":{"ts":"2024-02-29T12:34:56Z"}}"#),
+            Some(1_709_210_096.0)
+        );
+        assert_eq!(
+            at(r#"{"meta":{"ts":"2024-02-29T14:34:56.25+02:00"}}"#),
+            Some(1_709_210_096.25)
+        );
+        assert_eq!(at(r#"{"meta":{"ts":"1970-01-01 00:00:00"}}"#), Some(0.0));
+        assert_eq!(at(r#"{"meta":{"ts":"yesterday"}}"#), None);
+        assert_eq!(at(r#"{"ts":1}"#), None);
+    }
+
+    #[test]
+    fn time_units_are_detected_per_line_unless_given() {
+        let field = Projection::single("ts");
+        let at = |line: &str, unit| timestamp(line.as_bytes(), &field, unit);
+        for line in [
+            r#"{"ts":1709210096}"#,
+            r#"{"ts":1709210096000}"#,
+            r#"{"ts":1709210096000000}"#,
+            r#"{"ts":1709210096000000000}"#,
+        ] {
+            assert_eq!(at(line, TimeUnit::Auto), Some(1_709_210_096.0), "{}", line);
+        }
+        assert_eq!(at(r#"{"ts":1.5}"#, TimeUnit::Auto), Some(1.5));
+        assert_eq!(at(r#"{"ts":1500}"#, TimeUnit::Milliseconds), Some(1.5));
+        assert_eq!(
+            at(r#"{"ts":1709210096}"#, TimeUnit::Nanoseconds),
+            Some(1.709210096)
+        );
+        assert_eq!(
+            at(r#"{"ts":"2024-02-29T12:34:56Z"}"#, TimeUnit::Milliseconds),
+            Some(1_709_210_096.0)
+        );
+        assert_eq!("us".parse(), Ok(TimeUnit::Microseconds));
+        assert!("minutes".parse::<TimeUnit>().is_err());
+    }
+
+    #[test]
+    fn parse_speed_accepts_a_multiplier() {
+        assert_eq!(parse_speed("10x"), Ok(10.0));
+        assert_eq!(parse_speed("0.5x"), Ok(0.5));
+        assert_eq!(parse_speed("2"), Ok(2.0));
+        assert!(parse_speed("0x").is_err());
+        assert!(parse_speed("fast").is_err());
+    }
+}
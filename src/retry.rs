@@ -0,0 +1,182 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    thread::sleep,
+    time::Duration,
+};
+
+/// Reader that survives intermittent failures, like the ones of network filesystems.
+/// When a read fails, the source is opened again and the reading resumes from the
+/// last byte read successfully. It gives up after `retries` consecutive failures.
+pub struct RetryingReader<R, F> {
+    open: F,
+    reader: R,
+    offset: u64,
+    retries: u32,
+    delay: Duration,
+}
+
+impl<R, F> RetryingReader<R, F>
+where
+    R: Read + Seek,
+    F: FnMut() -> io::Result<R>,
+{
+    pub fn new(mut open: F, retries: u32, delay: Duration) -> io::Result<Self> {
+        let reader = open()?;
+        Ok(Self {
+            open,
+            reader,
+            offset: 0,
+            retries,
+            delay,
+        })
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let mut reader = (self.open)()?;
+        reader.seek(SeekFrom::Start(self.offset))?;
+        self.reader = reader;
+        Ok(())
+    }
+}
+
+impl<R, F> Read for RetryingReader<R, F>
+where
+    R: Read + Seek,
+    F: FnMut() -> io::Result<R>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempt = 0;
+        loop {
+            match self.reader.read(buf) {
+                Ok(read) => {
+                    self.offset += read as u64;
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(_) => {
+                    attempt += 1;
+                    sleep(self.delay);
+                    // a failed reopen counts as another attempt
+                    while let Err(e) = self.reopen() {
+                        if attempt >= self.retries {
+                            return Err(e);
+                        }
+                        attempt += 1;
+                        sleep(self.delay);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R, F> Seek for RetryingReader<R, F>
+where
+    R: Read + Seek,
+    F: FnMut() -> io::Result<R>,
+{
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        self.offset = self.reader.seek(position)?;
+        Ok(self.offset)
+    }
+}
+
+/// Parses durations like `500ms`, `2s` or `1m`. Plain numbers are milliseconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "ms"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", s))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("invalid duration `{}`", s)),
+        _ => Err(format!(
+            "invalid duration `{}`, expected a number followed by ms, s or m",
+            s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    /// Fails once every read starting at one of the `failures` offsets.
+    /// The failures are shared by all the openings, like a real flaky mount would.
+    struct Flaky {
+        inner: Cursor<&'static [u8]>,
+        failures: Rc<RefCell<Vec<u64>>>,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let position = self.inner.position();
+            let mut failures = self.failures.borrow_mut();
+            if let Some(i) = failures.iter().position(|f| *f == position) {
+                failures.remove(i);
+                return Err(io::Error::other("stale file handle"));
+            }
+            // short reads make the failures land in the middle of the content
+            let len = buf.len().min(3);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn open_flaky(content: &'static [u8], failures: Vec<u64>) -> impl FnMut() -> io::Result<Flaky> {
+        let failures = Rc::new(RefCell::new(failures));
+        move || {
+            Ok(Flaky {
+                inner: Cursor::new(content),
+                failures: failures.clone(),
+            })
+        }
+    }
+
+    #[test]
+    fn retrying_reader_resumes_from_the_last_good_offset() {
+        let content = b"{\"type\":\"A\"}\n{\"type\":\"B\"}\n";
+        let open = open_flaky(content, vec![3, 9, 9, 18]);
+        let mut reader = RetryingReader::new(open, 2, Duration::ZERO).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content.to_vec());
+    }
+
+    #[test]
+    fn retrying_reader_gives_up_after_the_retries() {
+        let content = b"{\"type\":\"A\"}\n";
+        let open = open_flaky(content, vec![3, 3, 3]);
+        let mut reader = RetryingReader::new(open, 2, Duration::ZERO).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn parse_duration_accepts_several_units() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("250"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("2h").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert_eq!(
+            parse_duration("18446744073709551615m"),
+            Err("invalid duration `18446744073709551615m`".to_string())
+        );
+    }
+}
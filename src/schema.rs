@@ -0,0 +1,84 @@
+use std::{fmt, str::FromStr};
+
+/// Version of the layout of the machine readable outputs, carried by them as `report_version`.
+/// It's bumped whenever a field or a column is added, removed or changed, and the older layouts
+/// can still be asked for with a `SchemaVersion`.
+pub const REPORT_VERSION: u32 = 2;
+
+/// Layout of the machine readable outputs: the JSON and CSV source reports and the runs of
+/// the history file. Outputs written before the versions existed are version 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SchemaVersion(u32);
+
+impl SchemaVersion {
+    /// The layout the outputs are written with unless another one is asked for.
+    pub const LATEST: SchemaVersion = SchemaVersion(REPORT_VERSION);
+
+    pub fn new(version: u32) -> Result<Self, String> {
+        if (1..=REPORT_VERSION).contains(&version) {
+            Ok(SchemaVersion(version))
+        } else {
+            Err(format!(
+                "unknown schema version `{}`, expected one from 1 to {}",
+                version, REPORT_VERSION
+            ))
+        }
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The `report_version` written in the outputs, which version 1 didn't have.
+    pub fn report_version(self) -> Option<u32> {
+        if self.0 >= 2 {
+            Some(self.0)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+impl FromStr for SchemaVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let version = s.trim().parse().map_err(|_| {
+            format!(
+                "invalid schema version `{}`, expected a number from 1 to {}",
+                s, REPORT_VERSION
+            )
+        })?;
+        SchemaVersion::new(version)
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_versions_go_from_the_first_to_the_latest() {
+        assert_eq!("1".parse::<SchemaVersion>().unwrap().report_version(), None);
+        assert_eq!(
+            "2".parse::<SchemaVersion>().unwrap(),
+            SchemaVersion::default()
+        );
+        assert_eq!(SchemaVersion::LATEST.report_version(), Some(REPORT_VERSION));
+        assert!("0".parse::<SchemaVersion>().is_err());
+        assert!("3".parse::<SchemaVersion>().is_err());
+        assert!("v2".parse::<SchemaVersion>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::error::AnalyzeError;
+use crate::error_report::error_category;
+use crate::html::utc_timestamp;
+use crate::models::Report;
+use crate::schema::SchemaVersion;
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Writes the events of a run of the tool as JSON lines, so the runs can be analyzed
+/// with the tool itself. The events are told apart by their `type` field:
+/// `run_started`, `chunk_completed`, `line_error`, `run_finished` and `run_failed`, and carry
+/// the `report_version` of their layout.
+/// Clones share the same file so one of them can be moved into the callbacks.
+#[derive(Debug, Clone)]
+pub struct SelfLog(Arc<Mutex<SelfLogFile>>);
+
+#[derive(Debug)]
+struct SelfLogFile {
+    writer: BufWriter<File>,
+    started: Instant,
+    /// Minimum bytes between two `chunk_completed` events, as progress may be notified per line.
+    step: u64,
+    logged_bytes: u64,
+    /// First error writing the file, reported by `finish`.
+    error: Option<io::Error>,
+    report_version: Option<u32>,
+}
+
+impl SelfLog {
+    /// Creates the file. `step` is the minimum amount of bytes between two `chunk_completed` events.
+    /// Version 1 of the layout had no `report_version` in the events.
+    pub fn create(path: &Path, step: u64, version: SchemaVersion) -> io::Result<Self> {
+        Ok(Self(Arc::new(Mutex::new(SelfLogFile {
+            writer: BufWriter::new(File::create(path)?),
+            started: Instant::now(),
+            step: step.max(1),
+            logged_bytes: 0,
+            error: None,
+            report_version: version.report_version(),
+        }))))
+    }
+
+    pub fn started(&self, source: &str) {
+        self.write(json!({ "type": "run_started", "source": source }));
+    }
+
+    /// Its signature matches the `on_progress` callback.
+    pub fn progress(&self, bytes_done: u64, total: Option<u64>) {
+        let mut file = self.0.lock().expect("The self log lock is poisoned");
+        if bytes_done < file.logged_bytes + file.step && Some(bytes_done) != total {
+            return;
+        }
+        file.logged_bytes = bytes_done;
+        file.write_event(json!({
+            "type": "chunk_completed",
+            "bytes_done": bytes_done,
+            "total": total,
+        }));
+    }
+
+    /// Its signature matches the `on_error` callback.
+    pub fn error(&self, line_number: usize, offset: u64, _line: &[u8], e: &serde_json::Error) {
+        self.write(json!({
+            "type": "line_error",
+            "line_number": line_number,
+            "offset": offset,
+            "category": error_category(e),
+            "message": e.to_string(),
+        }));
+    }
+
+    /// Summary of the run: the totals when it succeeded or the error when it didn't.
+    pub fn finished(&self, outcome: &Result<Report, AnalyzeError>, duration: Duration) {
+        let duration_ms = duration.as_millis() as u64;
+        self.write(match outcome {
+            Ok(report) => json!({
+                "type": "run_finished",
+                "duration_ms": duration_ms,
+                "types": report.len(),
+                "lines": report.types.values().map(|c| c.count).sum::<usize>(),
+                "bytes": report.types.values().map(|c| c.bytes).sum::<usize>(),
+            }),
+            Err(e) => json!({
+                "type": "run_failed",
+                "duration_ms": duration_ms,
+                "message": e.to_string(),
+            }),
+        });
+    }
+
+    /// Flushes the file, reporting the first error found while writing it.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut file = self.0.lock().expect("The self log lock is poisoned");
+        if let Some(e) = file.error.take() {
+            return Err(e);
+        }
+        file.writer.flush()
+    }
+
+    fn write(&self, event: Value) {
+        self.0
+            .lock()
+            .expect("The self log lock is poisoned")
+            .write_event(event);
+    }
+}
+
+impl SelfLogFile {
+    /// Adds the time of the event, the time elapsed since the log was created and the version.
+    fn write_event(&mut self, mut event: Value) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Some(report_version) = self.report_version {
+            event["report_version"] = json!(report_version);
+        }
+        event["time"] = json!(utc_timestamp(SystemTime::now()));
+        event["elapsed_ms"] = json!(self.started.elapsed().as_millis() as u64);
+        let result = serde_json::to_writer(&mut self.writer, &event)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_reader, AnalyzeOptions, Strategy};
+
+    #[test]
+    fn self_log_can_be_analyzed_by_the_tool_itself() {
+        let path = std::env::temp_dir().join(format!("fr-self-log-{}.jsonl", std::process::id()));
+        let log = SelfLog::create(&path, 20, SchemaVersion::LATEST).unwrap();
+        let (progress, errors) = (log.clone(), log.clone());
+        let options = AnalyzeOptions::builder()
+            .strategy(Strategy::Naive)
+            .on_progress(move |done, total| progress.progress(done, total))
+            .on_error(move |line_number, offset, line, e| {
+                errors.error(line_number, offset, line, e)
+            })
+            .build();
+        let content = "{\"type\":\"A\"}\n{\"type\":\"A\"}\n{\"type\":\"B\"\n{\"type\":\"A\"}\n";
+        log.started("the test");
+        let init = Instant::now();
+        let outcome = analyze_reader(content.as_bytes(), &options);
+        log.finished(&outcome, init.elapsed());
+        log.finish().unwrap();
+
+        let events =
+            analyze_reader(File::open(&path).unwrap(), &AnalyzeOptions::default()).unwrap();
+        let versions = analyze_reader(
+            File::open(&path).unwrap(),
+            &AnalyzeOptions::builder().group_by("report_version").build(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(versions.get("2").map(|c| c.count), Some(5));
+        let count = |key: &str| events.get(key).map(|c| c.count);
+        assert_eq!(count("run_started"), Some(1));
+        // 13 bytes per line and events every 20 bytes at least
+        assert_eq!(count("chunk_completed"), Some(2));
+        assert_eq!(count("line_error"), Some(1));
+        assert_eq!(count("run_finished"), Some(1));
+        assert_eq!(count("ERROR"), None);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::condition::Comparison;
+use crate::models::Report;
+use crate::schema::SchemaVersion;
+use serde_json::json;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+/// Time a client has to send its request, or to take the answer, before it's dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes of the request line and the headers read at most, way over what the routes need.
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// Serves the report as JSON over HTTP, so dashboards and scripts can query it
+/// without running the analysis again:
+///
+/// - `GET /report`: the whole report, with the `report_version` of its layout.
+/// - `GET /types/{name}`: the count and the bytes of a type.
+/// - `GET /query?filter=count>1000`: the types matching a comparison (see `Comparison`).
+///
+/// Requests are handled one at a time until the process is stopped. Clients get `TIMEOUT` to send
+/// theirs, of `MAX_HEAD_BYTES` before the body, and as long to take the answer, so a slow or a
+/// broken one doesn't hold the rest for long.
+pub fn serve(report: &Report, addr: impl ToSocketAddrs, version: SchemaVersion) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        // a broken connection shouldn't stop the server
+        if let Err(e) = stream.and_then(|stream| handle_connection(report, stream, version)) {
+            eprintln!("Error handling a request - {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(report: &Report, stream: TcpStream, version: SchemaVersion) -> io::Result<()> {
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let deadline = Deadline {
+        stream: &stream,
+        at: Instant::now() + TIMEOUT,
+    };
+    let mut reader = BufReader::new(deadline.take(MAX_HEAD_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are not needed, but they are read so the client doesn't get a reset
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = if !header.ends_with('\n') && reader.get_ref().limit() == 0 {
+        error(
+            431,
+            &format!(
+                "the request line and the headers take over {} bytes",
+                MAX_HEAD_BYTES
+            ),
+        )
+    } else {
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        route(report, method, target, version)
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        431 => "Request Header Fields Too Large",
+        _ => "Method Not Allowed",
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    if status == 431 {
+        // some more of the request is drained, so the client gets the answer instead of a reset
+        stream.shutdown(Shutdown::Write)?;
+        let mut rest = Deadline {
+            stream,
+            at: Instant::now() + TIMEOUT,
+        }
+        .take(MAX_HEAD_BYTES);
+        let _ = io::copy(&mut rest, &mut io::sink());
+    }
+    Ok(())
+}
+
+/// Reads from the stream until the deadline, however slowly the bytes come.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    at: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.at.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the client took too long to send the request",
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Status and JSON body answering the request.
+fn route(report: &Report, method: &str, target: &str, version: SchemaVersion) -> (u16, String) {
+    if method != "GET" {
+        return error(405, "only GET requests are supported");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path == "/report" {
+        let mut body = json!(report);
+        if let Some(report_version) = version.report_version() {
+            body["report_version"] = json!(report_version);
+        }
+        return (200, body.to_string());
+    }
+    if let Some(name) = path.strip_prefix("/types/") {
+        let name = percent_decode(name);
+        return match report.get(&name) {
+            Some(counter) => (200, json!(counter).to_string()),
+            None => error(404, &format!("unknown type `{}`", name)),
+        };
+    }
+    if path == "/query" {
+        let filter = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("filter="))
+            .map(percent_decode);
+        let comparison: Comparison = match filter.as_deref().map(str::parse) {
+            Some(Ok(comparison)) => comparison,
+            Some(Err(e)) => return error(400, &e),
+            None => return error(400, "missing the filter parameter"),
+        };
+        let types: serde_json::Map<_, _> = report
+            .types
+            .iter()
+            .filter(|(_, counter)| comparison.matches(counter))
+            .map(|(key, counter)| (key.to_string(), json!(counter)))
+            .collect();
+        return (200, json!({ "types": types }).to_string());
+    }
+    error(404, &format!("unknown path `{}`", path))
+}
+
+fn error(status: u16, message: &str) -> (u16, String) {
+    (status, json!({ "error": message }).to_string())
+}
+
+/// Decodes the `%XX` escapes and the `+` of a URL component.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (None, b'+') => decoded.push(b' '),
+            (None, byte) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let mut report = Report::default();
+        report.add_line("A".into(), 10);
+        report.add_line("A".into(), 10);
+        report.add_line("event login".into(), 50);
+        report
+    }
+
+    fn route_latest(report: &Report, method: &str, target: &str) -> (u16, String) {
+        route(report, method, target, SchemaVersion::LATEST)
+    }
+
+    fn json(body: &str) -> serde_json::Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn route_answers_the_report_the_types_and_the_queries() {
+        let report = report();
+        let (status, body) = route_latest(&report, "GET", "/report");
+        assert_eq!(status, 200);
+        assert_eq!(serde_json::from_str::<Report>(&body).unwrap(), report);
+        assert_eq!(json(&body)["report_version"], 2);
+        let (_, body) = route(&report, "GET", "/report", SchemaVersion::new(1).unwrap());
+        assert_eq!(json(&body).get("report_version"), None);
+
+        let (status, body) = route_latest(&report, "GET", "/types/event%20login");
+        assert_eq!(status, 200);
+        assert_eq!(json(&body), json(r#"{"bytes":50,"count":1}"#));
+        assert_eq!(route_latest(&report, "GET", "/types/B").0, 404);
+
+        let (status, body) = route_latest(&report, "GET", "/query?filter=count%3E%3D2");
+        assert_eq!(status, 200);
+        assert_eq!(
+            json(&body),
+            json(r#"{"types":{"A":{"bytes":20,"count":2}}}"#)
+        );
+        assert_eq!(route_latest(&report, "GET", "/query?filter=lines>2").0, 400);
+        assert_eq!(route_latest(&report, "GET", "/query").0, 400);
+        assert_eq!(route_latest(&report, "POST", "/report").0, 405);
+        assert_eq!(route_latest(&report, "GET", "/").0, 404);
+    }
+
+    /// Sends `request` to a server handling a single connection, returning the answer.
+    fn answer(request: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(&report(), stream, SchemaVersion::LATEST).unwrap();
+        });
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(request).unwrap();
+        let mut answer = String::new();
+        client.read_to_string(&mut answer).unwrap();
+        drop(client);
+        server.join().unwrap();
+        answer
+    }
+
+    #[test]
+    fn handle_connection_caps_the_size_of_the_requests() {
+        assert!(answer(b"GET /types/A HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let mut request = b"GET /types/A HTTP/1.1\r\n".to_vec();
+        for i in 0..600 {
+            request.extend_from_slice(format!("X-Header-{}: {}\r\n", i, "x".repeat(20)).as_bytes());
+        }
+        request.extend_from_slice(b"\r\n");
+        assert!(answer(&request).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+
+    #[test]
+    fn percent_decode_handles_the_escapes() {
+        assert_eq!(percent_decode("count+%3E+10"), "count > 10");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%"), "%zz%");
+    }
+}
@@ -0,0 +1,376 @@
+use crate::projection::Projection;
+use serde_json::Value;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+/// Runs created by the process, so the sorts running at the same time don't share their files.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts JSON lines by a field with an external merge sort, so files bigger than the memory
+/// can be ordered. Sorted runs of `run_size` bytes are written to `temp_dir` and merged,
+/// `max_open_runs` at a time, in as many passes as needed. The sort is stable: lines with the
+/// same value keep their order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalSort {
+    /// Field to sort by. Nested fields are separated by dots.
+    pub by: String,
+    /// Bytes of lines sorted in memory at once.
+    pub run_size: usize,
+    /// Directory of the sorted runs.
+    pub temp_dir: PathBuf,
+    /// Runs open at once while merging, to stay under the limit of open files. More runs are
+    /// merged in groups of this size into bigger runs first.
+    pub max_open_runs: usize,
+}
+
+impl Default for ExternalSort {
+    fn default() -> Self {
+        Self {
+            by: "timestamp".to_string(),
+            run_size: 100_000_000,
+            temp_dir: std::env::temp_dir(),
+            max_open_runs: 64,
+        }
+    }
+}
+
+/// Lines written by a sort.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SortStats {
+    pub lines: usize,
+    /// Lines without the field, or that couldn't be parsed, which go last.
+    pub unkeyed: usize,
+    /// Sorted runs written to disk, before merging them. Zero when the input fits in a single run.
+    pub runs: usize,
+}
+
+/// Value of the field of a line. Numbers go before strings, and lines without the field last.
+#[derive(Debug, Clone, PartialEq)]
+enum SortKey {
+    Number(f64),
+    Text(String),
+    Missing,
+}
+
+impl SortKey {
+    fn rank(&self) -> u8 {
+        match self {
+            SortKey::Number(_) => 0,
+            SortKey::Text(_) => 1,
+            SortKey::Missing => 2,
+        }
+    }
+}
+
+impl Eq for SortKey {}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl ExternalSort {
+    /// Writes the lines of the reader sorted into the writer. Empty lines are dropped.
+    pub fn run(&self, mut reader: impl BufRead, mut writer: impl Write) -> io::Result<SortStats> {
+        let field = Projection::single(&self.by);
+        let mut stats = SortStats::default();
+        // every run written, removed once the sort finishes or fails
+        let mut written: Vec<PathBuf> = Vec::new();
+        let mut runs: Vec<PathBuf> = Vec::new();
+        let mut run: Vec<(SortKey, Vec<u8>)> = Vec::new();
+        let mut run_bytes = 0;
+        let result = (|| {
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line)? > 0 {
+                let content = trim_newline(&line);
+                if content.iter().all(u8::is_ascii_whitespace) {
+                    line.clear();
+                    continue;
+                }
+                let key = sort_key(content, &field);
+                if key == SortKey::Missing {
+                    stats.unkeyed += 1;
+                }
+                stats.lines += 1;
+                run_bytes += content.len();
+                run.push((key, content.to_vec()));
+                line.clear();
+                if run_bytes >= self.run_size {
+                    runs.push(self.write_run(&mut run, &mut written)?);
+                    run_bytes = 0;
+                }
+            }
+            if runs.is_empty() {
+                // everything fits in memory, there's nothing to merge
+                run.sort_by(|a, b| a.0.cmp(&b.0));
+                for (_, line) in &run {
+                    write_line(&mut writer, line)?;
+                }
+            } else {
+                if !run.is_empty() {
+                    runs.push(self.write_run(&mut run, &mut written)?);
+                }
+                stats.runs = runs.len();
+                let max_open_runs = self.max_open_runs.max(2);
+                while runs.len() > max_open_runs {
+                    // consecutive runs are merged together, so equal keys keep their order
+                    runs = runs
+                        .chunks(max_open_runs)
+                        .map(|group| self.merge_run(group, &field, &mut written))
+                        .collect::<io::Result<_>>()?;
+                }
+                merge(&runs, &field, &mut writer)?;
+            }
+            writer.flush()
+        })();
+        for run in &written {
+            let _ = fs::remove_file(run);
+        }
+        result.map(|_| stats)
+    }
+
+    /// Writes the lines, sorted, into a new run.
+    fn write_run(
+        &self,
+        run: &mut Vec<(SortKey, Vec<u8>)>,
+        written: &mut Vec<PathBuf>,
+    ) -> io::Result<PathBuf> {
+        run.sort_by(|a, b| a.0.cmp(&b.0));
+        let (path, mut writer) = self.create_run(written)?;
+        for (_, line) in run.drain(..) {
+            write_line(&mut writer, &line)?;
+        }
+        writer.flush()?;
+        Ok(path)
+    }
+
+    /// Merges a group of runs into a new run, removing them.
+    fn merge_run(
+        &self,
+        group: &[PathBuf],
+        field: &Projection,
+        written: &mut Vec<PathBuf>,
+    ) -> io::Result<PathBuf> {
+        let (path, mut writer) = self.create_run(written)?;
+        merge(group, field, &mut writer)?;
+        writer.flush()?;
+        for run in group {
+            fs::remove_file(run)?;
+        }
+        Ok(path)
+    }
+
+    /// Creates a new run file. Files already there, like the ones left by a process with the same
+    /// id or links put in their place, are never opened.
+    fn create_run(&self, written: &mut Vec<PathBuf>) -> io::Result<(PathBuf, BufWriter<File>)> {
+        loop {
+            let path = self.temp_dir.join(format!(
+                "fr-sort-{}-{}.jsonl",
+                std::process::id(),
+                RUNS.fetch_add(1, AtomicOrdering::Relaxed)
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    written.push(path.clone());
+                    return Ok((path, BufWriter::new(file)));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Merges the sorted runs. Equal keys are taken from the earliest run first to keep the sort stable.
+fn merge(runs: &[PathBuf], field: &Projection, writer: &mut impl Write) -> io::Result<()> {
+    let mut readers = runs
+        .iter()
+        .map(|run| Ok(BufReader::new(File::open(run)?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(line) = read_line(reader)? {
+            heap.push(Reverse((sort_key(&line, field), index, line)));
+        }
+    }
+    while let Some(Reverse((_, index, line))) = heap.pop() {
+        write_line(writer, &line)?;
+        if let Some(line) = read_line(&mut readers[index])? {
+            heap.push(Reverse((sort_key(&line, field), index, line)));
+        }
+    }
+    Ok(())
+}
+
+/// Next line of a run, without its newline.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    let length = trim_newline(&line).len();
+    line.truncate(length);
+    Ok(Some(line))
+}
+
+fn write_line(writer: &mut impl Write, line: &[u8]) -> io::Result<()> {
+    writer.write_all(line)?;
+    writer.write_all(b"\n")
+}
+
+/// The line without its `\n` or `\r\n` terminator.
+fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn sort_key(line: &[u8], field: &Projection) -> SortKey {
+    match field.field(line) {
+        Some(Value::Number(n)) => n.as_f64().map_or(SortKey::Missing, SortKey::Number),
+        Some(Value::String(s)) => SortKey::Text(s),
+        _ => SortKey::Missing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_merges_the_runs_keeping_equal_keys_in_order() {
+        let input = "{\"ts\":3,\"n\":1}\n\
+                     {\"ts\":\"b\",\"n\":2}\n\
+                     {\"n\":3}\n\
+                     {\"ts\":1,\"n\":4}\n\
+                     \n\
+                     {\"ts\":3,\"n\":5}\n\
+                     not json\n\
+                     {\"ts\":\"a\",\"n\":6}\n\
+                     {\"ts\":2.5,\"n\":7}\n\
+                     {\"ts\":3,\"n\":8}\n";
+        let expected = "{\"ts\":1,\"n\":4}\n\
+                        {\"ts\":2.5,\"n\":7}\n\
+                        {\"ts\":3,\"n\":1}\n\
+                        {\"ts\":3,\"n\":5}\n\
+                        {\"ts\":3,\"n\":8}\n\
+                        {\"ts\":\"a\",\"n\":6}\n\
+                        {\"ts\":\"b\",\"n\":2}\n\
+                        {\"n\":3}\n\
+                        not json\n";
+        // two runs open at once merge the nine runs of a line in three passes
+        for (run_size, max_open_runs, runs) in [(1_000, 64, 0), (30, 64, 4), (1, 64, 9), (1, 2, 9)]
+            .iter()
+            .copied()
+        {
+            let sort = ExternalSort {
+                by: "ts".to_string(),
+                run_size,
+                max_open_runs,
+                ..ExternalSort::default()
+            };
+            let mut output = Vec::new();
+            let stats = sort.run(input.as_bytes(), &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+            assert_eq!(
+                stats,
+                SortStats {
+                    lines: 9,
+                    unkeyed: 2,
+                    runs
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn concurrent_sorts_never_share_or_reuse_run_files() {
+        let temp_dir = std::env::temp_dir().join(format!("fr-sort-runs-{}", std::process::id()));
+        fs::create_dir_all(&temp_dir).unwrap();
+        // files left where the next runs would go are skipped, not overwritten
+        let next = RUNS.load(AtomicOrdering::Relaxed);
+        let planted: Vec<_> = (next..next + 100)
+            .map(|run| temp_dir.join(format!("fr-sort-{}-{}.jsonl", std::process::id(), run)))
+            .collect();
+        for path in &planted {
+            fs::write(path, "planted\n").unwrap();
+        }
+        let sorts: Vec<_> = (0..4)
+            .map(|i| {
+                let temp_dir = temp_dir.clone();
+                std::thread::spawn(move || {
+                    let input: String = (0..20)
+                        .rev()
+                        .map(|ts| format!("{{\"ts\":{},\"sort\":{}}}\n", ts, i))
+                        .collect();
+                    let sort = ExternalSort {
+                        by: "ts".to_string(),
+                        run_size: 1,
+                        temp_dir,
+                        ..ExternalSort::default()
+                    };
+                    let mut output = Vec::new();
+                    sort.run(input.as_bytes(), &mut output).unwrap();
+                    let expected: String = (0..20)
+                        .map(|ts| format!("{{\"ts\":{},\"sort\":{}}}\n", ts, i))
+                        .collect();
+                    assert_eq!(String::from_utf8(output).unwrap(), expected);
+                })
+            })
+            .collect();
+        for sort in sorts {
+            sort.join().unwrap();
+        }
+        for path in &planted {
+            assert_eq!(fs::read_to_string(path).unwrap(), "planted\n");
+        }
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn sort_keeps_the_lines_that_are_not_utf8() {
+        let input = b"{\"ts\":2,\"s\":\"\xff\"}\r\n{\"ts\":1}\n\xfe\n".to_vec();
+        let sort = ExternalSort {
+            by: "ts".to_string(),
+            run_size: 1,
+            ..ExternalSort::default()
+        };
+        let mut output = Vec::new();
+        sort.run(&input[..], &mut output).unwrap();
+        assert_eq!(
+            output,
+            b"{\"ts\":1}\n{\"ts\":2,\"s\":\"\xff\"}\n\xfe\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn sort_by_nested_field() {
+        let input = "{\"meta\":{\"ts\":\"2024-01-02\"}}\n{\"meta\":{\"ts\":\"2024-01-01\"}}\n";
+        let sort = ExternalSort {
+            by: "meta.ts".to_string(),
+            ..ExternalSort::default()
+        };
+        let mut output = Vec::new();
+        sort.run(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"meta\":{\"ts\":\"2024-01-01\"}}\n{\"meta\":{\"ts\":\"2024-01-02\"}}\n"
+        );
+    }
+}
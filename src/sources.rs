@@ -0,0 +1,175 @@
+use crate::models::Report;
+use crate::pivot::csv_field;
+use crate::schema::SchemaVersion;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Reports of several sources (files, pods...) kept apart, so the results can be
+/// sliced by source downstream instead of only merged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceReports(pub BTreeMap<String, Report>);
+
+/// Results of a type within a source.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    pub source: String,
+    #[serde(rename = "type")]
+    pub key: String,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// Entries of a JSON source report along with the version of its layout.
+#[derive(Serialize)]
+struct VersionedEntries {
+    report_version: u32,
+    entries: Vec<SourceEntry>,
+}
+
+impl SourceReports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the report of a source, merging it if the source is already there.
+    pub fn insert(&mut self, source: impl Into<String>, report: Report) {
+        self.0.entry(source.into()).or_default().merge(report);
+    }
+
+    /// Results across all the sources.
+    pub fn merged(&self) -> Report {
+        self.0.values().cloned().fold(Report::new(), |a, b| a + b)
+    }
+
+    /// One entry per source and type, sorted by source and type.
+    pub fn entries(&self) -> Vec<SourceEntry> {
+        let mut entries = Vec::new();
+        for (source, report) in &self.0 {
+            let mut types: Vec<_> = report.types.iter().collect();
+            types.sort_by(|a, b| a.0.cmp(b.0));
+            entries.extend(types.into_iter().map(|(key, counter)| SourceEntry {
+                source: source.clone(),
+                key: key.to_string(),
+                count: counter.count,
+                bytes: counter.bytes,
+            }));
+        }
+        entries
+    }
+
+    /// Writes the entries as JSON, under `entries` next to the `report_version` of the layout.
+    /// Version 1 was a bare array of the entries.
+    pub fn write_json(&self, writer: impl Write, version: SchemaVersion) -> io::Result<()> {
+        let entries = self.entries();
+        match version.report_version() {
+            Some(report_version) => serde_json::to_writer_pretty(
+                writer,
+                &VersionedEntries {
+                    report_version,
+                    entries,
+                },
+            )?,
+            None => serde_json::to_writer_pretty(writer, &entries)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the entries as CSV with a `report_version,source,type,count,bytes` header.
+    /// Version 1 had no `report_version` column.
+    pub fn write_csv(&self, mut writer: impl Write, version: SchemaVersion) -> io::Result<()> {
+        let (header, prefix) = match version.report_version() {
+            Some(report_version) => ("report_version,", format!("{},", report_version)),
+            None => ("", String::new()),
+        };
+        writeln!(writer, "{}source,type,count,bytes", header)?;
+        for entry in self.entries() {
+            writeln!(
+                writer,
+                "{}{},{},{},{}",
+                prefix,
+                csv_field(&entry.source),
+                csv_field(&entry.key),
+                entry.count,
+                entry.bytes
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the entries as CSV if the path ends with `.csv` and as JSON otherwise.
+    pub fn write_to(&self, path: &Path, version: SchemaVersion) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => self.write_csv(writer, version),
+            _ => self.write_json(writer, version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_reports_keep_the_sources_apart() {
+        let mut a = Report::new();
+        a.add_line("A".into(), 10);
+        let mut b = Report::new();
+        b.add_line("A".into(), 5);
+        b.add_line("B,C".into(), 7);
+        let mut sources = SourceReports::new();
+        sources.insert("b.log", b);
+        sources.insert("a.log", a.clone());
+        sources.insert("a.log", a);
+        assert_eq!(
+            sources.merged().get("A").map(|c| (c.count, c.bytes)),
+            Some((3, 25))
+        );
+
+        let mut csv = Vec::new();
+        sources.write_csv(&mut csv, SchemaVersion::LATEST).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "report_version,source,type,count,bytes\n\
+             2,a.log,A,2,20\n2,b.log,A,1,5\n2,b.log,\"B,C\",1,7\n"
+        );
+        let mut json = Vec::new();
+        sources
+            .write_json(&mut json, SchemaVersion::LATEST)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["report_version"], 2);
+        assert_eq!(
+            json["entries"][0],
+            serde_json::json!({"source": "a.log", "type": "A", "count": 2, "bytes": 20})
+        );
+    }
+
+    #[test]
+    fn source_reports_can_be_written_with_the_first_layout() {
+        let mut report = Report::new();
+        report.add_line("A".into(), 10);
+        let mut sources = SourceReports::new();
+        sources.insert("a.log", report);
+        let v1 = SchemaVersion::new(1).unwrap();
+
+        let mut csv = Vec::new();
+        sources.write_csv(&mut csv, v1).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "source,type,count,bytes\na.log,A,1,10\n"
+        );
+        let mut json = Vec::new();
+        sources.write_json(&mut json, v1).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"source": "a.log", "type": "A", "count": 1, "bytes": 10}])
+        );
+    }
+}
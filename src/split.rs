@@ -0,0 +1,330 @@
+use crate::provenance::Provenance;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// How the workers of an analysis share the files of a split.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitStrategy {
+    /// A single set of files, each behind its own lock. Every line is written whole while
+    /// holding the lock of its file, so only the workers writing the same type wait.
+    #[default]
+    Mutex,
+    /// Every worker thread writes its own shard of each file, merged by `finish`.
+    /// Threads rarely wait for each other, at the cost of the final merge.
+    Shards,
+}
+
+impl FromStr for SplitStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mutex" => Ok(SplitStrategy::Mutex),
+            "shards" => Ok(SplitStrategy::Shards),
+            _ => Err(format!(
+                "unknown split strategy `{}`, expected one of: mutex, shards",
+                s
+            )),
+        }
+    }
+}
+
+/// Writes every line into the file of its type (`A.jsonl`, `B.jsonl`...).
+/// Lines are never interleaved, whatever the number of workers writing them.
+/// Clones share the same files so one of them can be moved into the `on_line` callback.
+#[derive(Debug, Clone)]
+pub struct Split {
+    dir: PathBuf,
+    strategy: SplitStrategy,
+    shards: Arc<Vec<SplitShard>>,
+    provenance: Option<Provenance>,
+}
+
+/// Files by name, as different types may share a file name once sanitized. The workers only
+/// share a read lock over them, unless a file has to be created.
+type SplitShard = RwLock<HashMap<String, Mutex<SplitFile>>>;
+
+#[derive(Debug, Default)]
+struct SplitFile {
+    writer: Option<BufWriter<File>>,
+    lines: usize,
+    /// First error creating or writing the file, reported by `finish`.
+    error: Option<io::Error>,
+}
+
+impl SplitFile {
+    fn write_line(&mut self, line: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Some(writer) = &mut self.writer {
+            let result = writer.write_all(line).and_then(|_| writer.write_all(b"\n"));
+            match result {
+                Ok(()) => self.lines += 1,
+                Err(e) => self.error = Some(e),
+            }
+        }
+    }
+}
+
+impl Split {
+    /// Creates the directory if needed. The files are created as the types show up.
+    pub fn new(dir: impl Into<PathBuf>, strategy: SplitStrategy) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let shards = match strategy {
+            SplitStrategy::Mutex => 1,
+            SplitStrategy::Shards => std::thread::available_parallelism().map_or(4, |n| n.get()),
+        };
+        Ok(Self {
+            dir,
+            strategy,
+            shards: Arc::new((0..shards).map(|_| RwLock::default()).collect()),
+            provenance: None,
+        })
+    }
+
+    /// Appends the fields of the provenance to every line written.
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// Writes the raw line to the file of its type. Its signature matches the `on_line` callback.
+    pub fn record(&self, key: &str, line_number: usize, offset: u64, line: &[u8]) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let annotated = self
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.annotate(line, line_number, offset));
+        let line = annotated.as_deref().unwrap_or(line);
+        let index = self.shard_index();
+        let name = file_name(key);
+        if let Some(file) = self.shards[index]
+            .read()
+            .expect("The split lock is poisoned")
+            .get(&name)
+        {
+            return file
+                .lock()
+                .expect("The split file lock is poisoned")
+                .write_line(line);
+        }
+        self.shards[index]
+            .write()
+            .expect("The split lock is poisoned")
+            .entry(name)
+            .or_insert_with_key(|name| Mutex::new(self.create(name, index)))
+            .get_mut()
+            .expect("The split file lock is poisoned")
+            .write_line(line);
+    }
+
+    /// Flushes the files, merging the shards if any, and returns the number of lines of each file.
+    pub fn finish(&self) -> io::Result<Vec<(String, usize)>> {
+        let mut summary: HashMap<String, usize> = HashMap::new();
+        let mut merged: Vec<String> = Vec::new();
+        for shard in self.shards.iter() {
+            let mut files = shard.write().expect("The split lock is poisoned");
+            for (name, file) in files.iter_mut() {
+                let file = file.get_mut().expect("The split file lock is poisoned");
+                if let Some(e) = file.error.take() {
+                    return Err(e);
+                }
+                if let Some(writer) = &mut file.writer {
+                    writer.flush()?;
+                }
+                *summary.entry(name.clone()).or_default() += file.lines;
+                merged.push(name.clone());
+            }
+        }
+        if self.strategy == SplitStrategy::Shards {
+            merged.sort();
+            merged.dedup();
+            for name in merged {
+                let mut file = File::create(self.dir.join(&name))?;
+                for index in 0..self.shards.len() {
+                    let shard_path = self.dir.join(shard_name(&name, index));
+                    if shard_path.exists() {
+                        io::copy(&mut File::open(&shard_path)?, &mut file)?;
+                        fs::remove_file(shard_path)?;
+                    }
+                }
+                file.flush()?;
+            }
+        }
+        let mut summary: Vec<(String, usize)> = summary.into_iter().collect();
+        summary.sort();
+        Ok(summary)
+    }
+
+    fn shard_index(&self) -> usize {
+        if self.shards.len() == 1 {
+            return 0;
+        }
+        let mut hasher = DefaultHasher::new();
+        std::thread::current().id().hash(&mut hasher);
+        hasher.finish() as usize % self.shards.len()
+    }
+
+    fn create(&self, name: &str, index: usize) -> SplitFile {
+        let path = match self.strategy {
+            SplitStrategy::Mutex => self.dir.join(name),
+            SplitStrategy::Shards => self.dir.join(shard_name(name, index)),
+        };
+        match File::create(path) {
+            Ok(f) => SplitFile {
+                writer: Some(BufWriter::new(f)),
+                ..SplitFile::default()
+            },
+            Err(e) => SplitFile {
+                error: Some(e),
+                ..SplitFile::default()
+            },
+        }
+    }
+}
+
+/// The type with anything but letters, digits, `-`, `_` and inner `.` replaced,
+/// so it's a safe file name.
+fn file_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_') || (c == '.' && i > 0) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "_.jsonl".to_string()
+    } else {
+        format!("{}.jsonl", name)
+    }
+}
+
+fn shard_name(name: &str, index: usize) -> String {
+    format!("{}.shard-{}", name, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyze_reader, AnalyzeOptions, Strategy};
+
+    #[test]
+    fn split_writes_whole_lines_with_every_strategy() {
+        let mut content = String::new();
+        for i in 0..300 {
+            let key = ["A", "B", "c/d"][i % 3];
+            content.push_str(&format!("{{\"type\":\"{}\",\"n\":{}}}\n", key, i));
+        }
+        content.push_str("{\"type\":\n");
+        for (i, split_strategy) in [SplitStrategy::Mutex, SplitStrategy::Shards]
+            .iter()
+            .enumerate()
+        {
+            let dir = std::env::temp_dir().join(format!("fr-split-{}-{}", std::process::id(), i));
+            let split = Split::new(&dir, *split_strategy).unwrap();
+            let collector = split.clone();
+            let options = AnalyzeOptions::builder()
+                .strategy(Strategy::Chunks)
+                .chunk_size(200)
+                .threads(4)
+                .on_line(move |key, line_number, offset, line| {
+                    collector.record(key, line_number, offset, line)
+                })
+                .build();
+            analyze_reader(content.as_bytes(), &options).unwrap();
+            assert_eq!(
+                split.finish().unwrap(),
+                vec![
+                    ("A.jsonl".to_string(), 100),
+                    ("B.jsonl".to_string(), 100),
+                    ("ERROR.jsonl".to_string(), 1),
+                    ("c_d.jsonl".to_string(), 100),
+                ]
+            );
+            let a = fs::read_to_string(dir.join("A.jsonl")).unwrap();
+            assert_eq!(a.lines().count(), 100);
+            assert!(a
+                .lines()
+                .all(|line| line.starts_with("{\"type\":\"A\",\"n\":") && line.ends_with('}')));
+            let mut files: Vec<_> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            assert_eq!(
+                files,
+                vec!["A.jsonl", "B.jsonl", "ERROR.jsonl", "c_d.jsonl"]
+            );
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn split_traces_the_lines_back_to_their_files() {
+        let dir = std::env::temp_dir().join(format!("fr-split-provenance-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths = vec![dir.join("one.jsonl"), dir.join("two.jsonl")];
+        fs::write(&paths[0], "{\"type\":\"A\"}\n{\"type\":\"B\"}\n").unwrap();
+        fs::write(&paths[1], "{\"type\":\"B\"}\n{\"type\":\"A\",\"n\":1}\n").unwrap();
+        let provenance = Provenance::new("");
+        let split = Split::new(dir.join("out"), SplitStrategy::Mutex)
+            .unwrap()
+            .with_provenance(provenance.clone());
+        let collector = split.clone();
+        let options = AnalyzeOptions::builder()
+            .on_source(move |source| provenance.set_source(source))
+            .on_line(move |key, line_number, offset, line| {
+                collector.record(key, line_number, offset, line)
+            })
+            .build();
+        crate::analyze_files(&paths, &options).unwrap();
+        split.finish().unwrap();
+        let a = fs::read_to_string(dir.join("out").join("A.jsonl")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let lines: Vec<serde_json::Value> = a
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                serde_json::json!({
+                    "type": "A",
+                    "_source_file": paths[0].display().to_string(),
+                    "_line_number": 1,
+                    "_byte_offset": 0,
+                }),
+                serde_json::json!({
+                    "type": "A",
+                    "n": 1,
+                    "_source_file": paths[1].display().to_string(),
+                    "_line_number": 2,
+                    "_byte_offset": 13,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_name_is_safe() {
+        assert_eq!(file_name("payment.card"), "payment.card.jsonl");
+        assert_eq!(file_name("../etc/passwd"), "_._etc_passwd.jsonl");
+        assert_eq!(file_name(""), "_.jsonl");
+        assert_eq!(file_name(".."), "_..jsonl");
+    }
+}
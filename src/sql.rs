@@ -0,0 +1,752 @@
+use crate::projection::Projection;
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    io::{self, BufRead},
+};
+
+/// Query over JSON lines written in a small SQL subset:
+///
+/// ```sql
+/// SELECT type, count(*), sum(length) FROM input WHERE level = 'error' GROUP BY type LIMIT 10
+/// ```
+///
+/// - Fields are JSON paths with their parts separated by dots (`event.type`). `length` is
+///   the bytes of the line, newline included, as counted by the reports.
+/// - The aggregates are `count(*)`, `count(field)`, `sum`, `min`, `max` and `avg`.
+/// - `WHERE` compares fields with strings, numbers, `true`, `false` or `null` using
+///   `=`, `!=`, `<>`, `<`, `<=`, `>` or `>=`, combined with `AND` and `OR` and grouped
+///   with parentheses. It may come before or after `GROUP BY`.
+/// - Keywords are case insensitive and the table name after `FROM` is ignored.
+///
+/// Only the fields used by the query are parsed, and every line is read up to the last of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    items: Vec<SelectItem>,
+    filter: Option<Filter>,
+    group_by: Vec<Field>,
+    limit: Option<usize>,
+    projection: Projection,
+}
+
+/// Standalone `WHERE` clause matched against single lines, like `type == "payment" && amount > 100`.
+/// Besides the SQL operators, it accepts `==`, `&&` and `||`, and both quotes delimit strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    filter: Filter,
+    projection: Projection,
+}
+
+/// Rows returned by a query.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Lines that aren't JSON objects, at least up to the last field used, and were left out.
+    pub errors: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Length,
+    Path(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SelectItem {
+    expr: Expr,
+    name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Field(Field),
+    /// `None` is the `*` of `count(*)`.
+    Aggregate(Aggregate, Option<Field>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Compare(Field, CompareOp, Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Accumulated value of a select item within a group.
+#[derive(Debug, Clone)]
+enum Accumulator {
+    Field(Value),
+    Count(u64),
+    Sum(f64),
+    Min(Option<f64>),
+    Max(Option<f64>),
+    Avg(f64, u64),
+}
+
+impl Query {
+    pub fn columns(&self) -> Vec<String> {
+        self.items.iter().map(|item| item.name.clone()).collect()
+    }
+
+    /// Runs the query over the lines of the reader.
+    pub fn run(&self, mut reader: impl BufRead) -> io::Result<QueryResult> {
+        let aggregated = !self.group_by.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| matches!(item.expr, Expr::Aggregate(..)));
+        let mut result = QueryResult {
+            columns: self.columns(),
+            ..QueryResult::default()
+        };
+        let mut groups: BTreeMap<Vec<String>, Vec<Accumulator>> = BTreeMap::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let content = line.trim_ascii();
+            if content.is_empty() {
+                continue;
+            }
+            let value = match self.projection.project(content) {
+                Some(value) => value,
+                None => {
+                    result.errors += 1;
+                    continue;
+                }
+            };
+            let length = line.len();
+            if let Some(filter) = &self.filter {
+                if !filter.matches(&value, length) {
+                    continue;
+                }
+            }
+            if aggregated {
+                let key = self
+                    .group_by
+                    .iter()
+                    .map(|field| field.get(&value, length).to_string())
+                    .collect();
+                let accumulators = groups.entry(key).or_insert_with(|| {
+                    self.items
+                        .iter()
+                        .map(|item| Accumulator::new(&item.expr, &value, length))
+                        .collect()
+                });
+                for (accumulator, item) in accumulators.iter_mut().zip(&self.items) {
+                    accumulator.add(&item.expr, &value, length);
+                }
+            } else {
+                let row = self
+                    .items
+                    .iter()
+                    .map(|item| match &item.expr {
+                        Expr::Field(field) => field.get(&value, length),
+                        Expr::Aggregate(..) => unreachable!("Aggregates make the query aggregated"),
+                    })
+                    .collect();
+                result.rows.push(row);
+                if Some(result.rows.len()) == self.limit {
+                    break;
+                }
+            }
+        }
+        if aggregated {
+            let rows = groups
+                .into_values()
+                .map(|accumulators| accumulators.into_iter().map(Accumulator::finish).collect());
+            result.rows = rows.take(self.limit.unwrap_or(usize::MAX)).collect();
+        }
+        Ok(result)
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s, true)?,
+            position: 0,
+        };
+        parser.query()
+    }
+}
+
+impl Predicate {
+    /// Whether the line is a JSON object matching the predicate. Only the fields compared
+    /// are parsed. `line` must include its terminator, if any, which is part of the `length`.
+    pub fn matches(&self, line: &[u8]) -> bool {
+        self.projection
+            .project(line)
+            .is_some_and(|value| self.filter.matches(&value, line.len()))
+    }
+}
+
+impl std::str::FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s, false)?,
+            position: 0,
+        };
+        let filter = parser.or_filter()?;
+        match parser.tokens.get(parser.position) {
+            Some(token) => Err(format!(
+                "unexpected {} at the end of the expression",
+                describe(token)
+            )),
+            None => {
+                let mut paths = Vec::new();
+                filter.paths(&mut paths);
+                Ok(Predicate {
+                    projection: Projection::new(paths),
+                    filter,
+                })
+            }
+        }
+    }
+}
+
+impl Field {
+    fn new(name: &str) -> Self {
+        match name {
+            "length" => Field::Length,
+            _ => Field::Path(name.split('.').map(String::from).collect()),
+        }
+    }
+
+    fn path(&self) -> Option<&[String]> {
+        match self {
+            Field::Length => None,
+            Field::Path(path) => Some(path),
+        }
+    }
+
+    fn get(&self, value: &Value, length: usize) -> Value {
+        match self {
+            Field::Length => Value::from(length),
+            Field::Path(path) => path
+                .iter()
+                .try_fold(value, |value, part| value.get(part))
+                .cloned()
+                .unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl Filter {
+    /// Adds the paths of the fields compared.
+    fn paths<'a>(&'a self, paths: &mut Vec<&'a [String]>) {
+        match self {
+            Filter::And(a, b) | Filter::Or(a, b) => {
+                a.paths(paths);
+                b.paths(paths);
+            }
+            Filter::Compare(field, _, _) => paths.extend(field.path()),
+        }
+    }
+
+    fn matches(&self, value: &Value, length: usize) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(value, length) && b.matches(value, length),
+            Filter::Or(a, b) => a.matches(value, length) || b.matches(value, length),
+            Filter::Compare(field, op, literal) => {
+                let actual = field.get(value, length);
+                let ordering = match (&actual, literal) {
+                    (Value::Number(a), Value::Number(b)) => a
+                        .as_f64()
+                        .zip(b.as_f64())
+                        .and_then(|(a, b)| a.partial_cmp(&b)),
+                    (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                    (a, b) if a == b => Some(Ordering::Equal),
+                    _ => None,
+                };
+                match (op, ordering) {
+                    (CompareOp::Equal, ordering) => ordering == Some(Ordering::Equal),
+                    (CompareOp::NotEqual, ordering) => ordering != Some(Ordering::Equal),
+                    (_, None) => false,
+                    (CompareOp::Less, Some(ordering)) => ordering.is_lt(),
+                    (CompareOp::LessOrEqual, Some(ordering)) => ordering.is_le(),
+                    (CompareOp::Greater, Some(ordering)) => ordering.is_gt(),
+                    (CompareOp::GreaterOrEqual, Some(ordering)) => ordering.is_ge(),
+                }
+            }
+        }
+    }
+}
+
+impl Accumulator {
+    fn new(expr: &Expr, value: &Value, length: usize) -> Self {
+        match expr {
+            // grouped fields have the same value in the whole group
+            Expr::Field(field) => Accumulator::Field(field.get(value, length)),
+            Expr::Aggregate(Aggregate::Count, _) => Accumulator::Count(0),
+            Expr::Aggregate(Aggregate::Sum, _) => Accumulator::Sum(0.0),
+            Expr::Aggregate(Aggregate::Min, _) => Accumulator::Min(None),
+            Expr::Aggregate(Aggregate::Max, _) => Accumulator::Max(None),
+            Expr::Aggregate(Aggregate::Avg, _) => Accumulator::Avg(0.0, 0),
+        }
+    }
+
+    fn add(&mut self, expr: &Expr, value: &Value, length: usize) {
+        let field = match expr {
+            Expr::Aggregate(_, Some(field)) => field.get(value, length),
+            _ => Value::Null,
+        };
+        // non numeric values are ignored by the numeric aggregates, as SQL does with nulls
+        let number = field.as_f64();
+        match self {
+            Accumulator::Field(_) => {}
+            Accumulator::Count(count) => {
+                if matches!(expr, Expr::Aggregate(_, None)) || !field.is_null() {
+                    *count += 1;
+                }
+            }
+            Accumulator::Sum(sum) => *sum += number.unwrap_or(0.0),
+            Accumulator::Min(min) => {
+                *min = match (*min, number) {
+                    (Some(min), Some(n)) => Some(min.min(n)),
+                    (min, n) => min.or(n),
+                }
+            }
+            Accumulator::Max(max) => {
+                *max = match (*max, number) {
+                    (Some(max), Some(n)) => Some(max.max(n)),
+                    (max, n) => max.or(n),
+                }
+            }
+            Accumulator::Avg(sum, count) => {
+                if let Some(n) = number {
+                    *sum += n;
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Accumulator::Field(value) => value,
+            Accumulator::Count(count) => Value::from(count),
+            Accumulator::Sum(sum) => number(sum),
+            Accumulator::Min(min) | Accumulator::Max(min) => min.map_or(Value::Null, number),
+            Accumulator::Avg(_, 0) => Value::Null,
+            Accumulator::Avg(sum, count) => number(sum / count as f64),
+        }
+    }
+}
+
+/// Integral numbers are shown without decimals.
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Symbol(&'static str),
+}
+
+/// Double quotes delimit field names when `quoted_fields` is set, and strings otherwise.
+fn tokenize(s: &str, quoted_fields: bool) -> Result<Vec<Token>, String> {
+    const SYMBOLS: [&str; 14] = [
+        "<=", ">=", "!=", "<>", "==", "&&", "||", "=", "<", ">", "(", ")", ",", "*",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else if c == '\'' || c == '"' {
+            // quotes are escaped by doubling them
+            let mut content = String::new();
+            let mut chars = rest.char_indices().skip(1).peekable();
+            let end = loop {
+                match chars.next() {
+                    Some((i, q)) if q == c => match chars.peek() {
+                        Some((_, next)) if *next == c => {
+                            content.push(c);
+                            chars.next();
+                        }
+                        _ => break i,
+                    },
+                    Some((_, other)) => content.push(other),
+                    None => return Err(format!("unterminated {} in the query", c)),
+                }
+            };
+            tokens.push(if c == '"' && quoted_fields {
+                Token::Ident(content)
+            } else {
+                Token::Literal(Value::String(content))
+            });
+            end + 1
+        } else if c.is_ascii_digit() || c == '-' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+'))
+                .unwrap_or(rest.len());
+            let number = serde_json::from_str::<Value>(&rest[..len])
+                .ok()
+                .filter(Value::is_number)
+                .ok_or_else(|| format!("invalid number `{}` in the query", &rest[..len]))?;
+            tokens.push(Token::Literal(number));
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else {
+            return Err(format!("unexpected `{}` in the query", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn query(&mut self) -> Result<Query, String> {
+        self.expect_keyword("select")?;
+        let mut items = vec![self.select_item()?];
+        while self.eat_symbol(",") {
+            items.push(self.select_item()?);
+        }
+        self.expect_keyword("from")?;
+        self.ident()?;
+        let mut filter = None;
+        let mut group_by = Vec::new();
+        loop {
+            if filter.is_none() && self.eat_keyword("where") {
+                filter = Some(self.or_filter()?);
+            } else if group_by.is_empty() && self.eat_keyword("group") {
+                self.expect_keyword("by")?;
+                group_by.push(Field::new(&self.ident()?));
+                while self.eat_symbol(",") {
+                    group_by.push(Field::new(&self.ident()?));
+                }
+            } else {
+                break;
+            }
+        }
+        let limit = if self.eat_keyword("limit") {
+            match self.next() {
+                Some(Token::Literal(Value::Number(n))) if n.is_u64() => {
+                    n.as_u64().map(|n| n as usize)
+                }
+                _ => return Err("expected a number after LIMIT".to_string()),
+            }
+        } else {
+            None
+        };
+        if let Some(token) = self.tokens.get(self.position) {
+            return Err(format!(
+                "unexpected {} at the end of the query",
+                describe(token)
+            ));
+        }
+        for item in &items {
+            if let Expr::Field(field) = &item.expr {
+                if !group_by.is_empty() && !group_by.contains(field) {
+                    return Err(format!(
+                        "`{}` must be in the GROUP BY or inside an aggregate",
+                        item.name
+                    ));
+                }
+            }
+        }
+        let mut paths: Vec<&[String]> = group_by.iter().filter_map(Field::path).collect();
+        for item in &items {
+            match &item.expr {
+                Expr::Field(field) | Expr::Aggregate(_, Some(field)) => paths.extend(field.path()),
+                Expr::Aggregate(_, None) => {}
+            }
+        }
+        if let Some(filter) = &filter {
+            filter.paths(&mut paths);
+        }
+        let projection = Projection::new(paths);
+        Ok(Query {
+            items,
+            filter,
+            group_by,
+            limit,
+            projection,
+        })
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, String> {
+        let name = self.ident()?;
+        let aggregate = match name.to_lowercase().as_str() {
+            "count" => Some(Aggregate::Count),
+            "sum" => Some(Aggregate::Sum),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            "avg" => Some(Aggregate::Avg),
+            _ => None,
+        };
+        let (expr, default_name) = match aggregate {
+            Some(aggregate) if self.eat_symbol("(") => {
+                let (field, argument) = if aggregate == Aggregate::Count && self.eat_symbol("*") {
+                    (None, "*".to_string())
+                } else {
+                    let field = self.ident()?;
+                    (Some(Field::new(&field)), field)
+                };
+                self.expect_symbol(")")?;
+                let default_name = format!("{}({})", name.to_lowercase(), argument);
+                (Expr::Aggregate(aggregate, field), default_name)
+            }
+            _ => (Expr::Field(Field::new(&name)), name),
+        };
+        let name = if self.eat_keyword("as") {
+            self.ident()?
+        } else {
+            default_name
+        };
+        Ok(SelectItem { expr, name })
+    }
+
+    fn or_filter(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and_filter()?;
+        while self.eat_keyword("or") || self.eat_symbol("||") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and_filter()?));
+        }
+        Ok(filter)
+    }
+
+    fn and_filter(&mut self) -> Result<Filter, String> {
+        let mut filter = self.comparison()?;
+        while self.eat_keyword("and") || self.eat_symbol("&&") {
+            filter = Filter::And(Box::new(filter), Box::new(self.comparison()?));
+        }
+        Ok(filter)
+    }
+
+    fn comparison(&mut self) -> Result<Filter, String> {
+        if self.eat_symbol("(") {
+            let filter = self.or_filter()?;
+            self.expect_symbol(")")?;
+            return Ok(filter);
+        }
+        let field = Field::new(&self.ident()?);
+        let op = match self.next() {
+            Some(Token::Symbol("=")) | Some(Token::Symbol("==")) => CompareOp::Equal,
+            Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => CompareOp::NotEqual,
+            Some(Token::Symbol("<")) => CompareOp::Less,
+            Some(Token::Symbol("<=")) => CompareOp::LessOrEqual,
+            Some(Token::Symbol(">")) => CompareOp::Greater,
+            Some(Token::Symbol(">=")) => CompareOp::GreaterOrEqual,
+            _ => return Err("expected a comparison operator in the WHERE clause".to_string()),
+        };
+        let literal = match self.next() {
+            Some(Token::Literal(value)) => value,
+            Some(Token::Ident(word)) => match word.to_lowercase().as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                _ => {
+                    return Err(format!(
+                        "expected a value to compare with, found `{}`",
+                        word
+                    ))
+                }
+            },
+            _ => return Err("expected a value to compare with in the WHERE clause".to_string()),
+        };
+        Ok(Filter::Compare(field, op, literal))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(ident)) => Ok(ident),
+            Some(token) => Err(format!("expected a name, found {}", describe(&token))),
+            None => Err("expected a name at the end of the query".to_string()),
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", keyword.to_uppercase()))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.tokens.get(self.position), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", symbol))
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(ident) => format!("`{}`", ident),
+        Token::Literal(value) => format!("`{}`", value),
+        Token::Symbol(symbol) => format!("`{}`", symbol),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const LINES: &str = r#"{"type":"A","level":"error","ms":10}
+{"type":"B","level":"info","ms":30}
+{"type":"A","level":"error","ms":20}
+not json
+{"type":"A","level":"info"}
+"#;
+
+    fn run(query: &str) -> QueryResult {
+        query
+            .parse::<Query>()
+            .unwrap()
+            .run(LINES.as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn query_groups_and_aggregates_the_lines() {
+        let result =
+            run("SELECT type, count(*), sum(length), avg(ms) AS avg_ms FROM input GROUP BY type");
+        assert_eq!(
+            result.columns,
+            vec!["type", "count(*)", "sum(length)", "avg_ms"]
+        );
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![json!("A"), json!(3), json!(102), json!(15)],
+                vec![json!("B"), json!(1), json!(36), json!(30)],
+            ]
+        );
+        assert_eq!(result.errors, 1);
+    }
+
+    #[test]
+    fn query_filters_the_lines_before_or_after_the_group_by() {
+        let expected = vec![vec![json!("A"), json!(2), json!(10), json!(20)]];
+        let result = run(
+            "select type, count(ms), min(ms), max(ms) from input where level='error' group by type",
+        );
+        assert_eq!(result.rows, expected);
+        let result =
+            run("SELECT type, count(ms), min(ms), max(ms) FROM input GROUP BY type WHERE level = 'error'");
+        assert_eq!(result.rows, expected);
+        let result =
+            run("SELECT count(*) FROM input WHERE ms >= 20 AND level != 'error' OR ms < 15");
+        assert_eq!(result.rows, vec![vec![json!(2)]]);
+    }
+
+    #[test]
+    fn query_projects_the_lines_without_aggregates() {
+        let result = run(r#"SELECT type, "ms" FROM input WHERE ms = null OR ms > 15 LIMIT 2"#);
+        assert_eq!(
+            result.rows,
+            vec![vec![json!("B"), json!(30)], vec![json!("A"), json!(20)]]
+        );
+    }
+
+    #[test]
+    fn query_rejects_what_it_doesnt_support() {
+        let error = |query: &str| query.parse::<Query>().unwrap_err();
+        assert_eq!(error("SELECT type input"), "expected FROM");
+        assert_eq!(
+            error("SELECT level, count(*) FROM input GROUP BY type"),
+            "`level` must be in the GROUP BY or inside an aggregate"
+        );
+        assert_eq!(
+            error("SELECT type FROM input ORDER BY type"),
+            "unexpected `ORDER` at the end of the query"
+        );
+        assert!("SELECT type FROM input WHERE type ~ 'A'"
+            .parse::<Query>()
+            .is_err());
+        assert!("SELECT type FROM input WHERE type = 'A"
+            .parse::<Query>()
+            .is_err());
+    }
+
+    #[test]
+    fn predicate_matches_single_lines() {
+        let predicate: Predicate =
+            r#"type=="payment" && (amount>100 || vip == true)"#.parse().unwrap();
+        assert!(predicate.matches(br#"{"type":"payment","amount":150}"#));
+        assert!(predicate.matches(b"{\"type\":\"payment\",\"vip\":true}\n"));
+        assert!(!predicate.matches(br#"{"type":"payment","amount":50}"#));
+        assert!(!predicate.matches(br#"{"type":"refund","amount":150}"#));
+        assert!(!predicate.matches(br#"{"type":"payment","amount":150"#));
+        let predicate: Predicate = "length > 12 and type = 'A'".parse().unwrap();
+        assert!(predicate.matches(b"{\"type\":\"A\"}\n"));
+        assert!(!predicate.matches(b"{\"type\":\"A\"}"));
+        assert_eq!(
+            "type == 'A' type".parse::<Predicate>().unwrap_err(),
+            "unexpected `type` at the end of the expression"
+        );
+        assert!("(type == 'A'".parse::<Predicate>().is_err());
+    }
+}
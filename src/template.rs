@@ -0,0 +1,227 @@
+use crate::models::{Report, TypeLineCounter};
+
+/// Replaces every `{{name}}` placeholder of the template with its value.
+/// Values are inserted as they are, so escape them with `escape` when they come from the data.
+/// Unknown placeholders are left untouched.
+pub(crate) fn render(template: &str, values: &[(&str, String)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match values.iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => output.push_str(value),
+                    None => output.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Escapes the HTML special chars.
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Layout of every line of the results given by the user, like `{type}\t{count}\t{bytes}`.
+/// `\t`, `\n` and `\\` are unescaped, and `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// Values a template can show. Those that weren't tracked are left empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Type,
+    Count,
+    Bytes,
+    FirstLine,
+    FirstOffset,
+    LastLine,
+    LastOffset,
+    Duration,
+    AvgDuration,
+}
+
+const FIELDS: [(&str, Field); 9] = [
+    ("type", Field::Type),
+    ("count", Field::Count),
+    ("bytes", Field::Bytes),
+    ("first_line", Field::FirstLine),
+    ("first_offset", Field::FirstOffset),
+    ("last_line", Field::LastLine),
+    ("last_offset", Field::LastOffset),
+    ("duration", Field::Duration),
+    ("avg_duration", Field::AvgDuration),
+];
+
+impl std::str::FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some('t')) => text.push('\t'),
+                ('\\', Some('n')) => text.push('\n'),
+                ('\\', Some('\\')) => text.push('\\'),
+                ('{', Some('{')) => text.push('{'),
+                ('}', Some('}')) => text.push('}'),
+                ('{', _) => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("unclosed placeholder `{{{}` in the template", name));
+                    }
+                    let field = FIELDS
+                        .iter()
+                        .find(|(key, _)| *key == name.trim())
+                        .map(|(_, field)| *field)
+                        .ok_or_else(|| {
+                            let names: Vec<_> = FIELDS.iter().map(|(key, _)| *key).collect();
+                            format!(
+                                "unknown placeholder `{{{}}}`, expected one of: {}",
+                                name,
+                                names.join(", ")
+                            )
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                    continue;
+                }
+                (c, _) => {
+                    text.push(c);
+                    continue;
+                }
+            }
+            // the second char of the escape sequence
+            chars.next();
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// The line of a type of the report. Keys are full and numbers raw, so scripts can read them.
+    pub fn render(&self, key: &str, counter: &TypeLineCounter, results: &Report) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            let value = match part {
+                Part::Text(text) => {
+                    line.push_str(text);
+                    continue;
+                }
+                Part::Field(Field::Type) => key.to_string(),
+                Part::Field(Field::Count) => counter.count.to_string(),
+                Part::Field(Field::Bytes) => counter.bytes.to_string(),
+                Part::Field(Field::FirstLine) => {
+                    optional(results.first_seen.get(key), |p| p.line_number)
+                }
+                Part::Field(Field::FirstOffset) => {
+                    optional(results.first_seen.get(key), |p| p.offset)
+                }
+                Part::Field(Field::LastLine) => {
+                    optional(results.last_seen.get(key), |p| p.line_number)
+                }
+                Part::Field(Field::LastOffset) => {
+                    optional(results.last_seen.get(key), |p| p.offset)
+                }
+                Part::Field(Field::Duration) => {
+                    optional(results.durations.get(key), |d| d.seconds())
+                }
+                Part::Field(Field::AvgDuration) => {
+                    optional(results.durations.get(key).and_then(|d| d.average()), |a| a)
+                }
+            };
+            line.push_str(&value);
+        }
+        line
+    }
+}
+
+fn optional<T, V: ToString>(value: Option<T>, f: impl FnOnce(T) -> V) -> String {
+    value.map_or_else(String::new, |value| f(value).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_replaces_the_known_placeholders() {
+        let values = [("name", "fr".to_string()), ("count", "3".to_string())];
+        assert_eq!(
+            render("<b>{{name}}</b> {{ count }} {{other}} {{open", &values),
+            "<b>fr</b> 3 {{other}} {{open"
+        );
+    }
+
+    #[test]
+    fn escape_replaces_the_html_special_chars() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn templates_render_the_fields_of_every_type() {
+        let mut results = Report::new();
+        results.add_line("A{b}".into(), 10);
+        results.add_line("A{b}".into(), 20);
+        let template: Template = r"{type}\t{count}\t{ bytes }|{{{first_line}}}\\n"
+            .parse()
+            .unwrap();
+        let counter = results.get("A{b}").unwrap();
+        assert_eq!(
+            template.render("A{b}", counter, &results),
+            "A{b}\t2\t30|{}\\n"
+        );
+
+        let err = "{type} {size}".parse::<Template>().unwrap_err();
+        assert!(err.starts_with("unknown placeholder `{size}`"), "{}", err);
+        assert!("{type".parse::<Template>().is_err());
+    }
+}
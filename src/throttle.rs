@@ -0,0 +1,111 @@
+use std::{
+    io::{self, Read},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Reader limiting the bytes read per second with a token bucket,
+/// so background analyses don't saturate a shared box.
+/// The bucket holds at most a second worth of bytes.
+pub struct ThrottledReader<R> {
+    inner: R,
+    bytes_per_second: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl<R: Read> ThrottledReader<R> {
+    pub fn new(inner: R, bytes_per_second: u64) -> Self {
+        Self {
+            inner,
+            bytes_per_second: bytes_per_second.max(1),
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        let rate = self.bytes_per_second as f64;
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.refill();
+        if self.tokens < 1.0 {
+            let rate = self.bytes_per_second as f64;
+            sleep(Duration::from_secs_f64((1.0 - self.tokens) / rate));
+            self.refill();
+        }
+        // never read more than the bucket allows, so the bursts stay bounded
+        let allowed = (self.tokens as usize).clamp(1, buf.len().max(1));
+        let len = buf.len().min(allowed);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.tokens -= read as f64;
+        Ok(read)
+    }
+}
+
+/// Parses throughputs like `50MB/s`, `512KiB` or `1000`. Plain numbers are bytes per second.
+/// `KB`, `MB` and `GB` are powers of 1000 while `KiB`, `MiB` and `GiB` are powers of 1024.
+pub fn parse_throughput(s: &str) -> Result<u64, String> {
+    let trimmed = s.trim();
+    parse_size(trimmed.strip_suffix("/s").unwrap_or(trimmed)).ok_or_else(|| {
+        format!(
+            "invalid throughput `{}`, expected a number followed by B, KB, MB, GB, KiB, MiB or GiB",
+            s
+        )
+    })
+}
+
+/// Parses sizes like `5GB`, `512KiB` or `1000`, with the units of the throughputs.
+pub(crate) fn parse_size(s: &str) -> Option<u64> {
+    let trimmed = s.trim();
+    let (number, unit) = match trimmed.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => trimmed.split_at(i),
+        None => (trimmed, "B"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let multiplier = match unit {
+        "B" => 1,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttled_reader_limits_the_throughput() {
+        let content = vec![b'x'; 2_000];
+        let init = Instant::now();
+        let mut read = Vec::new();
+        ThrottledReader::new(&content[..], 10_000)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, content);
+        assert!(init.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn parse_throughput_accepts_several_units() {
+        assert_eq!(parse_throughput("50MB/s"), Ok(50_000_000));
+        assert_eq!(parse_throughput("512KiB"), Ok(512 * 1024));
+        assert_eq!(parse_throughput("1GiB/s"), Ok(1 << 30));
+        assert_eq!(parse_throughput("1000"), Ok(1_000));
+        assert!(parse_throughput("50Mb/s").is_err());
+        assert!(parse_throughput("MB/s").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::projection::Projection;
+use crate::replay::{parse_rfc3339, timestamp, TimeUnit};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// Restricts the analysis of a time-ordered file to the lines whose timestamp is within
+/// the bounds. The beginning and the end of the range are found by binary search on the
+/// byte offsets, so only a few lines are read before reaching it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    /// Field holding the timestamp. Nested fields are separated by dots.
+    pub field: String,
+    /// Seconds since the epoch of the first moment in the range.
+    pub since: Option<f64>,
+    /// Seconds since the epoch of the last moment in the range.
+    pub until: Option<f64>,
+    /// Unit of the numeric timestamps of the lines.
+    pub unit: TimeUnit,
+}
+
+impl TimeRange {
+    /// Offsets where the lines in the range begin and end in a file of `size` bytes.
+    /// Lines without the timestamp go with the timed line before them.
+    pub fn locate(&self, reader: &mut (impl Read + Seek), size: u64) -> io::Result<(u64, u64)> {
+        let field = Projection::single(&self.field);
+        let start = match self.since {
+            Some(since) => partition(reader, size, &field, self.unit, |seconds| seconds >= since)?,
+            None => 0,
+        };
+        let end = match self.until {
+            Some(until) => partition(reader, size, &field, self.unit, |seconds| seconds > until)?,
+            None => size,
+        };
+        Ok((start, end.max(start)))
+    }
+}
+
+/// Offset of the first timed line whose timestamp is past the bound, or `size` if there's none.
+/// The timestamps must be sorted, so the lines past the bound are all at the end.
+fn partition(
+    reader: &mut (impl Read + Seek),
+    size: u64,
+    field: &Projection,
+    unit: TimeUnit,
+    is_past: impl Fn(f64) -> bool,
+) -> io::Result<u64> {
+    // every timed line ending before `low` is not past the bound,
+    // and every timed line starting at `high` or after is
+    let mut low = 0;
+    let mut high = size;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let start = line_start(reader, middle, low)?;
+        if start >= high {
+            high = middle;
+            continue;
+        }
+        match first_timed_line(reader, start, high, field, unit)? {
+            Some((line_start, _, seconds)) if is_past(seconds) => high = line_start,
+            Some((_, line_end, _)) => low = line_end,
+            // the lines between `middle` and `high` have no timestamp
+            None => high = middle,
+        }
+    }
+    // `low` may still be followed by lines without timestamp, which go with the line before
+    Ok(first_timed_line(reader, low, size, field, unit)?
+        .map_or(size, |(line_start, _, _)| line_start))
+}
+
+/// Offset of the first line beginning at `offset` or after. `low` is known to be a line start.
+fn line_start(reader: &mut (impl Read + Seek), offset: u64, low: u64) -> io::Result<u64> {
+    if offset == low {
+        return Ok(low);
+    }
+    // reading from the byte before tells whether `offset` is already a line start
+    reader.seek(SeekFrom::Start(offset - 1))?;
+    let mut skipped = Vec::new();
+    let read = BufReader::new(reader).read_until(b'\n', &mut skipped)?;
+    Ok(offset - 1 + read as u64)
+}
+
+/// Beginning, end and timestamp of the first line with a timestamp starting between
+/// `start` and `high`.
+fn first_timed_line(
+    reader: &mut (impl Read + Seek),
+    start: u64,
+    high: u64,
+    field: &Projection,
+    unit: TimeUnit,
+) -> io::Result<Option<(u64, u64, f64)>> {
+    reader.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(reader);
+    let mut line_start = start;
+    let mut line = Vec::new();
+    while line_start < high {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        let line_end = line_start + read as u64;
+        if let Some(seconds) = timestamp(&line, field, unit) {
+            return Ok(Some((line_start, line_end, seconds)));
+        }
+        line_start = line_end;
+    }
+    Ok(None)
+}
+
+/// Parses the bounds of a time range: RFC 3339 timestamps like `2024-02-29T12:00:00Z`,
+/// dates like `2024-02-29` (midnight UTC) or seconds since the epoch.
+pub fn parse_timestamp(s: &str) -> Result<f64, String> {
+    let trimmed = s.trim();
+    let seconds = match trimmed.parse::<f64>() {
+        Ok(seconds) => Some(seconds).filter(|seconds| seconds.is_finite()),
+        Err(_) if trimmed.len() == 10 => parse_rfc3339(&format!("{}T00:00:00Z", trimmed)),
+        Err(_) => parse_rfc3339(trimmed),
+    };
+    seconds.ok_or_else(|| {
+        format!(
+            "invalid timestamp `{}`, expected one like 2024-02-29T12:00:00Z, 2024-02-29 or 1709208000",
+            s
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn logs() -> String {
+        let mut logs = String::from("{\"ts\":1}\n");
+        for second in 1..=1_000 {
+            logs.push_str(&format!("{{\"ts\":{},\"n\":{}}}\n", second, second));
+            if second % 7 == 0 {
+                logs.push_str("not json\n");
+            }
+        }
+        logs
+    }
+
+    fn range(logs: &str, since: Option<f64>, until: Option<f64>) -> &str {
+        let range = TimeRange {
+            field: "ts".to_string(),
+            since,
+            until,
+            unit: TimeUnit::Auto,
+        };
+        let (start, end) = range
+            .locate(&mut Cursor::new(logs), logs.len() as u64)
+            .unwrap();
+        &logs[start as usize..end as usize]
+    }
+
+    #[test]
+    fn time_range_finds_the_lines_by_binary_search() {
+        let logs = logs();
+        let lines = range(&logs, Some(500.0), Some(502.0));
+        assert_eq!(
+            lines,
+            "{\"ts\":500,\"n\":500}\n{\"ts\":501,\"n\":501}\n{\"ts\":502,\"n\":502}\n"
+        );
+        // untimed lines stay with the line before them
+        let lines = range(&logs, Some(699.5), Some(700.0));
+        assert_eq!(lines, "{\"ts\":700,\"n\":700}\nnot json\n");
+
+        assert_eq!(range(&logs, None, None), logs);
+        assert!(range(&logs, Some(1.0), None).starts_with("{\"ts\":1}\n{\"ts\":1,"));
+        assert!(range(&logs, None, Some(1_000.0)).ends_with("{\"ts\":1000,\"n\":1000}\n"));
+        assert_eq!(range(&logs, Some(2_000.0), None), "");
+        assert_eq!(range(&logs, None, Some(0.0)), "");
+        assert_eq!(range(&logs, Some(600.0), Some(400.0)), "");
+    }
+
+    #[test]
+    fn time_range_handles_missing_terminators_and_untimed_files() {
+        let logs = "{\"ts\":\"2024-02-29T12:00:00Z\"}\n{\"ts\":\"2024-02-29T13:00:00Z\"}";
+        let since = parse_timestamp("2024-02-29T12:30:00Z").unwrap();
+        assert_eq!(
+            range(logs, Some(since), None),
+            "{\"ts\":\"2024-02-29T13:00:00Z\"}"
+        );
+        let untimed = "a\nb\nc\n";
+        assert_eq!(range(untimed, Some(0.0), None), "");
+        assert_eq!(range(untimed, None, Some(0.0)), untimed);
+    }
+
+    #[test]
+    fn timestamps_are_parsed() {
+        assert_eq!(parse_timestamp("1709208000"), Ok(1_709_208_000.0));
+        assert_eq!(parse_timestamp("2024-02-29"), Ok(1_709_164_800.0));
+        assert_eq!(parse_timestamp("2024-02-29T12:00:00Z"), Ok(1_709_208_000.0));
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::time_range::parse_timestamp;
+use chrono::{DateTime, Offset, TimeZone as _};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// IANA time zone, like `Europe/Madrid`, the dates and the timestamps without offset
+/// of the time ranges are local to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZone(Tz);
+
+impl FromStr for TimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(TimeZone).map_err(|_| {
+            format!(
+                "unknown time zone `{}`, expected an IANA name like Europe/Madrid",
+                s
+            )
+        })
+    }
+}
+
+impl TimeZone {
+    /// Parses a bound like `parse_timestamp`, but dates mean the local midnight and timestamps
+    /// without offset are local times. Epoch seconds and timestamps with offset are kept as they are.
+    pub fn parse_timestamp(&self, s: &str) -> Result<f64, String> {
+        let seconds = parse_timestamp(s)?;
+        if !is_local(s.trim()) {
+            return Ok(seconds);
+        }
+        let whole = seconds.floor();
+        let naive = match DateTime::from_timestamp(whole as i64, 0) {
+            Some(utc) => utc.naive_utc(),
+            None => return Ok(seconds),
+        };
+        // times skipped by a daylight saving change take the offset in effect before it
+        let local = match self.0.from_local_datetime(&naive).earliest() {
+            Some(local) => local.timestamp(),
+            None => {
+                let offset = self.0.offset_from_utc_datetime(&naive).fix();
+                whole as i64 - i64::from(offset.local_minus_utc()) + 3_600
+            }
+        };
+        Ok(local as f64 + seconds - whole)
+    }
+}
+
+/// Whether the bound is a date or a timestamp without offset, as opposed to epoch seconds
+/// or an RFC 3339 timestamp with `Z` or `+hh:mm`.
+fn is_local(s: &str) -> bool {
+    if s.parse::<f64>().is_ok() {
+        return false;
+    }
+    let bytes = s.as_bytes();
+    let offset = bytes.len() > 19
+        && (matches!(bytes[bytes.len() - 1], b'Z' | b'z')
+            || (matches!(bytes[bytes.len() - 6], b'+' | b'-') && bytes[bytes.len() - 3] == b':'));
+    !offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_without_offset_are_local_times() {
+        let madrid: TimeZone = "Europe/Madrid".parse().unwrap();
+        // winter (+01:00) and summer (+02:00) midnights
+        assert_eq!(madrid.parse_timestamp("2024-02-29"), Ok(1_709_161_200.0));
+        assert_eq!(madrid.parse_timestamp("2024-07-01"), Ok(1_719_784_800.0));
+        assert_eq!(
+            madrid.parse_timestamp("2024-02-29T12:00:00.5"),
+            Ok(1_709_204_400.5)
+        );
+        // skipped by the change to summer time
+        assert_eq!(
+            madrid.parse_timestamp("2024-03-31T02:30:00"),
+            madrid.parse_timestamp("2024-03-31T03:30:00")
+        );
+
+        assert_eq!(madrid.parse_timestamp("1709208000"), Ok(1_709_208_000.0));
+        assert_eq!(
+            madrid.parse_timestamp("2024-02-29T12:00:00Z"),
+            Ok(1_709_208_000.0)
+        );
+        assert_eq!(
+            madrid.parse_timestamp("2024-02-29T12:00:00+02:00"),
+            Ok(1_709_200_800.0)
+        );
+        assert!(madrid.parse_timestamp("yesterday").is_err());
+        assert!("Mars/Olympus".parse::<TimeZone>().is_err());
+    }
+}
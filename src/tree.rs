@@ -0,0 +1,110 @@
+use crate::models::{Report, TypeLineCounter};
+use std::collections::BTreeMap;
+
+/// Hierarchical types, like `payment.card.authorized`, arranged by their levels.
+#[derive(Debug, Default)]
+struct Node {
+    /// Lines whose type ends at this level.
+    own: Option<TypeLineCounter>,
+    total: TypeLineCounter,
+    children: BTreeMap<String, Node>,
+}
+
+/// Row of the tree view: the indented label and its figures. Parents without lines of their
+/// own have no figures, and every parent is followed by its children and a subtotal row.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TreeRow {
+    pub(crate) depth: usize,
+    pub(crate) label: String,
+    pub(crate) subtotal: bool,
+    pub(crate) counter: Option<TypeLineCounter>,
+}
+
+pub(crate) fn tree_rows(report: &Report, separator: &str) -> Vec<TreeRow> {
+    let mut root = Node::default();
+    for (key, counter) in &report.types {
+        let mut node = &mut root;
+        let levels: Vec<&str> = if separator.is_empty() {
+            vec![key]
+        } else {
+            key.split(separator).collect()
+        };
+        for level in levels {
+            node = node.children.entry(level.to_string()).or_default();
+            node.total.merge(counter);
+        }
+        node.own
+            .get_or_insert_with(TypeLineCounter::default)
+            .merge(counter);
+    }
+    let mut rows = Vec::new();
+    for (label, node) in &root.children {
+        push_rows(&mut rows, label, node, 0);
+    }
+    rows
+}
+
+fn push_rows(rows: &mut Vec<TreeRow>, label: &str, node: &Node, depth: usize) {
+    rows.push(TreeRow {
+        depth,
+        label: label.to_string(),
+        subtotal: false,
+        counter: node.own,
+    });
+    if node.children.is_empty() {
+        return;
+    }
+    for (child, child_node) in &node.children {
+        push_rows(rows, child, child_node, depth + 1);
+    }
+    rows.push(TreeRow {
+        depth,
+        label: label.to_string(),
+        subtotal: true,
+        counter: Some(node.total),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn row(depth: usize, label: &str, subtotal: bool, figures: Option<(usize, usize)>) -> TreeRow {
+        TreeRow {
+            depth,
+            label: label.to_string(),
+            subtotal,
+            counter: figures.map(|(count, bytes)| TypeLineCounter { count, bytes }),
+        }
+    }
+
+    #[test]
+    fn tree_rows_nest_the_children_under_their_parents_with_subtotals() {
+        let mut report = Report::new();
+        for (key, count, bytes) in [
+            ("payment.card.authorized", 1, 10),
+            ("payment.card.declined", 2, 20),
+            ("payment.transfer", 3, 30),
+            ("payment", 4, 40),
+            ("ERROR", 5, 50),
+        ] {
+            report
+                .types
+                .insert(Cow::Borrowed(key), TypeLineCounter { count, bytes });
+        }
+        assert_eq!(
+            tree_rows(&report, "."),
+            vec![
+                row(0, "ERROR", false, Some((5, 50))),
+                row(0, "payment", false, Some((4, 40))),
+                row(1, "card", false, None),
+                row(2, "authorized", false, Some((1, 10))),
+                row(2, "declined", false, Some((2, 20))),
+                row(1, "card", true, Some((3, 30))),
+                row(1, "transfer", false, Some((3, 30))),
+                row(0, "payment", true, Some((10, 100))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::models::Report;
+use crate::schema::{SchemaVersion, REPORT_VERSION};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::OpenOptions,
+    io::{self, BufRead, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Bars of the sparklines, from the lowest value to the highest.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A run kept in a history file, one JSON line per run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// Version of the layout of the line. Runs written before the versions existed have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_version: Option<u32>,
+    /// Seconds since the epoch when the run finished.
+    pub timestamp: u64,
+    pub report: Report,
+}
+
+impl HistoryEntry {
+    /// The run finishing now, written with the latest layout.
+    pub fn new(report: Report) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            report_version: Some(REPORT_VERSION),
+            timestamp,
+            report,
+        }
+    }
+
+    /// The same run written with the layout of another version.
+    pub fn with_schema(self, version: SchemaVersion) -> Self {
+        Self {
+            report_version: version.report_version(),
+            ..self
+        }
+    }
+
+    /// Adds the run at the end of the history file, creating it if it doesn't exist.
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.write_line(file)
+    }
+
+    /// Writes the run as a single JSON line.
+    pub fn write_line(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        writer.write_all(&line)
+    }
+}
+
+/// Reads the last `runs` runs of a history file, the oldest first.
+pub fn read_history(reader: impl BufRead, runs: usize) -> io::Result<Vec<HistoryEntry>> {
+    // only the last lines are worth parsing
+    let mut last = VecDeque::with_capacity(runs);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || runs == 0 {
+            continue;
+        }
+        if last.len() == runs {
+            last.pop_front();
+        }
+        last.push_back((i + 1, line));
+    }
+    last.into_iter()
+        .map(|(line_number, line)| {
+            serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid run at line {} of the history: {}", line_number, e),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Counts and bytes of every type across some runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trend {
+    /// When every run finished, the oldest first.
+    pub timestamps: Vec<u64>,
+    /// Types seen in any of the runs, sorted by key.
+    pub types: Vec<TypeTrend>,
+}
+
+/// Counts and bytes of a type in every run, 0 in the runs without it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeTrend {
+    pub key: String,
+    pub counts: Vec<usize>,
+    pub bytes: Vec<usize>,
+}
+
+impl Trend {
+    pub fn new(runs: &[HistoryEntry]) -> Self {
+        let mut types: BTreeMap<&str, TypeTrend> = BTreeMap::new();
+        for (i, run) in runs.iter().enumerate() {
+            for (key, counter) in &run.report.types {
+                let trend = types.entry(key).or_insert_with(|| TypeTrend {
+                    key: key.to_string(),
+                    counts: vec![0; runs.len()],
+                    bytes: vec![0; runs.len()],
+                });
+                trend.counts[i] = counter.count;
+                trend.bytes[i] = counter.bytes;
+            }
+        }
+        Trend {
+            timestamps: runs.iter().map(|run| run.timestamp).collect(),
+            types: types.into_values().collect(),
+        }
+    }
+}
+
+impl TypeTrend {
+    /// Lines gained since the previous run.
+    pub fn count_delta(&self) -> i64 {
+        delta(&self.counts)
+    }
+
+    /// Bytes gained since the previous run.
+    pub fn bytes_delta(&self) -> i64 {
+        delta(&self.bytes)
+    }
+}
+
+fn delta(values: &[usize]) -> i64 {
+    match values {
+        [.., previous, last] => *last as i64 - *previous as i64,
+        _ => 0,
+    }
+}
+
+/// One bar per value, scaled between the lowest and the highest of them.
+pub fn sparkline(values: &[usize]) -> String {
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+    values
+        .iter()
+        .map(|value| {
+            if max == min {
+                return BARS[0];
+            }
+            let level = (value - min) as f64 / (max - min) as f64 * (BARS.len() - 1) as f64;
+            BARS[level.round() as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn run(timestamp: u64, types: &[(&'static str, usize)]) -> HistoryEntry {
+        let mut report = Report::new();
+        for (key, count) in types {
+            for _ in 0..*count {
+                report.add_line((*key).into(), 10);
+            }
+        }
+        HistoryEntry {
+            report_version: Some(REPORT_VERSION),
+            timestamp,
+            report,
+        }
+    }
+
+    #[test]
+    fn history_keeps_the_last_runs() {
+        let mut history = Vec::new();
+        for day in 1..=5 {
+            run(day, &[("A", day as usize)])
+                .write_line(&mut history)
+                .unwrap();
+        }
+        history.extend(b"\n");
+        let runs = read_history(Cursor::new(&history), 3).unwrap();
+        assert_eq!(
+            runs,
+            vec![
+                run(3, &[("A", 3)]),
+                run(4, &[("A", 4)]),
+                run(5, &[("A", 5)])
+            ]
+        );
+        assert_eq!(read_history(Cursor::new(&history), 10).unwrap().len(), 5);
+
+        // runs written with the first layout have no version
+        run(6, &[("A", 6)])
+            .with_schema(SchemaVersion::new(1).unwrap())
+            .write_line(&mut history)
+            .unwrap();
+        let runs = read_history(Cursor::new(&history), 2).unwrap();
+        assert_eq!(runs[0].report_version, Some(2));
+        assert_eq!(runs[1].report_version, None);
+        let lines = String::from_utf8(history.clone()).unwrap();
+        assert!(lines.starts_with("{\"report_version\":2,"));
+        assert!(!lines.lines().last().unwrap().contains("report_version"));
+
+        history.extend(b"not json\n");
+        let err = read_history(Cursor::new(&history), 3).unwrap_err();
+        assert!(err.to_string().contains("line 8"), "{}", err);
+    }
+
+    #[test]
+    fn trends_give_the_deltas_and_sparklines_of_every_type() {
+        let runs = [
+            run(1, &[("A", 1), ("B", 4)]),
+            run(2, &[("A", 5)]),
+            run(3, &[("A", 8), ("B", 2)]),
+        ];
+        let trend = Trend::new(&runs);
+        assert_eq!(trend.timestamps, vec![1, 2, 3]);
+        let keys: Vec<_> = trend.types.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(keys, vec!["A", "B"]);
+
+        let (a, b) = (&trend.types[0], &trend.types[1]);
+        assert_eq!(a.counts, vec![1, 5, 8]);
+        assert_eq!((a.count_delta(), a.bytes_delta()), (3, 30));
+        assert_eq!(b.counts, vec![4, 0, 2]);
+        assert_eq!(b.count_delta(), 2);
+        assert_eq!(sparkline(&a.counts), "▁▅█");
+        assert_eq!(sparkline(&b.counts), "█▁▅");
+        assert_eq!(sparkline(&[3, 3]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
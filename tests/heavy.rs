@@ -0,0 +1,95 @@
+//! End-to-end checks over fixtures of hundreds of megabytes, run with
+//! `cargo test --release --features heavy-tests --test heavy`.
+//! `HEAVY_TESTS_LINES` changes the number of lines of the fixture.
+
+use file_reader::{analyze, AnalyzeOptions, Corpus, LineSize, Report, Strategy};
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+const DEFAULT_LINES: usize = 2_500_000;
+/// Peak memory allowed for the whole run, far below the size of the fixture.
+const MAX_RESIDENT_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Fixture written to the temp dir, removed when dropped.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    fn new(corpus: &Corpus) -> Self {
+        let path = env::temp_dir().join(format!("fr_heavy_{}.jsonl", std::process::id()));
+        let file = BufWriter::new(File::create(&path).unwrap());
+        corpus.write_to(file).unwrap();
+        Fixture(path)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Peak resident memory of the process, if the platform tells it.
+fn peak_resident_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[test]
+fn every_strategy_gets_the_same_results_in_bounded_memory() {
+    let lines = env::var("HEAVY_TESTS_LINES")
+        .ok()
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(DEFAULT_LINES);
+    let corpus = Corpus {
+        lines,
+        types: 16,
+        line_size: LineSize::Mixed,
+        error_rate: 2,
+        ..Corpus::default()
+    };
+    let fixture = Fixture::new(&corpus);
+    let size = fs::metadata(&fixture.0).unwrap().len();
+
+    let runs = [
+        ("naive", AnalyzeOptions::builder().strategy(Strategy::Naive)),
+        (
+            "chunks",
+            AnalyzeOptions::builder().strategy(Strategy::Chunks),
+        ),
+        (
+            "adaptive chunks",
+            AnalyzeOptions::builder()
+                .strategy(Strategy::Chunks)
+                .adaptive_chunk_size(true),
+        ),
+        (
+            "batches",
+            AnalyzeOptions::builder().strategy(Strategy::Batches),
+        ),
+    ];
+    let mut expected: Option<Report> = None;
+    for (name, options) in runs {
+        let report = analyze(&fixture.0, &options.build()).unwrap();
+        let counted: usize = report.types.values().map(|c| c.count).sum();
+        assert_eq!(counted, lines, "{} counted every line", name);
+        match &expected {
+            Some(expected) => assert_eq!(&report, expected, "{} differs from naive", name),
+            None => expected = Some(report),
+        }
+    }
+
+    if let Some(peak) = peak_resident_bytes() {
+        assert!(
+            peak < MAX_RESIDENT_BYTES.min(size),
+            "peak memory of {} bytes analyzing {} bytes",
+            peak,
+            size
+        );
+    }
+}